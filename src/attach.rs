@@ -59,7 +59,8 @@ pub struct AttachConfig {
 
 impl AttachConfig {
     /// Create a new config with the given agent ID.
-    pub fn new(agent_id: String) -> Self {
+    #[must_use] 
+    pub const fn new(agent_id: String) -> Self {
         Self {
             agent_id,
             detach_prefix: 0x07, // Ctrl+G
@@ -161,6 +162,7 @@ impl Drop for TerminalState {
 /// - Connection lost during attach
 /// - Protocol error with server
 #[allow(clippy::missing_panics_doc)] // serde_json::to_string on valid types won't panic
+#[allow(clippy::items_after_statements)] // Scoped constants/imports kept next to their use
 pub async fn run_attach(
     stream: &mut UnixStream,
     agent_id: &str,
@@ -262,11 +264,10 @@ pub async fn run_attach(
                     // If we got a full buffer, there might be more
                     if n < extra_buf.len() {
                         // Partial read - probably done, but try once more
-                        if let Ok(Ok(n2)) = timeout(Duration::from_millis(20), stream.read(&mut extra_buf)).await {
-                            if n2 > 0 {
+                        if let Ok(Ok(n2)) = timeout(Duration::from_millis(20), stream.read(&mut extra_buf)).await
+                            && n2 > 0 {
                                 initial_screen_data.extend_from_slice(&extra_buf[..n2]);
                             }
-                        }
                         break;
                     }
                 }
@@ -276,13 +277,13 @@ pub async fn run_attach(
     }
 
     // Output any initial screen data
-    if !initial_screen_data.is_empty() {
+    if initial_screen_data.is_empty() {
+        info!("No initial screen data received");
+    } else {
         info!("Outputting {} bytes of initial screen data", initial_screen_data.len());
         use std::io::Write;
         std::io::stdout().write_all(&initial_screen_data).map_err(AttachError::Io)?;
         std::io::stdout().flush().map_err(AttachError::Io)?;
-    } else {
-        info!("No initial screen data received");
     }
 
     // Run the I/O bridge
@@ -334,7 +335,7 @@ async fn run_io_bridge(
     
     // Set up SIGWINCH handler for terminal resize
     let mut sigwinch = signal(SignalKind::window_change())
-        .map_err(|e| AttachError::Io(e))?;
+        .map_err(AttachError::Io)?;
     
     // Track current size to detect changes
     let mut current_size = get_terminal_size();
@@ -1,6 +1,12 @@
 //! Command-line interface for botty.
 
-use clap::{Parser, Subcommand};
+use crate::keys::{encode_key, KeyModes};
+use crate::protocol::{MouseAction, MouseButton, Region, TerminalIdentity};
+use crate::redact::Redaction;
+use crate::report::{Report, ReportError, ReportFormat};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::ffi::OsString;
 use std::path::PathBuf;
 
 /// Parse a key notation string into a byte value.
@@ -46,54 +52,177 @@ pub fn parse_key_notation(s: &str) -> Option<u8> {
     None
 }
 
-/// Parse a named key sequence into bytes.
+/// Parse a named key sequence into bytes, as sent in normal (non-application)
+/// cursor and keypad mode.
 ///
 /// Supported keys:
 /// - Arrow keys: `up`, `down`, `left`, `right`
-/// - Special keys: `enter`, `tab`, `escape`, `backspace`, `delete`
+/// - Special keys: `enter`, `tab`, `escape`, `backspace`, `insert`, `delete`
 /// - Navigation: `home`, `end`, `pageup`, `pagedown`
-/// - Control sequences: `ctrl-c`, `ctrl-d`, etc.
+/// - Function keys: `f1` to `f24`
+/// - Keypad: `kp0`-`kp9`, `kpenter`, `kpplus`, etc.
+/// - Modifiers: `ctrl-c`, `alt-x`, `M-b`, `ctrl-left`, `shift-tab`, etc.
 /// - Single characters: `a`, `b`, `x`, etc.
 ///
-/// Returns None if the key name is not recognized.
+/// See [`crate::keys`] for the full syntax. Returns None if the key name is
+/// not recognized.
 #[must_use]
 pub fn parse_key_sequence(s: &str) -> Option<Vec<u8>> {
-    let s = s.trim().to_lowercase();
+    encode_key(s, KeyModes::default())
+}
 
-    // Try single-byte keys first (ctrl-X, single chars)
-    if let Some(byte) = parse_key_notation(&s) {
-        return Some(vec![byte]);
+/// Parse a `KEY=VALUE` annotation argument.
+fn parse_annotation(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got {s:?}")),
     }
+}
 
-    // Multi-byte ANSI escape sequences
-    match s.as_str() {
-        // Arrow keys (ESC [ X)
-        "up" => Some(vec![0x1b, 0x5b, 0x41]),       // ESC [ A
-        "down" => Some(vec![0x1b, 0x5b, 0x42]),     // ESC [ B
-        "right" => Some(vec![0x1b, 0x5b, 0x43]),    // ESC [ C
-        "left" => Some(vec![0x1b, 0x5b, 0x44]),     // ESC [ D
-
-        // Special keys
-        "enter" => Some(vec![0x0d]),                // CR
-        "return" => Some(vec![0x0d]),               // Alias for enter
-        "tab" => Some(vec![0x09]),                  // HT
-        "escape" | "esc" => Some(vec![0x1b]),       // ESC
-        "backspace" => Some(vec![0x7f]),            // DEL
-        "delete" | "del" => Some(vec![0x1b, 0x5b, 0x33, 0x7e]), // ESC [ 3 ~
-
-        // Navigation keys
-        "home" => Some(vec![0x1b, 0x5b, 0x48]),     // ESC [ H
-        "end" => Some(vec![0x1b, 0x5b, 0x46]),      // ESC [ F
-        "pageup" | "pgup" => Some(vec![0x1b, 0x5b, 0x35, 0x7e]), // ESC [ 5 ~
-        "pagedown" | "pgdn" | "pgdown" => Some(vec![0x1b, 0x5b, 0x36, 0x7e]), // ESC [ 6 ~
-
-        // Function keys (commonly used)
-        "f1" => Some(vec![0x1b, 0x4f, 0x50]),       // ESC O P
-        "f2" => Some(vec![0x1b, 0x4f, 0x51]),       // ESC O Q
-        "f3" => Some(vec![0x1b, 0x4f, 0x52]),       // ESC O R
-        "f4" => Some(vec![0x1b, 0x4f, 0x53]),       // ESC O S
-
-        _ => None,
+/// Parse a `--rows` range: `START..END` (end exclusive), `START..`, `..END`
+/// or a single row.
+fn parse_rows(s: &str) -> Result<Region, String> {
+    let number = |n: &str, default: u16| {
+        if n.is_empty() {
+            Ok(default)
+        } else {
+            n.parse::<u16>().map_err(|_| format!("invalid row {n:?} in {s:?}"))
+        }
+    };
+    let (start, end) = if let Some((start, end)) = s.split_once("..") {
+        (number(start, 0)?, number(end, u16::MAX)?)
+    } else {
+        let row = number(s, 0)?;
+        (row, row.saturating_add(1))
+    };
+    if start >= end {
+        return Err(format!("empty row range {s:?}"));
+    }
+    Ok(Region::Rows { start, end })
+}
+
+/// Parse a `--region` rectangle: `ROW,COL,ROWS,COLS`.
+fn parse_region(s: &str) -> Result<Region, String> {
+    let numbers: Vec<u16> = s
+        .split(',')
+        .map(|n| n.trim().parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("expected ROW,COL,ROWS,COLS, got {s:?}"))?;
+    match numbers[..] {
+        [row, col, rows, cols] => Ok(Region::Rect { row, col, rows, cols }),
+        _ => Err(format!("expected ROW,COL,ROWS,COLS, got {s:?}")),
+    }
+}
+
+/// Parse a built-in redaction rule's name.
+fn parse_redaction(s: &str) -> Result<Redaction, String> {
+    s.parse().map_err(|e: crate::redact::RedactError| e.to_string())
+}
+
+/// Parse a `REGEX=REPLACEMENT` redaction rule, split at the last `=`.
+fn parse_redaction_pattern(s: &str) -> Result<Redaction, String> {
+    match s.rsplit_once('=') {
+        Some((pattern, replacement)) if !pattern.is_empty() => Ok(Redaction::Custom {
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+        }),
+        _ => Err(format!("expected REGEX=REPLACEMENT, got {s:?}")),
+    }
+}
+
+/// Redaction rules for volatile content such as timestamps and PIDs.
+#[derive(Debug, Clone, Default, Args)]
+pub struct RedactArgs {
+    /// Replace volatile content: timestamp, duration, pid, hash, temp-path or
+    /// spinner (repeatable, or comma-separated).
+    #[arg(long, value_name = "RULE", value_delimiter = ',', value_parser = parse_redaction)]
+    pub redact: Vec<Redaction>,
+
+    /// Apply every built-in redaction rule.
+    #[arg(long)]
+    pub redact_all: bool,
+
+    /// Replace matches of a regex; the replacement may use `$1` for capture
+    /// groups and can't contain `=` (repeatable).
+    #[arg(long, value_name = "REGEX=REPLACEMENT", value_parser = parse_redaction_pattern)]
+    pub redact_pattern: Vec<Redaction>,
+}
+
+impl RedactArgs {
+    /// The rules selected: built-ins first, then custom patterns in order.
+    #[must_use]
+    pub fn redactions(&self) -> Vec<Redaction> {
+        let builtin = if self.redact_all {
+            Redaction::BUILTIN.to_vec()
+        } else {
+            Redaction::BUILTIN
+                .into_iter()
+                .filter(|rule| self.redact.contains(rule))
+                .collect()
+        };
+        builtin
+            .into_iter()
+            .chain(self.redact_pattern.iter().cloned())
+            .collect()
+    }
+}
+
+/// Where to record a check's result for CI.
+#[derive(Debug, Clone, Default, Args)]
+pub struct ReportArgs {
+    /// Append the result to a JUnit XML (.xml) or TAP (.tap) report, which
+    /// collects the results of every check that uses it.
+    #[arg(long, value_name = "FILE")]
+    pub report: Option<PathBuf>,
+
+    /// Report format when the file extension doesn't say: junit or tap.
+    #[arg(long, value_name = "FORMAT", requires = "report")]
+    pub report_format: Option<ReportFormat>,
+}
+
+impl ReportArgs {
+    /// The report to append to, if any.
+    pub fn report(&self) -> Result<Option<Report>, ReportError> {
+        self.report
+            .as_ref()
+            .map(|path| Report::new(path, self.report_format))
+            .transpose()
+    }
+}
+
+/// Which part of the screen to look at (default: all of it).
+#[derive(Debug, Clone, Default, Args)]
+#[group(multiple = false)]
+pub struct RegionArgs {
+    /// Only these rows: START..END (0-indexed, end exclusive), START.., ..END
+    /// or a single row.
+    #[arg(long, value_name = "START..END", value_parser = parse_rows)]
+    pub rows: Option<Region>,
+
+    /// Only this rectangle: ROW,COL,ROWS,COLS (0-indexed).
+    #[arg(long, value_name = "ROW,COL,ROWS,COLS", value_parser = parse_region)]
+    pub region: Option<Region>,
+
+    /// Only the last line with text on it.
+    #[arg(long)]
+    pub last_line: bool,
+
+    /// Only the line the cursor is on.
+    #[arg(long)]
+    pub cursor_line: bool,
+}
+
+impl RegionArgs {
+    /// The region selected, or None for the whole screen.
+    #[must_use]
+    pub fn region(&self) -> Option<Region> {
+        if self.last_line {
+            Some(Region::LastLine)
+        } else if self.cursor_line {
+            Some(Region::CursorLine)
+        } else {
+            self.rows.or(self.region)
+        }
     }
 }
 
@@ -113,6 +242,79 @@ pub struct Cli {
     pub command: Command,
 }
 
+impl Cli {
+    /// Parse arguments, exiting with usage on error like [`Parser::parse_from`].
+    #[must_use]
+    pub fn parse_ordered<I, T>(args: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        Self::try_parse_ordered(args).unwrap_or_else(|e| e.exit())
+    }
+
+    /// Parse arguments, also recording the order of `wait` conditions, which
+    /// the derived parser loses by collecting each kind into its own field.
+    pub fn try_parse_ordered<I, T>(args: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let matches = Self::command().try_get_matches_from(args)?;
+        let mut cli = Self::from_arg_matches(&matches)?;
+        if let Command::Wait { order, .. } = &mut cli.command
+            && let Some(("wait", wait)) = matches.subcommand()
+        {
+            *order = ConditionOrder::from_matches(wait);
+        }
+        Ok(cli)
+    }
+}
+
+/// The command line positions of `wait` conditions, so `--sequence` can
+/// follow the order they were given in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConditionOrder {
+    /// Argument ID and command line index of every condition given.
+    indices: Vec<(&'static str, usize)>,
+}
+
+impl ConditionOrder {
+    const ARGS: [&'static str; 8] = [
+        "matches",
+        "match_pattern",
+        "contains",
+        "pattern",
+        "prompt",
+        "stable",
+        "exit",
+        "code",
+    ];
+
+    fn from_matches(matches: &ArgMatches) -> Self {
+        let mut indices = Vec::new();
+        for arg in Self::ARGS {
+            if matches.value_source(arg) == Some(ValueSource::CommandLine)
+                && let Some(found) = matches.indices_of(arg)
+            {
+                indices.extend(found.map(|index| (arg, index)));
+            }
+        }
+        Self { indices }
+    }
+
+    /// Position of the `nth` use of a condition argument (by field name), or
+    /// `usize::MAX` if it wasn't given on the command line.
+    #[must_use]
+    pub fn position(&self, arg: &str, nth: usize) -> usize {
+        self.indices
+            .iter()
+            .filter(|(id, _)| *id == arg)
+            .nth(nth)
+            .map_or(usize::MAX, |(_, index)| *index)
+    }
+}
+
 #[derive(Debug, Subcommand)]
 pub enum Command {
     /// Spawn a new agent.
@@ -133,6 +335,10 @@ pub enum Command {
         #[arg(long, short)]
         label: Vec<String>,
 
+        /// Key/value annotations (can be repeated, e.g., --annotation task=1234).
+        #[arg(long, value_name = "KEY=VALUE", value_parser = parse_annotation)]
+        annotation: Vec<(String, String)>,
+
         /// Auto-kill agent after this many seconds. Sends SIGTERM first, then SIGKILL after 5s grace.
         #[arg(long, short)]
         timeout: Option<u64>,
@@ -141,6 +347,11 @@ pub enum Command {
         #[arg(long)]
         max_output: Option<u64>,
 
+        /// Most bytes of input that may wait for the agent to read them (default 1MB).
+        /// Sends that would go over the limit are rejected.
+        #[arg(long)]
+        max_input: Option<u64>,
+
         /// Environment variables (KEY=VALUE format, can be repeated).
         #[arg(long, short, value_name = "KEY=VALUE")]
         env: Vec<String>,
@@ -149,6 +360,28 @@ pub enum Command {
         #[arg(long)]
         env_clear: bool,
 
+        /// Make bash or zsh mark prompts and commands (OSC 133), enabling
+        /// command events and wait --prompt. A login bash (-l) runs as an
+        /// interactive shell that reads the login profiles instead.
+        #[arg(long)]
+        shell_integration: bool,
+
+        /// TERM to give the program (default xterm-256color). COLORTERM is set
+        /// to match for 256-color terminals. LINES and COLUMNS are not set, since
+        /// they would go stale on resize; pass them with --env if a program needs
+        /// them.
+        #[arg(long)]
+        term: Option<String>,
+
+        /// Terminal to identify as when the program asks: vt100, vt220 or xterm.
+        #[arg(long, default_value = "xterm")]
+        terminal: TerminalIdentity,
+
+        /// Don't answer the program's terminal queries (cursor position,
+        /// device status and attributes).
+        #[arg(long)]
+        no_terminal_replies: bool,
+
         /// Wait for agent(s) to exit before spawning (can be repeated).
         #[arg(long)]
         after: Vec<String>,
@@ -173,6 +406,15 @@ pub enum Command {
         #[arg(long, short)]
         label: Vec<String>,
 
+        /// Filter by annotation (can be repeated, agents must match ALL).
+        #[arg(long, value_name = "KEY=VALUE", value_parser = parse_annotation)]
+        annotation: Vec<(String, String)>,
+
+        /// Selector expression, e.g. "tier=worker,!canary" or "env in (dev,test)".
+        /// Comma means AND, | means OR, ! negates.
+        #[arg(long, short)]
+        selector: Option<String>,
+
         /// Output format: toon (default, token-optimized), json, or text.
         #[arg(long, default_value = "toon")]
         format: String,
@@ -191,6 +433,15 @@ pub enum Command {
         #[arg(long, short)]
         label: Vec<String>,
 
+        /// Kill all agents with these annotations (can be repeated, matches ALL).
+        #[arg(long, value_name = "KEY=VALUE", value_parser = parse_annotation)]
+        annotation: Vec<(String, String)>,
+
+        /// Selector expression, e.g. "tier=worker,!canary" or "env in (dev,test)".
+        /// Comma means AND, | means OR, ! negates.
+        #[arg(long, short)]
+        selector: Option<String>,
+
         /// Kill all running agents.
         #[arg(long, short)]
         all: bool,
@@ -204,13 +455,57 @@ pub enum Command {
         proc: Option<String>,
     },
 
+    /// Change an agent's labels and annotations.
+    ///
+    /// Removals are applied before additions. Event subscribers are told
+    /// about the change.
+    Label {
+        /// Agent ID.
+        id: String,
+
+        /// Labels to add (can be repeated).
+        #[arg(long, short)]
+        add: Vec<String>,
+
+        /// Labels to remove (can be repeated).
+        #[arg(long, short)]
+        remove: Vec<String>,
+
+        /// Annotations to set (can be repeated, e.g., --set phase=review).
+        #[arg(long, short, value_name = "KEY=VALUE", value_parser = parse_annotation)]
+        set: Vec<(String, String)>,
+
+        /// Annotation keys to remove (can be repeated).
+        #[arg(long, short, value_name = "KEY")]
+        unset: Vec<String>,
+    },
+
     /// Send input to an agent.
     Send {
         /// Agent ID.
         id: String,
 
         /// Text to send.
-        text: String,
+        #[arg(required_unless_present = "file")]
+        text: Option<String>,
+
+        /// Send the contents of a file instead ("-" for stdin).
+        #[arg(long, short, conflicts_with = "text")]
+        file: Option<PathBuf>,
+
+        /// Send as a paste, so multi-line text isn't autoindented or run line by
+        /// line. Uses bracketed paste if the agent enabled it, plain input otherwise.
+        #[arg(long, short, conflicts_with_all = ["delay", "jitter"])]
+        paste: bool,
+
+        /// Type one character at a time, this many milliseconds apart, for
+        /// programs that drop input arriving all at once.
+        #[arg(long, value_name = "MS")]
+        delay: Option<u64>,
+
+        /// Add a random extra delay of up to this many milliseconds per character.
+        #[arg(long, value_name = "MS")]
+        jitter: Option<u64>,
 
         /// Do not append a newline.
         #[arg(long)]
@@ -228,8 +523,10 @@ pub enum Command {
 
     /// Send named key sequences to an agent.
     ///
-    /// Supports arrow keys (up/down/left/right), special keys (enter/tab/escape),
-    /// control sequences (ctrl-c/ctrl-d), and more. See --help for full list.
+    /// Supports the keys of an xterm keyboard with ctrl/alt/shift modifiers.
+    /// Cursor and keypad keys follow the application modes the agent has set,
+    /// so vim, htop and fzf see what a real terminal would send. See --help
+    /// for the full list.
     SendKeys {
         /// Agent ID.
         id: String,
@@ -238,14 +535,87 @@ pub enum Command {
         ///
         /// Supported keys:
         /// - Arrow keys: up, down, left, right
-        /// - Special: enter, tab, escape, backspace, delete
+        /// - Special: enter, tab, escape, backspace, space, insert, delete
         /// - Navigation: home, end, pageup, pagedown
-        /// - Control: ctrl-c, ctrl-d, etc.
-        /// - Function: f1, f2, f3, f4
-        /// - Single chars: a, b, x, etc.
+        /// - Function: f1 to f24
+        /// - Keypad: kp0-kp9, kpenter, kpplus, kpminus, kpmultiply, kpdivide,
+        ///   kpdecimal, kpequal
+        /// - Single chars: a, G, /, etc.
+        ///
+        /// Modifiers: ctrl- (or c-, ^X), alt- (or meta-, m-), shift- (or s-),
+        /// combinable, e.g. ctrl-c, M-b, ctrl-left, shift-tab, ctrl-alt-delete.
         keys: Vec<String>,
     },
 
+    /// Send a mouse click, scroll or drag to an agent.
+    ///
+    /// Encoded for the mouse mode the agent's application enabled (SGR or
+    /// X10-style); fails if it hasn't enabled mouse reporting.
+    SendMouse {
+        /// Agent ID.
+        id: String,
+
+        /// Action: click, scroll or drag.
+        action: MouseAction,
+
+        /// Screen row, 0-indexed (where a drag starts).
+        #[arg(long)]
+        row: u16,
+
+        /// Screen column, 0-indexed (where a drag starts).
+        #[arg(long)]
+        col: u16,
+
+        /// Button: left, middle, right, or up/down for the wheel
+        /// (default: left, or down for scroll).
+        #[arg(long, short)]
+        button: Option<MouseButton>,
+
+        /// Row where a drag ends.
+        #[arg(long, required_if_eq("action", "drag"))]
+        to_row: Option<u16>,
+
+        /// Column where a drag ends.
+        #[arg(long, required_if_eq("action", "drag"))]
+        to_col: Option<u16>,
+    },
+
+    /// Send the same input to several agents at once.
+    ///
+    /// Writes to every --id agent plus every running agent matching --label,
+    /// --annotation or --selector, and prints one result line per agent.
+    /// Exits non-zero if any agent couldn't be written to.
+    Broadcast {
+        /// Agent IDs to send to (can be repeated).
+        #[arg(long, short)]
+        id: Vec<String>,
+
+        /// Send to running agents with these labels (can be repeated, all must match).
+        #[arg(long, short)]
+        label: Vec<String>,
+
+        /// Send to running agents with these annotations (can be repeated, all must match).
+        #[arg(long, value_name = "KEY=VALUE", value_parser = parse_annotation)]
+        annotation: Vec<(String, String)>,
+
+        /// Selector expression, e.g. "tier=worker,!canary" or "env in (dev,test)".
+        /// Comma means AND, | means OR, ! negates.
+        #[arg(long, short)]
+        selector: Option<String>,
+
+        /// Treat the input as key names, as in send-keys (e.g. "ctrl-c").
+        #[arg(long, short)]
+        keys: bool,
+
+        /// Do not append a newline to text input.
+        #[arg(long)]
+        no_newline: bool,
+
+        /// Text to send (words are joined with spaces), or key names with --keys.
+        #[arg(required = true)]
+        input: Vec<String>,
+    },
+
     /// Tail agent output.
     Tail {
         /// Agent ID.
@@ -295,6 +665,25 @@ pub enum Command {
         /// Compare with previous snapshot file and show diff.
         #[arg(long)]
         diff: Option<String>,
+
+        /// Compare with the golden snapshot DIR/NAME.snap; on a mismatch, show
+        /// a diff and write DIR/NAME.snap.new for `botty accept`.
+        #[arg(long, value_name = "DIR/NAME", conflicts_with = "diff")]
+        golden: Option<PathBuf>,
+
+        #[command(flatten)]
+        area: RegionArgs,
+
+        #[command(flatten)]
+        redact: RedactArgs,
+    },
+
+    /// Accept snapshots waiting for review, replacing their goldens.
+    Accept {
+        /// Directories to search for .snap.new files, .snap.new files, or
+        /// snapshot names.
+        #[arg(default_value = ".")]
+        paths: Vec<PathBuf>,
     },
 
     /// Attach to an agent interactively.
@@ -323,11 +712,16 @@ pub enum Command {
     /// Shut down the server.
     Shutdown,
 
-    /// Wait for agent output to match a condition.
+    /// Wait for agent output to match a condition, or for the agent to exit.
+    ///
+    /// Conditions are combined with AND logic unless `--any` or `--sequence`
+    /// is given. For example: `--stable 200 --contains "$ "` waits for the
+    /// screen to be stable for 200ms AND contain the prompt, and `--any
+    /// --match pass=PASS --match fail=FAIL` waits for either and prints the
+    /// name of the one that appeared.
     ///
-    /// Conditions can be combined with AND logic. For example:
-    /// `--stable 200 --contains "$ "` waits for the screen to be stable
-    /// for 200ms AND contain the prompt.
+    /// When the wait ends because the agent exited, botty exits with the
+    /// agent's own exit status.
     Wait {
         /// Agent ID.
         id: String,
@@ -340,10 +734,53 @@ pub enum Command {
         #[arg(long)]
         pattern: Option<String>,
 
+        /// Named condition: output contains TEXT (can be repeated).
+        #[arg(long = "match", value_name = "NAME=TEXT", value_parser = parse_annotation)]
+        matches: Vec<(String, String)>,
+
+        /// Named condition: output matches REGEX (can be repeated).
+        #[arg(long, value_name = "NAME=REGEX", value_parser = parse_annotation)]
+        match_pattern: Vec<(String, String)>,
+
         /// Wait until screen is stable (hasn't changed for this duration).
         #[arg(long, value_name = "MILLIS")]
         stable: Option<u64>,
 
+        /// Wait until the shell shows a prompt and is waiting for input.
+        /// Needs OSC 133 prompt markers (see spawn --shell-integration).
+        #[arg(long)]
+        prompt: bool,
+
+        /// Wait until the agent exits.
+        #[arg(long)]
+        exit: bool,
+
+        /// Wait until the agent exits with this code (implies --exit). Fails
+        /// if it exits with any other code.
+        #[arg(long, value_name = "N", allow_hyphen_values = true)]
+        code: Option<i32>,
+
+        /// Finish when any one condition is met, rather than all of them,
+        /// and print the name of the one that was (`contains`, `exit`, or the
+        /// NAME of a --match).
+        #[arg(long, conflicts_with = "sequence")]
+        any: bool,
+
+        /// Wait for the conditions one after another, in the order given.
+        /// Each condition on the screen's content must match after the one
+        /// before it, if that is still on screen.
+        #[arg(long)]
+        sequence: bool,
+
+        /// Where each condition was given on the command line.
+        #[arg(skip)]
+        order: ConditionOrder,
+
+        /// Print the result as JSON: the conditions met, the agent's exit code
+        /// and, with --print, the snapshot.
+        #[arg(long)]
+        json: bool,
+
         /// Timeout in seconds.
         #[arg(long, short, default_value = "30")]
         timeout: u64,
@@ -351,6 +788,12 @@ pub enum Command {
         /// Print the snapshot when condition is met.
         #[arg(long, short)]
         print: bool,
+
+        #[command(flatten)]
+        area: RegionArgs,
+
+        #[command(flatten)]
+        report: ReportArgs,
     },
 
     /// Assert that agent output matches a condition.
@@ -376,12 +819,20 @@ pub enum Command {
         /// Timeout in seconds (default: check immediately).
         #[arg(long, short, default_value = "0")]
         timeout: u64,
+
+        #[command(flatten)]
+        area: RegionArgs,
+
+        #[command(flatten)]
+        report: ReportArgs,
     },
 
     /// Execute a command and return its output.
     ///
-    /// Spawns a shell, runs the command, waits for completion, and returns
-    /// the output. The agent is automatically killed after completion.
+    /// Runs the command through the shell as the agent's own process, waits for
+    /// it to exit, prints its full output and exits with its exit code (128 +
+    /// the signal number if it was killed by a signal). The agent is removed
+    /// afterwards.
     Exec {
         /// Terminal rows.
         #[arg(long, default_value = "24")]
@@ -399,11 +850,41 @@ pub enum Command {
         #[arg(long, default_value = "sh")]
         shell: String,
 
+        /// Maximum output to keep in bytes. Beyond this the oldest output is
+        /// dropped, with a warning on stderr (or `truncated` in the JSON).
+        #[arg(long, default_value = "67108864")]
+        max_output: u64,
+
+        /// Print a JSON object with output, exit code and timing instead.
+        #[arg(long)]
+        json: bool,
+
         /// Command to execute.
         #[arg(last = true, required = true)]
         cmd: Vec<String>,
     },
 
+    /// Run an expect-style script of steps against one or more agents.
+    ///
+    /// The script is a JSON file listing steps such as spawn, send, expect,
+    /// snapshot and conditional branches. Prints a per-step JSON report and
+    /// exits with code 1 if a step fails.
+    Script {
+        /// Script file.
+        file: PathBuf,
+
+        /// Default agent for steps that don't name one (overrides the script's).
+        #[arg(long)]
+        agent: Option<String>,
+
+        /// Leave agents spawned by the script running afterwards.
+        #[arg(long)]
+        keep: bool,
+
+        #[command(flatten)]
+        report: ReportArgs,
+    },
+
     /// Check system health and configuration.
     Doctor,
 
@@ -416,6 +897,38 @@ pub enum Command {
         /// Include output events (can be noisy).
         #[arg(long)]
         output: bool,
+
+        /// Include screen change events with the rows that changed, coalesced
+        /// to at most one per agent every MILLIS (default 100).
+        #[arg(long, value_name = "MILLIS", num_args = 0..=1, default_missing_value = "100")]
+        screen: Option<u64>,
+
+        /// Replay retained events after sequence number SEQ before streaming
+        /// live ones (use the seq of the last event seen to resume).
+        #[arg(long, value_name = "SEQ")]
+        since: Option<u64>,
+
+        /// Only agents with these labels (all must match). Checked as events
+        /// arrive, so agents spawned or relabeled later are included.
+        #[arg(long, short, value_delimiter = ',')]
+        label: Vec<String>,
+
+        /// Only agents with these annotations (can be repeated, all must match).
+        #[arg(long, value_name = "KEY=VALUE", value_parser = parse_annotation)]
+        annotation: Vec<(String, String)>,
+
+        /// Selector expression, e.g. "tier=worker,!canary" or "env in (dev,test)".
+        /// Comma means AND, | means OR, ! negates.
+        #[arg(long, short)]
+        selector: Option<String>,
+
+        /// Only agents running these programs, by name or path.
+        #[arg(long, value_delimiter = ',')]
+        command: Vec<String>,
+
+        /// Only these event types, comma-separated (see the event field of the output).
+        #[arg(long = "type", value_name = "TYPE", value_delimiter = ',')]
+        event_type: Vec<String>,
     },
 
     /// Subscribe to agent output streams.
@@ -427,10 +940,16 @@ pub enum Command {
         #[arg(long, short)]
         id: Vec<String>,
 
-        /// Subscribe to agents with these labels (can be repeated).
+        /// Subscribe to agents with these labels (can be repeated; all must
+        /// match). Agents spawned later with the labels are picked up too.
         #[arg(long, short)]
         label: Vec<String>,
 
+        /// Selector expression, e.g. "tier=worker,!canary" or "env in (dev,test)".
+        /// Comma means AND, | means OR, ! negates.
+        #[arg(long, short)]
+        selector: Option<String>,
+
         /// Prefix each output chunk with [agent-id] for multiplexed viewing.
         #[arg(long, short)]
         prefix: bool,
@@ -460,6 +979,11 @@ pub enum Command {
         #[arg(long, short)]
         label: Vec<String>,
 
+        /// Selector expression, e.g. "tier=worker,!canary" or "env in (dev,test)".
+        /// Comma means AND, | means OR, ! negates.
+        #[arg(long, short)]
+        selector: Option<String>,
+
         /// Destroy and recreate the tmux session instead of reattaching.
         #[arg(long)]
         new_session: bool,
@@ -590,4 +1114,70 @@ mod tests {
         assert_eq!(parse_key_sequence("arrow-up"), None);
         assert_eq!(parse_key_sequence(""), None);
     }
+
+    #[test]
+    fn test_parse_regions() {
+        assert_eq!(parse_rows("0..1"), Ok(Region::Rows { start: 0, end: 1 }));
+        assert_eq!(parse_rows("5"), Ok(Region::Rows { start: 5, end: 6 }));
+        assert_eq!(parse_rows("20.."), Ok(Region::Rows { start: 20, end: u16::MAX }));
+        assert_eq!(parse_rows("..3"), Ok(Region::Rows { start: 0, end: 3 }));
+        assert!(parse_rows("3..3").is_err());
+        assert!(parse_rows("a..b").is_err());
+
+        assert_eq!(
+            parse_region("10,0,5,80"),
+            Ok(Region::Rect { row: 10, col: 0, rows: 5, cols: 80 })
+        );
+        assert!(parse_region("10,0,5").is_err());
+    }
+
+    #[test]
+    fn test_wait_condition_order() {
+        let cli = Cli::try_parse_ordered([
+            "botty", "wait", "a", "--sequence", "--contains", "one", "--match", "x=two",
+            "--exit", "--match", "y=three",
+        ])
+        .expect("parse failed");
+        let Command::Wait { order, .. } = cli.command else {
+            panic!("expected wait");
+        };
+        let positions = [
+            order.position("contains", 0),
+            order.position("matches", 0),
+            order.position("exit", 0),
+            order.position("matches", 1),
+        ];
+        assert!(positions.is_sorted(), "{positions:?}");
+        assert_eq!(order.position("matches", 2), usize::MAX);
+        assert_eq!(order.position("prompt", 0), usize::MAX);
+    }
+
+    #[test]
+    fn test_redact_args() {
+        let cli = Cli::parse_from([
+            "botty",
+            "snapshot",
+            "a",
+            "--redact",
+            "pid,timestamp",
+            "--redact-pattern",
+            "v=\\d+=v<N>",
+        ]);
+        let Command::Snapshot { redact, .. } = cli.command else {
+            panic!("expected snapshot");
+        };
+        assert_eq!(
+            redact.redactions(),
+            vec![
+                Redaction::Timestamp,
+                Redaction::Pid,
+                Redaction::Custom {
+                    pattern: "v=\\d+".into(),
+                    replacement: "v<N>".into(),
+                },
+            ]
+        );
+        assert!(parse_redaction("colors").is_err());
+        assert!(parse_redaction_pattern("no-replacement").is_err());
+    }
 }
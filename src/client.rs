@@ -1,16 +1,33 @@
 //! Client for communicating with the botty server.
 //!
 //! Handles Unix socket connection and auto-starting the server.
+//!
+//! Besides the raw [`Client::request`], the client offers a typed API that
+//! hides the JSON protocol:
+//!
+//! ```ignore
+//! let mut client = Client::with_default_path();
+//! let id = client.spawn(SpawnOptions::new(["bash"])).await?;
+//! client.send(&id, "echo hello", true).await?;
+//! let snapshot = client.snapshot(&id).await?;
+//! ```
 
 #![allow(unsafe_code)] // getuid() call
 
-use crate::protocol::{Request, Response};
+use crate::protocol::{
+    AgentId, AgentInfo, Event, EventMessage, EventOptions, MouseAction, MouseButton, Region, Request,
+    Response, SendResult, Snapshot, SpawnOptions, TranscriptEntry,
+};
+use crate::redact::Redaction;
+use futures::{Stream, StreamExt};
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::time::Duration;
 use thiserror::Error;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::net::UnixStream;
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
 use tracing::{debug, info, warn};
 
 /// Errors that can occur in the client.
@@ -40,8 +57,32 @@ pub enum ClientError {
     #[error("server returned error: {0}")]
     ServerError(String),
 
+    #[error("agent not found: {0}")]
+    AgentNotFound(String),
+
+    #[error("unexpected response: {0}")]
+    UnexpectedResponse(String),
+
     #[error("connection lost")]
     ConnectionLost,
+
+    #[error("event subscriber lagged behind, {0} events missed")]
+    Lagged(u64),
+}
+
+impl ClientError {
+    /// Classify a server error message into a typed error.
+    fn from_server_message(message: String) -> Self {
+        if let Some(id) = message.strip_prefix("agent not found: ") {
+            return Self::AgentNotFound(id.to_string());
+        }
+        Self::ServerError(message)
+    }
+
+    /// Build an error for a response variant the caller did not expect.
+    fn unexpected(response: &Response) -> Self {
+        Self::UnexpectedResponse(format!("{response:?}"))
+    }
 }
 
 /// Get the default socket path for the botty server.
@@ -120,6 +161,17 @@ impl Client {
         Err(ClientError::ServerTimeout)
     }
 
+    /// Connect to an already-running server without auto-starting one.
+    pub async fn connect_existing(&mut self) -> Result<(), ClientError> {
+        if self.stream.is_none() {
+            let stream = UnixStream::connect(&self.socket_path)
+                .await
+                .map_err(ClientError::Connect)?;
+            self.stream = Some(BufReader::new(stream));
+        }
+        Ok(())
+    }
+
     /// Start the server as a background process.
     #[allow(clippy::unused_async)] // async for API consistency with other methods
     async fn start_server(&self) -> Result<(), ClientError> {
@@ -177,8 +229,480 @@ impl Client {
         Ok(response)
     }
 
+    /// Send a request and turn `Response::Error` into a typed error.
+    pub async fn call(&mut self, request: Request) -> Result<Response, ClientError> {
+        match self.request(request).await? {
+            Response::Error { message } => Err(ClientError::from_server_message(message)),
+            response => Ok(response),
+        }
+    }
+
+    /// Send a request that is expected to return `Response::Ok`.
+    async fn call_ok(&mut self, request: Request) -> Result<(), ClientError> {
+        match self.call(request).await? {
+            Response::Ok => Ok(()),
+            other => Err(ClientError::unexpected(&other)),
+        }
+    }
+
+    /// Check that the server is alive.
+    pub async fn ping(&mut self) -> Result<(), ClientError> {
+        match self.call(Request::Ping).await? {
+            Response::Pong => Ok(()),
+            other => Err(ClientError::unexpected(&other)),
+        }
+    }
+
+    /// Spawn a new agent and return its ID.
+    pub async fn spawn(&mut self, options: SpawnOptions) -> Result<AgentId, ClientError> {
+        match self.call(options.into()).await? {
+            Response::Spawned { id, pid } => {
+                debug!("Spawned agent {id} (pid {pid})");
+                Ok(id)
+            }
+            other => Err(ClientError::unexpected(&other)),
+        }
+    }
+
+    /// List agents (including exited ones) that have all of the given labels.
+    pub async fn list(&mut self, labels: &[String]) -> Result<Vec<AgentInfo>, ClientError> {
+        self.list_matching(labels, &BTreeMap::new(), None).await
+    }
+
+    /// List agents (including exited ones) that have all of the given labels
+    /// and annotation values and match the selector expression, if any.
+    pub async fn list_matching(
+        &mut self,
+        labels: &[String],
+        annotations: &BTreeMap<String, String>,
+        selector: Option<&str>,
+    ) -> Result<Vec<AgentInfo>, ClientError> {
+        let request = Request::List {
+            labels: labels.to_vec(),
+            annotations: annotations.clone(),
+            selector: selector.map(ToString::to_string),
+        };
+        match self.call(request).await? {
+            Response::Agents { agents } => Ok(agents),
+            other => Err(ClientError::unexpected(&other)),
+        }
+    }
+
+    /// Add and remove an agent's labels. Removals are applied first.
+    pub async fn label(
+        &mut self,
+        id: &str,
+        add: &[String],
+        remove: &[String],
+    ) -> Result<(), ClientError> {
+        self.call_ok(Request::Label {
+            id: id.to_string(),
+            add: add.to_vec(),
+            remove: remove.to_vec(),
+            set: BTreeMap::new(),
+            unset: vec![],
+        })
+        .await
+    }
+
+    /// Set and unset an agent's annotations. Keys are unset first.
+    pub async fn annotate(
+        &mut self,
+        id: &str,
+        set: BTreeMap<String, String>,
+        unset: &[String],
+    ) -> Result<(), ClientError> {
+        self.call_ok(Request::Label {
+            id: id.to_string(),
+            add: vec![],
+            remove: vec![],
+            set,
+            unset: unset.to_vec(),
+        })
+        .await
+    }
+
+    /// Get information about a single agent.
+    pub async fn agent(&mut self, id: &str) -> Result<AgentInfo, ClientError> {
+        self.list(&[])
+            .await?
+            .into_iter()
+            .find(|a| a.id == id)
+            .ok_or_else(|| ClientError::AgentNotFound(id.to_string()))
+    }
+
+    /// Send a signal to a single agent.
+    pub async fn kill(&mut self, id: &str, signal: i32) -> Result<(), ClientError> {
+        self.call_ok(Request::Kill {
+            id: Some(id.to_string()),
+            labels: vec![],
+            annotations: BTreeMap::new(),
+            selector: None,
+            all: false,
+            signal,
+            proc_filter: None,
+        })
+        .await
+    }
+
+    /// Send UTF-8 text to an agent, optionally followed by a newline.
+    pub async fn send(&mut self, id: &str, data: &str, newline: bool) -> Result<(), ClientError> {
+        self.call_ok(Request::Send {
+            id: id.to_string(),
+            data: data.to_string(),
+            newline,
+            paste: false,
+            delay_ms: 0,
+            jitter_ms: 0,
+        })
+        .await
+    }
+
+    /// Type UTF-8 text into an agent one character at a time, `delay` plus up
+    /// to `jitter` apart, optionally followed by a newline. Returns once the
+    /// last character is written.
+    pub async fn type_text(
+        &mut self,
+        id: &str,
+        data: &str,
+        newline: bool,
+        delay: Duration,
+        jitter: Duration,
+    ) -> Result<(), ClientError> {
+        self.call_ok(Request::Send {
+            id: id.to_string(),
+            data: data.to_string(),
+            newline,
+            paste: false,
+            delay_ms: u64::try_from(delay.as_millis()).unwrap_or(u64::MAX),
+            jitter_ms: u64::try_from(jitter.as_millis()).unwrap_or(u64::MAX),
+        })
+        .await
+    }
+
+    /// Paste UTF-8 text into an agent, using bracketed paste if the agent has
+    /// enabled it, optionally followed by a newline.
+    pub async fn paste(&mut self, id: &str, data: &str, newline: bool) -> Result<(), ClientError> {
+        self.call_ok(Request::Send {
+            id: id.to_string(),
+            data: data.to_string(),
+            newline,
+            paste: true,
+            delay_ms: 0,
+            jitter_ms: 0,
+        })
+        .await
+    }
+
+    /// Send raw bytes to an agent.
+    pub async fn send_bytes(&mut self, id: &str, data: &[u8]) -> Result<(), ClientError> {
+        self.call_ok(Request::SendBytes {
+            id: id.to_string(),
+            data: data.to_vec(),
+        })
+        .await
+    }
+
+    /// Send named keys to an agent. The server encodes them for the agent's
+    /// current cursor and keypad modes.
+    pub async fn send_keys(&mut self, id: &str, keys: &[String]) -> Result<(), ClientError> {
+        self.call_ok(Request::SendKeys {
+            id: id.to_string(),
+            keys: keys.to_vec(),
+        })
+        .await
+    }
+
+    /// Send a mouse action to an agent at a 0-indexed (row, col) position.
+    /// `to` is where a drag ends.
+    pub async fn send_mouse(
+        &mut self,
+        id: &str,
+        action: MouseAction,
+        (row, col): (u16, u16),
+        button: Option<MouseButton>,
+        to: Option<(u16, u16)>,
+    ) -> Result<(), ClientError> {
+        self.call_ok(Request::SendMouse {
+            id: id.to_string(),
+            action,
+            row,
+            col,
+            button,
+            to,
+        })
+        .await
+    }
+
+    /// Send raw bytes followed by named keys to the listed agents and every
+    /// running agent with all of the labels and annotation values and matching
+    /// the selector expression, returning each agent's result.
+    #[allow(clippy::too_many_arguments)] // mirrors the fields of Request::Broadcast
+    pub async fn broadcast(
+        &mut self,
+        ids: &[String],
+        labels: &[String],
+        annotations: &BTreeMap<String, String>,
+        selector: Option<&str>,
+        data: &[u8],
+        keys: &[String],
+    ) -> Result<Vec<SendResult>, ClientError> {
+        let request = Request::Broadcast {
+            ids: ids.to_vec(),
+            labels: labels.to_vec(),
+            annotations: annotations.clone(),
+            selector: selector.map(ToString::to_string),
+            data: data.to_vec(),
+            keys: keys.to_vec(),
+        };
+        match self.call(request).await? {
+            Response::Broadcast { results } => Ok(results),
+            other => Err(ClientError::unexpected(&other)),
+        }
+    }
+
+    /// Get a normalized (color-stripped) snapshot of an agent's screen.
+    pub async fn snapshot(&mut self, id: &str) -> Result<Snapshot, ClientError> {
+        self.snapshot_with(id, true, None, &[]).await
+    }
+
+    /// Get a snapshot of an agent's screen with ANSI colors preserved.
+    pub async fn snapshot_formatted(&mut self, id: &str) -> Result<Snapshot, ClientError> {
+        self.snapshot_with(id, false, None, &[]).await
+    }
+
+    /// Get a snapshot of part of an agent's screen, with or without colors.
+    pub async fn snapshot_region(
+        &mut self,
+        id: &str,
+        region: Region,
+        strip_colors: bool,
+    ) -> Result<Snapshot, ClientError> {
+        self.snapshot_with(id, strip_colors, Some(region), &[]).await
+    }
+
+    /// Get a snapshot with volatile content replaced by the redaction rules,
+    /// optionally of only part of the screen.
+    pub async fn snapshot_redacted(
+        &mut self,
+        id: &str,
+        region: Option<Region>,
+        strip_colors: bool,
+        redact: &[Redaction],
+    ) -> Result<Snapshot, ClientError> {
+        self.snapshot_with(id, strip_colors, region, redact).await
+    }
+
+    async fn snapshot_with(
+        &mut self,
+        id: &str,
+        strip_colors: bool,
+        region: Option<Region>,
+        redact: &[Redaction],
+    ) -> Result<Snapshot, ClientError> {
+        let request = Request::Snapshot {
+            id: id.to_string(),
+            strip_colors,
+            region,
+            redact: redact.to_vec(),
+        };
+        match self.call(request).await? {
+            Response::Snapshot { content, cursor, size } => Ok(Snapshot { content, cursor, size }),
+            other => Err(ClientError::unexpected(&other)),
+        }
+    }
+
+    /// Get the full transcript of an agent's raw output.
+    pub async fn transcript(&mut self, id: &str) -> Result<Vec<u8>, ClientError> {
+        let request = Request::Tail {
+            id: id.to_string(),
+            lines: 0,
+            follow: false,
+        };
+        match self.call(request).await? {
+            Response::Output { data } => Ok(data),
+            other => Err(ClientError::unexpected(&other)),
+        }
+    }
+
+    /// Get timestamped transcript entries, optionally only those since a Unix timestamp (millis).
+    pub async fn dump(
+        &mut self,
+        id: &str,
+        since: Option<u64>,
+    ) -> Result<Vec<TranscriptEntry>, ClientError> {
+        let request = Request::Dump {
+            id: id.to_string(),
+            since,
+            format: crate::protocol::DumpFormat::Jsonl,
+        };
+        match self.call(request).await? {
+            Response::Transcript { entries } => Ok(entries),
+            other => Err(ClientError::unexpected(&other)),
+        }
+    }
+
+    /// Resize an agent's terminal.
+    pub async fn resize(
+        &mut self,
+        id: &str,
+        rows: u16,
+        cols: u16,
+        clear_transcript: bool,
+    ) -> Result<(), ClientError> {
+        self.call_ok(Request::Resize {
+            id: id.to_string(),
+            rows,
+            cols,
+            clear_transcript,
+        })
+        .await
+    }
+
+    /// Forget an exited agent.
+    pub async fn remove(&mut self, id: &str) -> Result<(), ClientError> {
+        self.call_ok(Request::Remove { id: id.to_string() }).await
+    }
+
+    /// Ask the server to shut down.
+    pub async fn shutdown(&mut self) -> Result<(), ClientError> {
+        self.call_ok(Request::Shutdown).await
+    }
+
+    /// Subscribe to the server event stream.
+    ///
+    /// Opens a dedicated connection; the stream ends when the server closes it.
+    /// An error item (e.g. a malformed message) is always the last item. If the
+    /// subscriber falls behind and events are lost, the stream ends with
+    /// [`ClientError::Lagged`]; use [`Client::event_stream`] to keep going.
+    /// Unlike [`Client::request`], this does not auto-start the server.
+    pub async fn events(
+        &self,
+        options: EventOptions,
+    ) -> Result<impl Stream<Item = Result<Event, ClientError>> + use<>, ClientError> {
+        let messages = self.event_stream(options).await?.map(|message| match message? {
+            EventMessage::Event { event, .. } => Ok(event),
+            EventMessage::Lagged { missed } => Err(ClientError::Lagged(missed)),
+        });
+
+        // Stop after the first error
+        Ok(futures::stream::unfold(Some(Box::pin(messages)), |messages| async move {
+            let mut messages = messages?;
+            let item = messages.next().await?;
+            let next = item.is_ok().then_some(messages);
+            Some((item, next))
+        }))
+    }
+
+    /// Subscribe to the server event stream with sequence numbers.
+    ///
+    /// Like [`Client::events`], but yields each event with its sequence number
+    /// and reports lost events as [`EventMessage::Lagged`] without ending.
+    /// Pass the last sequence number seen as `since_seq` to resume.
+    pub async fn event_stream(
+        &self,
+        options: EventOptions,
+    ) -> Result<impl Stream<Item = Result<EventMessage, ClientError>> + use<>, ClientError> {
+        let (reader, mut writer) = self.open_stream().await?.into_split();
+        write_request(&mut writer, &options.into()).await?;
+
+        let state = Some((BufReader::new(reader), writer));
+        Ok(futures::stream::unfold(state, |state| async move {
+            let (mut reader, writer) = state?;
+            let mut line = String::new();
+            let item = match reader.read_line(&mut line).await {
+                Ok(0) => return None,
+                Ok(_) => match serde_json::from_str::<Response>(&line) {
+                    Ok(Response::Event { seq, event }) => Ok(EventMessage::Event { seq, event }),
+                    Ok(Response::Lagged { missed }) => Ok(EventMessage::Lagged { missed }),
+                    Ok(Response::Error { message }) => Err(ClientError::from_server_message(message)),
+                    Ok(other) => Err(ClientError::unexpected(&other)),
+                    Err(e) => Err(ClientError::Deserialize(e)),
+                },
+                Err(e) => Err(ClientError::Receive(e)),
+            };
+            let next = item.is_ok().then_some((reader, writer));
+            Some((item, next))
+        }))
+    }
+
+    /// Attach to an agent, returning a raw byte stream pair.
+    ///
+    /// The reader yields the initial screen render followed by live PTY output;
+    /// bytes written to the writer go to the agent (unless `readonly`). Shutting
+    /// down the writer detaches. When the session ends the server sends a final
+    /// `AttachEnded` JSON line on the reader.
+    pub async fn attach(
+        &self,
+        id: &str,
+        readonly: bool,
+    ) -> Result<(BufReader<OwnedReadHalf>, OwnedWriteHalf), ClientError> {
+        let (reader, mut writer) = self.open_stream().await?.into_split();
+        let request = Request::Attach {
+            id: id.to_string(),
+            readonly,
+        };
+        write_request(&mut writer, &request).await?;
+
+        let mut reader = BufReader::new(reader);
+        match read_response(&mut reader).await? {
+            Response::AttachStarted { .. } => Ok((reader, writer)),
+            Response::Error { message } => Err(ClientError::from_server_message(message)),
+            other => Err(ClientError::unexpected(&other)),
+        }
+    }
+
+    /// Open an additional connection for a streaming request.
+    ///
+    /// Streams are useless without agents, so this never starts the server.
+    async fn open_stream(&self) -> Result<UnixStream, ClientError> {
+        UnixStream::connect(&self.socket_path)
+            .await
+            .map_err(ClientError::Connect)
+    }
+
     /// Get the socket path.
     pub fn socket_path(&self) -> &Path {
         &self.socket_path
     }
 }
+
+/// Write a single newline-terminated JSON request.
+async fn write_request(writer: &mut OwnedWriteHalf, request: &Request) -> Result<(), ClientError> {
+    let mut json = serde_json::to_string(request).map_err(ClientError::Serialize)?;
+    json.push('\n');
+    writer
+        .write_all(json.as_bytes())
+        .await
+        .map_err(ClientError::Send)
+}
+
+/// Read a single newline-terminated JSON response.
+async fn read_response(reader: &mut BufReader<OwnedReadHalf>) -> Result<Response, ClientError> {
+    let mut line = String::new();
+    let n = reader
+        .read_line(&mut line)
+        .await
+        .map_err(ClientError::Receive)?;
+    if n == 0 {
+        return Err(ClientError::ConnectionLost);
+    }
+    serde_json::from_str(&line).map_err(ClientError::Deserialize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_message_classification() {
+        assert!(matches!(
+            ClientError::from_server_message("agent not found: rusty-nail".into()),
+            ClientError::AgentNotFound(id) if id == "rusty-nail"
+        ));
+        assert!(matches!(
+            ClientError::from_server_message("command is empty".into()),
+            ClientError::ServerError(msg) if msg == "command is empty"
+        ));
+    }
+}
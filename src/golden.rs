@@ -0,0 +1,253 @@
+//! Golden snapshots: compare screens against saved copies and review changes.
+//!
+//! A golden snapshot named `dir/name` is stored at `dir/name.snap`. When a
+//! screen doesn't match it, or there's no golden yet, the screen is written
+//! next to it as `dir/name.snap.new` and the check fails with a diff. Once the
+//! `.new` file looks right, [`accept`] (`botty accept`) promotes it to the
+//! golden. A later match removes any stale `.new` file.
+//!
+//! The golden is redacted with the same rules as the screen before comparing,
+//! so goldens saved before a rule was added still match.
+
+use crate::redact::Redactor;
+use similar::{ChangeTag, TextDiff};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Extension of golden snapshot files.
+pub const EXTENSION: &str = "snap";
+
+/// Extension of snapshots waiting for review.
+pub const NEW_EXTENSION: &str = "snap.new";
+
+/// Errors from checking or accepting golden snapshots.
+#[derive(Debug, Error)]
+pub enum GoldenError {
+    #[error("snapshot doesn't match {}; review {} and run `botty accept` to update it\n{diff}", .path.display(), .new.display())]
+    Mismatch {
+        path: PathBuf,
+        new: PathBuf,
+        diff: String,
+    },
+
+    #[error("no golden snapshot at {}; review {} and run `botty accept` to create it", .path.display(), .new.display())]
+    Missing { path: PathBuf, new: PathBuf },
+
+    #[error("no snapshot to accept at {}", .0.display())]
+    NothingToAccept(PathBuf),
+
+    #[error("{}: {source}", .path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+impl GoldenError {
+    fn io(path: &Path) -> impl FnOnce(std::io::Error) -> Self + '_ {
+        move |source| Self::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+}
+
+/// The golden file for a snapshot name (`dir/name` → `dir/name.snap`).
+#[must_use]
+pub fn golden_path(name: &Path) -> PathBuf {
+    if name.extension().is_some_and(|ext| ext == EXTENSION) {
+        name.to_path_buf()
+    } else {
+        with_suffix(name, EXTENSION)
+    }
+}
+
+/// The review file for a snapshot name (`dir/name` → `dir/name.snap.new`).
+#[must_use]
+pub fn new_path(name: &Path) -> PathBuf {
+    with_suffix(&golden_path(name), "new")
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(suffix);
+    path.into()
+}
+
+/// Compare a screen with its golden snapshot, writing a `.new` file for
+/// review if it differs or there is no golden yet.
+pub fn check(name: &Path, content: &str, redactor: &Redactor, color: bool) -> Result<(), GoldenError> {
+    let path = golden_path(name);
+    let new = new_path(name);
+
+    let golden = match std::fs::read_to_string(&path) {
+        Ok(golden) => Some(redactor.apply(golden.strip_suffix('\n').unwrap_or(&golden))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(GoldenError::io(&path)(e)),
+    };
+
+    if golden.as_deref() == Some(content) {
+        match std::fs::remove_file(&new) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(GoldenError::io(&new)(e)),
+        }
+        return Ok(());
+    }
+
+    if let Some(dir) = new.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(GoldenError::io(dir))?;
+    }
+    std::fs::write(&new, format!("{content}\n")).map_err(GoldenError::io(&new))?;
+
+    match golden {
+        Some(golden) => Err(GoldenError::Mismatch {
+            path,
+            new,
+            diff: diff(&golden, content, color),
+        }),
+        None => Err(GoldenError::Missing { path, new }),
+    }
+}
+
+/// Promote snapshots waiting for review to goldens.
+///
+/// Each path is a directory (every `.snap.new` file under it), a `.snap.new`
+/// file, or a snapshot name. Returns the goldens written.
+pub fn accept(paths: &[PathBuf]) -> Result<Vec<PathBuf>, GoldenError> {
+    let mut pending = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            find_new(path, &mut pending)?;
+        } else if path.to_string_lossy().ends_with(NEW_EXTENSION) {
+            pending.push(path.clone());
+        } else {
+            let new = new_path(path);
+            if !new.exists() {
+                return Err(GoldenError::NothingToAccept(path.clone()));
+            }
+            pending.push(new);
+        }
+    }
+    pending.sort();
+
+    pending
+        .into_iter()
+        .map(|new| {
+            let golden = new.with_extension("");
+            std::fs::rename(&new, &golden).map_err(GoldenError::io(&new))?;
+            Ok(golden)
+        })
+        .collect()
+}
+
+/// Collect every `.snap.new` file under a directory.
+fn find_new(dir: &Path, found: &mut Vec<PathBuf>) -> Result<(), GoldenError> {
+    for entry in std::fs::read_dir(dir).map_err(GoldenError::io(dir))? {
+        let path = entry.map_err(GoldenError::io(dir))?.path();
+        if path.is_dir() {
+            find_new(&path, found)?;
+        } else if path.to_string_lossy().ends_with(NEW_EXTENSION) {
+            found.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// A line diff from `old` to `new`, with removed lines in red and added
+/// lines in green if `color`.
+#[must_use]
+pub fn diff(old: &str, new: &str, color: bool) -> String {
+    use std::fmt::Write;
+    let mut out = String::new();
+    for change in TextDiff::from_lines(old, new).iter_all_changes() {
+        let (sign, style) = match change.tag() {
+            ChangeTag::Delete => ("-", "\x1b[31m"),
+            ChangeTag::Insert => ("+", "\x1b[32m"),
+            ChangeTag::Equal => (" ", ""),
+        };
+        let line = change.as_str().unwrap_or_default().trim_end_matches('\n');
+        if color && !style.is_empty() {
+            let _ = writeln!(out, "{style}{sign}{line}\x1b[0m");
+        } else {
+            let _ = writeln!(out, "{sign}{line}");
+        }
+    }
+    out
+}
+
+/// Whether to color diffs written to a stream: only terminals, and not when
+/// `NO_COLOR` is set.
+#[must_use]
+pub fn use_color(is_terminal: bool) -> bool {
+    is_terminal && std::env::var_os("NO_COLOR").is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("botty-golden-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_golden_review_workflow() {
+        let dir = temp_dir("review");
+        let name = dir.join("screens/home");
+        let none = Redactor::default();
+
+        // First run: nothing to compare with, so the screen waits for review
+        let err = check(&name, "hello\nworld", &none, false).expect_err("no golden yet");
+        assert!(matches!(err, GoldenError::Missing { .. }));
+        assert_eq!(
+            std::fs::read_to_string(dir.join("screens/home.snap.new")).expect("new file"),
+            "hello\nworld\n"
+        );
+
+        let accepted = accept(std::slice::from_ref(&dir)).expect("accept");
+        assert_eq!(accepted, vec![dir.join("screens/home.snap")]);
+        check(&name, "hello\nworld", &none, false).expect("matches the golden");
+
+        // A change fails with a diff and leaves the new screen for review
+        let err = check(&name, "hello\nthere", &none, false).expect_err("changed");
+        let GoldenError::Mismatch { diff, new, .. } = err else {
+            panic!("expected a mismatch, got {err}");
+        };
+        assert_eq!(diff, " hello\n-world\n+there\n");
+        assert!(new.exists());
+
+        // Matching again cleans up the stale review file
+        check(&name, "hello\nworld", &none, false).expect("matches the golden");
+        assert!(!new.exists());
+        assert!(matches!(
+            accept(&[name]),
+            Err(GoldenError::NothingToAccept(_))
+        ));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_golden_redacts_saved_snapshot() {
+        let dir = temp_dir("redact");
+        std::fs::create_dir_all(&dir).expect("create dir");
+        std::fs::write(dir.join("log.snap"), "pid 4242 ready\n").expect("write golden");
+
+        let redactor = Redactor::new(&[crate::redact::Redaction::Pid]).expect("redactor");
+        check(&dir.join("log"), "pid <PID> ready", &redactor, false).expect("matches once redacted");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_colored_diff() {
+        assert_eq!(diff("a\n", "b\n", true), "\x1b[31m-a\x1b[0m\n\x1b[32m+b\x1b[0m\n");
+        assert_eq!(golden_path(Path::new("x/home.snap")), Path::new("x/home.snap"));
+        assert_eq!(new_path(Path::new("x/home")), Path::new("x/home.snap.new"));
+    }
+}
@@ -0,0 +1,286 @@
+//! Key names and the bytes an xterm sends for them.
+//!
+//! A key name is an optional chain of modifiers followed by a key:
+//!
+//! - Modifiers: `ctrl-`/`c-`, `alt-`/`meta-`/`m-`, `shift-`/`s-` (`+` works
+//!   as well as `-`, case doesn't matter), and `^X` for `ctrl-X`
+//! - Characters: `a`, `G`, `/`, `space`
+//! - Editing: `enter`, `tab`, `escape`, `backspace`, `insert`, `delete`
+//! - Cursor and navigation: `up`, `down`, `left`, `right`, `home`, `end`,
+//!   `pageup`, `pagedown`
+//! - Function keys: `f1` to `f24` (`f13`-`f24` are xterm's shifted `f1`-`f12`)
+//! - Keypad: `kp0`-`kp9`, `kpenter`, `kpplus`, `kpminus`, `kpmultiply`,
+//!   `kpdivide`, `kpdecimal`, `kpequal`
+//!
+//! Cursor keys and the keypad send different sequences when the application
+//! has switched them to application mode (DECCKM/DECKPAM), so encoding takes
+//! the agent's current [`KeyModes`].
+
+const ESC: u8 = 0x1b;
+
+const SHIFT: u8 = 1;
+const ALT: u8 = 2;
+const CTRL: u8 = 4;
+
+/// Terminal input modes that change what some keys send.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyModes {
+    /// Cursor keys send `ESC O x` instead of `ESC [ x` (DECCKM).
+    pub application_cursor: bool,
+    /// Keypad keys send `ESC O x` instead of their characters (DECKPAM).
+    pub application_keypad: bool,
+}
+
+/// Encode a key name into the bytes xterm would send for it.
+///
+/// Returns None if the name or the modifier combination isn't recognized.
+#[must_use]
+pub fn encode_key(name: &str, modes: KeyModes) -> Option<Vec<u8>> {
+    let mut key = name.trim();
+    let mut modifiers = 0;
+    while let Some((modifier, rest)) = strip_modifier(key) {
+        modifiers |= modifier;
+        key = rest;
+    }
+    if let Some(rest) = key.strip_prefix('^').filter(|r| r.chars().count() == 1) {
+        modifiers |= CTRL;
+        key = rest;
+    }
+
+    let mut chars = key.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return encode_char(c, modifiers);
+    }
+
+    let key = key.to_ascii_lowercase();
+    let alt_prefixed = |bytes: &[u8]| {
+        let mut out = if modifiers & ALT == 0 { vec![] } else { vec![ESC] };
+        out.extend_from_slice(bytes);
+        Some(out)
+    };
+    match key.as_str() {
+        "enter" | "return" => alt_prefixed(b"\r"),
+        "escape" | "esc" => alt_prefixed(&[ESC]),
+        "space" => encode_char(' ', modifiers & !SHIFT),
+        "tab" if modifiers & SHIFT != 0 => alt_prefixed(b"\x1b[Z"),
+        "tab" => alt_prefixed(b"\t"),
+        "backtab" | "btab" => alt_prefixed(b"\x1b[Z"),
+        "backspace" | "bs" if modifiers & CTRL != 0 => alt_prefixed(&[0x08]),
+        "backspace" | "bs" => alt_prefixed(&[0x7f]),
+
+        "up" => Some(cursor_key(b'A', modifiers, modes.application_cursor)),
+        "down" => Some(cursor_key(b'B', modifiers, modes.application_cursor)),
+        "right" => Some(cursor_key(b'C', modifiers, modes.application_cursor)),
+        "left" => Some(cursor_key(b'D', modifiers, modes.application_cursor)),
+        "home" => Some(cursor_key(b'H', modifiers, modes.application_cursor)),
+        "end" => Some(cursor_key(b'F', modifiers, modes.application_cursor)),
+
+        "insert" | "ins" => Some(tilde_key(2, modifiers)),
+        "delete" | "del" => Some(tilde_key(3, modifiers)),
+        "pageup" | "pgup" => Some(tilde_key(5, modifiers)),
+        "pagedown" | "pgdn" | "pgdown" => Some(tilde_key(6, modifiers)),
+
+        pad if pad.starts_with("kp") => {
+            keypad_key(&pad[2..], modifiers, modes.application_keypad)
+        }
+        _ => key
+            .strip_prefix('f')
+            .and_then(|n| n.parse().ok())
+            .and_then(|n| function_key(n, modifiers)),
+    }
+}
+
+/// Split one leading modifier off a key name.
+fn strip_modifier(key: &str) -> Option<(u8, &str)> {
+    let (head, rest) = key.split_once(['-', '+'])?;
+    if rest.is_empty() {
+        return None;
+    }
+    let modifier = match head.to_ascii_lowercase().as_str() {
+        "shift" | "s" => SHIFT,
+        "alt" | "meta" | "m" => ALT,
+        "ctrl" | "control" | "c" => CTRL,
+        _ => return None,
+    };
+    Some((modifier, rest))
+}
+
+/// A printable character, with ctrl mapped to its control code and alt
+/// sent as an ESC prefix.
+fn encode_char(c: char, mods: u8) -> Option<Vec<u8>> {
+    let c = if mods & SHIFT == 0 {
+        c
+    } else if c.is_ascii_alphabetic() {
+        c.to_ascii_uppercase()
+    } else {
+        return None;
+    };
+    let mut out = if mods & ALT == 0 { vec![] } else { vec![ESC] };
+    if mods & CTRL == 0 {
+        let mut buf = [0; 4];
+        out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+    } else {
+        out.push(control_code(c)?);
+    }
+    Some(out)
+}
+
+/// The control code ctrl-`c` produces.
+const fn control_code(c: char) -> Option<u8> {
+    match c {
+        'a'..='z' => Some(c as u8 - b'a' + 1),
+        'A'..='Z' => Some(c as u8 - b'A' + 1),
+        '@' | ' ' | '2' => Some(0x00),
+        '[' | '3' => Some(ESC),
+        '\\' | '4' => Some(0x1c),
+        ']' | '5' => Some(0x1d),
+        '^' | '6' => Some(0x1e),
+        '_' | '/' | '7' => Some(0x1f),
+        '?' | '8' => Some(0x7f),
+        _ => None,
+    }
+}
+
+/// The xterm modifier parameter: 1 + shift(1) + alt(2) + ctrl(4).
+fn modifier_param(mods: u8) -> String {
+    (1 + mods).to_string()
+}
+
+/// Arrows, home/end and f1-f4: `CSI x` (`SS3 x` in application mode),
+/// `CSI 1 ; m x` when modified.
+fn cursor_key(code: u8, mods: u8, application: bool) -> Vec<u8> {
+    if mods != 0 {
+        return [b"\x1b[1;", modifier_param(mods).as_bytes(), &[code]].concat();
+    }
+    vec![ESC, if application { b'O' } else { b'[' }, code]
+}
+
+/// Editing keys and f5-f12: `CSI n ~`, `CSI n ; m ~` when modified.
+fn tilde_key(n: u8, mods: u8) -> Vec<u8> {
+    let mut out = format!("\x1b[{n}").into_bytes();
+    if mods != 0 {
+        out.push(b';');
+        out.extend_from_slice(modifier_param(mods).as_bytes());
+    }
+    out.push(b'~');
+    out
+}
+
+fn function_key(n: u8, mods: u8) -> Option<Vec<u8>> {
+    match n {
+        // f1-f4 are always SS3 unmodified, regardless of DECCKM
+        1..=4 => Some(cursor_key(b'P' + n - 1, mods, true)),
+        5 => Some(tilde_key(15, mods)),
+        6..=10 => Some(tilde_key(n + 11, mods)),
+        11..=12 => Some(tilde_key(n + 12, mods)),
+        13..=24 => function_key(n - 12, mods | SHIFT),
+        _ => None,
+    }
+}
+
+fn keypad_key(key: &str, mods: u8, application: bool) -> Option<Vec<u8>> {
+    if mods & !ALT != 0 {
+        return None;
+    }
+    let (normal, app) = match key {
+        "enter" => (b'\r', b'M'),
+        "plus" => (b'+', b'k'),
+        "minus" => (b'-', b'm'),
+        "multiply" | "star" => (b'*', b'j'),
+        "divide" | "slash" => (b'/', b'o'),
+        "decimal" | "dot" | "period" => (b'.', b'n'),
+        "equal" => (b'=', b'X'),
+        digit => {
+            let &[d @ b'0'..=b'9'] = digit.as_bytes() else {
+                return None;
+            };
+            (d, b'p' + (d - b'0'))
+        }
+    };
+    let mut out = if mods & ALT == 0 { vec![] } else { vec![ESC] };
+    if application {
+        out.extend_from_slice(&[ESC, b'O', app]);
+    } else {
+        out.push(normal);
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str) -> Vec<u8> {
+        encode_key(name, KeyModes::default()).unwrap_or_else(|| panic!("unknown key {name}"))
+    }
+
+    #[test]
+    fn test_modified_characters() {
+        assert_eq!(key("G"), b"G");
+        assert_eq!(key("ctrl-c"), [0x03]);
+        assert_eq!(key("C-x"), [0x18]);
+        assert_eq!(key("^["), [ESC]);
+        assert_eq!(key("ctrl-space"), [0x00]);
+        assert_eq!(key("alt-x"), b"\x1bx");
+        assert_eq!(key("M-b"), b"\x1bb");
+        assert_eq!(key("meta+ctrl+a"), [ESC, 0x01]);
+        assert_eq!(key("shift-a"), b"A");
+        assert_eq!(key("alt-backspace"), [ESC, 0x7f]);
+        assert_eq!(key("shift-tab"), b"\x1b[Z");
+        assert_eq!(key("-"), b"-");
+        assert_eq!(encode_key("shift-1", KeyModes::default()), None);
+        assert_eq!(encode_key("ctrl-", KeyModes::default()), None);
+        assert_eq!(encode_key("hyper-a", KeyModes::default()), None);
+    }
+
+    #[test]
+    fn test_cursor_and_editing_keys() {
+        assert_eq!(key("up"), b"\x1b[A");
+        assert_eq!(key("ctrl-left"), b"\x1b[1;5D");
+        assert_eq!(key("shift-up"), b"\x1b[1;2A");
+        assert_eq!(key("ctrl-alt-shift-end"), b"\x1b[1;8F");
+        assert_eq!(key("insert"), b"\x1b[2~");
+        assert_eq!(key("ctrl-delete"), b"\x1b[3;5~");
+        assert_eq!(key("shift-pageup"), b"\x1b[5;2~");
+
+        let app = KeyModes {
+            application_cursor: true,
+            ..KeyModes::default()
+        };
+        assert_eq!(encode_key("up", app).unwrap(), b"\x1bOA");
+        assert_eq!(encode_key("home", app).unwrap(), b"\x1bOH");
+        // Modified keys use CSI in both modes
+        assert_eq!(encode_key("ctrl-right", app).unwrap(), b"\x1b[1;5C");
+    }
+
+    #[test]
+    fn test_function_keys() {
+        assert_eq!(key("f1"), b"\x1bOP");
+        assert_eq!(key("f5"), b"\x1b[15~");
+        assert_eq!(key("f10"), b"\x1b[21~");
+        assert_eq!(key("f11"), b"\x1b[23~");
+        assert_eq!(key("F12"), b"\x1b[24~");
+        assert_eq!(key("f13"), b"\x1b[1;2P");
+        assert_eq!(key("f24"), b"\x1b[24;2~");
+        assert_eq!(key("ctrl-f5"), b"\x1b[15;5~");
+        assert_eq!(encode_key("f25", KeyModes::default()), None);
+        assert_eq!(encode_key("f0", KeyModes::default()), None);
+    }
+
+    #[test]
+    fn test_keypad_keys() {
+        assert_eq!(key("kp5"), b"5");
+        assert_eq!(key("kpenter"), b"\r");
+        assert_eq!(key("kpplus"), b"+");
+
+        let app = KeyModes {
+            application_keypad: true,
+            ..KeyModes::default()
+        };
+        assert_eq!(encode_key("kp0", app).unwrap(), b"\x1bOp");
+        assert_eq!(encode_key("kp9", app).unwrap(), b"\x1bOy");
+        assert_eq!(encode_key("kpenter", app).unwrap(), b"\x1bOM");
+        assert_eq!(encode_key("kpdivide", app).unwrap(), b"\x1bOo");
+        assert_eq!(encode_key("kp10", app), None);
+    }
+}
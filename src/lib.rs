@@ -9,8 +9,14 @@
 pub mod attach;
 pub mod cli;
 pub mod client;
+pub mod golden;
+pub mod keys;
 pub mod protocol;
 pub mod pty;
+pub mod redact;
+pub mod report;
+pub mod script;
+pub mod selector;
 pub mod server;
 pub mod testing;
 pub mod view;
@@ -18,7 +24,21 @@ pub mod view;
 pub use attach::{run_attach, AttachConfig, AttachError};
 pub use cli::{parse_key_notation, parse_key_sequence, Cli, Command};
 pub use client::{default_socket_path, Client, ClientError};
-pub use protocol::{AgentInfo, AgentState, DumpFormat, Event, ExitReason, Request, ResourceLimits, Response};
-pub use server::{Server, ServerError};
-pub use testing::{AgentHandle, TestError, TestHarness};
+pub use golden::GoldenError;
+pub use keys::{encode_key, KeyModes};
+pub use protocol::{
+    AgentId, AgentInfo, AgentState, DumpFormat, Event, EventMessage, EventOptions, ExitReason,
+    InputStats, MouseAction, MouseButton, OutputStats, Request, ResourceLimits, Response, RowChange,
+    SendResult, Region, ShellState, Snapshot, SpawnOptions, TerminalIdentity,
+};
+pub use redact::{RedactError, Redaction, Redactor};
+pub use report::{CheckResult, Report, ReportError, ReportFormat};
+pub use script::{Script, ScriptError, ScriptReport, ScriptRunner};
+pub use selector::{Selector, SelectorError};
+pub use server::{Runtime, Server, ServerError};
+pub use testing::{AgentExit, AgentHandle, TestError, TestHarness};
 pub use view::{TmuxView, ViewError, ViewMode};
+
+/// Longest regex accepted from a user, in scripts, redaction rules, `wait`
+/// and `assert`, to mitigate `ReDoS`.
+pub const MAX_PATTERN_LEN: usize = 1000;
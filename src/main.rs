@@ -1,8 +1,19 @@
 //! botty — PTY-based Agent Runtime
 
-use botty::{default_socket_path, run_attach, AttachConfig, Cli, Client, Command, DumpFormat, Request, Response, Server, TmuxView, ViewError};
-use clap::Parser;
-use std::io::Write;
+// CLI dispatch functions are long but linear; splitting them would reduce clarity
+#![allow(clippy::too_many_lines)]
+// tmux setup helpers keep a Result signature so call sites can use `?` uniformly
+#![allow(clippy::unnecessary_wraps)]
+// Assertion failure messages are built up across several checks
+#![allow(clippy::useless_let_if_seq)]
+
+use botty::{
+    default_socket_path, golden, run_attach, AttachConfig, Cli, Client, ClientError, Command, DumpFormat,
+    CheckResult, Event, EventMessage, EventOptions, Redaction, Redactor, Region, Report, Request, Response, Script, ScriptRunner, Server, SpawnOptions, TmuxView,
+    ViewError, MAX_PATTERN_LEN,
+};
+use std::collections::BTreeMap;
+use std::io::{IsTerminal, Write};
 use tracing::error;
 use tracing_subscriber::EnvFilter;
 
@@ -56,7 +67,7 @@ fn disable_output_postprocessing() -> Option<RawOutputGuard> {
 
 #[tokio::main]
 async fn main() {
-    let cli = Cli::parse();
+    let cli = Cli::parse_ordered(std::env::args_os());
 
     // Initialize logging
     let filter = if cli.verbose {
@@ -74,6 +85,7 @@ async fn main() {
     let result = match cli.command {
         Command::Server { daemon } => run_server(socket_path, daemon).await,
         Command::Doctor => run_doctor(socket_path).await,
+        Command::Accept { paths } => run_accept(&paths),
         cmd => run_client(socket_path, cmd).await,
     };
 
@@ -98,6 +110,18 @@ async fn run_server(
     Ok(())
 }
 
+/// Promote snapshots waiting for review to goldens.
+fn run_accept(paths: &[std::path::PathBuf]) -> Result<(), Box<dyn std::error::Error>> {
+    let accepted = golden::accept(paths)?;
+    if accepted.is_empty() {
+        println!("No snapshots to accept");
+    }
+    for path in accepted {
+        println!("accepted {}", path.display());
+    }
+    Ok(())
+}
+
 async fn run_doctor(
     socket_path: std::path::PathBuf,
 ) -> Result<(), Box<dyn std::error::Error>> {
@@ -154,55 +178,38 @@ async fn run_doctor(
         }
     }
 
-    // 4. Check daemon connectivity (start if needed)
+    // 4. Check the terminfo entry agents' TERM points at
+    print!("Terminfo ({}): ", botty::pty::DEFAULT_TERM);
+    match botty::pty::find_terminfo(botty::pty::DEFAULT_TERM) {
+        Some(path) => println!("[OK] {}", path.display()),
+        None => {
+            println!("[WARN] no terminfo entry found (install ncurses-term, or spawn with --term)");
+        }
+    }
+
+    // 5. Check daemon connectivity (start if needed)
     print!("Daemon connection: ");
     let mut client = Client::new(socket_path.clone());
-    match client.request(Request::Ping).await {
-        Ok(Response::Pong) => println!("[OK]"),
-        Ok(other) => {
-            println!("[FAIL] unexpected response: {other:?}");
-            all_ok = false;
-        }
+    match client.ping().await {
+        Ok(()) => println!("[OK]"),
         Err(e) => {
             println!("[FAIL] {e}");
             all_ok = false;
         }
     }
 
-    // 5. Test spawn/kill cycle
+    // 6. Test spawn/kill cycle
     print!("Spawn/kill cycle: ");
-    match client
-        .request(Request::Spawn {
-            cmd: vec!["sleep".to_string(), "60".to_string()],
-            rows: 24,
-            cols: 80,
-            name: Some("__doctor_test__".to_string()),
-            labels: vec![],
-            timeout: None,
-            max_output: None,
-            env: vec![],
-            env_clear: false,
-        })
-        .await
-    {
-        Ok(Response::Spawned { id, .. }) => {
-            // Kill it
-            match client.request(Request::Kill { id: Some(id.clone()), labels: vec![], all: false, signal: 9, proc_filter: None }).await {
-                Ok(Response::Ok) => println!("[OK]"),
-                Ok(other) => {
-                    println!("[FAIL] kill returned: {other:?}");
-                    all_ok = false;
-                }
-                Err(e) => {
-                    println!("[FAIL] kill failed: {e}");
-                    all_ok = false;
-                }
+    let mut options = SpawnOptions::new(["sleep", "60"]);
+    options.name = Some("__doctor_test__".to_string());
+    match client.spawn(options).await {
+        Ok(id) => match client.kill(&id, 9).await {
+            Ok(()) => println!("[OK]"),
+            Err(e) => {
+                println!("[FAIL] kill failed: {e}");
+                all_ok = false;
             }
-        }
-        Ok(other) => {
-            println!("[FAIL] spawn returned: {other:?}");
-            all_ok = false;
-        }
+        },
         Err(e) => {
             println!("[FAIL] spawn failed: {e}");
             all_ok = false;
@@ -219,7 +226,6 @@ async fn run_doctor(
     }
 }
 
-#[allow(clippy::too_many_lines)] // Command dispatch function, splitting would reduce clarity
 async fn run_client(
     socket_path: std::path::PathBuf,
     command: Command,
@@ -230,19 +236,42 @@ async fn run_client(
     }
 
     // Events command needs direct socket access (long-lived connection)
-    if let Command::Events { filter, output } = command {
-        return run_events_command(socket_path, filter, output).await;
+    if let Command::Events {
+        filter,
+        output,
+        screen,
+        since,
+        label,
+        annotation,
+        selector,
+        command,
+        event_type,
+    } = command
+    {
+        let options = EventOptions {
+            filter,
+            include_output: output,
+            screen_interval: screen,
+            since_seq: since,
+            labels: label,
+            annotations: annotation.into_iter().collect(),
+            selector,
+            commands: command,
+            event_types: event_type,
+        };
+        return run_events_command(socket_path, options).await;
     }
 
     // Subscribe command streams output from agents
-    if let Command::Subscribe { id, label, prefix, format } = command {
-        return run_subscribe_command(socket_path, id, label, prefix, format).await;
+    if let Command::Subscribe { id, label, selector, prefix, format } = command {
+        return run_subscribe_command(socket_path, id, label, selector, prefix, format).await;
     }
 
     // View command manages tmux session
-    if let Command::View { mux, mode, no_resize, label, new_session } = command {
+    if let Command::View { mux, mode, no_resize, label, selector, new_session } = command {
         let auto_resize = !no_resize; // auto-resize is now the default
-        return run_view_command(socket_path, mux, mode, auto_resize, label, new_session).await;
+        return run_view_command(socket_path, mux, mode, auto_resize, label, selector, new_session)
+            .await;
     }
 
     // ResizePanes command (called from tmux hook)
@@ -260,233 +289,300 @@ async fn run_client(
     let mut client = Client::new(socket_path);
 
     match command {
-        Command::Spawn { rows, cols, name, label, timeout, max_output, env, env_clear, after, wait_for, cmd } => {
+        Command::Spawn {
+            rows,
+            cols,
+            name,
+            label,
+            annotation,
+            timeout,
+            max_output,
+            max_input,
+            env,
+            env_clear,
+            shell_integration,
+            term,
+            terminal,
+            no_terminal_replies,
+            after,
+            wait_for,
+            cmd,
+        } => {
             // Wait for dependencies before spawning
             if !after.is_empty() || !wait_for.is_empty() {
                 wait_for_dependencies(&socket_path_ref, &after, &wait_for).await?;
             }
 
-            let request = Request::Spawn { cmd, rows, cols, name, labels: label, timeout, max_output, env, env_clear };
-            let response = client.request(request).await?;
-
-            match response {
-                Response::Spawned { id, pid } => {
-                    println!("{id}");
-                    tracing::debug!("Spawned agent {id} (pid {pid})");
-                }
-                Response::Error { message } => {
-                    return Err(message.into());
-                }
-                _ => {
-                    return Err("unexpected response".into());
-                }
-            }
+            let options = SpawnOptions {
+                cmd,
+                rows,
+                cols,
+                name,
+                labels: label,
+                annotations: annotation.into_iter().collect(),
+                timeout,
+                max_output,
+                max_input,
+                env,
+                env_clear,
+                shell_integration,
+                term,
+                terminal,
+                no_terminal_replies,
+            };
+            let id = client.spawn(options).await?;
+            println!("{id}");
         }
 
-        Command::List { all, label, format, json } => {
+        Command::List { all, label, annotation, selector, format, json } => {
             // --json is deprecated shorthand for --format json
             let format = if json { "json".to_string() } else { format };
 
-            let response = client.request(Request::List { labels: label }).await?;
-
-            match response {
-                Response::Agents { agents } => {
-                    // Filter to running only unless --all is specified
-                    let agents: Vec<_> = if all {
-                        agents
-                    } else {
-                        agents
-                            .into_iter()
-                            .filter(|a| matches!(a.state, botty::AgentState::Running))
-                            .collect()
-                    };
+            let annotations = annotation.into_iter().collect();
+            let agents = client
+                .list_matching(&label, &annotations, selector.as_deref())
+                .await?;
+            // Filter to running only unless --all is specified
+            let agents: Vec<_> = if all {
+                agents
+            } else {
+                agents
+                    .into_iter()
+                    .filter(|a| matches!(a.state, botty::AgentState::Running))
+                    .collect()
+            };
 
-                    // Build full JSON objects (used by json and toon formats)
-                    let build_full_json = |agents: &[botty::AgentInfo]| -> Vec<serde_json::Value> {
-                        agents
-                            .iter()
-                            .map(|a| {
-                                let mut obj = serde_json::json!({
-                                    "id": a.id,
-                                    "pid": a.pid,
-                                    "state": match a.state {
-                                        botty::AgentState::Running => "running",
-                                        botty::AgentState::Exited => "exited",
-                                    },
-                                    "command": a.command.join(" "),
-                                    "labels": a.labels,
-                                    "size": { "rows": a.size.0, "cols": a.size.1 },
-                                    "exit_code": a.exit_code,
-                                });
-                                if let Some(reason) = &a.exit_reason {
-                                    obj["exit_reason"] = serde_json::json!(match reason {
-                                        botty::ExitReason::Normal => "normal",
-                                        botty::ExitReason::Timeout => "timeout",
-                                        botty::ExitReason::Killed => "killed",
-                                    });
-                                }
-                                if let Some(limits) = &a.limits {
-                                    obj["limits"] = serde_json::json!({
-                                        "timeout": limits.timeout,
-                                        "max_output": limits.max_output,
-                                    });
-                                }
-                                obj
-                            })
-                            .collect()
-                    };
+            // Build full JSON objects (used by json and toon formats)
+            let build_full_json = |agents: &[botty::AgentInfo]| -> Vec<serde_json::Value> {
+                agents
+                    .iter()
+                    .map(|a| {
+                        let mut obj = serde_json::json!({
+                            "id": a.id,
+                            "pid": a.pid,
+                            "state": match a.state {
+                                botty::AgentState::Running => "running",
+                                botty::AgentState::Exited => "exited",
+                            },
+                            "command": a.command.join(" "),
+                            "labels": a.labels,
+                            "annotations": a.annotations,
+                            "size": { "rows": a.size.0, "cols": a.size.1 },
+                            "exit_code": a.exit_code,
+                        });
+                        if let Some(reason) = &a.exit_reason {
+                            obj["exit_reason"] = serde_json::json!(match reason {
+                                botty::ExitReason::Normal => "normal",
+                                botty::ExitReason::Timeout => "timeout",
+                                botty::ExitReason::Killed => "killed",
+                            });
+                        }
+                        if let Some(limits) = &a.limits {
+                            obj["limits"] = serde_json::json!({
+                                "timeout": limits.timeout,
+                                "max_output": limits.max_output,
+                                "max_input": limits.max_input,
+                            });
+                        }
+                        obj["input"] = serde_json::json!({
+                            "queued": a.input.queued,
+                            "written": a.input.written,
+                            "limit": a.input.limit,
+                        });
+                        obj
+                    })
+                    .collect()
+            };
 
-                    match format.as_str() {
-                        "json" => {
-                            println!("{}", serde_json::to_string(&build_full_json(&agents))?);
+            match format.as_str() {
+                "json" => {
+                    println!("{}", serde_json::to_string(&build_full_json(&agents))?);
+                }
+                "text" => {
+                    if agents.is_empty() {
+                        if all {
+                            println!("(no agents)");
+                        } else {
+                            println!("(no agents currently active)");
                         }
-                        "text" => {
-                            if agents.is_empty() {
-                                if all {
-                                    println!("(no agents)");
-                                } else {
-                                    println!("(no agents currently active)");
-                                }
+                    } else {
+                        // Columnar text output
+                        println!("{:<20} {:<8} {:<10} COMMAND", "ID", "PID", "STATE");
+                        for a in &agents {
+                            let state = match a.state {
+                                botty::AgentState::Running => "running",
+                                botty::AgentState::Exited => "exited",
+                            };
+                            let cmd = a.command.join(" ");
+                            let labels = if a.labels.is_empty() {
+                                String::new()
                             } else {
-                                // Columnar text output
-                                println!("{:<20} {:<8} {:<10} {}", "ID", "PID", "STATE", "COMMAND");
-                                for a in &agents {
-                                    let state = match a.state {
-                                        botty::AgentState::Running => "running",
-                                        botty::AgentState::Exited => "exited",
-                                    };
-                                    let cmd = a.command.join(" ");
-                                    let labels = if a.labels.is_empty() {
-                                        String::new()
-                                    } else {
-                                        format!(" [{}]", a.labels.join(","))
-                                    };
-                                    println!("{:<20} {:<8} {:<10} {}{}", a.id, a.pid, state, cmd, labels);
-                                }
-                            }
-                        }
-                        _ => {
-                            // Default: TOON format (token-efficient for LLMs)
-                            if agents.is_empty() {
-                                if all {
-                                    println!("(no agents)");
-                                } else {
-                                    println!("(no agents currently active)");
-                                }
+                                format!(" [{}]", a.labels.join(","))
+                            };
+                            let annotations = if a.annotations.is_empty() {
+                                String::new()
                             } else {
-                                let json_data = serde_json::json!({ "agents": build_full_json(&agents) });
-                                let toon = toon_format::encode(&json_data, &toon_format::EncodeOptions::default())
-                                    .unwrap_or_else(|_| format!("{json_data:?}"));
-                                println!("{toon}");
-                            }
+                                let pairs: Vec<String> =
+                                    a.annotations.iter().map(|(k, v)| format!("{k}={v}")).collect();
+                                format!(" {{{}}}", pairs.join(","))
+                            };
+                            println!("{:<20} {:<8} {:<10} {cmd}{labels}{annotations}", a.id, a.pid, state);
                         }
                     }
                 }
-                Response::Error { message } => {
-                    return Err(message.into());
-                }
                 _ => {
-                    return Err("unexpected response".into());
+                    // Default: TOON format (token-efficient for LLMs)
+                    if agents.is_empty() {
+                        if all {
+                            println!("(no agents)");
+                        } else {
+                            println!("(no agents currently active)");
+                        }
+                    } else {
+                        let json_data = serde_json::json!({ "agents": build_full_json(&agents) });
+                        let toon = toon_format::encode(&json_data, &toon_format::EncodeOptions::default())
+                            .unwrap_or_else(|_| format!("{json_data:?}"));
+                        println!("{toon}");
+                    }
                 }
             }
         }
 
-        Command::Kill { id, label, all, term, proc } => {
-            // Must specify either id, label, proc, or all
-            if id.is_none() && label.is_empty() && !all && proc.is_none() {
-                return Err("must specify agent ID, --label, --proc, or --all".into());
+        Command::Kill { id, label, annotation, selector, all, term, proc } => {
+            let filtered = !label.is_empty()
+                || !annotation.is_empty()
+                || selector.is_some()
+                || proc.is_some();
+            // Must specify either id, label, annotation, selector, proc, or all
+            if id.is_none() && !filtered && !all {
+                return Err(
+                    "must specify agent ID, --label, --annotation, --selector, --proc, or --all"
+                        .into(),
+                );
             }
-            // Can't combine --all with specific id, labels, or proc
-            if all && (id.is_some() || !label.is_empty() || proc.is_some()) {
-                return Err("--all cannot be combined with agent ID, --label, or --proc".into());
+            // Can't combine --all with specific id or filters
+            if all && (id.is_some() || filtered) {
+                return Err("--all cannot be combined with agent ID, --label, --annotation, --selector, or --proc"
+                    .into());
             }
             let signal = if term { 15 } else { 9 }; // SIGTERM or SIGKILL (default)
-            let request = Request::Kill { id, labels: label, all, signal, proc_filter: proc };
-            let response = client.request(request).await?;
+            let request = Request::Kill {
+                id,
+                labels: label,
+                annotations: annotation.into_iter().collect(),
+                selector,
+                all,
+                signal,
+                proc_filter: proc,
+            };
 
-            match response {
-                Response::Ok => {
+            match client.call(request).await {
+                Ok(_) => {
                     println!("Signal sent");
                 }
-                Response::Error { message } => {
-                    // Make kill idempotent: exit 0 when agent/agents not found
-                    // This matches behavior of Unix tools like rm -f, pkill
-                    if message.contains("agent not found")
-                        || message.contains("no running agents to kill")
-                        || message.contains("no agents match the specified labels")
-                    {
-                        // Silently succeed - agent is already gone or wasn't there
-                        return Ok(());
-                    }
-                    // For other errors (permission denied, signal failures), still error
-                    return Err(message.into());
-                }
-                _ => {
-                    return Err("unexpected response".into());
-                }
+                // Make kill idempotent: exit 0 when agent/agents not found
+                // This matches behavior of Unix tools like rm -f, pkill
+                Err(ClientError::AgentNotFound(_)) => return Ok(()),
+                Err(ClientError::ServerError(message))
+                    if message.contains("no running agents to kill")
+                        || message.contains("no agents match the specified labels") =>
+                {
+                    // Silently succeed - agents are already gone or weren't there
+                    return Ok(());
+                }
+                // For other errors (permission denied, signal failures), still error
+                Err(e) => return Err(e.into()),
             }
         }
 
         Command::Send {
             id,
             text,
+            file,
+            paste,
+            delay,
+            jitter,
             no_newline,
         } => {
-            let request = Request::Send {
-                id,
-                data: text,
-                newline: !no_newline,
+            let text = match (text, file) {
+                (Some(text), _) => text,
+                (None, Some(path)) if path.as_os_str() == "-" => {
+                    std::io::read_to_string(std::io::stdin())?
+                }
+                (None, Some(path)) => std::fs::read_to_string(&path)
+                    .map_err(|e| format!("failed to read {}: {e}", path.display()))?,
+                (None, None) => unreachable!("clap requires text or --file"),
             };
-            let response = client.request(request).await?;
-
-            match response {
-                Response::Ok => {}
-                Response::Error { message } => {
-                    return Err(message.into());
-                }
-                _ => {
-                    return Err("unexpected response".into());
-                }
+            if paste {
+                client.paste(&id, &text, !no_newline).await?;
+            } else if delay.is_some() || jitter.is_some() {
+                let delay = std::time::Duration::from_millis(delay.unwrap_or(0));
+                let jitter = std::time::Duration::from_millis(jitter.unwrap_or(0));
+                client.type_text(&id, &text, !no_newline, delay, jitter).await?;
+            } else {
+                client.send(&id, &text, !no_newline).await?;
             }
         }
 
         Command::SendBytes { id, hex } => {
             let data = hex::decode(&hex).map_err(|e| format!("invalid hex: {e}"))?;
-            let request = Request::SendBytes { id, data };
-            let response = client.request(request).await?;
-
-            match response {
-                Response::Ok => {}
-                Response::Error { message } => {
-                    return Err(message.into());
-                }
-                _ => {
-                    return Err("unexpected response".into());
-                }
-            }
+            client.send_bytes(&id, &data).await?;
         }
 
         Command::SendKeys { id, keys } => {
-            use botty::parse_key_sequence;
-            for key in keys {
-                let data = parse_key_sequence(&key)
-                    .ok_or_else(|| format!("unknown key: {key}"))?;
-                let request = Request::SendBytes { id: id.clone(), data };
-                let response = client.request(request).await?;
-
-                match response {
-                    Response::Ok => {}
-                    Response::Error { message } => {
-                        return Err(message.into());
-                    }
-                    _ => {
-                        return Err("unexpected response".into());
-                    }
+            client.send_keys(&id, &keys).await?;
+        }
+
+        Command::SendMouse {
+            id,
+            action,
+            row,
+            col,
+            button,
+            to_row,
+            to_col,
+        } => {
+            let to = to_row.zip(to_col);
+            client.send_mouse(&id, action, (row, col), button, to).await?;
+        }
+
+        Command::Broadcast {
+            id,
+            label,
+            annotation,
+            selector,
+            keys,
+            no_newline,
+            input,
+        } => {
+            // Keys are encoded by the server for each agent's own modes
+            let (data, keys) = if keys {
+                (Vec::new(), input)
+            } else {
+                let mut data = input.join(" ").into_bytes();
+                if !no_newline {
+                    data.push(b'\n');
+                }
+                (data, Vec::new())
+            };
+            let annotations = annotation.into_iter().collect();
+            let results = client
+                .broadcast(&id, &label, &annotations, selector.as_deref(), &data, &keys)
+                .await?;
+
+            let failed = results.iter().filter(|r| !r.is_ok()).count();
+            for result in &results {
+                match &result.error {
+                    None => println!("{}: ok", result.id),
+                    Some(e) => println!("{}: {e}", result.id),
                 }
             }
+            if failed > 0 {
+                return Err(format!("failed to send to {failed} of {} agents", results.len()).into());
+            }
         }
 
-        Command::Tail { id, lines, follow, raw, replay } => {
+        Command::Tail { id, lines: _, follow, raw, replay } => {
             // --replay implies --follow and --raw
             let follow = follow || replay;
             let raw = raw || replay;
@@ -524,90 +620,42 @@ async fn run_client(
                     std::io::stdout().flush()?;
 
                     // Get and output the entire transcript so far
-                    let response = client
-                        .request(Request::Dump {
-                            id: id.clone(),
-                            since: None,
-                            format: crate::DumpFormat::Text,
-                        })
-                        .await?;
-
-                    match response {
-                        Response::Output { data } => {
-                            std::io::stdout().write_all(&data)?;
-                            std::io::stdout().flush()?;
-                            last_len = data.len();
-                        }
-                        Response::Error { message } => {
-                            return Err(message.into());
-                        }
-                        _ => {
-                            return Err("unexpected response".into());
-                        }
-                    }
+                    let data = client.transcript(&id).await?;
+                    std::io::stdout().write_all(&data)?;
+                    std::io::stdout().flush()?;
+                    last_len = data.len();
                 }
 
                 loop {
-                    let response = client
-                        .request(Request::Tail {
-                            id: id.clone(),
-                            lines,
-                            follow: false, // Server doesn't implement follow
-                        })
-                        .await?;
-
-                    match response {
-                        Response::Output { data } => {
-                            if data.len() < last_len {
-                                // Transcript shrank (cleared or ring buffer wrapped)
-                                // Just reset our position - TUI programs will redraw
-                                // themselves via SIGWINCH from the resize
-                                last_len = data.len();
-                            } else if data.len() > last_len {
-                                // Only print new data
-                                let new_data = &data[last_len..];
-                                let output = process_output(new_data, raw);
-                                std::io::stdout().write_all(&output)?;
-                                std::io::stdout().flush()?;
-                                last_len = data.len();
-                            }
-                        }
-                        Response::Error { message } => {
-                            // Agent may have exited
-                            if message.contains("not found") || message.contains("exited") {
-                                break;
-                            }
-                            return Err(message.into());
-                        }
-                        _ => {
-                            return Err("unexpected response".into());
-                        }
+                    let data = match client.transcript(&id).await {
+                        Ok(data) => data,
+                        // Agent may have exited
+                        Err(ClientError::AgentNotFound(_)) => break,
+                        Err(e) => return Err(e.into()),
+                    };
+
+                    if data.len() < last_len {
+                        // Transcript shrank (cleared or ring buffer wrapped)
+                        // Just reset our position - TUI programs will redraw
+                        // themselves via SIGWINCH from the resize
+                        last_len = data.len();
+                    } else if data.len() > last_len {
+                        // Only print new data
+                        let new_data = &data[last_len..];
+                        let output = process_output(new_data, raw);
+                        std::io::stdout().write_all(&output)?;
+                        std::io::stdout().flush()?;
+                        last_len = data.len();
                     }
 
                     tokio::time::sleep(poll_interval).await;
                 }
             } else {
                 // One-shot mode: just get current tail
-                let request = Request::Tail {
-                    id,
-                    lines,
-                    follow: false,
-                };
-                let response = client.request(request).await?;
-
-                match response {
-                    Response::Output { data } => {
-                        let output = process_output(&data, raw);
-                        std::io::stdout().write_all(&output)?;
-                        std::io::stdout().flush()?;
-                    }
-                    Response::Error { message } => {
-                        return Err(message.into());
-                    }
-                    _ => {
-                        return Err("unexpected response".into());
-                    }
-                }
+                let data = client.transcript(&id).await?;
+                let output = process_output(&data, raw);
+                std::io::stdout().write_all(&output)?;
+                std::io::stdout().flush()?;
             }
         }
 
@@ -617,9 +665,8 @@ async fn run_client(
                 _ => DumpFormat::Text,
             };
             let request = Request::Dump { id, since, format };
-            let response = client.request(request).await?;
 
-            match response {
+            match client.call(request).await? {
                 Response::Output { data } => {
                     std::io::stdout().write_all(&data)?;
                     std::io::stdout().flush()?;
@@ -636,92 +683,92 @@ async fn run_client(
                         println!("{}", serde_json::to_string(&json)?);
                     }
                 }
-                Response::Error { message } => {
-                    return Err(message.into());
-                }
                 _ => {
                     return Err("unexpected response".into());
                 }
             }
         }
 
-        Command::Snapshot { id, raw, diff } => {
-            let request = Request::Snapshot {
-                id,
-                strip_colors: !raw,
-            };
-            let response = client.request(request).await?;
-
-            match response {
-                Response::Snapshot { content, .. } => {
-                    if let Some(diff_file) = diff {
-                        // Validate path to prevent path traversal
-                        let diff_path = std::path::Path::new(&diff_file);
+        Command::Snapshot {
+            id,
+            raw,
+            diff,
+            golden,
+            area,
+            redact,
+        } => {
+            let redactions = redact.redactions();
+            let redactor = Redactor::new(&redactions)?;
+            let content =
+                snapshot_content(&mut client, &id, area.region(), raw, &redactions).await?;
 
-                        // Reject paths with .. components
-                        if diff_path.components().any(|c| matches!(c, std::path::Component::ParentDir)) {
-                            return Err("path traversal not allowed (.. in path)".into());
-                        }
+            if let Some(diff_file) = diff {
+                // Validate path to prevent path traversal
+                let diff_path = std::path::Path::new(&diff_file);
 
-                        // Read previous snapshot
-                        let previous = std::fs::read_to_string(diff_path)
-                            .map_err(|e| format!("failed to read {diff_file}: {e}"))?;
+                // Reject paths with .. components
+                if diff_path.components().any(|c| matches!(c, std::path::Component::ParentDir)) {
+                    return Err("path traversal not allowed (.. in path)".into());
+                }
 
-                        // Compare snapshots
-                        if content == previous {
-                            println!("No changes");
-                            return Ok(());
-                        }
+                // Read previous snapshot, redacted the same way so snapshots
+                // saved before the rules were added still compare cleanly
+                let previous = std::fs::read_to_string(diff_path)
+                    .map_err(|e| format!("failed to read {diff_file}: {e}"))?;
+                let previous = redactor.apply(&previous);
 
-                        // Show unified diff
-                        use similar::{ChangeTag, TextDiff};
-                        let diff = TextDiff::from_lines(&previous, &content);
+                // Compare snapshots
+                if content == previous {
+                    println!("No changes");
+                    return Ok(());
+                }
 
-                        for change in diff.iter_all_changes() {
-                            let sign = match change.tag() {
-                                ChangeTag::Delete => "-",
-                                ChangeTag::Insert => "+",
-                                ChangeTag::Equal => " ",
-                            };
-                            print!("{sign}{change}");
-                        }
+                // Show unified diff
+                print!("{}", golden::diff(&previous, &content, false));
 
-                        std::process::exit(1);
-                    } else {
-                        println!("{content}");
-                    }
-                }
-                Response::Error { message } => {
-                    return Err(message.into());
+                std::process::exit(1);
+            } else if let Some(name) = golden {
+                if name.components().any(|c| matches!(c, std::path::Component::ParentDir)) {
+                    return Err("path traversal not allowed (.. in path)".into());
                 }
-                _ => {
-                    return Err("unexpected response".into());
+                let color = golden::use_color(std::io::stderr().is_terminal());
+                if let Err(e) = golden::check(&name, &content, &redactor, color) {
+                    eprintln!("{e}");
+                    std::process::exit(1);
                 }
+                println!("Snapshot matches {}", golden::golden_path(&name).display());
+            } else {
+                println!("{content}");
             }
         }
 
         // These commands are handled before this match
-        Command::Attach { .. } | Command::Server { .. } | Command::Doctor | Command::Events { .. } | Command::Subscribe { .. } | Command::View { .. } | Command::ResizePanes { .. } => {
+        Command::Attach { .. } | Command::Server { .. } | Command::Doctor | Command::Accept { .. } | Command::Events { .. } | Command::Subscribe { .. } | Command::View { .. } | Command::ResizePanes { .. } => {
             unreachable!("handled above")
         }
 
-        Command::Resize { id, rows, cols, clear } => {
-            let response = client.request(Request::Resize { id, rows, cols, clear_transcript: clear }).await?;
+        Command::Label { id, add, remove, set, unset } => {
+            let request = Request::Label {
+                id: id.clone(),
+                add,
+                remove,
+                set: set.into_iter().collect(),
+                unset,
+            };
+            client.call(request).await?;
+            let agent = client.agent(&id).await?;
+            let annotations: Vec<String> =
+                agent.annotations.iter().map(|(k, v)| format!("{k}={v}")).collect();
+            println!("labels: {}", agent.labels.join(","));
+            println!("annotations: {}", annotations.join(","));
+        }
 
-            match response {
-                Response::Ok => {
-                    if clear {
-                        println!("Resized to {rows}x{cols} and cleared transcript");
-                    } else {
-                        println!("Resized to {rows}x{cols}");
-                    }
-                }
-                Response::Error { message } => {
-                    return Err(message.into());
-                }
-                _ => {
-                    return Err("unexpected response".into());
-                }
+        Command::Resize { id, rows, cols, clear } => {
+            client.resize(&id, rows, cols, clear).await?;
+            if clear {
+                println!("Resized to {rows}x{cols} and cleared transcript");
+            } else {
+                println!("Resized to {rows}x{cols}");
             }
         }
 
@@ -729,99 +776,206 @@ async fn run_client(
             id,
             contains,
             pattern,
+            matches,
+            match_pattern,
             stable,
+            prompt,
+            exit,
+            code,
+            any,
+            sequence,
+            order,
+            json,
             timeout,
             print,
+            area,
+            report,
         } => {
-            use regex::Regex;
             use std::time::{Duration, Instant};
 
+            let report = report.report()?;
+            let started = Instant::now();
+
             let timeout_duration = Duration::from_secs(timeout);
             let poll_interval = Duration::from_millis(50);
             let deadline = Instant::now() + timeout_duration;
 
-            let mut last_snapshot = String::new();
-            let mut stable_since = Instant::now();
-
-            loop {
-                if Instant::now() >= deadline {
-                    return Err("timeout waiting for condition".into());
-                }
-
-                let response = client
-                    .request(Request::Snapshot {
-                        id: id.clone(),
-                        strip_colors: true,
-                    })
-                    .await?;
-
-                let snapshot = match response {
-                    Response::Snapshot { content, .. } => content,
-                    Response::Error { message } => return Err(message.into()),
-                    _ => return Err("unexpected response".into()),
-                };
-
-                // Check conditions - all specified conditions must be met (AND logic)
-                let mut all_conditions_met = true;
-                let mut any_condition_specified = false;
-
-                // Check contains condition
-                if let Some(ref needle) = contains {
-                    any_condition_specified = true;
-                    if !snapshot.contains(needle) {
-                        all_conditions_met = false;
-                    }
+            // Every condition gets a name, so --any can report which one fired,
+            // and its command line position, so --sequence keeps their order
+            let mut conditions = Vec::new();
+            for (i, (name, text)) in matches.into_iter().enumerate() {
+                let position = order.position("matches", i);
+                conditions.push((position, name, WaitCondition::Contains(text)));
+            }
+            for (i, (name, pat)) in match_pattern.into_iter().enumerate() {
+                let position = order.position("match_pattern", i);
+                conditions.push((position, name, WaitCondition::Pattern(wait_regex(&pat)?)));
+            }
+            if let Some(text) = contains {
+                let position = order.position("contains", 0);
+                conditions.push((position, "contains".to_string(), WaitCondition::Contains(text)));
+            }
+            if let Some(pat) = pattern {
+                let condition = WaitCondition::Pattern(wait_regex(&pat)?);
+                conditions.push((order.position("pattern", 0), "pattern".to_string(), condition));
+            }
+            if prompt {
+                let position = order.position("prompt", 0);
+                conditions.push((position, "prompt".to_string(), WaitCondition::Prompt));
+            }
+            if let Some(stable_ms) = stable {
+                let stable = WaitCondition::Stable(Duration::from_millis(stable_ms));
+                conditions.push((order.position("stable", 0), "stable".to_string(), stable));
+            }
+            if exit || code.is_some() {
+                let position = order.position("exit", 0).min(order.position("code", 0));
+                conditions.push((position, "exit".to_string(), WaitCondition::Exit));
+            }
+            conditions.sort_by_key(|(position, _, _)| *position);
+            let conditions: Vec<(String, WaitCondition)> = conditions
+                .into_iter()
+                .map(|(_, name, condition)| (name, condition))
+                .collect();
+            for (i, (name, _)) in conditions.iter().enumerate() {
+                if conditions[..i].iter().any(|(other, _)| other == name) {
+                    return Err(format!("condition {name:?} is given more than once").into());
                 }
+            }
+            let check = describe_wait(&conditions, any, sequence, code);
+            let needs_info = conditions
+                .iter()
+                .any(|(_, c)| matches!(c, WaitCondition::Prompt | WaitCondition::Exit));
+
+            // The exit status to pass on, if the agent exited with an error
+            let outcome: Result<Option<i32>, Box<dyn std::error::Error>> = async {
+                let mut last_snapshot = String::new();
+                let mut stable_since = Instant::now();
+                // Conditions of a --sequence met so far
+                let mut step = 0;
 
-                // Check pattern condition
-                if let Some(ref pat) = pattern {
-                    any_condition_specified = true;
-                    // Limit pattern length to mitigate ReDoS
-                    if pat.len() > 1000 {
-                        return Err("regex pattern too long (max 1000 chars)".into());
-                    }
-                    let re = Regex::new(pat).map_err(|e| format!("invalid regex: {e}"))?;
-                    if !re.is_match(&snapshot) {
-                        all_conditions_met = false;
+                loop {
+                    if Instant::now() >= deadline {
+                        if sequence && let Some((name, _)) = conditions.get(step) {
+                            let total = conditions.len();
+                            return Err(format!(
+                                "timeout waiting for condition {name:?} (step {} of {total})",
+                                step + 1
+                            )
+                            .into());
+                        }
+                        return Err("timeout waiting for condition".into());
                     }
-                }
 
-                // Check stable condition (always track stability)
-                let is_stable = if let Some(stable_ms) = stable {
-                    any_condition_specified = true;
-                    let stable_duration = Duration::from_millis(stable_ms);
-                    if snapshot == last_snapshot {
-                        stable_since.elapsed() >= stable_duration
+                    let snapshot = snapshot_content(&mut client, &id, area.region(), false, &[]).await?;
+                    let info = if needs_info {
+                        Some(client.agent(&id).await?)
                     } else {
-                        stable_since = Instant::now();
-                        false
+                        None
+                    };
+                    let exited = info
+                        .as_ref()
+                        .filter(|info| matches!(info.state, botty::AgentState::Exited));
+
+                    if let (Some(want), Some(info)) = (code, exited)
+                        && info.exit_code != Some(want)
+                    {
+                        let got = info
+                            .exit_code
+                            .map_or_else(|| "no code".to_string(), |c| format!("code {c}"));
+                        return Err(format!("agent exited with {got}, expected {want}").into());
                     }
-                } else {
-                    // Update stability tracking even if not checking for it
+
+                    // Always track stability, so it's measured from the last change
                     if snapshot != last_snapshot {
                         stable_since = Instant::now();
                     }
-                    true // Not checking stability, so treat as satisfied
-                };
-
-                if !is_stable {
-                    all_conditions_met = false;
-                }
-
-                // If no conditions specified, wait for any output change
-                if !any_condition_specified {
-                    all_conditions_met = !snapshot.is_empty() && snapshot != last_snapshot;
-                }
+                    let results: Vec<bool> = conditions
+                        .iter()
+                        .map(|(_, condition)| match condition {
+                            WaitCondition::Contains(text) => snapshot.contains(text.as_str()),
+                            WaitCondition::Pattern(re) => re.is_match(&snapshot),
+                            // Exact, from OSC 133 shell integration markers
+                            WaitCondition::Prompt => info
+                                .as_ref()
+                                .and_then(|info| info.shell)
+                                .is_some_and(|shell| shell.at_prompt),
+                            WaitCondition::Stable(duration) => {
+                                snapshot == last_snapshot && stable_since.elapsed() >= *duration
+                            }
+                            WaitCondition::Exit => exited.is_some(),
+                        })
+                        .collect();
+
+                    let met = if conditions.is_empty() {
+                        // If no conditions specified, wait for any output change
+                        !snapshot.is_empty() && snapshot != last_snapshot
+                    } else if any {
+                        results.contains(&true)
+                    } else if sequence {
+                        step = sequence_step(&conditions, &results, &snapshot, step);
+                        step == conditions.len()
+                    } else {
+                        !results.contains(&false)
+                    };
 
-                if all_conditions_met {
-                    if print {
-                        println!("{snapshot}");
+                    if met {
+                        // Earlier steps of a sequence may have scrolled away since
+                        let matched: Vec<&str> = conditions
+                            .iter()
+                            .zip(&results)
+                            .filter(|&(_, &result)| result || sequence)
+                            .map(|((name, _), _)| name.as_str())
+                            .collect();
+                        let exit_code = exited.and_then(|info| info.exit_code);
+                        if json {
+                            let mut result = serde_json::json!({
+                                "id": id,
+                                "matched": matched,
+                                "exit_code": exit_code,
+                            });
+                            if print {
+                                result["snapshot"] = serde_json::json!(snapshot);
+                            }
+                            println!("{}", serde_json::to_string(&result)?);
+                        } else {
+                            if any && let Some(name) = matched.first() {
+                                println!("{name}");
+                            }
+                            if print {
+                                println!("{snapshot}");
+                            }
+                        }
+                        // Pass the agent's own exit status on, so scripts can
+                        // `botty wait --exit` in place of running the command
+                        if code.is_none()
+                            && let Some(status) = exit_code
+                            && status != 0
+                        {
+                            return Ok(Some(status));
+                        }
+                        break;
                     }
-                    break;
-                }
 
-                last_snapshot = snapshot;
-                tokio::time::sleep(poll_interval).await;
+                    last_snapshot = snapshot;
+                    tokio::time::sleep(poll_interval).await;
+                }
+                Ok(None)
+            }
+            .await;
+            // Only the message, so no error that isn't Send is held across an await
+            let outcome = outcome.map_err(|e| e.to_string());
+
+            if let Some(report) = report {
+                let failure = match &outcome {
+                    Ok(None) => None,
+                    Ok(Some(status)) => Some(format!("agent exited with code {status}")),
+                    Err(e) => Some(e.clone()),
+                };
+                record_check(&mut client, &report, &id, check, started, failure, area.region())
+                    .await?;
+            }
+            if let Some(status) = outcome? {
+                std::process::exit(status);
             }
         }
 
@@ -831,62 +985,114 @@ async fn run_client(
             not_contains,
             pattern,
             timeout,
+            area,
+            report,
         } => {
             use regex::Regex;
             use std::time::{Duration, Instant};
 
-            let timeout_duration = Duration::from_secs(timeout);
-            let poll_interval = Duration::from_millis(50);
-            let deadline = if timeout > 0 {
-                Some(Instant::now() + timeout_duration)
-            } else {
-                None
-            };
+            let report = report.report()?;
+            let started = Instant::now();
+            let mut described = Vec::new();
+            if let Some(needle) = &contains {
+                described.push(format!("contains {needle:?}"));
+            }
+            if let Some(needle) = &not_contains {
+                described.push(format!("not-contains {needle:?}"));
+            }
+            if let Some(pat) = &pattern {
+                described.push(format!("pattern {pat:?}"));
+            }
+            let check = format!("assert {}", described.join(" and ")).trim_end().to_string();
+
+            // The reason the assertion failed and the screen at the time
+            let outcome: Result<Option<(String, String)>, Box<dyn std::error::Error>> = async {
+                let timeout_duration = Duration::from_secs(timeout);
+                let poll_interval = Duration::from_millis(50);
+                let deadline = if timeout > 0 {
+                    Some(Instant::now() + timeout_duration)
+                } else {
+                    None
+                };
 
-            let response = client
-                .request(Request::Snapshot {
-                    id: id.clone(),
-                    strip_colors: true,
-                })
-                .await?;
+                let region = area.region();
+                let mut snapshot = snapshot_content(&mut client, &id, region, false, &[]).await?;
 
-            let mut snapshot = match response {
-                Response::Snapshot { content, .. } => content,
-                Response::Error { message } => return Err(message.into()),
-                _ => return Err("unexpected response".into()),
-            };
+                // If timeout specified, poll until conditions met or timeout
+                if let Some(deadline_time) = deadline {
+                    loop {
+                        // Check all conditions
+                        let mut all_passed = true;
+                        let mut failure_reason = String::new();
 
-            // If timeout specified, poll until conditions met or timeout
-            if let Some(deadline_time) = deadline {
-                loop {
-                    // Check all conditions
+                        // Check contains
+                        if let Some(ref needle) = contains
+                            && !snapshot.contains(needle) {
+                                all_passed = false;
+                                failure_reason = format!("expected output to contain: {needle:?}");
+                            }
+
+                        // Check not_contains
+                        if all_passed
+                            && let Some(ref needle) = not_contains
+                                && snapshot.contains(needle) {
+                                    all_passed = false;
+                                    failure_reason = format!("expected output NOT to contain: {needle:?}");
+                                }
+
+                        // Check pattern
+                        if all_passed
+                            && let Some(ref pat) = pattern {
+                                // Limit pattern length to mitigate ReDoS
+                                if pat.len() > MAX_PATTERN_LEN {
+                                    return Err(format!("regex pattern too long (max {MAX_PATTERN_LEN} chars)").into());
+                                }
+                                let re = Regex::new(pat).map_err(|e| format!("invalid regex: {e}"))?;
+                                if !re.is_match(&snapshot) {
+                                    all_passed = false;
+                                    failure_reason = format!("expected output to match pattern: {pat:?}");
+                                }
+                            }
+
+                        if all_passed {
+                            return Ok(None);
+                        }
+
+                        if Instant::now() >= deadline_time {
+                            return Ok(Some((failure_reason, snapshot)));
+                        }
+
+                        tokio::time::sleep(poll_interval).await;
+
+                        // Get new snapshot
+                        snapshot = snapshot_content(&mut client, &id, region, false, &[]).await?;
+                    }
+                } else {
+                    // No timeout - check immediately
                     let mut all_passed = true;
                     let mut failure_reason = String::new();
 
                     // Check contains
-                    if let Some(ref needle) = contains {
-                        if !snapshot.contains(needle) {
+                    if let Some(ref needle) = contains
+                        && !snapshot.contains(needle) {
                             all_passed = false;
                             failure_reason = format!("expected output to contain: {needle:?}");
                         }
-                    }
 
                     // Check not_contains
-                    if all_passed {
-                        if let Some(ref needle) = not_contains {
-                            if snapshot.contains(needle) {
+                    if all_passed
+                        && let Some(ref needle) = not_contains
+                            && snapshot.contains(needle) {
                                 all_passed = false;
                                 failure_reason = format!("expected output NOT to contain: {needle:?}");
                             }
-                        }
-                    }
 
                     // Check pattern
-                    if all_passed {
-                        if let Some(ref pat) = pattern {
+                    if all_passed
+                        && let Some(ref pat) = pattern {
                             // Limit pattern length to mitigate ReDoS
-                            if pat.len() > 1000 {
-                                return Err("regex pattern too long (max 1000 chars)".into());
+                            if pat.len() > MAX_PATTERN_LEN {
+                                return Err(format!("regex pattern too long (max {MAX_PATTERN_LEN} chars)").into());
                             }
                             let re = Regex::new(pat).map_err(|e| format!("invalid regex: {e}"))?;
                             if !re.is_match(&snapshot) {
@@ -894,96 +1100,37 @@ async fn run_client(
                                 failure_reason = format!("expected output to match pattern: {pat:?}");
                             }
                         }
-                    }
-
-                    if all_passed {
-                        return Ok(());
-                    }
-
-                    if Instant::now() >= deadline_time {
-                        eprintln!("Assertion failed: {failure_reason}");
-                        eprintln!("\nActual output:");
-                        eprintln!("{snapshot}");
-                        std::process::exit(1);
-                    }
-
-                    tokio::time::sleep(poll_interval).await;
-
-                    // Get new snapshot
-                    let response = client
-                        .request(Request::Snapshot {
-                            id: id.clone(),
-                            strip_colors: true,
-                        })
-                        .await?;
-
-                    snapshot = match response {
-                        Response::Snapshot { content, .. } => content,
-                        Response::Error { message } => return Err(message.into()),
-                        _ => return Err("unexpected response".into()),
-                    };
-                }
-            } else {
-                // No timeout - check immediately
-                let mut all_passed = true;
-                let mut failure_reason = String::new();
-
-                // Check contains
-                if let Some(ref needle) = contains {
-                    if !snapshot.contains(needle) {
-                        all_passed = false;
-                        failure_reason = format!("expected output to contain: {needle:?}");
-                    }
-                }
 
-                // Check not_contains
-                if all_passed {
-                    if let Some(ref needle) = not_contains {
-                        if snapshot.contains(needle) {
-                            all_passed = false;
-                            failure_reason = format!("expected output NOT to contain: {needle:?}");
-                        }
+                    if !all_passed {
+                        return Ok(Some((failure_reason, snapshot)));
                     }
                 }
-
-                // Check pattern
-                if all_passed {
-                    if let Some(ref pat) = pattern {
-                        // Limit pattern length to mitigate ReDoS
-                        if pat.len() > 1000 {
-                            return Err("regex pattern too long (max 1000 chars)".into());
-                        }
-                        let re = Regex::new(pat).map_err(|e| format!("invalid regex: {e}"))?;
-                        if !re.is_match(&snapshot) {
-                            all_passed = false;
-                            failure_reason = format!("expected output to match pattern: {pat:?}");
-                        }
-                    }
-                }
-
-                if !all_passed {
-                    eprintln!("Assertion failed: {failure_reason}");
-                    eprintln!("\nActual output:");
-                    eprintln!("{snapshot}");
-                    std::process::exit(1);
-                }
+                Ok(None)
+            }
+            .await;
+            // Only the message, so no error that isn't Send is held across an await
+            let outcome = outcome.map_err(|e| e.to_string());
+
+            if let Some(report) = report {
+                let failure = match &outcome {
+                    Ok(None) => None,
+                    Ok(Some((reason, _))) => Some(reason.clone()),
+                    Err(e) => Some(e.clone()),
+                };
+                record_check(&mut client, &report, &id, check, started, failure, area.region())
+                    .await?;
+            }
+            if let Some((failure_reason, snapshot)) = outcome? {
+                eprintln!("Assertion failed: {failure_reason}");
+                eprintln!("\nActual output:");
+                eprintln!("{snapshot}");
+                std::process::exit(1);
             }
         }
 
         Command::Shutdown => {
-            let response = client.request(Request::Shutdown).await?;
-
-            match response {
-                Response::Ok => {
-                    println!("Server shutting down");
-                }
-                Response::Error { message } => {
-                    return Err(message.into());
-                }
-                _ => {
-                    return Err("unexpected response".into());
-                }
-            }
+            client.shutdown().await?;
+            println!("Server shutting down");
         }
 
         Command::Exec {
@@ -991,168 +1138,297 @@ async fn run_client(
             cols,
             timeout,
             shell,
+            max_output,
+            json,
             cmd,
         } => {
-            use std::time::{Duration, Instant};
-
-            // Build the command string
-            let cmd_str = cmd.join(" ");
-
-            // Spawn a shell
-            let request = Request::Spawn {
-                cmd: vec![shell.clone()],
-                rows,
-                cols,
-                name: None,
-                labels: vec![],
-                timeout: None,
-                max_output: None,
-                env: vec![],
-                env_clear: false,
-            };
-            let response = client.request(request).await?;
-
-            let agent_id = match response {
-                Response::Spawned { id, .. } => id,
-                Response::Error { message } => return Err(message.into()),
-                _ => return Err("unexpected response".into()),
-            };
-
-            // Give shell time to start
-            tokio::time::sleep(Duration::from_millis(100)).await;
-
-            // Send the command with a unique marker for detecting completion
-            // The marker includes the exit code: __BOTTY_DONE_<pid>_<exitcode>__
-            let marker_prefix = format!("__BOTTY_DONE_{}_", std::process::id());
-            let full_cmd = format!("{cmd_str}; echo {marker_prefix}$?__\n");
-
-            let send_response = client
-                .request(Request::Send {
-                    id: agent_id.clone(),
-                    data: full_cmd,
-                    newline: false, // Already has newline
+            use futures::StreamExt;
+            use std::io::Write;
+            use std::time::Duration;
+
+            // Run the command as the agent's own process, so its exit status
+            // comes from the process itself rather than from the screen
+            let mut options = SpawnOptions::new([shell, "-c".to_string(), cmd.join(" ")]);
+            options.rows = rows;
+            options.cols = cols;
+            options.max_output = Some(max_output);
+            let agent_id = client.spawn(options).await?;
+
+            // Replay retained events too, so an exit before the subscription
+            // started isn't missed; if those were lost, ask for the state instead
+            let exits = client
+                .event_stream(EventOptions {
+                    filter: vec![agent_id.clone()],
+                    event_types: vec!["agent_exited".to_string()],
+                    since_seq: Some(0),
+                    ..EventOptions::default()
                 })
                 .await?;
-
-            if let Response::Error { message } = send_response {
-                // Kill the agent before returning error
-                let _ = client
-                    .request(Request::Kill {
-                        id: Some(agent_id),
-                        labels: vec![],
-                        all: false,
-                        signal: 9,
-                        proc_filter: None,
-                    })
-                    .await;
-                return Err(message.into());
-            }
-
-            // Wait for the marker to appear
-            let timeout_duration = Duration::from_secs(timeout);
-            let poll_interval = Duration::from_millis(50);
-            let deadline = Instant::now() + timeout_duration;
-
-            let mut output = String::new();
-            loop {
-                if Instant::now() >= deadline {
-                    // Kill the agent and return timeout error
-                    let _ = client
-                        .request(Request::Kill {
-                            id: Some(agent_id),
-                            labels: vec![],
-                            all: false,
-                            signal: 9,
-                            proc_filter: None,
-                        })
-                        .await;
+            let mut exits = std::pin::pin!(exits);
+
+            let exited = async {
+                while let Some(message) = exits.next().await {
+                    match message.map_err(|e| e.to_string())? {
+                        EventMessage::Event { .. } => return Ok(()),
+                        EventMessage::Lagged { .. } => {
+                            let info = client.agent(&agent_id).await.map_err(|e| e.to_string())?;
+                            if info.state == botty::AgentState::Exited {
+                                return Ok(());
+                            }
+                        }
+                    }
+                }
+                Err("server closed connection while waiting for the command".to_string())
+            };
+            match tokio::time::timeout(Duration::from_secs(timeout), exited).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    let _ = client.kill(&agent_id, 9).await;
+                    return Err(e.into());
+                }
+                Err(_) => {
+                    // Kill the agent, then forget it once it has exited
+                    let _ = client.kill(&agent_id, 9).await;
+                    let exited = async {
+                        while let Some(Ok(message)) = exits.next().await {
+                            if matches!(message, EventMessage::Event { .. }) {
+                                break;
+                            }
+                        }
+                    };
+                    let _ = tokio::time::timeout(Duration::from_secs(1), exited).await;
+                    let _ = client.remove(&agent_id).await;
                     return Err("timeout waiting for command completion".into());
                 }
+            }
 
-                let response = client
-                    .request(Request::Snapshot {
-                        id: agent_id.clone(),
-                        strip_colors: true,
-                    })
-                    .await?;
+            let info = client.agent(&agent_id).await?;
+            // The PTY translates \n to \r\n; undo that and drop escape sequences
+            let raw = client.transcript(&agent_id).await;
+            client.remove(&agent_id).await?;
+            let output = String::from_utf8_lossy(&strip_ansi_escapes::strip(&raw?))
+                .replace("\r\n", "\n");
+            let truncated = info.output.dropped > 0;
+            // Deaths by signal are already reported as 128 + signal
+            let exit_code = info
+                .exit_code
+                .ok_or("command exited without an exit status")?;
+
+            if json {
+                let duration_ms = info.exited_at.map(|t| t.saturating_sub(info.started_at));
+                let result = serde_json::json!({
+                    "id": agent_id,
+                    "exit_code": exit_code,
+                    "output": output,
+                    "truncated": truncated,
+                    "started_at": info.started_at,
+                    "exited_at": info.exited_at,
+                    "duration_ms": duration_ms,
+                });
+                println!("{}", serde_json::to_string(&result)?);
+            } else {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(output.as_bytes())?;
+                stdout.flush()?;
+            }
+            if truncated {
+                eprintln!(
+                    "warning: output exceeded --max-output; the first {} of {} bytes were dropped",
+                    info.output.dropped, info.output.written
+                );
+            }
 
-                let snapshot = match response {
-                    Response::Snapshot { content, .. } => content,
-                    Response::Error { message } => {
-                        // Agent may have exited
-                        return Err(message.into());
-                    }
-                    _ => return Err("unexpected response".into()),
-                };
+            if exit_code != 0 {
+                std::process::exit(exit_code);
+            }
+        }
 
-                // Look for marker at the start of a line (not in command echo)
-                // Format: \n__BOTTY_DONE_<pid>_<exitcode>__
-                let marker_pattern = format!("\n{marker_prefix}");
-                if let Some(marker_start) = snapshot.find(&marker_pattern) {
-                    // Extract output between the command echo and the marker
-                    let before_marker = &snapshot[..marker_start];
-                    let lines: Vec<&str> = before_marker.lines().collect();
-
-                    // Skip the first line (command echo), take the rest as output
-                    if lines.len() > 1 {
-                        let output_lines: Vec<&str> = lines
-                            .iter()
-                            .skip(1) // Skip command echo
-                            .copied()
-                            .collect();
-                        output = output_lines.join("\n");
-                    }
+        Command::Script {
+            file,
+            agent,
+            keep,
+            report: report_args,
+        } => {
+            let ci_report = report_args.report()?;
+            let mut script = Script::from_file(&file)?;
+            if agent.is_some() {
+                script.agent = agent;
+            }
 
-                    // Extract exit code from marker
-                    let after_marker = &snapshot[marker_start + 1..]; // Skip the \n
-                    if let Some(exit_code_start) = after_marker.find(&marker_prefix) {
-                        let code_start = exit_code_start + marker_prefix.len();
-                        if let Some(code_end) = after_marker[code_start..].find("__") {
-                            let code_str = &after_marker[code_start..code_start + code_end];
-                            if let Ok(code) = code_str.parse::<i32>()
-                                && code != 0 {
-                                    // Kill agent, print output, then exit with the command's exit code
-                                    let _ = client
-                                        .request(Request::Kill {
-                                            id: Some(agent_id.clone()),
-                                            labels: vec![],
-                                            all: false,
-                                            signal: 9,
-                                            proc_filter: None,
-                                        })
-                                        .await;
-                                    if !output.is_empty() {
-                                        println!("{output}");
-                                    }
-                                    std::process::exit(code);
-                                }
-                        }
+            let report = ScriptRunner::new(&mut client).run(&script).await;
+
+            if !keep {
+                for id in &report.spawned {
+                    let _ = client.kill(id, 9).await;
+                }
+                // Wait briefly for the kills to land so the agents are gone when we return
+                let deadline = std::time::Instant::now() + std::time::Duration::from_secs(2);
+                for id in &report.spawned {
+                    while std::time::Instant::now() < deadline
+                        && client
+                            .agent(id)
+                            .await
+                            .is_ok_and(|a| a.state == botty::AgentState::Running)
+                    {
+                        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
                     }
-                    break;
                 }
+            }
 
-                tokio::time::sleep(poll_interval).await;
+            if let Some(ci_report) = ci_report {
+                ci_report.append(&report.checks(&file.display().to_string()))?;
             }
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            if !report.passed {
+                std::process::exit(1);
+            }
+        }
+    }
 
-            // Kill the agent
-            let _ = client
-                .request(Request::Kill {
-                    id: Some(agent_id),
-                    labels: vec![],
-                    all: false,
-                    signal: 9,
-                    proc_filter: None,
-                })
-                .await;
+    Ok(())
+}
+
+/// Get an agent's screen, or only part of it, as text (with colors if `raw`),
+/// with volatile content redacted.
+async fn snapshot_content(
+    client: &mut Client,
+    id: &str,
+    region: Option<Region>,
+    raw: bool,
+    redact: &[Redaction],
+) -> Result<String, ClientError> {
+    let snapshot = client.snapshot_redacted(id, region, !raw, redact).await?;
+    Ok(snapshot.content)
+}
 
-            // Print the output
-            if !output.is_empty() {
-                println!("{output}");
+/// Append a check's result to the report, with the agent's screen if it failed.
+async fn record_check(
+    client: &mut Client,
+    report: &Report,
+    id: &str,
+    name: String,
+    started: std::time::Instant,
+    failure: Option<String>,
+    region: Option<Region>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let snapshot = if failure.is_some() {
+        snapshot_content(client, id, region, false, &[]).await.ok()
+    } else {
+        None
+    };
+    report.append(&[CheckResult {
+        name,
+        agent: Some(id.to_string()),
+        passed: failure.is_none(),
+        elapsed: started.elapsed(),
+        message: failure,
+        snapshot,
+    }])?;
+    Ok(())
+}
+
+/// Describe a `wait`'s conditions for a report, e.g. `wait contains "ready"`.
+fn describe_wait(
+    conditions: &[(String, WaitCondition)],
+    any: bool,
+    sequence: bool,
+    code: Option<i32>,
+) -> String {
+    let described: Vec<String> = conditions
+        .iter()
+        .map(|(name, condition)| {
+            let description = match condition {
+                WaitCondition::Contains(text) => format!("contains {text:?}"),
+                WaitCondition::Pattern(re) => format!("pattern {:?}", re.as_str()),
+                WaitCondition::Prompt => "prompt".to_string(),
+                WaitCondition::Stable(duration) => format!("stable {}ms", duration.as_millis()),
+                WaitCondition::Exit => "exit".to_string(),
+            };
+            if description.starts_with(name.as_str()) {
+                description
+            } else {
+                format!("{name} ({description})")
             }
+        })
+        .collect();
+    let joiner = if any {
+        " or "
+    } else if sequence {
+        " then "
+    } else {
+        " and "
+    };
+    let mut check = if described.is_empty() {
+        "wait for output".to_string()
+    } else {
+        format!("wait {}", described.join(joiner))
+    };
+    if let Some(code) = code {
+        use std::fmt::Write as _;
+        let _ = write!(check, " with code {code}");
+    }
+    check
+}
+
+/// How many conditions of a `wait --sequence` are met, given `step` met
+/// before this screen.
+///
+/// Consecutive content conditions are ordered by where they match: each must
+/// match after the end of the one before it, so output that arrives all at
+/// once still meets them in order but output in the wrong order doesn't. The
+/// earlier steps are found again on each screen; one that has scrolled away
+/// no longer constrains the next.
+fn sequence_step(
+    conditions: &[(String, WaitCondition)],
+    results: &[bool],
+    snapshot: &str,
+    mut step: usize,
+) -> usize {
+    let is_content =
+        |i: usize| matches!(conditions[i].1, WaitCondition::Contains(_) | WaitCondition::Pattern(_));
+    // Where the run of content conditions leading up to this step starts
+    let run_start = (0..step).rev().find(|&i| !is_content(i)).map_or(0, |i| i + 1);
+    let mut from = 0;
+    for i in run_start..conditions.len() {
+        let end = match &conditions[i].1 {
+            WaitCondition::Contains(text) => snapshot
+                .get(from..)
+                .and_then(|rest| rest.find(text.as_str()))
+                .map(|start| from + start + text.len()),
+            WaitCondition::Pattern(re) => re.find_at(snapshot, from).map(|m| m.end()),
+            _ if results[i] => Some(0),
+            _ => None,
+        };
+        match end {
+            Some(end) => from = end,
+            None if i < step => from = 0,
+            None => break,
         }
+        step = step.max(i + 1);
     }
+    step
+}
 
-    Ok(())
+/// One condition of a `wait`.
+enum WaitCondition {
+    /// The screen contains the text.
+    Contains(String),
+    /// The screen matches the regex.
+    Pattern(regex::Regex),
+    /// The shell is at a prompt (OSC 133).
+    Prompt,
+    /// The screen hasn't changed for this long.
+    Stable(std::time::Duration),
+    /// The agent has exited.
+    Exit,
+}
+
+/// Compile a `wait` regex, limiting its length to guard against slow regexes.
+fn wait_regex(pattern: &str) -> Result<regex::Regex, String> {
+    if pattern.len() > MAX_PATTERN_LEN {
+        return Err(format!("regex pattern too long (max {MAX_PATTERN_LEN} chars)"));
+    }
+    regex::Regex::new(pattern).map_err(|e| format!("invalid regex: {e}"))
 }
 
 async fn run_attach_command(
@@ -1224,52 +1500,19 @@ async fn run_attach_command(
 
 async fn run_events_command(
     socket_path: std::path::PathBuf,
-    filter: Vec<String>,
-    include_output: bool,
+    options: EventOptions,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-    use tokio::net::UnixStream;
-
-    // Connect to the server (don't auto-start - events are useless with no agents)
-    let stream = UnixStream::connect(&socket_path).await?;
-    let (reader, mut writer) = stream.into_split();
-    let mut reader = BufReader::new(reader);
-
-    // Send events request
-    let request = Request::Events {
-        filter,
-        include_output,
-    };
-    let mut json = serde_json::to_string(&request)?;
-    json.push('\n');
-    writer.write_all(json.as_bytes()).await?;
+    use futures::StreamExt;
 
-    // Stream events to stdout
-    let mut line = String::new();
-    loop {
-        line.clear();
-        let n = reader.read_line(&mut line).await?;
-        if n == 0 {
-            // Server disconnected
-            break;
-        }
+    // Events are useless with no agents, so the stream doesn't auto-start the server
+    let client = Client::new(socket_path);
+    let events = client.event_stream(options).await?;
+    let mut events = std::pin::pin!(events);
 
-        // Parse and re-emit just the event (strip Response wrapper)
-        if let Ok(response) = serde_json::from_str::<Response>(&line) {
-            match response {
-                Response::Event(event) => {
-                    // Output the event as JSON (newline-delimited)
-                    let event_json = serde_json::to_string(&event)?;
-                    println!("{event_json}");
-                }
-                Response::Error { message } => {
-                    return Err(message.into());
-                }
-                _ => {
-                    // Ignore other responses
-                }
-            }
-        }
+    // Stream events to stdout as newline-delimited JSON, in wire format so
+    // sequence numbers and lag notices are visible
+    while let Some(message) = events.next().await {
+        println!("{}", serde_json::to_string(&Response::from(message?))?);
     }
 
     Ok(())
@@ -1279,138 +1522,106 @@ async fn run_subscribe_command(
     socket_path: std::path::PathBuf,
     ids: Vec<String>,
     labels: Vec<String>,
+    selector: Option<String>,
     prefix: bool,
     format: String,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    use botty::protocol::Event;
-    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-    use tokio::net::UnixStream;
+    use futures::StreamExt;
 
     // Must specify at least one filter
-    if ids.is_empty() && labels.is_empty() {
-        return Err("must specify at least one --id or --label to subscribe to".into());
+    if ids.is_empty() && labels.is_empty() && selector.is_none() {
+        return Err("must specify at least one --id, --label or --selector to subscribe to".into());
     }
 
     // Connect to server (don't auto-start - subscriptions are useless with no agents)
-    let stream = UnixStream::connect(&socket_path).await?;
-    let (reader, mut writer) = stream.into_split();
-    let mut reader = BufReader::new(reader);
-
-    // If we have labels, first get the list of matching agent IDs
-    // Then subscribe to events for those specific IDs
-    let mut filter_ids = ids.clone();
-    
-    if !labels.is_empty() {
-        // Get current agents matching the labels
-        let list_request = Request::List { labels: labels.clone() };
-        let mut json = serde_json::to_string(&list_request)?;
-        json.push('\n');
-        writer.write_all(json.as_bytes()).await?;
-        
-        let mut line = String::new();
-        reader.read_line(&mut line).await?;
-        
-        match serde_json::from_str::<Response>(&line)? {
-            Response::Agents { agents } => {
-                for agent in agents {
-                    if !filter_ids.contains(&agent.id) {
-                        filter_ids.push(agent.id);
-                    }
-                }
+    let mut client = Client::new(socket_path);
+    client.connect_existing().await?;
+
+    // Labels and selectors are matched by the server as events arrive, so
+    // agents spawned later that match are picked up. Subscribing by ID alone
+    // ends once all the agents have exited.
+    let follow_new = !labels.is_empty() || selector.is_some();
+    let mut watching = ids.clone();
+    if follow_new {
+        let agents = client
+            .list_matching(&labels, &BTreeMap::new(), selector.as_deref())
+            .await?;
+        for agent in agents {
+            if !watching.contains(&agent.id) {
+                watching.push(agent.id);
             }
-            Response::Error { message } => return Err(message.into()),
-            _ => return Err("unexpected response to list".into()),
         }
-        line.clear();
     }
 
     // Subscribe to events (include output, filter to our agents)
-    let request = Request::Events {
-        filter: filter_ids.clone(),
-        include_output: true,
-    };
-    let mut json = serde_json::to_string(&request)?;
-    json.push('\n');
-    writer.write_all(json.as_bytes()).await?;
+    let events = client
+        .events(EventOptions {
+            filter: ids,
+            include_output: true,
+            labels,
+            selector,
+            ..EventOptions::default()
+        })
+        .await?;
+    let mut events = std::pin::pin!(events);
 
     // Process events
-    let mut line = String::new();
     let jsonl_format = format == "jsonl";
     
-    loop {
-        line.clear();
-        let n = reader.read_line(&mut line).await?;
-        if n == 0 {
-            // Server disconnected
-            break;
-        }
-
-        if let Ok(response) = serde_json::from_str::<Response>(&line) {
-            match response {
-                Response::Event(Event::AgentOutput { id, data }) => {
-                    if jsonl_format {
-                        // JSONL format: emit JSON object per output chunk
-                        let json_out = serde_json::json!({
-                            "agent": id,
-                            "data": base64::Engine::encode(
-                                &base64::engine::general_purpose::STANDARD,
-                                &data
-                            ),
-                        });
-                        println!("{}", serde_json::to_string(&json_out)?);
-                    } else if prefix {
-                        // Prefixed raw output: [agent-id] data
-                        // Split by newlines to prefix each line
-                        let text = String::from_utf8_lossy(&data);
-                        for chunk in text.split_inclusive('\n') {
-                            print!("[{id}] {chunk}");
-                        }
-                        std::io::Write::flush(&mut std::io::stdout())?;
-                    } else {
-                        // Raw output
-                        std::io::Write::write_all(&mut std::io::stdout(), &data)?;
-                        std::io::Write::flush(&mut std::io::stdout())?;
-                    }
-                }
-                Response::Event(Event::AgentSpawned { id, labels: agent_labels, .. }) => {
-                    // If we're filtering by labels and a new agent matches, add it to our filter
-                    if !labels.is_empty() && labels.iter().all(|l| agent_labels.contains(l)) {
-                        if !filter_ids.contains(&id) {
-                            filter_ids.push(id.clone());
-                            // Note: We can't dynamically update the filter on existing connection
-                            // The new agent will be picked up if we reconnect
-                            eprintln!("[subscribe] new agent matches labels: {}", id);
-                        }
+    while let Some(event) = events.next().await {
+        match event? {
+            Event::AgentOutput { id, data } => {
+                if jsonl_format {
+                    // JSONL format: emit JSON object per output chunk
+                    let json_out = serde_json::json!({
+                        "agent": id,
+                        "data": base64::Engine::encode(
+                            &base64::engine::general_purpose::STANDARD,
+                            &data
+                        ),
+                    });
+                    println!("{}", serde_json::to_string(&json_out)?);
+                } else if prefix {
+                    // Prefixed raw output: [agent-id] data
+                    // Split by newlines to prefix each line
+                    let text = String::from_utf8_lossy(&data);
+                    for chunk in text.split_inclusive('\n') {
+                        print!("[{id}] {chunk}");
                     }
+                    std::io::Write::flush(&mut std::io::stdout())?;
+                } else {
+                    // Raw output
+                    std::io::Write::write_all(&mut std::io::stdout(), &data)?;
+                    std::io::Write::flush(&mut std::io::stdout())?;
                 }
-                Response::Event(Event::AgentExited { id, exit_code }) => {
-                    if jsonl_format {
-                        let json_out = serde_json::json!({
-                            "agent": id,
-                            "event": "exited",
-                            "exit_code": exit_code,
-                        });
-                        println!("{}", serde_json::to_string(&json_out)?);
-                    } else if prefix {
-                        if let Some(code) = exit_code {
-                            eprintln!("[{id}] exited with code {code}");
-                        } else {
-                            eprintln!("[{id}] exited");
-                        }
-                    }
-                    // Remove from filter
-                    filter_ids.retain(|i| i != &id);
-                    
-                    // If no more agents to watch, exit
-                    if filter_ids.is_empty() {
-                        break;
+            }
+            Event::AgentSpawned { id, .. } if follow_new && !watching.contains(&id) => {
+                eprintln!("[subscribe] new agent matches labels: {id}");
+                watching.push(id);
+            }
+            Event::AgentExited { id, exit_code } => {
+                if jsonl_format {
+                    let json_out = serde_json::json!({
+                        "agent": id,
+                        "event": "exited",
+                        "exit_code": exit_code,
+                    });
+                    println!("{}", serde_json::to_string(&json_out)?);
+                } else if prefix {
+                    if let Some(code) = exit_code {
+                        eprintln!("[{id}] exited with code {code}");
+                    } else {
+                        eprintln!("[{id}] exited");
                     }
                 }
-                Response::Error { message } => {
-                    return Err(message.into());
+                watching.retain(|i| i != &id);
+
+                // If no more agents to watch, exit
+                if watching.is_empty() && !follow_new {
+                    break;
                 }
-                _ => {}
             }
+            _ => {}
         }
     }
 
@@ -1423,11 +1634,10 @@ async fn run_view_command(
     mode: String,
     auto_resize: bool,
     labels: Vec<String>,
+    selector: Option<String>,
     new_session: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     use botty::ViewMode;
-    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-    use tokio::net::UnixStream;
 
     // Only tmux is supported for now
     if mux != "tmux" {
@@ -1447,62 +1657,48 @@ async fn run_view_command(
     let mut view = TmuxView::with_mode(botty_path.clone(), view_mode);
 
     // Connect to server, auto-starting if necessary
-    let stream = match UnixStream::connect(&socket_path).await {
-        Ok(s) => s,
-        Err(e) => {
-            // Only auto-start for expected "not running" errors
-            use std::io::ErrorKind;
-            match e.kind() {
-                ErrorKind::NotFound | ErrorKind::ConnectionRefused => {
-                    // Server not running, start it
-                    tracing::info!("Starting server...");
-                    std::process::Command::new(&botty_path)
-                        .arg("server")
-                        .arg("--daemon")
-                        .spawn()?;
-                    
-                    // Wait for server to be ready (exponential backoff: 50ms → 500ms cap)
-                    let mut connected = None;
-                    let mut delay_ms = 50u64;
-                    for _ in 0..20 {
-                        tokio::time::sleep(std::time::Duration::from_millis(delay_ms)).await;
-                        if let Ok(s) = UnixStream::connect(&socket_path).await {
-                            connected = Some(s);
-                            break;
-                        }
-                        delay_ms = (delay_ms * 2).min(500);
+    let mut client = Client::new(socket_path.clone());
+    if let Err(ClientError::Connect(e)) = client.connect_existing().await {
+        // Only auto-start for expected "not running" errors
+        use std::io::ErrorKind;
+        match e.kind() {
+            ErrorKind::NotFound | ErrorKind::ConnectionRefused => {
+                // Server not running, start it
+                tracing::info!("Starting server...");
+                std::process::Command::new(&botty_path)
+                    .arg("server")
+                    .arg("--daemon")
+                    .spawn()?;
+                
+                // Wait for server to be ready (exponential backoff: 50ms → 500ms cap)
+                let mut connected = false;
+                let mut delay_ms = 50u64;
+                for _ in 0..20 {
+                    tokio::time::sleep(std::time::Duration::from_millis(delay_ms)).await;
+                    if client.connect_existing().await.is_ok() {
+                        connected = true;
+                        break;
                     }
-                    connected.ok_or_else(|| -> Box<dyn std::error::Error> { 
-                        "server did not start in time".into() 
-                    })?
+                    delay_ms = (delay_ms * 2).min(500);
                 }
-                _ => {
-                    // Real error (permission denied, etc.) - don't mask it
-                    return Err(e.into());
+                if !connected {
+                    return Err("server did not start in time".into());
                 }
             }
+            _ => {
+                // Real error (permission denied, etc.) - don't mask it
+                return Err(e.into());
+            }
         }
-    };
-    let (reader, mut writer) = stream.into_split();
-    let mut reader = BufReader::new(reader);
-
-    // Get the list of current agents (optionally filtered by labels)
-    let list_request = Request::List { labels: labels.clone() };
-    let mut json = serde_json::to_string(&list_request)?;
-    json.push('\n');
-    writer.write_all(json.as_bytes()).await?;
+    }
 
-    let mut line = String::new();
-    reader.read_line(&mut line).await?;
-    
-    let current_agents: Vec<botty::AgentInfo> = match serde_json::from_str::<Response>(&line)? {
-        Response::Agents { agents } => agents
-            .into_iter()
-            .filter(|a| a.state == botty::AgentState::Running)
-            .collect(),
-        Response::Error { message } => return Err(message.into()),
-        _ => return Err("unexpected response to list".into()),
-    };
+    // Get the list of current agents (optionally filtered by labels or selector)
+    let current_agents: Vec<botty::AgentInfo> = client
+        .list_matching(&labels, &BTreeMap::new(), selector.as_deref())
+        .await?
+        .into_iter()
+        .filter(|a| a.state == botty::AgentState::Running)
+        .collect();
     let current_agent_ids: Vec<String> = current_agents.iter().map(|a| a.id.clone()).collect();
 
     if view.session_exists() && !new_session {
@@ -1562,8 +1758,15 @@ async fn run_view_command(
     // Spawn a task to listen for events and manage panes
     let socket_path_clone = socket_path.clone();
     let existing_agents = current_agent_ids.clone();
+    let filter = EventOptions {
+        labels,
+        selector,
+        ..EventOptions::default()
+    };
     let event_handle = tokio::spawn(async move {
-        if let Err(e) = run_view_event_loop(socket_path_clone, existing_agents, view_mode).await {
+        if let Err(e) =
+            run_view_event_loop(socket_path_clone, existing_agents, view_mode, filter).await
+        {
             tracing::warn!("Event loop error: {}", e);
         }
     });
@@ -1586,27 +1789,25 @@ async fn run_view_command(
     // After detach, check if there are any running agents
     // If not, clean up the server and tmux session
     let mut client = Client::new(socket_path.clone());
-    let response = client.request(Request::List { labels: vec![] }).await?;
+    let agents = client.list(&[]).await?;
 
-    if let Response::Agents { agents } = response {
-        let running_count = agents
-            .iter()
-            .filter(|a| matches!(a.state, botty::AgentState::Running))
-            .count();
+    let running_count = agents
+        .iter()
+        .filter(|a| matches!(a.state, botty::AgentState::Running))
+        .count();
 
-        if running_count == 0 {
-            tracing::info!("No agents running after detach - shutting down server and cleaning up tmux session");
+    if running_count == 0 {
+        tracing::info!("No agents running after detach - shutting down server and cleaning up tmux session");
 
-            // Request server shutdown
-            let _ = client.request(Request::Shutdown).await;
+        // Request server shutdown
+        let _ = client.shutdown().await;
 
-            // Kill tmux session (hardcoded to "botty" for now - see bd-1tr for unique names)
-            let _ = std::process::Command::new("tmux")
-                .args(["kill-session", "-t", "botty"])
-                .status();
-        } else {
-            tracing::debug!("Agents still running after detach - leaving server and session active");
-        }
+        // Kill tmux session (hardcoded to "botty" for now - see bd-1tr for unique names)
+        let _ = std::process::Command::new("tmux")
+            .args(["kill-session", "-t", "botty"])
+            .status();
+    } else {
+        tracing::debug!("Agents still running after detach - leaving server and session active");
     }
 
     Ok(())
@@ -1617,14 +1818,9 @@ async fn run_view_event_loop(
     socket_path: std::path::PathBuf,
     existing_agents: Vec<String>,
     mode: botty::ViewMode,
+    filter: EventOptions,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    use botty::protocol::Event;
-    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-    use tokio::net::UnixStream;
-
-    let stream = UnixStream::connect(&socket_path).await?;
-    let (reader, mut writer) = stream.into_split();
-    let mut reader = BufReader::new(reader);
+    use futures::StreamExt;
 
     // Get botty path
     let botty_path = std::env::current_exe()
@@ -1637,61 +1833,40 @@ async fn run_view_event_loop(
         view.mark_pane_exists(&agent_id);
     }
 
-    // Subscribe to events (no output, just lifecycle)
-    let request = Request::Events {
-        filter: vec![],
-        include_output: false,
-    };
-    let mut json = serde_json::to_string(&request)?;
-    json.push('\n');
-    writer.write_all(json.as_bytes()).await?;
+    // Subscribe to events (no output, just lifecycle) for the agents we show
+    let events = Client::new(socket_path).events(filter).await?;
+    let mut events = std::pin::pin!(events);
 
     // Process events
-    let mut line = String::new();
-    loop {
-        line.clear();
-        let n = reader.read_line(&mut line).await?;
-        if n == 0 {
-            // Server disconnected
-            break;
-        }
-
-        if let Ok(response) = serde_json::from_str::<Response>(&line) {
-            match response {
-                Response::Event(Event::AgentSpawned { id, command, labels, .. }) => {
-                    let was_empty = view.is_empty();
-                    if let Err(e) = view.add_pane(&id) {
-                        tracing::warn!("Failed to add pane for {}: {}", id, e);
+    while let Some(event) = events.next().await {
+        match event? {
+            Event::AgentSpawned { id, command, labels, .. } => {
+                let was_empty = view.is_empty();
+                if let Err(e) = view.add_pane(&id) {
+                    tracing::warn!("Failed to add pane for {}: {}", id, e);
+                }
+                view.set_pane_metadata(&id, &command.join(" "), &labels);
+                // When transitioning from placeholder to first real pane,
+                // retile so it fills the window properly
+                if was_empty
+                    && let Err(e) = view.retile() {
+                        tracing::warn!("Failed to retile after placeholder transition: {}", e);
                     }
-                    view.set_pane_metadata(&id, &command.join(" "), &labels);
-                    // When transitioning from placeholder to first real pane,
-                    // retile so it fills the window properly
-                    if was_empty {
-                        if let Err(e) = view.retile() {
-                            tracing::warn!("Failed to retile after placeholder transition: {}", e);
-                        }
-                    }
-                }
-                Response::Event(Event::AgentExited { id, .. }) => {
-                    // Check if this is the last pane BEFORE removing
-                    // If so, show placeholder instead of killing the pane
-                    // (killing the last pane would destroy the session)
-                    if view.pane_count() == 1 {
-                        view.clear_pane_tracking();
-                        if let Err(e) = view.show_waiting_placeholder() {
-                            tracing::warn!("Failed to show placeholder: {}", e);
-                        }
-                    } else {
-                        if let Err(e) = view.remove_pane(&id) {
-                            tracing::warn!("Failed to remove pane for {}: {}", id, e);
-                        }
+            }
+            Event::AgentExited { id, .. } => {
+                // Check if this is the last pane BEFORE removing
+                // If so, show placeholder instead of killing the pane
+                // (killing the last pane would destroy the session)
+                if view.pane_count() == 1 {
+                    view.clear_pane_tracking();
+                    if let Err(e) = view.show_waiting_placeholder() {
+                        tracing::warn!("Failed to show placeholder: {}", e);
                     }
+                } else if let Err(e) = view.remove_pane(&id) {
+                    tracing::warn!("Failed to remove pane for {}: {}", id, e);
                 }
-                Response::Error { message } => {
-                    return Err(message.into());
-                }
-                _ => {}
             }
+            _ => {}
         }
     }
 
@@ -1707,18 +1882,16 @@ async fn wait_for_dependencies(
     after: &[String],
     wait_for: &[String],
 ) -> Result<(), Box<dyn std::error::Error>> {
-    use botty::protocol::Event;
+    use futures::StreamExt;
     use regex::Regex;
     use std::collections::{HashMap, HashSet};
-    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-    use tokio::net::UnixStream;
 
     // Parse wait_for specs into (agent_id, optional_pattern)
     let mut pattern_waits: HashMap<String, Option<Regex>> = HashMap::new();
     for spec in wait_for {
         if let Some((agent_id, pattern)) = spec.split_once(':') {
             let regex = Regex::new(pattern)
-                .map_err(|e| format!("invalid pattern '{}': {}", pattern, e))?;
+                .map_err(|e| format!("invalid pattern '{pattern}': {e}"))?;
             pattern_waits.insert(agent_id.to_string(), Some(regex));
         } else {
             // No pattern - wait for any output
@@ -1736,24 +1909,9 @@ async fn wait_for_dependencies(
     }
 
     // First, check current state - some agents may have already exited
-    let stream = UnixStream::connect(socket_path).await?;
-    let (reader, mut writer) = stream.into_split();
-    let mut reader = BufReader::new(reader);
-
-    // List current agents
-    let list_request = Request::List { labels: vec![] };
-    let mut json = serde_json::to_string(&list_request)?;
-    json.push('\n');
-    writer.write_all(json.as_bytes()).await?;
-
-    let mut line = String::new();
-    reader.read_line(&mut line).await?;
-
-    let agents: Vec<botty::AgentInfo> = match serde_json::from_str::<Response>(&line)? {
-        Response::Agents { agents } => agents,
-        Response::Error { message } => return Err(message.into()),
-        _ => return Err("unexpected response to list".into()),
-    };
+    let mut client = Client::new(socket_path.to_path_buf());
+    client.connect_existing().await?;
+    let agents = client.list(&[]).await?;
 
     // Check for already-exited agents in --after list
     for agent in &agents {
@@ -1767,12 +1925,12 @@ async fn wait_for_dependencies(
     let agent_ids: HashSet<_> = agents.iter().map(|a| a.id.as_str()).collect();
     for id in &waiting_for_exit {
         if !agent_ids.contains(id.as_str()) {
-            return Err(format!("--after: agent '{}' not found", id).into());
+            return Err(format!("--after: agent '{id}' not found").into());
         }
     }
     for id in waiting_for_pattern.keys() {
         if !agent_ids.contains(id.as_str()) {
-            return Err(format!("--wait-for: agent '{}' not found", id).into());
+            return Err(format!("--wait-for: agent '{id}' not found").into());
         }
     }
 
@@ -1781,54 +1939,40 @@ async fn wait_for_dependencies(
         return Ok(());
     }
 
-    // Subscribe to events to wait for remaining conditions
-    drop(reader);
-    drop(writer);
-
-    let stream = UnixStream::connect(socket_path).await?;
-    let (reader, mut writer) = stream.into_split();
-    let mut reader = BufReader::new(reader);
-
     // Subscribe to events with output (needed for pattern matching)
-    let events_request = Request::Events {
-        filter: vec![], // All agents
-        include_output: !waiting_for_pattern.is_empty(),
-    };
-    let mut json = serde_json::to_string(&events_request)?;
-    json.push('\n');
-    writer.write_all(json.as_bytes()).await?;
+    let events = client
+        .events(EventOptions {
+            filter: vec![], // All agents
+            include_output: !waiting_for_pattern.is_empty(),
+            ..EventOptions::default()
+        })
+        .await?;
+    let mut events = std::pin::pin!(events);
 
     // Wait for conditions
     loop {
-        let mut line = String::new();
-        if reader.read_line(&mut line).await? == 0 {
-            return Err("server closed connection while waiting for dependencies".into());
-        }
-
-        let response: Response = serde_json::from_str(&line)?;
-
-        match response {
-            Response::Event(Event::AgentExited { id, .. }) => {
-                if waiting_for_exit.remove(&id) {
+        let event = events
+            .next()
+            .await
+            .ok_or("server closed connection while waiting for dependencies")?
+            .map_err(|e| format!("error while waiting: {e}"))?;
+
+        match event {
+            Event::AgentExited { id, .. }
+                if waiting_for_exit.remove(&id) => {
                     tracing::debug!("Dependency satisfied: {} exited", id);
                 }
-            }
-            Response::Event(Event::AgentOutput { id, data }) => {
+            Event::AgentOutput { id, data } => {
                 if let Some(pattern_opt) = waiting_for_pattern.get(&id) {
                     let output = String::from_utf8_lossy(&data);
-                    let matched = match pattern_opt {
-                        Some(regex) => regex.is_match(&output),
-                        None => true, // Any output matches
-                    };
+                    // No pattern means any output matches
+                    let matched = pattern_opt.as_ref().is_none_or(|regex| regex.is_match(&output));
                     if matched {
                         tracing::debug!("Dependency satisfied: {} matched pattern", id);
                         waiting_for_pattern.remove(&id);
                     }
                 }
             }
-            Response::Error { message } => {
-                return Err(format!("error while waiting: {}", message).into());
-            }
             _ => {}
         }
 
@@ -1846,12 +1990,12 @@ fn setup_resize_hook(view: &TmuxView, mode: &str) -> Result<(), ViewError> {
     
     let botty_path = view.botty_path();
     let session_name = "botty";
-    let session_window = format!("{}:agents", session_name);
+    let session_window = format!("{session_name}:agents");
     
     // Hook command: call botty resize-panes when any pane is resized
     // The hook runs asynchronously (-b) so it won't block tmux
-    let hook_cmd = format!("{} resize-panes --mode={}", botty_path, mode);
-    let run_shell = format!("run-shell -b '{}'", hook_cmd);
+    let hook_cmd = format!("{botty_path} resize-panes --mode={mode}");
+    let run_shell = format!("run-shell -b '{hook_cmd}'");
     
     // Session-level hook: after-resize-pane (fires when individual panes are resized)
     let _ = Command::new("tmux")
@@ -1962,16 +2106,13 @@ fn setup_command_palette(view: &TmuxView) -> Result<(), ViewError> {
 
     // Register tmux command aliases (server-level, available from any session)
     let list_alias = format!(
-        "botty-list=display-popup -h 75% -w 80% -E '{} list --format text | less -R'",
-        botty_path
+        "botty-list=display-popup -h 75% -w 80% -E '{botty_path} list --format text | less -R'"
     );
     let snapshot_alias = format!(
-        "botty-snapshot=display-popup -h 75% -w 80% -E '{} snapshot --raw #{{@agent_id}} | less -R'",
-        botty_path
+        "botty-snapshot=display-popup -h 75% -w 80% -E '{botty_path} snapshot --raw #{{@agent_id}} | less -R'"
     );
     let shutdown_alias = format!(
-        "botty-shutdown=display-popup -E '{} shutdown && tmux detach-client'",
-        botty_path
+        "botty-shutdown=display-popup -E '{botty_path} shutdown && tmux detach-client'"
     );
 
     let aliases: &[(&str, &str)] = &[
@@ -1996,6 +2137,7 @@ fn setup_command_palette(view: &TmuxView) -> Result<(), ViewError> {
     }
 
     // Bind Ctrl+P scoped to botty session: shows menu in botty, passes through elsewhere
+    #[allow(clippy::literal_string_with_formatting_args)] // tmux format, not Rust
     let _ = Command::new("tmux")
         .args([
             "bind-key", "-T", "root", "C-p",
@@ -2023,42 +2165,25 @@ async fn resize_agents_to_panes(
     socket_path: &std::path::Path,
     view: &TmuxView,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-    use tokio::net::UnixStream;
 
     let pane_sizes = view.get_pane_sizes()?;
-    
+
     if pane_sizes.is_empty() {
         return Ok(());
     }
 
-    let stream = UnixStream::connect(socket_path).await?;
-    let (reader, mut writer) = stream.into_split();
-    let mut reader = BufReader::new(reader);
+    let mut client = Client::new(socket_path.to_path_buf());
+    client.connect_existing().await?;
 
     for (agent_id, (rows, cols)) in pane_sizes {
-        let request = Request::Resize {
-            id: agent_id.clone(),
-            rows,
-            cols,
-            clear_transcript: true, // Clear to avoid displaying old-size output
-        };
-        
-        let mut json = serde_json::to_string(&request)?;
-        json.push('\n');
-        writer.write_all(json.as_bytes()).await?;
-
-        let mut line = String::new();
-        reader.read_line(&mut line).await?;
-
-        match serde_json::from_str::<Response>(&line)? {
-            Response::Ok => {
+        // Clear to avoid displaying old-size output
+        match client.resize(&agent_id, rows, cols, true).await {
+            Ok(()) => {
                 tracing::debug!("Resized {} to {}x{} (cleared transcript)", agent_id, rows, cols);
             }
-            Response::Error { message } => {
-                tracing::warn!("Failed to resize {}: {}", agent_id, message);
+            Err(e) => {
+                tracing::warn!("Failed to resize {}: {}", agent_id, e);
             }
-            _ => {}
         }
     }
 
@@ -2071,8 +2196,6 @@ async fn run_resize_panes_command(
     mode: String,
 ) -> Result<(), Box<dyn std::error::Error>> {
     use botty::ViewMode;
-    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-    use tokio::net::UnixStream;
 
     let view_mode = ViewMode::from_str(&mode)?;
     
@@ -2084,28 +2207,17 @@ async fn run_resize_panes_command(
     let mut view = TmuxView::with_mode(botty_path.clone(), view_mode);
     
     // First, get the list of running agents to populate active_panes
-    let stream = UnixStream::connect(&socket_path).await?;
-    let (reader, mut writer) = stream.into_split();
-    let mut reader = BufReader::new(reader);
-
-    let list_request = Request::List { labels: vec![] };
-    let mut json = serde_json::to_string(&list_request)?;
-    json.push('\n');
-    writer.write_all(json.as_bytes()).await?;
-
-    let mut line = String::new();
-    reader.read_line(&mut line).await?;
+    let mut client = Client::new(socket_path);
+    client.connect_existing().await?;
 
     // Collect agent IDs and their PIDs for SIGWINCH
-    let agents: Vec<(String, u32)> = match serde_json::from_str::<Response>(&line)? {
-        Response::Agents { agents } => agents
-            .into_iter()
-            .filter(|a| a.state == botty::AgentState::Running)
-            .map(|a| (a.id, a.pid))
-            .collect(),
-        Response::Error { message } => return Err(message.into()),
-        _ => return Err("unexpected response to list".into()),
-    };
+    let agents: Vec<(String, u32)> = client
+        .list(&[])
+        .await?
+        .into_iter()
+        .filter(|a| a.state == botty::AgentState::Running)
+        .map(|a| (a.id, a.pid))
+        .collect();
 
     // Mark agents as having panes
     for (agent_id, _) in &agents {
@@ -2121,21 +2233,7 @@ async fn run_resize_panes_command(
     for (agent_id, (rows, cols)) in &pane_sizes {
         // Don't clear transcript - let the running tail continue and programs
         // will redraw themselves when they receive SIGWINCH from the resize
-        let request = Request::Resize {
-            id: agent_id.clone(),
-            rows: *rows,
-            cols: *cols,
-            clear_transcript: false,
-        };
-        
-        let mut json = serde_json::to_string(&request)?;
-        json.push('\n');
-        writer.write_all(json.as_bytes()).await?;
-
-        let mut line = String::new();
-        reader.read_line(&mut line).await?;
-
-        if let Ok(Response::Ok) = serde_json::from_str::<Response>(&line) {
+        if client.resize(agent_id, *rows, *cols, false).await.is_ok() {
             tracing::debug!("Resized {} to {}x{}", agent_id, rows, cols);
         }
     }
@@ -2148,6 +2246,7 @@ async fn run_resize_panes_command(
     for (agent_id, (_, _)) in &pane_sizes {
         if let Some(&pid) = agent_pids.get(agent_id) {
             // Send SIGWINCH (28) to the process
+            #[allow(unsafe_code, clippy::cast_possible_wrap)] // PIDs fit in pid_t
             unsafe {
                 libc::kill(pid as libc::pid_t, libc::SIGWINCH);
             }
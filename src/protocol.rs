@@ -3,7 +3,13 @@
 //! All communication between the botty CLI (client) and the botty server
 //! happens over a Unix socket using JSON-serialized Request/Response messages.
 
+use crate::redact::Redaction;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// Unique agent identifier (e.g., "rusty-nail" or a custom `--name`).
+pub type AgentId = String;
 
 /// Format for transcript dump output.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -37,35 +43,67 @@ pub enum Request {
         /// Labels for grouping agents.
         #[serde(default)]
         labels: Vec<String>,
+        /// Free-form key/value annotations (e.g. `task=1234`).
+        #[serde(default)]
+        annotations: BTreeMap<String, String>,
         /// Auto-kill after this many seconds (None = no timeout).
         #[serde(default)]
         timeout: Option<u64>,
         /// Stop recording transcript after this many bytes (None = unlimited).
         #[serde(default)]
         max_output: Option<u64>,
+        /// Most bytes of input that may wait for the agent to read them
+        /// (None = the server default of 1 MiB).
+        #[serde(default)]
+        max_input: Option<u64>,
         /// Environment variables to set (KEY=VALUE pairs).
         #[serde(default)]
         env: Vec<String>,
         /// Clear environment before spawning.
         #[serde(default)]
         env_clear: bool,
+        /// Inject OSC 133 prompt markers into known shells (bash, zsh).
+        #[serde(default)]
+        shell_integration: bool,
+        /// `TERM` to give the agent (None = `xterm-256color`).
+        #[serde(default)]
+        term: Option<String>,
+        /// Terminal to identify as when the agent asks (device attributes).
+        #[serde(default)]
+        terminal: TerminalIdentity,
+        /// Don't answer the agent's terminal queries (cursor position,
+        /// device status and attributes).
+        #[serde(default)]
+        no_terminal_replies: bool,
     },
 
-    /// List all agents (optionally filtered by labels).
+    /// List all agents (optionally filtered by labels and annotations).
     List {
         /// Filter by labels (agents must have ALL specified labels).
         #[serde(default)]
         labels: Vec<String>,
+        /// Filter by annotations (agents must have ALL specified values).
+        #[serde(default)]
+        annotations: BTreeMap<String, String>,
+        /// Filter by a selector expression, e.g. `tier=worker,!canary`.
+        #[serde(default)]
+        selector: Option<String>,
     },
 
     /// Kill an agent by ID, by labels, by process name, or all agents.
     Kill {
-        /// Agent ID (optional if using labels, proc_filter, or all).
+        /// Agent ID (optional if using labels, `proc_filter`, or all).
         #[serde(default)]
         id: Option<String>,
         /// Kill all agents with these labels.
         #[serde(default)]
         labels: Vec<String>,
+        /// Kill all agents with these annotation values.
+        #[serde(default)]
+        annotations: BTreeMap<String, String>,
+        /// Kill all agents matching this selector expression.
+        #[serde(default)]
+        selector: Option<String>,
         /// Kill all running agents.
         #[serde(default)]
         all: bool,
@@ -86,6 +124,18 @@ pub enum Request {
         /// Whether to append a newline.
         #[serde(default)]
         newline: bool,
+        /// Send as a paste: wrapped in bracketed paste markers if the agent has
+        /// enabled bracketed paste mode, so editors and REPLs don't autoindent
+        /// or run it line by line. The newline, if any, follows the paste.
+        #[serde(default)]
+        paste: bool,
+        /// Type the text one character at a time, this many milliseconds apart,
+        /// for programs that drop or reorder input arriving in one burst.
+        #[serde(default)]
+        delay_ms: u64,
+        /// Add a random extra delay of up to this many milliseconds per character.
+        #[serde(default)]
+        jitter_ms: u64,
     },
 
     /// Send raw bytes to an agent.
@@ -97,6 +147,58 @@ pub enum Request {
         data: Vec<u8>,
     },
 
+    /// Send named keys to an agent, encoded for its current cursor and keypad
+    /// modes (e.g. `up` is `ESC O A` while an editor has application cursor
+    /// keys enabled).
+    SendKeys {
+        /// Agent ID.
+        id: String,
+        /// Key names, e.g. `ctrl-c`, `alt-x`, `shift-tab`, `f12`.
+        keys: Vec<String>,
+    },
+
+    /// Send a mouse action to an agent, encoded for the mouse reporting mode it
+    /// enabled. Fails if the agent hasn't enabled mouse reporting.
+    SendMouse {
+        /// Agent ID.
+        id: String,
+        /// What to do.
+        action: MouseAction,
+        /// Screen row, 0-indexed (where a drag starts).
+        row: u16,
+        /// Screen column, 0-indexed (where a drag starts).
+        col: u16,
+        /// Button to use (default: left, or wheel down for scroll).
+        #[serde(default)]
+        button: Option<MouseButton>,
+        /// Where a drag ends (row, col), 0-indexed.
+        #[serde(default)]
+        to: Option<(u16, u16)>,
+    },
+
+    /// Write the same bytes to several agents at once: the listed IDs plus
+    /// every running agent matching the labels, annotations and selector.
+    Broadcast {
+        /// Agent IDs to send to.
+        #[serde(default)]
+        ids: Vec<String>,
+        /// Send to all running agents with these labels.
+        #[serde(default)]
+        labels: Vec<String>,
+        /// Send to all running agents with these annotation values.
+        #[serde(default)]
+        annotations: BTreeMap<String, String>,
+        /// Send to all running agents matching this selector expression.
+        #[serde(default)]
+        selector: Option<String>,
+        /// Raw bytes (base64 encoded in JSON).
+        #[serde(default, with = "base64_bytes")]
+        data: Vec<u8>,
+        /// Key names to send after `data`, encoded for each agent's modes.
+        #[serde(default)]
+        keys: Vec<String>,
+    },
+
     /// Tail the transcript buffer.
     Tail {
         /// Agent ID.
@@ -128,6 +230,12 @@ pub enum Request {
         /// Whether to strip ANSI color codes (default: true).
         #[serde(default = "default_true")]
         strip_colors: bool,
+        /// Only this part of the screen (None = the whole screen).
+        #[serde(default)]
+        region: Option<Region>,
+        /// Redaction rules for volatile content, applied in order.
+        #[serde(default)]
+        redact: Vec<Redaction>,
     },
 
     /// Attach to an agent (interactive mode).
@@ -155,6 +263,48 @@ pub enum Request {
         /// Include output events (can be noisy).
         #[serde(default)]
         include_output: bool,
+        /// Send coalesced `screen_changed` events at most this often, in
+        /// milliseconds (None = no screen change events).
+        #[serde(default)]
+        screen_interval: Option<u64>,
+        /// Replay retained events after this sequence number before streaming
+        /// live ones (None = live events only).
+        #[serde(default)]
+        since_seq: Option<u64>,
+        /// Only agents that currently have all these labels (empty = any).
+        #[serde(default)]
+        labels: Vec<String>,
+        /// Only agents that currently have these annotation values (empty = any).
+        #[serde(default)]
+        annotations: BTreeMap<String, String>,
+        /// Only agents that currently match this selector expression.
+        #[serde(default)]
+        selector: Option<String>,
+        /// Only agents running one of these programs, matched against the
+        /// command's program path or file name (empty = any).
+        #[serde(default)]
+        commands: Vec<String>,
+        /// Only these event types, e.g. `agent_exited` (empty = all).
+        #[serde(default)]
+        event_types: Vec<String>,
+    },
+
+    /// Change a running or exited agent's labels and annotations.
+    Label {
+        /// Agent ID.
+        id: String,
+        /// Labels to add.
+        #[serde(default)]
+        add: Vec<String>,
+        /// Labels to remove (applied before `add`).
+        #[serde(default)]
+        remove: Vec<String>,
+        /// Annotations to set, replacing existing values.
+        #[serde(default)]
+        set: BTreeMap<String, String>,
+        /// Annotation keys to remove (applied before `set`).
+        #[serde(default)]
+        unset: Vec<String>,
     },
 
     /// Resize an agent's terminal.
@@ -170,6 +320,12 @@ pub enum Request {
         #[serde(default)]
         clear_transcript: bool,
     },
+
+    /// Forget an exited agent, freeing its transcript and screen.
+    Remove {
+        /// Agent ID.
+        id: String,
+    },
 }
 
 /// Information about a single agent.
@@ -186,10 +342,16 @@ pub struct AgentInfo {
     /// Labels assigned to this agent.
     #[serde(default)]
     pub labels: Vec<String>,
+    /// Key/value annotations on this agent.
+    #[serde(default)]
+    pub annotations: BTreeMap<String, String>,
     /// Terminal size (rows, cols).
     pub size: (u16, u16),
     /// Unix timestamp when the agent was spawned (millis).
     pub started_at: u64,
+    /// Unix timestamp when the agent exited (millis).
+    #[serde(default)]
+    pub exited_at: Option<u64>,
     /// Exit code if the agent has exited.
     pub exit_code: Option<i32>,
     /// Exit reason (normal, timeout, killed).
@@ -198,6 +360,46 @@ pub struct AgentInfo {
     /// Resource limits applied to this agent.
     #[serde(default)]
     pub limits: Option<ResourceLimits>,
+    /// Shell integration state (None unless the agent emits OSC 133 markers).
+    #[serde(default)]
+    pub shell: Option<ShellState>,
+    /// Input queued for and written to the agent.
+    #[serde(default)]
+    pub input: InputStats,
+    /// Output written by the agent and dropped from its transcript.
+    #[serde(default)]
+    pub output: OutputStats,
+}
+
+/// Outcome of writing broadcast input to one agent.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SendResult {
+    /// Agent ID.
+    pub id: String,
+    /// Why the write failed, or `None` if it succeeded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl SendResult {
+    /// Whether the input was written.
+    #[must_use]
+    pub const fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Shell integration state, tracked from OSC 133 prompt markers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShellState {
+    /// The shell is showing a prompt and waiting for input.
+    pub at_prompt: bool,
+    /// A command is currently running.
+    pub running: bool,
+    /// Number of commands started so far.
+    pub commands: u64,
+    /// Exit code of the last finished command.
+    pub last_exit_code: Option<i32>,
 }
 
 /// Why an agent exited.
@@ -221,6 +423,29 @@ pub struct ResourceLimits {
     /// Max transcript bytes (None = unlimited).
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub max_output: Option<u64>,
+    /// Max bytes of input waiting for the agent (None = server default).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_input: Option<u64>,
+}
+
+/// Input sent to an agent so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputStats {
+    /// Bytes accepted for the agent since it started.
+    pub queued: u64,
+    /// Bytes the agent's PTY has taken; the rest are waiting for it to read.
+    pub written: u64,
+    /// Most bytes that may wait at once.
+    pub limit: u64,
+}
+
+/// Output written by an agent so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputStats {
+    /// Bytes the agent has written since it started.
+    pub written: u64,
+    /// Oldest bytes dropped from the transcript to stay under `max_output`.
+    pub dropped: u64,
 }
 
 /// Agent lifecycle state.
@@ -274,6 +499,12 @@ pub enum Response {
         data: Vec<u8>,
     },
 
+    /// Per-agent outcome of a broadcast, in the order the agents were written to.
+    Broadcast {
+        /// One result per targeted agent.
+        results: Vec<SendResult>,
+    },
+
     /// Transcript dump (for dump command).
     Transcript {
         /// Transcript entries.
@@ -324,7 +555,124 @@ pub enum Response {
     },
 
     /// Server event (sent during event subscription).
-    Event(Event),
+    Event {
+        /// Sequence number, increasing with every event the server publishes.
+        seq: u64,
+        /// The event itself.
+        #[serde(flatten)]
+        event: Event,
+    },
+
+    /// Events were dropped from the subscription because it fell too far behind
+    /// (sent during event subscription, before the next delivered event).
+    Lagged {
+        /// Number of events dropped (an upper bound when resuming from an old `since_seq`).
+        missed: u64,
+    },
+}
+
+/// Mouse actions for `Request::SendMouse`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseAction {
+    /// Press and release a button.
+    Click,
+    /// Turn the wheel one step.
+    Scroll,
+    /// Press a button, move to another cell, and release it there.
+    Drag,
+}
+
+impl FromStr for MouseAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "click" => Ok(Self::Click),
+            "scroll" => Ok(Self::Scroll),
+            "drag" => Ok(Self::Drag),
+            _ => Err(format!("unknown mouse action {s:?} (expected click, scroll or drag)")),
+        }
+    }
+}
+
+/// Mouse buttons, including the wheel directions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+    WheelUp,
+    WheelDown,
+}
+
+impl MouseButton {
+    /// Whether this is a wheel direction rather than a button.
+    #[must_use]
+    pub const fn is_wheel(self) -> bool {
+        matches!(self, Self::WheelUp | Self::WheelDown)
+    }
+}
+
+impl FromStr for MouseButton {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('_', "-").as_str() {
+            "left" => Ok(Self::Left),
+            "middle" => Ok(Self::Middle),
+            "right" => Ok(Self::Right),
+            "wheel-up" | "up" => Ok(Self::WheelUp),
+            "wheel-down" | "down" => Ok(Self::WheelDown),
+            _ => Err(format!(
+                "unknown mouse button {s:?} (expected left, middle, right, up or down)"
+            )),
+        }
+    }
+}
+
+/// Part of an agent's screen, for snapshots and waits that should ignore the rest.
+///
+/// Rows and columns are 0-indexed. Parts that fall outside the screen are
+/// left out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Region {
+    /// Rows `start..end` (end exclusive), full width.
+    Rows { start: u16, end: u16 },
+    /// `rows` rows and `cols` columns starting at (`row`, `col`).
+    Rect { row: u16, col: u16, rows: u16, cols: u16 },
+    /// The last row with any text on it.
+    LastLine,
+    /// The row the cursor is on.
+    CursorLine,
+}
+
+/// The terminal botty identifies as in answer to device attribute queries.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TerminalIdentity {
+    /// A VT100 with advanced video.
+    Vt100,
+    /// A VT220 with ANSI color.
+    Vt220,
+    /// xterm, matching the `TERM` agents are given.
+    #[default]
+    Xterm,
+}
+
+impl FromStr for TerminalIdentity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "vt100" => Ok(Self::Vt100),
+            "vt220" => Ok(Self::Vt220),
+            "xterm" => Ok(Self::Xterm),
+            _ => Err(format!("unknown terminal {s:?} (expected vt100, vt220 or xterm)")),
+        }
+    }
 }
 
 /// Reason attach mode ended.
@@ -356,6 +704,9 @@ pub enum Event {
         /// Labels assigned to this agent.
         #[serde(default)]
         labels: Vec<String>,
+        /// Key/value annotations on this agent.
+        #[serde(default)]
+        annotations: BTreeMap<String, String>,
     },
     /// An agent produced output.
     AgentOutput {
@@ -372,6 +723,198 @@ pub enum Event {
         /// Exit code (None if killed by signal).
         exit_code: Option<i32>,
     },
+    /// An exited agent was removed, or replaced by a new agent with its name.
+    AgentRemoved {
+        /// Agent ID.
+        id: String,
+    },
+    /// An agent's labels or annotations were changed.
+    LabelsChanged {
+        /// Agent ID.
+        id: String,
+        /// The agent's labels after the change.
+        labels: Vec<String>,
+        /// The agent's annotations after the change.
+        #[serde(default)]
+        annotations: BTreeMap<String, String>,
+    },
+    /// A shell agent started running a command (OSC 133 shell integration).
+    CommandStarted {
+        /// Agent ID.
+        id: String,
+        /// Command number within the agent, starting at 1.
+        command: u64,
+    },
+    /// A shell agent finished running a command (OSC 133 shell integration).
+    CommandFinished {
+        /// Agent ID.
+        id: String,
+        /// Command number within the agent, starting at 1.
+        command: u64,
+        /// Exit code reported by the shell, if any.
+        exit_code: Option<i32>,
+    },
+    /// An agent set its terminal window title.
+    TitleChanged {
+        /// Agent ID.
+        id: String,
+        /// New window title.
+        title: String,
+    },
+    /// An agent rang the terminal bell.
+    Bell {
+        /// Agent ID.
+        id: String,
+    },
+    /// An agent switched to the alternate screen (full-screen TUI started).
+    AltScreenEntered {
+        /// Agent ID.
+        id: String,
+    },
+    /// An agent switched back from the alternate screen.
+    AltScreenExited {
+        /// Agent ID.
+        id: String,
+    },
+    /// An agent showed or hid the cursor.
+    CursorVisibilityChanged {
+        /// Agent ID.
+        id: String,
+        /// Whether the cursor is now visible.
+        visible: bool,
+    },
+    /// An agent cleared the whole screen.
+    ScreenCleared {
+        /// Agent ID.
+        id: String,
+    },
+    /// An agent's terminal was resized.
+    Resized {
+        /// Agent ID.
+        id: String,
+        /// New number of rows.
+        rows: u16,
+        /// New number of columns.
+        cols: u16,
+    },
+    /// Rows of an agent's screen changed since the last `screen_changed` event.
+    ///
+    /// Only sent to subscriptions that set `screen_interval`; changes within
+    /// one interval are coalesced. The first event for an agent carries every
+    /// non-empty row, and the first after a resize carries every row, so rows
+    /// beyond the new size can be dropped. An agent's final screen follows its
+    /// `agent_exited` event. Each is numbered for its own subscription only,
+    /// so other subscribers see gaps in the sequence numbers instead.
+    ScreenChanged {
+        /// Agent ID.
+        id: String,
+        /// Changed rows with their new text.
+        rows: Vec<RowChange>,
+        /// Cursor position (row, col), 0-indexed.
+        cursor: (u16, u16),
+        /// Screen size (rows, cols).
+        size: (u16, u16),
+    },
+}
+
+/// A message on an event subscription.
+#[derive(Debug, Clone)]
+pub enum EventMessage {
+    /// An event with its sequence number.
+    Event {
+        /// Sequence number, increasing with every event the server publishes.
+        seq: u64,
+        /// The event itself.
+        event: Event,
+    },
+    /// Events were dropped because the subscriber fell too far behind.
+    Lagged {
+        /// Number of events dropped (an upper bound when resuming from an old `since_seq`).
+        missed: u64,
+    },
+}
+
+impl From<EventMessage> for Response {
+    fn from(message: EventMessage) -> Self {
+        match message {
+            EventMessage::Event { seq, event } => Self::Event { seq, event },
+            EventMessage::Lagged { missed } => Self::Lagged { missed },
+        }
+    }
+}
+
+/// A changed screen row in a `screen_changed` event.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RowChange {
+    /// Row index, 0-indexed.
+    pub row: u16,
+    /// New text of the row, without trailing whitespace.
+    pub text: String,
+}
+
+impl Event {
+    /// The ID of the agent this event is about.
+    #[must_use]
+    pub fn id(&self) -> &str {
+        match self {
+            Self::AgentSpawned { id, .. }
+            | Self::AgentOutput { id, .. }
+            | Self::AgentExited { id, .. }
+            | Self::AgentRemoved { id }
+            | Self::LabelsChanged { id, .. }
+            | Self::CommandStarted { id, .. }
+            | Self::CommandFinished { id, .. }
+            | Self::TitleChanged { id, .. }
+            | Self::Bell { id }
+            | Self::AltScreenEntered { id }
+            | Self::AltScreenExited { id }
+            | Self::CursorVisibilityChanged { id, .. }
+            | Self::ScreenCleared { id }
+            | Self::Resized { id, .. }
+            | Self::ScreenChanged { id, .. } => id,
+        }
+    }
+
+    /// The event type name, as sent in the `event` field.
+    #[must_use]
+    pub const fn kind(&self) -> &'static str {
+        match self {
+            Self::AgentSpawned { .. } => "agent_spawned",
+            Self::AgentOutput { .. } => "agent_output",
+            Self::AgentExited { .. } => "agent_exited",
+            Self::AgentRemoved { .. } => "agent_removed",
+            Self::LabelsChanged { .. } => "labels_changed",
+            Self::CommandStarted { .. } => "command_started",
+            Self::CommandFinished { .. } => "command_finished",
+            Self::TitleChanged { .. } => "title_changed",
+            Self::Bell { .. } => "bell",
+            Self::AltScreenEntered { .. } => "alt_screen_entered",
+            Self::AltScreenExited { .. } => "alt_screen_exited",
+            Self::CursorVisibilityChanged { .. } => "cursor_visibility_changed",
+            Self::ScreenCleared { .. } => "screen_cleared",
+            Self::Resized { .. } => "resized",
+            Self::ScreenChanged { .. } => "screen_changed",
+        }
+    }
+
+    /// All event type names, for validating `event_types` filters.
+    pub const KINDS: &'static [&'static str] = &[
+        "agent_spawned",
+        "agent_output",
+        "agent_exited",
+        "agent_removed",
+        "labels_changed",
+        "command_started",
+        "command_finished",
+        "title_changed",
+        "bell",
+        "alt_screen_entered",
+        "alt_screen_exited",
+        "cursor_visibility_changed",
+        "screen_cleared",
+        "resized",
+        "screen_changed",
+    ];
 }
 
 impl Response {
@@ -383,6 +926,146 @@ impl Response {
     }
 }
 
+/// Options for spawning an agent.
+///
+/// Mirrors the fields of [`Request::Spawn`] with the same defaults, so typed
+/// APIs can build a spawn request without spelling out every field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpawnOptions {
+    /// Command and arguments to execute.
+    pub cmd: Vec<String>,
+    /// Terminal rows (default: 24).
+    pub rows: u16,
+    /// Terminal columns (default: 80).
+    pub cols: u16,
+    /// Optional custom agent ID (must be unique).
+    pub name: Option<String>,
+    /// Labels for grouping agents.
+    pub labels: Vec<String>,
+    /// Free-form key/value annotations (e.g. `task=1234`).
+    pub annotations: BTreeMap<String, String>,
+    /// Auto-kill after this many seconds (None = no timeout).
+    pub timeout: Option<u64>,
+    /// Stop recording transcript after this many bytes (None = unlimited).
+    pub max_output: Option<u64>,
+    /// Most bytes of input that may wait for the agent to read them
+    /// (None = the server default of 1 MiB).
+    pub max_input: Option<u64>,
+    /// Environment variables to set (KEY=VALUE pairs).
+    pub env: Vec<String>,
+    /// Clear environment before spawning.
+    pub env_clear: bool,
+    /// Inject OSC 133 prompt markers into known shells (bash, zsh).
+    pub shell_integration: bool,
+    /// `TERM` to give the agent (None = `xterm-256color`).
+    pub term: Option<String>,
+    /// Terminal to identify as when the agent asks (device attributes).
+    pub terminal: TerminalIdentity,
+    /// Don't answer the agent's terminal queries.
+    pub no_terminal_replies: bool,
+}
+
+impl SpawnOptions {
+    /// Create spawn options for the given command with default settings.
+    pub fn new<I, S>(cmd: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            cmd: cmd.into_iter().map(Into::into).collect(),
+            rows: default_rows(),
+            cols: default_cols(),
+            name: None,
+            labels: Vec::new(),
+            annotations: BTreeMap::new(),
+            timeout: None,
+            max_output: None,
+            max_input: None,
+            env: Vec::new(),
+            env_clear: false,
+            shell_integration: false,
+            term: None,
+            terminal: TerminalIdentity::default(),
+            no_terminal_replies: false,
+        }
+    }
+}
+
+impl From<SpawnOptions> for Request {
+    fn from(opts: SpawnOptions) -> Self {
+        Self::Spawn {
+            cmd: opts.cmd,
+            rows: opts.rows,
+            cols: opts.cols,
+            name: opts.name,
+            labels: opts.labels,
+            annotations: opts.annotations,
+            timeout: opts.timeout,
+            max_output: opts.max_output,
+            max_input: opts.max_input,
+            env: opts.env,
+            env_clear: opts.env_clear,
+            shell_integration: opts.shell_integration,
+            term: opts.term,
+            terminal: opts.terminal,
+            no_terminal_replies: opts.no_terminal_replies,
+        }
+    }
+}
+
+/// Options for subscribing to the event stream.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EventOptions {
+    /// Filter to specific agent IDs (empty = all agents).
+    pub filter: Vec<String>,
+    /// Include output events (can be noisy).
+    pub include_output: bool,
+    /// Send coalesced `screen_changed` events at most this often, in
+    /// milliseconds (None = no screen change events).
+    pub screen_interval: Option<u64>,
+    /// Replay retained events after this sequence number before streaming
+    /// live ones (None = live events only).
+    pub since_seq: Option<u64>,
+    /// Only agents that currently have all these labels (empty = any).
+    pub labels: Vec<String>,
+    /// Only agents that currently have these annotation values (empty = any).
+    pub annotations: BTreeMap<String, String>,
+    /// Only agents that currently match this selector expression.
+    pub selector: Option<String>,
+    /// Only agents running one of these programs (empty = any).
+    pub commands: Vec<String>,
+    /// Only these event types, e.g. `agent_exited` (empty = all).
+    pub event_types: Vec<String>,
+}
+
+impl From<EventOptions> for Request {
+    fn from(opts: EventOptions) -> Self {
+        Self::Events {
+            filter: opts.filter,
+            include_output: opts.include_output,
+            screen_interval: opts.screen_interval,
+            since_seq: opts.since_seq,
+            labels: opts.labels,
+            annotations: opts.annotations,
+            selector: opts.selector,
+            commands: opts.commands,
+            event_types: opts.event_types,
+        }
+    }
+}
+
+/// A snapshot of an agent's virtual screen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Screen content (normalized text, or with ANSI colors if requested).
+    pub content: String,
+    /// Cursor position (row, col), 0-indexed.
+    pub cursor: (u16, u16),
+    /// Screen size (rows, cols).
+    pub size: (u16, u16),
+}
+
 // Default value helpers
 const fn default_rows() -> u16 {
     24
@@ -438,28 +1121,31 @@ mod tests {
                 cols: 80,
                 name: None,
                 labels: vec!["worker".into()],
+                annotations: BTreeMap::from([("task".into(), "1234".into())]),
                 timeout: Some(60),
                 max_output: Some(1024 * 1024),
+                max_input: None,
                 env: vec![],
                 env_clear: false,
+                shell_integration: true,
+                term: None,
+                terminal: TerminalIdentity::default(),
+                no_terminal_replies: false,
+            },
+            Request::List {
+                labels: vec![],
+                annotations: BTreeMap::from([("owner".into(), "planner".into())]),
+                selector: Some("tier=worker,!canary".into()),
             },
-            Request::List { labels: vec![] },
             Request::Kill {
                 id: Some("test-agent".into()),
                 labels: vec![],
+                annotations: BTreeMap::new(),
+                selector: None,
                 all: false,
                 signal: 9,
                 proc_filter: None,
             },
-            Request::Send {
-                id: "test-agent".into(),
-                data: "hello\n".into(),
-                newline: false,
-            },
-            Request::SendBytes {
-                id: "test-agent".into(),
-                data: vec![0x1b, 0x5b, 0x41], // ESC [ A (up arrow)
-            },
             Request::Tail {
                 id: "test-agent".into(),
                 lines: 20,
@@ -468,12 +1154,39 @@ mod tests {
             Request::Snapshot {
                 id: "test-agent".into(),
                 strip_colors: true,
+                region: Some(Region::Rect {
+                    row: 10,
+                    col: 0,
+                    rows: 5,
+                    cols: 80,
+                }),
+                redact: vec![
+                    Redaction::Timestamp,
+                    Redaction::Custom {
+                        pattern: r"port \d+".into(),
+                        replacement: "port <PORT>".into(),
+                    },
+                ],
             },
             Request::Ping,
             Request::Shutdown,
             Request::Events {
                 filter: vec!["agent-1".into()],
                 include_output: true,
+                screen_interval: Some(100),
+                since_seq: Some(41),
+                labels: vec!["worker".into()],
+                annotations: BTreeMap::new(),
+                selector: Some("env in (dev,test)".into()),
+                commands: vec!["bash".into()],
+                event_types: vec!["agent_exited".into()],
+            },
+            Request::Label {
+                id: "test-agent".into(),
+                add: vec!["busy".into()],
+                remove: vec!["idle".into()],
+                set: BTreeMap::from([("phase".into(), "review".into())]),
+                unset: vec!["task".into()],
             },
             Request::Resize {
                 id: "test-agent".into(),
@@ -487,7 +1200,68 @@ mod tests {
             let json = serde_json::to_string(&req).expect("serialize");
             let parsed: Request = serde_json::from_str(&json).expect("deserialize");
             let json2 = serde_json::to_string(&parsed).expect("re-serialize");
-            assert_eq!(json, json2, "roundtrip failed for {:?}", req);
+            assert_eq!(json, json2, "roundtrip failed for {req:?}");
+        }
+    }
+
+    #[test]
+    fn test_input_request_serialization_roundtrip() {
+        let requests = vec![
+            Request::Send {
+                id: "test-agent".into(),
+                data: "hello\n".into(),
+                newline: false,
+                paste: false,
+                delay_ms: 0,
+                jitter_ms: 0,
+            },
+            Request::Send {
+                id: "test-agent".into(),
+                data: "def f():\n    return 1\n".into(),
+                newline: true,
+                paste: true,
+                delay_ms: 0,
+                jitter_ms: 0,
+            },
+            Request::Send {
+                id: "test-agent".into(),
+                data: "secret".into(),
+                newline: true,
+                paste: false,
+                delay_ms: 30,
+                jitter_ms: 20,
+            },
+            Request::SendBytes {
+                id: "test-agent".into(),
+                data: vec![0x1b, 0x5b, 0x41], // ESC [ A (up arrow)
+            },
+            Request::Broadcast {
+                ids: vec!["test-agent".into()],
+                labels: vec!["worker".into()],
+                annotations: BTreeMap::new(),
+                selector: Some("!canary".into()),
+                data: vec![],
+                keys: vec!["ctrl-c".into()],
+            },
+            Request::SendMouse {
+                id: "test-agent".into(),
+                action: MouseAction::Drag,
+                row: 2,
+                col: 3,
+                button: Some(MouseButton::Left),
+                to: Some((5, 10)),
+            },
+            Request::SendKeys {
+                id: "test-agent".into(),
+                keys: vec!["ctrl-left".into(), "f12".into()],
+            },
+        ];
+
+        for req in requests {
+            let json = serde_json::to_string(&req).expect("serialize");
+            let parsed: Request = serde_json::from_str(&json).expect("deserialize");
+            let json2 = serde_json::to_string(&parsed).expect("re-serialize");
+            assert_eq!(json, json2, "roundtrip failed for {req:?}");
         }
     }
 
@@ -507,46 +1281,125 @@ mod tests {
                     state: AgentState::Running,
                     command: vec!["bash".into()],
                     labels: vec!["worker".into()],
+                    annotations: BTreeMap::new(),
                     size: (24, 80),
-                    started_at: 1706140800000,
+                    started_at: 1_706_140_800_000,
+                    exited_at: None,
                     exit_code: None,
                     exit_reason: None,
                     limits: Some(ResourceLimits {
                         timeout: Some(60),
                         max_output: None,
+                        max_input: Some(4096),
                     }),
+                    shell: None,
+                    input: InputStats {
+                        queued: 12,
+                        written: 10,
+                        limit: 4096,
+                    },
+                    output: OutputStats {
+                        written: 2048,
+                        dropped: 0,
+                    },
                 }],
             },
             Response::Output {
                 data: b"hello world\n".to_vec(),
             },
+            Response::Broadcast {
+                results: vec![
+                    SendResult {
+                        id: "rusty-nail".into(),
+                        error: None,
+                    },
+                    SendResult {
+                        id: "old-gate".into(),
+                        error: Some("agent is not running".into()),
+                    },
+                ],
+            },
             Response::Snapshot {
                 content: "$ echo hello\nhello\n$ ".into(),
                 cursor: (2, 2),
                 size: (24, 80),
             },
             Response::error("agent not found"),
-            Response::Event(Event::AgentSpawned {
-                id: "test-agent".into(),
-                pid: 12345,
-                command: vec!["bash".into()],
-                labels: vec![],
-            }),
-            Response::Event(Event::AgentOutput {
-                id: "test-agent".into(),
-                data: b"hello".to_vec(),
-            }),
-            Response::Event(Event::AgentExited {
-                id: "test-agent".into(),
-                exit_code: Some(0),
-            }),
+            Response::Event {
+                seq: 1,
+                event: Event::AgentSpawned {
+                    id: "test-agent".into(),
+                    pid: 12345,
+                    command: vec!["bash".into()],
+                    labels: vec![],
+                    annotations: BTreeMap::new(),
+                },
+            },
+            Response::Event {
+                seq: 2,
+                event: Event::AgentOutput {
+                    id: "test-agent".into(),
+                    data: b"hello".to_vec(),
+                },
+            },
+            Response::Event {
+                seq: 3,
+                event: Event::AgentExited {
+                    id: "test-agent".into(),
+                    exit_code: Some(0),
+                },
+            },
+            Response::Lagged { missed: 7 },
         ];
 
         for resp in responses {
             let json = serde_json::to_string(&resp).expect("serialize");
             let parsed: Response = serde_json::from_str(&json).expect("deserialize");
             let json2 = serde_json::to_string(&parsed).expect("re-serialize");
-            assert_eq!(json, json2, "roundtrip failed for {:?}", resp);
+            assert_eq!(json, json2, "roundtrip failed for {resp:?}");
+        }
+    }
+
+    #[test]
+    fn test_spawn_options_defaults_match_request() {
+        let json = r#"{"type":"spawn","cmd":["bash"]}"#;
+        let from_json: Request = serde_json::from_str(json).expect("deserialize");
+        let from_opts: Request = SpawnOptions::new(["bash"]).into();
+        assert_eq!(
+            serde_json::to_string(&from_json).expect("serialize"),
+            serde_json::to_string(&from_opts).expect("serialize"),
+        );
+    }
+
+    #[test]
+    fn test_event_kind_matches_serialized_tag() {
+        let events = [
+            Event::Bell { id: "a".into() },
+            Event::LabelsChanged {
+                id: "a".into(),
+                labels: vec!["busy".into()],
+                annotations: BTreeMap::from([("task".into(), "1234".into())]),
+            },
+            Event::AgentExited {
+                id: "a".into(),
+                exit_code: None,
+            },
+            Event::Resized {
+                id: "a".into(),
+                rows: 40,
+                cols: 120,
+            },
+            Event::ScreenChanged {
+                id: "a".into(),
+                rows: vec![],
+                cursor: (0, 0),
+                size: (24, 80),
+            },
+        ];
+        for event in events {
+            let json = serde_json::to_value(&event).expect("serialize");
+            assert_eq!(json["event"], event.kind());
+            assert!(Event::KINDS.contains(&event.kind()));
         }
     }
 
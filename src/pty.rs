@@ -15,6 +15,7 @@ use nix::sys::signal::{self, Signal};
 use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
 use nix::unistd::{execvp, fork, setsid, ForkResult, Pid};
 use std::ffi::CString;
+use std::path::PathBuf;
 use std::os::unix::io::{AsRawFd, OwnedFd, RawFd};
 use thiserror::Error;
 
@@ -113,6 +114,57 @@ impl PtyProcess {
     }
 }
 
+/// `TERM` given to agents unless they ask for another.
+pub const DEFAULT_TERM: &str = "xterm-256color";
+
+/// Variables that pin a program's idea of the terminal size. They can't follow
+/// a resize, and ncurses prefers them to the PTY's real size, so agents only
+/// get them when set explicitly.
+pub const SIZE_VARS: [&str; 2] = ["LINES", "COLUMNS"];
+
+/// The variables describing the terminal to a program: `TERM`, and
+/// `COLORTERM` for terminals with 256 or direct colors, which the screen
+/// model renders in full. The size comes from the PTY itself.
+#[must_use]
+pub fn terminal_env(term: &str) -> Vec<(String, String)> {
+    let mut vars = vec![("TERM".to_string(), term.to_string())];
+    if term.ends_with("-256color") || term.ends_with("-direct") {
+        vars.push(("COLORTERM".to_string(), "truecolor".to_string()));
+    }
+    vars
+}
+
+/// Find the compiled terminfo entry for `term`, searching the same places
+/// ncurses does. Returns None if there isn't one.
+#[must_use]
+pub fn find_terminfo(term: &str) -> Option<PathBuf> {
+    let first = term.chars().next()?;
+    if term.contains('/') {
+        return None;
+    }
+
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    if let Some(dir) = std::env::var_os("TERMINFO") {
+        dirs.push(dir.into());
+    }
+    if let Some(home) = std::env::var_os("HOME") {
+        dirs.push(PathBuf::from(home).join(".terminfo"));
+    }
+    if let Some(list) = std::env::var_os("TERMINFO_DIRS") {
+        dirs.extend(std::env::split_paths(&list).filter(|d| !d.as_os_str().is_empty()));
+    }
+    dirs.extend(
+        ["/etc/terminfo", "/lib/terminfo", "/usr/share/terminfo", "/usr/lib/terminfo"]
+            .map(PathBuf::from),
+    );
+
+    // Entries live under their first letter, or its hex code on macOS
+    let subdirs = [first.to_string(), format!("{:x}", u32::from(first))];
+    dirs.iter()
+        .flat_map(|dir| subdirs.iter().map(move |sub| dir.join(sub).join(term)))
+        .find(|path| path.is_file())
+}
+
 /// Environment configuration for spawning.
 #[derive(Debug, Default)]
 pub struct SpawnEnv {
@@ -234,6 +286,10 @@ pub fn spawn_with_env(
                         std::env::remove_var(&key);
                     }
                 }
+                // Sizes inherited from the server's terminal would be wrong here
+                for key in SIZE_VARS {
+                    std::env::remove_var(key);
+                }
                 // Set requested environment variables
                 for (key, value) in &env.vars {
                     std::env::set_var(key, value);
@@ -278,6 +334,23 @@ mod tests {
         assert_eq!(exit_code, 42);
     }
 
+    #[test]
+    fn test_terminal_env() {
+        let vars = terminal_env("xterm-256color");
+        let get = |key: &str| vars.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+        assert_eq!(get("TERM"), Some("xterm-256color"));
+        assert_eq!(get("COLORTERM"), Some("truecolor"));
+        assert_eq!(get("LINES"), None);
+        assert_eq!(get("COLUMNS"), None);
+
+        let vars = terminal_env("vt100");
+        assert!(!vars.iter().any(|(k, _)| k == "COLORTERM"));
+
+        assert_eq!(find_terminfo("no-such-terminal"), None);
+        assert_eq!(find_terminfo("../x/xterm"), None);
+        assert_eq!(find_terminfo(""), None);
+    }
+
     #[test]
     fn test_spawn_empty_command() {
         let result = spawn(&[], 24, 80);
@@ -0,0 +1,253 @@
+//! Redaction rules that normalize volatile content in snapshots.
+//!
+//! Timestamps, PIDs, temp paths and the like change from run to run, so a
+//! snapshot containing them can never match a golden copy. Redaction replaces
+//! each match with a fixed placeholder such as `<TIMESTAMP>`:
+//!
+//! - `timestamp`: ISO 8601 dates and date-times, e.g. `2024-05-01T12:30:00Z`
+//! - `duration`: durations with a unit, e.g. `250ms`, `1.5s`, `1m30s`
+//! - `pid`: numbers labeled as process IDs, e.g. `pid 4242` or `PID=4242`
+//! - `hash`: hex strings of 7 or more digits mixing letters and numbers
+//! - `temp-path`: paths under `/tmp`, `/var/tmp` or macOS's `/var/folders`
+//! - `spinner`: braille and circle progress spinner glyphs
+//!
+//! Custom rules pair a regex with a replacement, which may refer to capture
+//! groups as `$1` or `${name}`. Rules apply in order, each to the output of
+//! the one before.
+
+use crate::MAX_PATTERN_LEN;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Errors from building a [`Redactor`] or parsing a rule.
+#[derive(Debug, Error)]
+pub enum RedactError {
+    #[error("unknown redaction {0:?} (expected one of: timestamp, duration, pid, hash, temp-path, spinner)")]
+    UnknownRule(String),
+
+    #[error("redaction pattern too long ({len} chars, max {MAX_PATTERN_LEN})")]
+    PatternTooLong { len: usize },
+
+    #[error("invalid redaction pattern {pattern:?}: {source}")]
+    Pattern {
+        pattern: String,
+        #[source]
+        source: regex::Error,
+    },
+}
+
+/// A rule for replacing volatile content.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Redaction {
+    /// ISO 8601 dates and date-times.
+    Timestamp,
+    /// Durations with a unit suffix.
+    Duration,
+    /// Numbers labeled as process IDs.
+    Pid,
+    /// Hex hashes and IDs.
+    Hash,
+    /// Paths in temporary directories.
+    TempPath,
+    /// Progress spinner glyphs.
+    Spinner,
+    /// Replace every match of `pattern` with `replacement`.
+    Custom { pattern: String, replacement: String },
+}
+
+impl Redaction {
+    /// Every built-in rule, in the order they're best applied.
+    pub const BUILTIN: [Self; 6] = [
+        Self::Timestamp,
+        Self::TempPath,
+        Self::Hash,
+        Self::Duration,
+        Self::Pid,
+        Self::Spinner,
+    ];
+
+    /// The pattern, replacement and extra check for a rule.
+    fn rule(&self) -> (&str, &str, fn(&str) -> bool) {
+        const fn any(_: &str) -> bool {
+            true
+        }
+        match self {
+            Self::Timestamp => (
+                r"\b\d{4}-\d{2}-\d{2}(?:[T ]\d{2}:\d{2}(?::\d{2}(?:[.,]\d+)?)?(?:Z|[+-]\d{2}:?\d{2})?)?\b",
+                "<TIMESTAMP>",
+                any,
+            ),
+            Self::Duration => (
+                r"\b(?:\d+(?:\.\d+)?(?:ns|us|µs|ms|s|m|h))+\b",
+                "<DURATION>",
+                any,
+            ),
+            Self::Pid => (r"(?i)\b(pid[ :=]\s*)\d+\b", "${1}<PID>", any),
+            // All-digit and all-letter runs are numbers and words, not hashes
+            Self::Hash => (r"\b[0-9a-fA-F]{7,}\b", "<HASH>", |s| {
+                s.bytes().any(|b| b.is_ascii_digit()) && s.bytes().any(|b| b.is_ascii_alphabetic())
+            }),
+            Self::TempPath => (
+                r#"(?:/private)?(?:/var/folders|/var/tmp|/tmp)/[^\s'"`:;,)\]]+"#,
+                "<TMP>",
+                any,
+            ),
+            Self::Spinner => (r"[\u{2800}-\u{28FF}◐◓◑◒◴◷◶◵]", "<SPINNER>", any),
+            Self::Custom {
+                pattern,
+                replacement,
+            } => (pattern, replacement, any),
+        }
+    }
+}
+
+impl FromStr for Redaction {
+    type Err = RedactError;
+
+    /// Parse a built-in rule's name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "timestamp" | "timestamps" => Ok(Self::Timestamp),
+            "duration" | "durations" => Ok(Self::Duration),
+            "pid" | "pids" => Ok(Self::Pid),
+            "hash" | "hashes" => Ok(Self::Hash),
+            "temp-path" | "temp-paths" | "temp_path" => Ok(Self::TempPath),
+            "spinner" | "spinners" => Ok(Self::Spinner),
+            _ => Err(RedactError::UnknownRule(s.to_string())),
+        }
+    }
+}
+
+/// A compiled set of redaction rules.
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    regex: Regex,
+    replacement: String,
+    /// Whether a match should really be replaced.
+    keep: fn(&str) -> bool,
+}
+
+impl Redactor {
+    /// Compile the rules, failing on the first invalid or overlong custom
+    /// pattern.
+    pub fn new(redactions: &[Redaction]) -> Result<Self, RedactError> {
+        let rules = redactions
+            .iter()
+            .map(|redaction| {
+                let (pattern, replacement, keep) = redaction.rule();
+                if pattern.len() > MAX_PATTERN_LEN {
+                    return Err(RedactError::PatternTooLong { len: pattern.len() });
+                }
+                let regex = Regex::new(pattern).map_err(|source| RedactError::Pattern {
+                    pattern: pattern.to_string(),
+                    source,
+                })?;
+                Ok(Rule {
+                    regex,
+                    replacement: replacement.to_string(),
+                    keep,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { rules })
+    }
+
+    /// Whether this redactor leaves text unchanged.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Apply every rule to the text, in order.
+    #[must_use]
+    pub fn apply(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for rule in &self.rules {
+            text = rule
+                .regex
+                .replace_all(&text, |caps: &Captures| {
+                    let mut out = String::new();
+                    if (rule.keep)(&caps[0]) {
+                        caps.expand(&rule.replacement, &mut out);
+                    } else {
+                        out.push_str(&caps[0]);
+                    }
+                    out
+                })
+                .into_owned();
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redact(rules: &[Redaction], text: &str) -> String {
+        Redactor::new(rules).expect("valid rules").apply(text)
+    }
+
+    #[test]
+    fn test_builtin_redactions() {
+        let all = Redaction::BUILTIN;
+        assert_eq!(
+            redact(&all, "started 2024-05-01T12:30:00.123Z, done 2024-05-01"),
+            "started <TIMESTAMP>, done <TIMESTAMP>"
+        );
+        assert_eq!(redact(&all, "took 250ms (1m30s, 1.5s)"), "took <DURATION> (<DURATION>, <DURATION>)");
+        assert_eq!(redact(&all, "worker pid 4242, PID=17"), "worker pid <PID>, PID=<PID>");
+        assert_eq!(
+            redact(&all, "commit 3f2a9c1d on deadbeef, build 1234567"),
+            "commit <HASH> on deadbeef, build 1234567"
+        );
+        assert_eq!(
+            redact(&all, "wrote /tmp/botty-x81k/out.txt and /var/folders/ab/T/f"),
+            "wrote <TMP> and <TMP>"
+        );
+        assert_eq!(redact(&all, "⠋ Loading ◐"), "<SPINNER> Loading <SPINNER>");
+        assert_eq!(redact(&all, "nothing to see at 10 items"), "nothing to see at 10 items");
+    }
+
+    #[test]
+    fn test_custom_redactions() {
+        let rules = [
+            Redaction::Custom {
+                pattern: r"port (\d+)".into(),
+                replacement: "port <PORT:$1>".into(),
+            },
+            Redaction::Custom {
+                pattern: "<PORT:8080>".into(),
+                replacement: "<PORT>".into(),
+            },
+        ];
+        assert_eq!(redact(&rules, "listening on port 8080"), "listening on port <PORT>");
+
+        let bad = [Redaction::Custom {
+            pattern: "(".into(),
+            replacement: String::new(),
+        }];
+        assert!(matches!(Redactor::new(&bad), Err(RedactError::Pattern { .. })));
+
+        let long = [Redaction::Custom {
+            pattern: "a".repeat(MAX_PATTERN_LEN + 1),
+            replacement: String::new(),
+        }];
+        assert!(matches!(Redactor::new(&long), Err(RedactError::PatternTooLong { .. })));
+    }
+
+    #[test]
+    fn test_parse_redaction_names() {
+        assert_eq!("temp-path".parse::<Redaction>().ok(), Some(Redaction::TempPath));
+        assert_eq!("PIDs".parse::<Redaction>().ok(), Some(Redaction::Pid));
+        assert!("colors".parse::<Redaction>().is_err());
+    }
+}
@@ -0,0 +1,308 @@
+//! JUnit XML and TAP reports of checks, for CI.
+//!
+//! `assert`, `wait` and `script` runs append their results to a report file,
+//! so many checks across many agents roll up into one report. Each append
+//! rewrites the file with the earlier results and the new ones, holding an
+//! exclusive lock so checks running in parallel don't lose each other's
+//! results. Failed checks carry the reason and the screen at the time.
+
+use nix::fcntl::{Flock, FlockArg};
+use regex::Regex;
+use std::fmt::Write as _;
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Errors from writing a report.
+#[derive(Debug, Error)]
+pub enum ReportError {
+    #[error("unknown report format {0:?} (expected junit or tap)")]
+    UnknownFormat(String),
+
+    #[error("can't tell the report format of {}; use a .xml or .tap file, or --report-format", .0.display())]
+    NoFormat(PathBuf),
+
+    #[error("failed to write report {}: {source}", .path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+/// Report file format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// JUnit XML, one `testcase` per check.
+    Junit,
+    /// Test Anything Protocol, version 13.
+    Tap,
+}
+
+impl FromStr for ReportFormat {
+    type Err = ReportError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "junit" | "xml" => Ok(Self::Junit),
+            "tap" => Ok(Self::Tap),
+            _ => Err(ReportError::UnknownFormat(s.to_string())),
+        }
+    }
+}
+
+/// The result of one check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckResult {
+    /// What was checked, e.g. `assert contains "ready"`.
+    pub name: String,
+    /// The agent checked.
+    pub agent: Option<String>,
+    pub passed: bool,
+    pub elapsed: Duration,
+    /// Why the check failed.
+    pub message: Option<String>,
+    /// The agent's screen when the check failed.
+    pub snapshot: Option<String>,
+}
+
+/// A report file that check results are appended to.
+#[derive(Debug, Clone)]
+pub struct Report {
+    path: PathBuf,
+    format: ReportFormat,
+}
+
+impl Report {
+    /// A report at `path`, in `format` or else the one its extension implies.
+    pub fn new(path: impl Into<PathBuf>, format: Option<ReportFormat>) -> Result<Self, ReportError> {
+        let path = path.into();
+        let format = match format {
+            Some(format) => format,
+            None => match path.extension().and_then(|ext| ext.to_str()) {
+                Some(ext) => ext.parse().map_err(|_| ReportError::NoFormat(path.clone()))?,
+                None => return Err(ReportError::NoFormat(path)),
+            },
+        };
+        Ok(Self { path, format })
+    }
+
+    /// Add results to the report, keeping the ones already in it.
+    pub fn append(&self, results: &[CheckResult]) -> Result<(), ReportError> {
+        let io = |source| ReportError::Io {
+            path: self.path.clone(),
+            source,
+        };
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.path)
+            .map_err(io)?;
+        let mut file = Flock::lock(file, FlockArg::LockExclusive).map_err(|(_, e)| io(e.into()))?;
+
+        let mut existing = String::new();
+        file.read_to_string(&mut existing).map_err(io)?;
+        let content = match self.format {
+            ReportFormat::Junit => junit(&existing, results),
+            ReportFormat::Tap => tap(&existing, results),
+        };
+        file.set_len(0).map_err(io)?;
+        file.rewind().map_err(io)?;
+        file.write_all(content.as_bytes()).map_err(io)?;
+        Ok(())
+    }
+
+    /// Where the report is written.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// A JUnit XML document with the test cases of `existing` and then `results`.
+fn junit(existing: &str, results: &[CheckResult]) -> String {
+    let testcase =
+        Regex::new(r"(?s)<testcase [^>]*?(?:/>|>.*?</testcase>)").expect("valid testcase pattern");
+    let time = Regex::new(r#"^<testcase [^>]*time="([0-9.]+)""#).expect("valid time pattern");
+
+    let mut cases: Vec<String> = testcase
+        .find_iter(existing)
+        .map(|m| m.as_str().to_string())
+        .collect();
+    for result in results {
+        cases.push(junit_case(result));
+    }
+
+    let failures = cases.iter().filter(|case| case.contains("<failure")).count();
+    let seconds: f64 = cases
+        .iter()
+        .filter_map(|case| time.captures(case)?[1].parse::<f64>().ok())
+        .sum();
+    let totals = format!(r#"tests="{}" failures="{failures}" time="{seconds:.3}""#, cases.len());
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(out, "<testsuites {totals}>");
+    let _ = writeln!(out, r#"  <testsuite name="botty" {totals}>"#);
+    for case in &cases {
+        let _ = writeln!(out, "    {case}");
+    }
+    out.push_str("  </testsuite>\n</testsuites>\n");
+    out
+}
+
+fn junit_case(result: &CheckResult) -> String {
+    let attrs = format!(
+        r#"name="{}" classname="{}" time="{:.3}""#,
+        xml_escape(&result.name),
+        xml_escape(result.agent.as_deref().unwrap_or("botty")),
+        result.elapsed.as_secs_f64()
+    );
+    if result.passed {
+        return format!("<testcase {attrs}/>");
+    }
+    let message = xml_escape(result.message.as_deref().unwrap_or("check failed"));
+    let mut case = format!("<testcase {attrs}>\n      <failure message=\"{message}\">{message}</failure>\n");
+    if let Some(snapshot) = &result.snapshot {
+        let _ = writeln!(case, "      <system-out>{}</system-out>", xml_escape(snapshot));
+    }
+    case.push_str("    </testcase>");
+    case
+}
+
+/// Escape text for XML, dropping control characters XML can't hold.
+fn xml_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\n' | '\t' | '\r' => out.push(c),
+            c if c.is_control() => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// A TAP document with the test points of `existing` and then `results`,
+/// numbered on from the existing ones, with the plan at the end.
+fn tap(existing: &str, results: &[CheckResult]) -> String {
+    let mut out = String::from("TAP version 13\n");
+    let mut count = 0;
+    for line in existing.lines() {
+        let is_plan = line.strip_prefix("1..").is_some_and(|n| n.parse::<u64>().is_ok());
+        if is_plan || line.starts_with("TAP version") {
+            continue;
+        }
+        if line.starts_with("ok ") || line.starts_with("not ok ") {
+            count += 1;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+
+    for result in results {
+        count += 1;
+        let status = if result.passed { "ok" } else { "not ok" };
+        let description = result
+            .agent
+            .as_ref()
+            .map_or_else(|| result.name.clone(), |agent| format!("{agent}: {}", result.name));
+        let description = description.replace('#', "\\#").replace('\n', " ");
+        let _ = writeln!(out, "{status} {count} - {description}");
+        out.push_str("  ---\n");
+        let _ = writeln!(out, "  duration_ms: {}", result.elapsed.as_millis());
+        if let Some(message) = &result.message {
+            let _ = writeln!(out, "  message: {}", yaml_string(message));
+        }
+        if let Some(snapshot) = &result.snapshot {
+            out.push_str("  snapshot: |2\n");
+            for line in snapshot.lines() {
+                let _ = writeln!(out, "    {}", line.replace(|c: char| c.is_control() && c != '\t', ""));
+            }
+        }
+        out.push_str("  ...\n");
+    }
+
+    let _ = writeln!(out, "1..{count}");
+    out
+}
+
+/// A double-quoted YAML string (JSON strings are valid YAML).
+fn yaml_string(s: &str) -> String {
+    serde_json::to_string(s).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn results() -> Vec<CheckResult> {
+        vec![
+            CheckResult {
+                name: "assert contains \"ready\"".into(),
+                agent: Some("calm-fox".into()),
+                passed: true,
+                elapsed: Duration::from_millis(120),
+                message: None,
+                snapshot: None,
+            },
+            CheckResult {
+                name: "wait pattern \"done #1\"".into(),
+                agent: Some("calm-fox".into()),
+                passed: false,
+                elapsed: Duration::from_secs(2),
+                message: Some("timeout waiting for condition".into()),
+                snapshot: Some("$ make <all>\n  building\x07".into()),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_junit_report_rolls_up() {
+        let first = junit("", &results()[..1]);
+        assert!(first.contains(r#"<testsuite name="botty" tests="1" failures="0" time="0.120">"#));
+
+        let both = junit(&first, &results()[1..]);
+        assert!(both.contains(r#"tests="2" failures="1" time="2.120""#), "{both}");
+        assert!(both.contains(
+            r#"<testcase name="assert contains &quot;ready&quot;" classname="calm-fox" time="0.120"/>"#
+        ));
+        assert!(both.contains(r#"<failure message="timeout waiting for condition">"#));
+        assert!(both.contains("<system-out>$ make &lt;all&gt;\n  building</system-out>"));
+        assert_eq!(junit(&both, &[]), both);
+    }
+
+    #[test]
+    fn test_tap_report_rolls_up() {
+        let first = tap("", &results()[..1]);
+        assert_eq!(
+            first,
+            "TAP version 13\nok 1 - calm-fox: assert contains \"ready\"\n  ---\n  duration_ms: 120\n  ...\n1..1\n"
+        );
+
+        let both = tap(&first, &results()[1..]);
+        assert!(both.starts_with("TAP version 13\nok 1 - "));
+        assert!(both.contains("not ok 2 - calm-fox: wait pattern \"done \\#1\"\n"));
+        assert!(both.contains("  message: \"timeout waiting for condition\"\n"));
+        assert!(both.contains("  snapshot: |2\n    $ make <all>\n      building\n  ...\n"));
+        assert!(both.ends_with("1..2\n"));
+        assert_eq!(both.matches("TAP version").count(), 1);
+    }
+
+    #[test]
+    fn test_report_format() {
+        assert_eq!(Report::new("out.xml", None).expect("xml").format, ReportFormat::Junit);
+        assert_eq!(Report::new("out.tap", None).expect("tap").format, ReportFormat::Tap);
+        let explicit = Report::new("results.txt", Some(ReportFormat::Tap)).expect("explicit");
+        assert_eq!(explicit.format, ReportFormat::Tap);
+        assert!(matches!(Report::new("results", None), Err(ReportError::NoFormat(_))));
+        assert!("yaml".parse::<ReportFormat>().is_err());
+    }
+}
@@ -0,0 +1,619 @@
+//! Declarative expect-style scripts.
+//!
+//! A script is a JSON document with a list of steps that drive one or more
+//! agents through the typed client API:
+//!
+//! ```json
+//! {
+//!   "timeout": 10,
+//!   "steps": [
+//!     { "spawn": { "cmd": ["bash", "--norc"] }, "agent": "sh" },
+//!     { "send": "python3" },
+//!     { "expect": ">>> " },
+//!     { "send": "print(6 * 7)" },
+//!     { "expect": "^42$", "timeout": 2 },
+//!     { "expect_not": "Traceback" },
+//!     { "snapshot": "repl.txt" },
+//!     { "if": { "match": "Python 2", "then": [{ "send": "exit" }] } },
+//!     { "send_keys": ["ctrl-d"] }
+//!   ]
+//! }
+//! ```
+//!
+//! Every step may name the `agent` it targets; otherwise the most recently
+//! used agent is targeted. Execution stops at the first failing step and the
+//! outcome of every executed step is collected in a [`ScriptReport`].
+
+use crate::cli::parse_key_sequence;
+use crate::client::{Client, ClientError};
+use crate::protocol::{AgentId, SpawnOptions};
+use crate::report::CheckResult;
+use crate::MAX_PATTERN_LEN;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// How often the screen is polled while expecting.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Errors from loading or running a script.
+#[derive(Debug, Error)]
+pub enum ScriptError {
+    #[error("failed to read script {path}: {source}")]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("invalid script: {0}")]
+    Parse(#[from] serde_json::Error),
+
+    #[error("invalid regex {pattern:?}: {message}")]
+    Pattern { pattern: String, message: String },
+
+    #[error("no agent to target (spawn one or set \"agent\")")]
+    NoAgent,
+
+    #[error("unknown key: {0}")]
+    UnknownKey(String),
+
+    #[error("timeout waiting for {0:?}")]
+    Timeout(String),
+
+    #[error("unexpected match for {0:?}")]
+    UnexpectedMatch(String),
+
+    #[error("invalid timeout {0} (expected a non-negative number of seconds)")]
+    InvalidTimeout(f64),
+
+    #[error("failed to write {path}: {source}")]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error(transparent)]
+    Client(#[from] ClientError),
+}
+
+/// A parsed script.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Script {
+    /// Default agent for steps that don't name one.
+    #[serde(default)]
+    pub agent: Option<String>,
+    /// Default timeout in seconds for `expect`, `expect_not` and `if`.
+    #[serde(default = "default_timeout")]
+    pub timeout: f64,
+    /// Steps to run in order.
+    pub steps: Vec<Step>,
+}
+
+const fn default_timeout() -> f64 {
+    10.0
+}
+
+/// A single script step.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "RawStep")]
+pub struct Step {
+    /// What the step does.
+    pub action: Action,
+    /// Agent to target (for `spawn`, the name to give the new agent).
+    pub agent: Option<String>,
+    /// Timeout in seconds, overriding the script default.
+    pub timeout: Option<f64>,
+}
+
+/// The action performed by a step.
+#[derive(Debug, Clone)]
+pub enum Action {
+    /// Spawn a new agent; it becomes the current agent.
+    Spawn(SpawnStep),
+    /// Send a line of text (a newline is appended).
+    Send(String),
+    /// Send named keys (see `botty send-keys`).
+    SendKeys(Vec<String>),
+    /// Wait until the screen matches a regex.
+    Expect(String),
+    /// Wait until the screen no longer matches a regex (immediately passes if absent).
+    ExpectNot(String),
+    /// Write the current screen to a file.
+    Snapshot(PathBuf),
+    /// Pause for the given number of milliseconds.
+    Sleep(u64),
+    /// Run `then` if the screen matches within the timeout, `else` otherwise.
+    If(Branch),
+}
+
+/// Parameters for a `spawn` step.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SpawnStep {
+    /// Command and arguments.
+    pub cmd: Vec<String>,
+    /// Terminal rows.
+    #[serde(default)]
+    pub rows: Option<u16>,
+    /// Terminal columns.
+    #[serde(default)]
+    pub cols: Option<u16>,
+    /// Labels for the agent.
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Environment variables (KEY=VALUE).
+    #[serde(default)]
+    pub env: Vec<String>,
+}
+
+/// Parameters for an `if` step.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Branch {
+    /// Regex to test against the screen.
+    #[serde(rename = "match")]
+    pub pattern: String,
+    /// Steps to run when the pattern matches.
+    #[serde(default)]
+    pub then: Vec<Step>,
+    /// Steps to run when it doesn't.
+    #[serde(default, rename = "else")]
+    pub otherwise: Vec<Step>,
+}
+
+/// A step as written, with one field per action.
+///
+/// `deny_unknown_fields` doesn't work through `#[serde(flatten)]`, so steps
+/// are read into this first and checked for exactly one action.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawStep {
+    spawn: Option<SpawnStep>,
+    send: Option<String>,
+    send_keys: Option<Vec<String>>,
+    expect: Option<String>,
+    expect_not: Option<String>,
+    snapshot: Option<PathBuf>,
+    sleep: Option<u64>,
+    #[serde(rename = "if")]
+    branch: Option<Branch>,
+    agent: Option<String>,
+    timeout: Option<f64>,
+}
+
+impl TryFrom<RawStep> for Step {
+    type Error = String;
+
+    fn try_from(raw: RawStep) -> Result<Self, Self::Error> {
+        if let Some(timeout) = raw.timeout {
+            seconds(timeout).map_err(|e| e.to_string())?;
+        }
+        let actions = [
+            raw.spawn.map(Action::Spawn),
+            raw.send.map(Action::Send),
+            raw.send_keys.map(Action::SendKeys),
+            raw.expect.map(Action::Expect),
+            raw.expect_not.map(Action::ExpectNot),
+            raw.snapshot.map(Action::Snapshot),
+            raw.sleep.map(Action::Sleep),
+            raw.branch.map(Action::If),
+        ];
+        let mut actions: Vec<Action> = actions.into_iter().flatten().collect();
+        match actions.len() {
+            0 => Err("step has no action (expected one of: spawn, send, send_keys, expect, expect_not, snapshot, sleep, if)".into()),
+            1 => Ok(Self {
+                action: actions.remove(0),
+                agent: raw.agent,
+                timeout: raw.timeout,
+            }),
+            _ => {
+                let names: Vec<_> = actions.iter().map(Action::name).collect();
+                Err(format!("step has more than one action: {}", names.join(", ")))
+            }
+        }
+    }
+}
+
+impl Action {
+    /// Short name used in reports.
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Spawn(_) => "spawn",
+            Self::Send(_) => "send",
+            Self::SendKeys(_) => "send_keys",
+            Self::Expect(_) => "expect",
+            Self::ExpectNot(_) => "expect_not",
+            Self::Snapshot(_) => "snapshot",
+            Self::Sleep(_) => "sleep",
+            Self::If(_) => "if",
+        }
+    }
+
+    /// The pattern a step checks the screen for, if it checks it.
+    #[must_use]
+    pub fn condition(&self) -> Option<&str> {
+        match self {
+            Self::Expect(pattern) | Self::ExpectNot(pattern) => Some(pattern),
+            Self::If(branch) => Some(&branch.pattern),
+            _ => None,
+        }
+    }
+}
+
+impl Script {
+    /// Parse a script from JSON text.
+    pub fn parse(text: &str) -> Result<Self, ScriptError> {
+        let script: Self = serde_json::from_str(text)?;
+        seconds(script.timeout)?;
+        Ok(script)
+    }
+
+    /// Load a script from a file.
+    pub fn from_file(path: &Path) -> Result<Self, ScriptError> {
+        let text = std::fs::read_to_string(path).map_err(|source| ScriptError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::parse(&text)
+    }
+}
+
+/// Outcome of a single step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    Passed,
+    Failed,
+}
+
+/// Report entry for one executed step.
+#[derive(Debug, Clone, Serialize)]
+pub struct StepReport {
+    /// Position in the script, e.g. `"3"` or `"5.then.0"` for nested steps.
+    pub step: String,
+    /// Action name (`send`, `expect`, ...).
+    pub action: &'static str,
+    /// Agent the step targeted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agent: Option<String>,
+    pub status: StepStatus,
+    pub elapsed_ms: u64,
+    /// The pattern the step checked the screen for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub condition: Option<String>,
+    /// Step-specific detail: the matched text, the branch taken, the spawned ID.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The agent's screen when the step failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<String>,
+}
+
+/// Report for a whole script run.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScriptReport {
+    pub passed: bool,
+    pub steps: Vec<StepReport>,
+    /// Agents spawned by the script.
+    pub spawned: Vec<AgentId>,
+}
+
+impl ScriptReport {
+    /// One check result per step, named after `script`, for a CI report.
+    #[must_use]
+    pub fn checks(&self, script: &str) -> Vec<CheckResult> {
+        self.steps
+            .iter()
+            .map(|step| {
+                let mut name = format!("{script}: step {} {}", step.step, step.action);
+                if let Some(condition) = &step.condition {
+                    let _ = write!(name, " {condition:?}");
+                }
+                CheckResult {
+                    name,
+                    agent: step.agent.clone(),
+                    passed: step.status == StepStatus::Passed,
+                    elapsed: Duration::from_millis(step.elapsed_ms),
+                    message: step.error.clone(),
+                    snapshot: step.snapshot.clone(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Runs a script against a server.
+pub struct ScriptRunner<'a> {
+    client: &'a mut Client,
+    /// Default timeout in seconds.
+    default_timeout: f64,
+    current: Option<String>,
+    spawned: Vec<AgentId>,
+    reports: Vec<StepReport>,
+}
+
+impl<'a> ScriptRunner<'a> {
+    /// Create a runner that talks to the server through `client`.
+    pub const fn new(client: &'a mut Client) -> Self {
+        Self {
+            client,
+            default_timeout: default_timeout(),
+            current: None,
+            spawned: Vec::new(),
+            reports: Vec::new(),
+        }
+    }
+
+    /// Run the script to completion or first failure.
+    pub async fn run(mut self, script: &Script) -> ScriptReport {
+        self.default_timeout = script.timeout;
+        self.current.clone_from(&script.agent);
+        let passed = self.run_steps(&script.steps, String::new()).await;
+        ScriptReport {
+            passed,
+            steps: self.reports,
+            spawned: self.spawned,
+        }
+    }
+
+    /// Run a list of steps, returning false as soon as one fails.
+    fn run_steps<'s>(
+        &'s mut self,
+        steps: &'s [Step],
+        prefix: String,
+    ) -> Pin<Box<dyn Future<Output = bool> + Send + 's>> {
+        Box::pin(async move {
+            for (i, step) in steps.iter().enumerate() {
+                let label = format!("{prefix}{i}");
+                let started = Instant::now();
+                let outcome = self.run_step(step).await;
+                let elapsed_ms = u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX);
+
+                let (status, detail, error, branch) = match outcome {
+                    Ok((detail, branch)) => (StepStatus::Passed, detail, None, branch),
+                    Err(e) => (StepStatus::Failed, None, Some(e.to_string()), None),
+                };
+                let snapshot = match (&self.current, status) {
+                    (Some(id), StepStatus::Failed) => {
+                        self.client.snapshot(id).await.ok().map(|s| s.content)
+                    }
+                    _ => None,
+                };
+                self.reports.push(StepReport {
+                    step: label.clone(),
+                    action: step.action.name(),
+                    agent: self.current.clone(),
+                    status,
+                    elapsed_ms,
+                    condition: step.action.condition().map(ToString::to_string),
+                    detail,
+                    error,
+                    snapshot,
+                });
+                if status == StepStatus::Failed {
+                    return false;
+                }
+
+                // Descend into the branch chosen by an `if` step
+                if let (Action::If(branch), Some(taken)) = (&step.action, branch) {
+                    let (name, nested) = if taken {
+                        ("then", &branch.then)
+                    } else {
+                        ("else", &branch.otherwise)
+                    };
+                    if !self.run_steps(nested, format!("{label}.{name}.")).await {
+                        return false;
+                    }
+                }
+            }
+            true
+        })
+    }
+
+    /// Run one step. Returns an optional detail and, for `if`, whether it matched.
+    async fn run_step(&mut self, step: &Step) -> Result<(Option<String>, Option<bool>), ScriptError> {
+        let timeout = seconds(step.timeout.unwrap_or(self.default_timeout))?;
+
+        if let Action::Spawn(spawn) = &step.action {
+            let mut options = SpawnOptions::new(spawn.cmd.iter());
+            options.name.clone_from(&step.agent);
+            options.labels.clone_from(&spawn.labels);
+            options.env.clone_from(&spawn.env);
+            if let Some(rows) = spawn.rows {
+                options.rows = rows;
+            }
+            if let Some(cols) = spawn.cols {
+                options.cols = cols;
+            }
+            let id = self.client.spawn(options).await?;
+            self.spawned.push(id.clone());
+            self.current = Some(id.clone());
+            return Ok((Some(id), None));
+        }
+
+        if let Some(agent) = &step.agent {
+            self.current = Some(agent.clone());
+        }
+        let id = self.current.clone().ok_or(ScriptError::NoAgent)?;
+
+        match &step.action {
+            Action::Spawn(_) => unreachable!("handled above"),
+            Action::Send(text) => {
+                self.client.send(&id, text, true).await?;
+                Ok((None, None))
+            }
+            Action::SendKeys(keys) => {
+                if let Some(key) = keys.iter().find(|k| parse_key_sequence(k).is_none()) {
+                    return Err(ScriptError::UnknownKey(key.clone()));
+                }
+                self.client.send_keys(&id, keys).await?;
+                Ok((None, None))
+            }
+            Action::Expect(pattern) => {
+                let re = compile(pattern)?;
+                let matched = self.poll(&id, timeout, |screen| find(&re, screen)).await?;
+                let matched = matched.ok_or_else(|| ScriptError::Timeout(pattern.clone()))?;
+                Ok((Some(matched), None))
+            }
+            Action::ExpectNot(pattern) => {
+                let re = compile(pattern)?;
+                let absent = |screen: &str| (!re.is_match(screen)).then_some(());
+                self.poll(&id, timeout, absent)
+                    .await?
+                    .ok_or_else(|| ScriptError::UnexpectedMatch(pattern.clone()))?;
+                Ok((None, None))
+            }
+            Action::Snapshot(path) => {
+                let snapshot = self.client.snapshot(&id).await?;
+                std::fs::write(path, snapshot.content).map_err(|source| ScriptError::Write {
+                    path: path.clone(),
+                    source,
+                })?;
+                Ok((Some(path.display().to_string()), None))
+            }
+            Action::Sleep(ms) => {
+                tokio::time::sleep(Duration::from_millis(*ms)).await;
+                Ok((None, None))
+            }
+            Action::If(branch) => {
+                let re = compile(&branch.pattern)?;
+                // Branch conditions default to an immediate check
+                let timeout = step.timeout.map_or(Duration::ZERO, |_| timeout);
+                let matched = self.poll(&id, timeout, |screen| find(&re, screen)).await?;
+                let detail = if matched.is_some() { "then" } else { "else" };
+                Ok((Some(detail.to_string()), Some(matched.is_some())))
+            }
+        }
+    }
+
+    /// Poll the agent's screen until `check` returns a value or the timeout expires.
+    async fn poll<T>(
+        &mut self,
+        id: &str,
+        timeout: Duration,
+        check: impl Fn(&str) -> Option<T> + Send,
+    ) -> Result<Option<T>, ScriptError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let screen = self.client.snapshot(id).await?.content;
+            if let Some(value) = check(&screen) {
+                return Ok(Some(value));
+            }
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+}
+
+/// A timeout in seconds as a duration, rejecting negative, non-finite and
+/// out of range values.
+fn seconds(timeout: f64) -> Result<Duration, ScriptError> {
+    Duration::try_from_secs_f64(timeout).map_err(|_| ScriptError::InvalidTimeout(timeout))
+}
+
+/// Compile a script regex in multi-line mode so `^`/`$` match screen lines.
+fn compile(pattern: &str) -> Result<Regex, ScriptError> {
+    let invalid = |message: String| ScriptError::Pattern {
+        pattern: pattern.to_string(),
+        message,
+    };
+    if pattern.len() > MAX_PATTERN_LEN {
+        return Err(invalid(format!("too long (max {MAX_PATTERN_LEN} chars)")));
+    }
+    Regex::new(&format!("(?m){pattern}")).map_err(|e| invalid(e.to_string()))
+}
+
+fn find(re: &Regex, screen: &str) -> Option<String> {
+    re.find(screen).map(|m| m.as_str().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_script() {
+        let script = Script::parse(
+            r#"{
+                "agent": "repl",
+                "steps": [
+                    { "send": "python3" },
+                    { "expect": ">>> ", "timeout": 2.5 },
+                    { "send_keys": ["ctrl-d"], "agent": "other" },
+                    { "if": { "match": "Error", "then": [{ "snapshot": "err.txt" }] } }
+                ]
+            }"#,
+        )
+        .expect("parse failed");
+
+        assert_eq!(script.agent.as_deref(), Some("repl"));
+        assert!((script.timeout - 10.0).abs() < f64::EPSILON);
+        assert_eq!(script.steps.len(), 4);
+        assert!(matches!(script.steps[0].action, Action::Send(ref t) if t == "python3"));
+        assert_eq!(script.steps[1].timeout, Some(2.5));
+        assert_eq!(script.steps[2].agent.as_deref(), Some("other"));
+        let Action::If(branch) = &script.steps[3].action else {
+            panic!("expected if step");
+        };
+        assert_eq!(branch.pattern, "Error");
+        assert_eq!(branch.then.len(), 1);
+        assert!(branch.otherwise.is_empty());
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown_action() {
+        assert!(Script::parse(r#"{ "steps": [{ "type": "hello" }] }"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed_steps() {
+        let unknown = Script::parse(r#"{ "steps": [{ "send": "ls", "agnet": "sh" }] }"#);
+        assert!(unknown.expect_err("unknown key").to_string().contains("agnet"));
+
+        let two = Script::parse(r#"{ "steps": [{ "send": "ls", "expect": "\\$" }] }"#);
+        let err = two.expect_err("two actions").to_string();
+        assert!(err.contains("more than one action: send, expect"), "{err}");
+
+        let none = Script::parse(r#"{ "steps": [{ "agent": "sh" }] }"#);
+        assert!(none.expect_err("no action").to_string().contains("no action"));
+
+        // Nested steps are checked too
+        let nested = r#"{ "steps": [{ "if": { "match": "x", "then": [{ "sned": "y" }] } }] }"#;
+        assert!(Script::parse(nested).is_err());
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_timeouts() {
+        for script in [
+            r#"{ "timeout": -1, "steps": [] }"#,
+            r#"{ "timeout": 1e300, "steps": [] }"#,
+            r#"{ "steps": [{ "expect": "x", "timeout": -0.5 }] }"#,
+            r#"{ "steps": [{ "expect": "x", "timeout": 1e300 }] }"#,
+        ] {
+            assert!(Script::parse(script).is_err(), "{script}");
+        }
+        assert!(matches!(seconds(f64::NAN), Err(ScriptError::InvalidTimeout(_))));
+        assert!(matches!(seconds(f64::INFINITY), Err(ScriptError::InvalidTimeout(_))));
+        assert_eq!(seconds(2.5).expect("valid"), Duration::from_millis(2500));
+    }
+
+    #[test]
+    fn test_compile_is_multiline() {
+        let re = compile("^42$").expect("compile failed");
+        assert!(re.is_match(">>> print(42)\n42\n>>> "));
+        assert!(compile(&"a".repeat(MAX_PATTERN_LEN + 1)).is_err());
+    }
+}
@@ -0,0 +1,308 @@
+//! Label selectors: expressions that pick agents by labels and annotations.
+//!
+//! A selector is a comma-separated list of requirements that must all hold.
+//! Alternatives separated by `|` need only one of them to hold:
+//!
+//! - `worker`: has the label `worker`
+//! - `!canary`: doesn't have the label `canary` (`!` negates any requirement)
+//! - `tier=worker` or `tier==worker`: annotation `tier` is `worker`
+//! - `tier!=worker`: annotation `tier` is missing or something else
+//! - `env in (dev,test)`, `env notin (dev,test)`: annotation value in (or not in) the set
+//! - `leader|reviewer`: has either label
+//!
+//! `key=value` requirements also match a literal `key=value` label, so agents
+//! labeled that way before annotations existed are still selected. The empty
+//! selector matches every agent.
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Errors from parsing a selector.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SelectorError {
+    #[error("invalid selector requirement {requirement:?}: {reason}")]
+    Requirement {
+        requirement: String,
+        reason: &'static str,
+    },
+
+    #[error("unbalanced parentheses in selector {0:?}")]
+    Unbalanced(String),
+}
+
+/// A parsed selector: all terms must match, and a term matches if any of its
+/// requirements does.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selector {
+    terms: Vec<Vec<Requirement>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Requirement {
+    /// Has the label (negated: doesn't).
+    Label { name: String, negated: bool },
+    /// Annotation `key` is one of `values` (negated: is missing or none of them).
+    Annotation {
+        key: String,
+        values: Vec<String>,
+        negated: bool,
+    },
+}
+
+impl Selector {
+    /// Parse a selector expression.
+    pub fn parse(s: &str) -> Result<Self, SelectorError> {
+        if s.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        let terms = split_top_level(s, ',')?
+            .into_iter()
+            .map(|term| {
+                split_top_level(term, '|')?
+                    .into_iter()
+                    .map(Requirement::parse)
+                    .collect()
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { terms })
+    }
+
+    /// Also require every one of `labels`.
+    #[must_use]
+    pub fn with_labels(mut self, labels: &[String]) -> Self {
+        self.terms.extend(labels.iter().map(|name| {
+            vec![Requirement::Label {
+                name: name.clone(),
+                negated: false,
+            }]
+        }));
+        self
+    }
+
+    /// Also require every one of the annotation values.
+    #[must_use]
+    pub fn with_annotations(mut self, annotations: &BTreeMap<String, String>) -> Self {
+        self.terms.extend(annotations.iter().map(|(key, value)| {
+            vec![Requirement::Annotation {
+                key: key.clone(),
+                values: vec![value.clone()],
+                negated: false,
+            }]
+        }));
+        self
+    }
+
+    /// Whether this selector matches every agent.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Check an agent's labels and annotations against the selector.
+    #[must_use]
+    pub fn matches(&self, labels: &[String], annotations: &BTreeMap<String, String>) -> bool {
+        self.terms
+            .iter()
+            .all(|term| term.iter().any(|r| r.matches(labels, annotations)))
+    }
+}
+
+impl FromStr for Selector {
+    type Err = SelectorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl Requirement {
+    fn parse(s: &str) -> Result<Self, SelectorError> {
+        let s = s.trim();
+        let invalid = |reason| SelectorError::Requirement {
+            requirement: s.to_string(),
+            reason,
+        };
+
+        if let Some(rest) = s.strip_prefix('!') {
+            return Ok(Self::parse(rest)?.negate());
+        }
+
+        // Set membership: `key in (a,b)` / `key notin (a,b)`
+        if let Some(inner) = s.strip_suffix(')') {
+            let (head, values) = inner.split_once('(').ok_or_else(|| invalid("missing '('"))?;
+            let mut words = head.split_whitespace();
+            let (Some(key), Some(op), None) = (words.next(), words.next(), words.next()) else {
+                return Err(invalid("expected `key in (values)` or `key notin (values)`"));
+            };
+            let negated = match op {
+                "in" => false,
+                "notin" => true,
+                _ => return Err(invalid("expected `in` or `notin`")),
+            };
+            let values: Vec<String> = values.split(',').map(|v| v.trim().to_string()).collect();
+            if values.iter().any(|v| !is_value(v)) {
+                return Err(invalid("invalid value in set"));
+            }
+            return Ok(Self::Annotation {
+                key: name(key).ok_or_else(|| invalid("invalid key"))?,
+                values,
+                negated,
+            });
+        }
+
+        let (key, value, negated) = if let Some((k, v)) = s.split_once("!=") {
+            (k, v, true)
+        } else if let Some((k, v)) = s.split_once("==").or_else(|| s.split_once('=')) {
+            (k, v, false)
+        } else {
+            let name = name(s).ok_or_else(|| invalid("invalid label"))?;
+            return Ok(Self::Label {
+                name,
+                negated: false,
+            });
+        };
+        let value = value.trim();
+        if !is_value(value) {
+            return Err(invalid("invalid value"));
+        }
+        Ok(Self::Annotation {
+            key: name(key.trim()).ok_or_else(|| invalid("invalid key"))?,
+            values: vec![value.to_string()],
+            negated,
+        })
+    }
+
+    fn negate(self) -> Self {
+        match self {
+            Self::Label { name, negated } => Self::Label {
+                name,
+                negated: !negated,
+            },
+            Self::Annotation {
+                key,
+                values,
+                negated,
+            } => Self::Annotation {
+                key,
+                values,
+                negated: !negated,
+            },
+        }
+    }
+
+    fn matches(&self, labels: &[String], annotations: &BTreeMap<String, String>) -> bool {
+        match self {
+            Self::Label { name, negated } => labels.contains(name) != *negated,
+            Self::Annotation {
+                key,
+                values,
+                negated,
+            } => {
+                let found = values.iter().any(|value| {
+                    annotations.get(key) == Some(value)
+                        || labels.iter().any(|l| l.split_once('=') == Some((key, value)))
+                });
+                found != *negated
+            }
+        }
+    }
+}
+
+/// Split on `sep` outside parentheses.
+fn split_top_level(s: &str, sep: char) -> Result<Vec<&str>, SelectorError> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth = depth
+                    .checked_sub(1)
+                    .ok_or_else(|| SelectorError::Unbalanced(s.to_string()))?;
+            }
+            c if c == sep && depth == 0 => {
+                parts.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return Err(SelectorError::Unbalanced(s.to_string()));
+    }
+    parts.push(&s[start..]);
+    Ok(parts)
+}
+
+/// A label name or annotation key: non-empty, no whitespace or operators.
+fn name(s: &str) -> Option<String> {
+    let valid = !s.is_empty()
+        && !s.contains(|c: char| c.is_whitespace() || ",()|!=".contains(c));
+    valid.then(|| s.to_string())
+}
+
+/// An annotation value: may be empty, but no operators or parentheses.
+fn is_value(s: &str) -> bool {
+    !s.contains(|c: char| ",()|!=".contains(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn agent(labels: &[&str], annotations: &[(&str, &str)]) -> (Vec<String>, BTreeMap<String, String>) {
+        (
+            labels.iter().map(ToString::to_string).collect(),
+            annotations.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        )
+    }
+
+    fn matches(selector: &str, (labels, annotations): &(Vec<String>, BTreeMap<String, String>)) -> bool {
+        Selector::parse(selector).expect("parse").matches(labels, annotations)
+    }
+
+    #[test]
+    fn test_selector_matching() {
+        let worker = agent(&["worker"], &[("tier", "backend"), ("env", "dev")]);
+        let leader = agent(&["worker", "leader"], &[("tier", "backend"), ("env", "prod")]);
+
+        assert!(matches("", &worker));
+        assert!(matches("worker,!leader", &worker));
+        assert!(!matches("worker,!leader", &leader));
+        assert!(matches("tier=backend", &leader));
+        assert!(matches("tier==backend,env!=prod", &worker));
+        assert!(matches("env in (dev, test)", &worker));
+        assert!(!matches("env notin (dev,test)", &worker));
+        assert!(matches("env notin (dev,test)", &leader));
+        assert!(matches("missing!=x", &worker));
+        assert!(matches("leader|env=dev", &worker));
+        assert!(!matches("!worker|env=prod", &worker));
+        assert!(matches("!tier=frontend", &worker));
+
+        // Old-style key=value labels still match
+        assert!(matches("env=prod", &agent(&["env=prod"], &[])));
+    }
+
+    #[test]
+    fn test_selector_with_labels_and_annotations() {
+        let (labels, annotations) = agent(&["worker"], &[("tier", "backend")]);
+        let selector = Selector::default()
+            .with_labels(&["worker".into()])
+            .with_annotations(&BTreeMap::from([("tier".into(), "backend".into())]));
+        assert!(selector.matches(&labels, &annotations));
+        assert!(!selector.with_labels(&["leader".into()]).matches(&labels, &annotations));
+    }
+
+    #[test]
+    fn test_selector_errors() {
+        for bad in ["a,,b", "env in (dev", "env in dev)", "env like (a)", "=x", "a b", "a=(b)"] {
+            assert!(Selector::parse(bad).is_err(), "{bad:?} should not parse");
+        }
+        assert_eq!(
+            Selector::parse("env in (dev").unwrap_err(),
+            SelectorError::Unbalanced("env in (dev".into())
+        );
+    }
+}
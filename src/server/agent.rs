@@ -1,9 +1,12 @@
 //! Agent representation.
 
+use super::input::{InputQueue, DEFAULT_MAX_INPUT};
 use super::screen::Screen;
 use super::transcript::Transcript;
 use crate::protocol::{ExitReason, ResourceLimits};
 use crate::pty::PtyProcess;
+use crate::selector::Selector;
+use std::collections::BTreeMap;
 use std::time::Instant;
 
 /// Internal agent state (different from `protocol::AgentState` for internal tracking).
@@ -21,6 +24,8 @@ pub struct Agent {
     pub command: Vec<String>,
     /// Labels for grouping agents.
     pub labels: Vec<String>,
+    /// Free-form key/value annotations.
+    pub annotations: BTreeMap<String, String>,
     /// The PTY process.
     pub pty: PtyProcess,
     /// Current state.
@@ -29,10 +34,14 @@ pub struct Agent {
     pub exit_reason: Option<ExitReason>,
     /// When the agent was started.
     pub started_at: Instant,
+    /// When the agent's exit was observed.
+    pub exited_at: Option<Instant>,
     /// Transcript buffer.
     pub transcript: Transcript,
     /// Virtual screen.
     pub screen: Screen,
+    /// Input waiting for the PTY to accept it.
+    pub input: InputQueue,
     /// Whether a client is currently attached to this agent.
     /// When attached, the background `pty_reader_task` should skip this agent
     /// since the attach bridge handles I/O directly.
@@ -43,6 +52,8 @@ pub struct Agent {
     pub sigterm_sent: bool,
     /// When SIGTERM was sent (for tracking grace period).
     pub sigterm_sent_at: Option<Instant>,
+    /// Whether a client has sent the agent a signal.
+    pub signalled: bool,
 }
 
 impl Agent {
@@ -61,21 +72,28 @@ impl Agent {
         let transcript_size = limits
             .and_then(|l| l.max_output)
             .map_or(1024 * 1024, |m| m as usize);
+        let input_limit = limits
+            .and_then(|l| l.max_input)
+            .unwrap_or(DEFAULT_MAX_INPUT);
 
         Self {
             id,
             command,
             labels,
+            annotations: BTreeMap::new(),
             pty,
             state: AgentState::Running,
             exit_reason: None,
             started_at: Instant::now(),
+            exited_at: None,
             transcript: Transcript::new(transcript_size),
             screen: Screen::new(rows, cols),
+            input: InputQueue::new(input_limit),
             attached: false,
             limits,
             sigterm_sent: false,
             sigterm_sent_at: None,
+            signalled: false,
         }
     }
 
@@ -83,13 +101,12 @@ impl Agent {
     /// Uses millisecond precision to avoid early triggering.
     #[must_use]
     pub fn is_timed_out(&self) -> bool {
-        if let Some(limits) = self.limits {
-            if let Some(timeout_secs) = limits.timeout {
+        if let Some(limits) = self.limits
+            && let Some(timeout_secs) = limits.timeout {
                 // Convert to millis for precision - timeout fires when elapsed >= timeout
                 let timeout_millis = timeout_secs * 1000;
                 return self.started_at.elapsed().as_millis() as u64 >= timeout_millis;
             }
-        }
         false
     }
 
@@ -110,6 +127,40 @@ impl Agent {
         labels.iter().all(|l| self.labels.contains(l))
     }
 
+    /// Check if the agent's labels and annotations match a selector.
+    #[must_use]
+    pub fn matches(&self, selector: &Selector) -> bool {
+        selector.matches(&self.labels, &self.annotations)
+    }
+
+    /// Remove then add labels, and unset then set annotations.
+    /// Returns whether anything changed.
+    pub fn relabel(
+        &mut self,
+        add: Vec<String>,
+        remove: &[String],
+        set: BTreeMap<String, String>,
+        unset: &[String],
+    ) -> bool {
+        let (labels, annotations) = (self.labels.clone(), self.annotations.clone());
+        self.labels.retain(|l| !remove.contains(l));
+        for label in add {
+            if !self.labels.contains(&label) {
+                self.labels.push(label);
+            }
+        }
+        self.annotations.retain(|k, _| !unset.contains(k));
+        self.annotations.extend(set);
+        self.labels != labels || self.annotations != annotations
+    }
+
+    /// Check if the agent runs one of the given programs, matched against the
+    /// program path or its file name.
+    #[must_use]
+    pub fn runs_command(&self, commands: &[String]) -> bool {
+        runs_command(&self.command, commands)
+    }
+
     /// Get the process ID.
     #[must_use]
     #[allow(clippy::cast_sign_loss)] // PIDs are always positive
@@ -124,6 +175,24 @@ impl Agent {
         matches!(self.state, AgentState::Running)
     }
 
+    /// Record that the agent exited with the given code, and why.
+    pub fn set_exited(&mut self, code: i32) {
+        self.state = AgentState::Exited { code };
+        self.exited_at = Some(Instant::now());
+        // Determine exit reason based on exit code:
+        // - 128 + signal_num indicates killed by signal
+        // - SIGTERM (15) -> 143, SIGKILL (9) -> 137
+        self.exit_reason = Some(if self.sigterm_sent && (code == 143 || code == 137) {
+            // Process was killed by our timeout signals
+            ExitReason::Timeout
+        } else if self.signalled && code > 128 {
+            // Died from a signal after a client sent one
+            ExitReason::Killed
+        } else {
+            ExitReason::Normal
+        });
+    }
+
     /// Get the exit code if the agent has exited.
     #[must_use]
     pub const fn exit_code(&self) -> Option<i32> {
@@ -133,3 +202,17 @@ impl Agent {
         }
     }
 }
+
+/// Check if `command` runs one of the given programs, matched against the
+/// program path or its file name.
+#[must_use]
+pub fn runs_command(command: &[String], commands: &[String]) -> bool {
+    let Some(program) = command.first() else {
+        return false;
+    };
+    let name = std::path::Path::new(program)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(program);
+    commands.iter().any(|c| c == program || c == name)
+}
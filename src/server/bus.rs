@@ -0,0 +1,459 @@
+//! Sequenced event bus with bounded replay logs.
+//!
+//! Every published event gets the next sequence number and is appended to one of
+//! three bounded logs: lifecycle events (spawn, exit, removal and label changes), screen
+//! events (bells, titles, commands and the like) and output events. Keeping them
+//! apart means a flood of output or screen changes can't push spawn/exit events
+//! out. Subscribers read from the logs at their own pace through cursors, so
+//! events are delivered in sequence order and a slow subscriber is told exactly
+//! how many events it lost instead of silently skipping them.
+
+use crate::protocol::{Event, EventMessage};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use tokio::sync::watch;
+
+/// Lifecycle events retained for replay and slow subscribers.
+const LIFECYCLE_LOG_CAPACITY: usize = 10_000;
+
+/// Screen events retained for replay and slow subscribers.
+const SCREEN_LOG_CAPACITY: usize = 10_000;
+
+/// Output chunks (up to 4KB each) retained for replay and slow subscribers.
+const OUTPUT_LOG_CAPACITY: usize = 4096;
+
+/// Most events handed to a subscriber per log read, to bound time under the lock.
+const READ_BATCH: usize = 256;
+
+/// Indexes of the logs in [`BusState::logs`] and subscription cursors.
+const LIFECYCLE: usize = 0;
+const SCREEN: usize = 1;
+const OUTPUT: usize = 2;
+const LOGS: usize = 3;
+
+/// The log an event is kept in.
+const fn log_for(event: &Event) -> usize {
+    match event {
+        Event::AgentSpawned { .. }
+        | Event::AgentExited { .. }
+        | Event::AgentRemoved { .. }
+        | Event::LabelsChanged { .. } => LIFECYCLE,
+        Event::AgentOutput { .. } => OUTPUT,
+        _ => SCREEN,
+    }
+}
+
+/// A bounded log of sequenced events.
+///
+/// Entries are addressed by index: the position in the log counting evicted
+/// entries, so cursors stay valid as the front is dropped.
+#[derive(Debug)]
+struct EventLog {
+    entries: VecDeque<(u64, Event)>,
+    capacity: usize,
+    /// Index of the front entry (number of entries evicted so far).
+    base: u64,
+    /// Sequence number of the most recently evicted entry (0 if none).
+    evicted_seq: u64,
+}
+
+impl EventLog {
+    const fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            capacity,
+            base: 0,
+            evicted_seq: 0,
+        }
+    }
+
+    fn push(&mut self, seq: u64, event: Event) {
+        if self.entries.len() == self.capacity
+            && let Some((evicted, _)) = self.entries.pop_front()
+        {
+            self.base += 1;
+            self.evicted_seq = evicted;
+        }
+        self.entries.push_back((seq, event));
+    }
+
+    /// Index one past the newest entry.
+    fn end(&self) -> u64 {
+        self.base + self.entries.len() as u64
+    }
+
+    /// Cursor for the first entry after `seq`, and how many entries after `seq`
+    /// may already have been evicted.
+    fn cursor_after(&self, seq: u64) -> (u64, u64) {
+        let retained = self.entries.partition_point(|(s, _)| *s <= seq) as u64;
+        let missed = if self.evicted_seq > seq {
+            self.base.min(self.evicted_seq - seq)
+        } else {
+            0
+        };
+        (self.base + retained, missed)
+    }
+
+    /// Read up to a batch of entries from `cursor`, returning how many were
+    /// evicted before they could be read, the index of the first entry returned,
+    /// and the entries.
+    fn read(&self, cursor: u64) -> (u64, u64, Vec<(u64, Event)>) {
+        let missed = self.base.saturating_sub(cursor);
+        let start = cursor.max(self.base);
+        let skip = usize::try_from(start - self.base).unwrap_or(usize::MAX);
+        let entries = self.entries.iter().skip(skip).take(READ_BATCH).cloned().collect();
+        (missed, start, entries)
+    }
+}
+
+/// Highest sequence number that can be delivered from a batch without risking
+/// that another log still holds lower-numbered events beyond its own batch.
+fn batch_limit(batch: &[(u64, Event)]) -> u64 {
+    if batch.len() == READ_BATCH {
+        batch.last().map_or(u64::MAX, |(seq, _)| *seq)
+    } else {
+        u64::MAX
+    }
+}
+
+#[derive(Debug)]
+struct BusState {
+    next_seq: u64,
+    /// Lifecycle, screen and output logs.
+    logs: [EventLog; LOGS],
+}
+
+#[derive(Debug)]
+struct BusInner {
+    state: Mutex<BusState>,
+    /// Latest published sequence number, to wake subscribers.
+    notify: watch::Sender<u64>,
+}
+
+/// Publishes events to subscribers and keeps a bounded log for replay.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    inner: Arc<BusInner>,
+}
+
+impl EventBus {
+    /// Create an empty bus. The first event gets sequence number 1.
+    #[must_use]
+    pub fn new() -> Self {
+        let state = BusState {
+            next_seq: 1,
+            logs: [
+                EventLog::new(LIFECYCLE_LOG_CAPACITY),
+                EventLog::new(SCREEN_LOG_CAPACITY),
+                EventLog::new(OUTPUT_LOG_CAPACITY),
+            ],
+        };
+        Self {
+            inner: Arc::new(BusInner {
+                state: Mutex::new(state),
+                notify: watch::Sender::new(0),
+            }),
+        }
+    }
+
+    /// Publish an event, returning its sequence number.
+    #[allow(clippy::must_use_candidate)] // most publishers don't need the seq
+    pub fn publish(&self, event: Event) -> u64 {
+        let mut state = self.lock();
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        state.logs[log_for(&event)].push(seq, event);
+        drop(state);
+        self.inner.notify.send_replace(seq);
+        seq
+    }
+
+    /// Take `count` sequence numbers without publishing anything under them,
+    /// returning the first. Every event numbered before it is already logged.
+    #[must_use]
+    pub fn reserve(&self, count: u64) -> u64 {
+        let mut state = self.lock();
+        let first = state.next_seq;
+        state.next_seq += count;
+        first
+    }
+
+    /// Subscribe to events published from now on.
+    #[must_use]
+    pub fn subscribe(&self, include_output: bool) -> EventSubscription {
+        let state = self.lock();
+        let cursors = std::array::from_fn(|log| {
+            (log != OUTPUT || include_output).then(|| state.logs[log].end())
+        });
+        drop(state);
+        self.subscription(cursors, 0)
+    }
+
+    /// Subscribe to retained events after `since_seq`, then live events.
+    #[must_use]
+    pub fn subscribe_since(&self, since_seq: u64, include_output: bool) -> EventSubscription {
+        let state = self.lock();
+        let mut missed = 0;
+        let cursors = std::array::from_fn(|log| {
+            (log != OUTPUT || include_output).then(|| {
+                let (cursor, log_missed) = state.logs[log].cursor_after(since_seq);
+                missed += log_missed;
+                cursor
+            })
+        });
+        drop(state);
+        self.subscription(cursors, missed)
+    }
+
+    fn subscription(&self, cursors: [Option<u64>; LOGS], missed: u64) -> EventSubscription {
+        let mut pending = VecDeque::new();
+        if missed > 0 {
+            pending.push_back(EventMessage::Lagged { missed });
+        }
+        EventSubscription {
+            bus: Arc::downgrade(&self.inner),
+            changed: self.inner.notify.subscribe(),
+            cursors,
+            pending,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BusState> {
+        self.inner.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A subscriber's position in the event logs.
+///
+/// Holds the bus weakly: once the bus is dropped, `recv` returns `None`.
+#[derive(Debug)]
+pub struct EventSubscription {
+    bus: Weak<BusInner>,
+    changed: watch::Receiver<u64>,
+    /// Cursor into each log, or None for logs that weren't requested.
+    cursors: [Option<u64>; LOGS],
+    pending: VecDeque<EventMessage>,
+}
+
+impl EventSubscription {
+    /// Wait for the next message. Returns `None` once the bus has been dropped.
+    pub async fn recv(&mut self) -> Option<EventMessage> {
+        loop {
+            if let Some(message) = self.pending.pop_front() {
+                return Some(message);
+            }
+
+            // Mark the current sequence as seen before reading, so a publish
+            // racing with the read still wakes us up
+            self.changed.borrow_and_update();
+            self.fill()?;
+            if self.pending.is_empty() && self.changed.changed().await.is_err() {
+                return None;
+            }
+        }
+    }
+
+    /// Take the next message without waiting, if it comes before `seq`. Lag
+    /// notices always come before it.
+    pub fn try_recv_before(&mut self, seq: u64) -> Option<EventMessage> {
+        if self.pending.is_empty() {
+            self.fill()?;
+        }
+        match self.pending.front()? {
+            EventMessage::Event { seq: next, .. } if *next >= seq => None,
+            _ => self.pending.pop_front(),
+        }
+    }
+
+    /// Move newly logged events into `pending`, merged in sequence order.
+    fn fill(&mut self) -> Option<()> {
+        let inner = self.bus.upgrade()?;
+        let state = inner.state.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let mut missed = 0;
+        let mut starts = [0; LOGS];
+        let mut batches: [Vec<(u64, Event)>; LOGS] = Default::default();
+        for (log, cursor) in self.cursors.iter().enumerate() {
+            if let Some(cursor) = cursor {
+                let (log_missed, start, batch) = state.logs[log].read(*cursor);
+                missed += log_missed;
+                starts[log] = start;
+                batches[log] = batch;
+            }
+        }
+        drop(state);
+
+        if missed > 0 {
+            self.pending.push_back(EventMessage::Lagged { missed });
+        }
+
+        // A full batch may stop short of events another batch skips past;
+        // deliver only up to the point every log has been read
+        let limit = batches.iter().map(|batch| batch_limit(batch)).min().unwrap_or(u64::MAX);
+        let mut batches = batches.map(|batch| {
+            batch.into_iter().take_while(move |(seq, _)| *seq <= limit).peekable()
+        });
+        let mut taken = [0; LOGS];
+        loop {
+            let next = batches
+                .iter_mut()
+                .enumerate()
+                .filter_map(|(log, batch)| batch.peek().map(|(seq, _)| (*seq, log)))
+                .min();
+            let Some((_, log)) = next else { break };
+            if let Some((seq, event)) = batches[log].next() {
+                taken[log] += 1;
+                self.pending.push_back(EventMessage::Event { seq, event });
+            }
+        }
+
+        for (log, cursor) in self.cursors.iter_mut().enumerate() {
+            if cursor.is_some() {
+                *cursor = Some(starts[log] + taken[log]);
+            }
+        }
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exited(id: &str) -> Event {
+        Event::AgentExited {
+            id: id.into(),
+            exit_code: Some(0),
+        }
+    }
+
+    fn output(id: &str) -> Event {
+        Event::AgentOutput {
+            id: id.into(),
+            data: b"x".to_vec(),
+        }
+    }
+
+    fn seqs(subscription: &mut EventSubscription) -> Vec<u64> {
+        subscription.fill();
+        subscription
+            .pending
+            .drain(..)
+            .map(|m| match m {
+                EventMessage::Event { seq, .. } => seq,
+                EventMessage::Lagged { missed } => panic!("unexpected lag of {missed}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_events_are_merged_in_sequence_order() {
+        let bus = EventBus::new();
+        let mut all = bus.subscribe(true);
+        let mut no_output = bus.subscribe(false);
+
+        assert_eq!(bus.publish(output("a")), 1);
+        assert_eq!(bus.publish(Event::Bell { id: "a".into() }), 2);
+        assert_eq!(bus.publish(exited("a")), 3);
+        assert_eq!(bus.publish(output("b")), 4);
+
+        assert_eq!(seqs(&mut all), vec![1, 2, 3, 4]);
+        assert_eq!(seqs(&mut no_output), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_batches_keep_sequence_order() {
+        let bus = EventBus::new();
+        let mut all = bus.subscribe(true);
+
+        // Output fills several batches while lifecycle events are sparse
+        for i in 0..READ_BATCH * 3 {
+            bus.publish(if i % 100 == 0 { exited("a") } else { output("a") });
+        }
+
+        let mut delivered = Vec::new();
+        loop {
+            let batch = seqs(&mut all);
+            if batch.is_empty() {
+                break;
+            }
+            delivered.extend(batch);
+        }
+        let expected: Vec<u64> = (1..=(READ_BATCH * 3) as u64).collect();
+        assert_eq!(delivered, expected);
+    }
+
+    #[test]
+    fn test_subscribe_since_replays_retained_events() {
+        let bus = EventBus::new();
+        for _ in 0..3 {
+            bus.publish(exited("a"));
+        }
+        assert_eq!(seqs(&mut bus.subscribe_since(1, false)), vec![2, 3]);
+        assert!(seqs(&mut bus.subscribe(false)).is_empty());
+    }
+
+    #[test]
+    fn test_output_flood_reports_lag_but_keeps_lifecycle() {
+        let bus = EventBus::new();
+        let mut slow = bus.subscribe(true);
+
+        bus.publish(exited("a"));
+        for _ in 0..OUTPUT_LOG_CAPACITY + 5 {
+            bus.publish(output("a"));
+        }
+
+        slow.fill();
+        assert!(matches!(slow.pending.pop_front(), Some(EventMessage::Lagged { missed: 5 })));
+        assert!(matches!(slow.pending.pop_front(), Some(EventMessage::Event { seq: 1, .. })));
+    }
+
+    #[test]
+    fn test_screen_event_flood_keeps_lifecycle() {
+        let bus = EventBus::new();
+        let mut slow = bus.subscribe(false);
+
+        bus.publish(exited("a"));
+        for _ in 0..SCREEN_LOG_CAPACITY + 5 {
+            bus.publish(Event::Bell { id: "a".into() });
+        }
+
+        slow.fill();
+        assert!(matches!(slow.pending.pop_front(), Some(EventMessage::Lagged { missed: 5 })));
+        assert!(matches!(slow.pending.pop_front(), Some(EventMessage::Event { seq: 1, .. })));
+    }
+
+    #[test]
+    fn test_reserved_seqs_split_the_stream() {
+        let bus = EventBus::new();
+        let mut all = bus.subscribe(true);
+
+        bus.publish(exited("a"));
+        bus.publish(output("a"));
+        assert_eq!(bus.reserve(2), 3);
+        assert_eq!(bus.publish(exited("b")), 5);
+
+        // Only events numbered before the reservation come out, without waiting
+        let mut before = Vec::new();
+        while let Some(EventMessage::Event { seq, .. }) = all.try_recv_before(3) {
+            before.push(seq);
+        }
+        assert_eq!(before, vec![1, 2]);
+        assert_eq!(seqs(&mut all), vec![5]);
+    }
+
+    #[tokio::test]
+    async fn test_recv_ends_when_bus_dropped() {
+        let bus = EventBus::new();
+        let mut subscription = bus.subscribe(false);
+        bus.publish(exited("a"));
+        drop(bus);
+        assert!(subscription.recv().await.is_none());
+    }
+}
@@ -0,0 +1,377 @@
+//! Event streaming to subscribed clients.
+
+use super::agent::runs_command;
+use super::bus::EventSubscription;
+use super::{agent_selector, Runtime, ServerError};
+use crate::protocol::{Event, EventMessage, EventOptions, Response, RowChange};
+use crate::selector::Selector;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::unix::OwnedWriteHalf;
+use tokio::time::Interval;
+use tracing::{debug, info, warn};
+
+/// Shortest allowed interval between `screen_changed` flushes.
+const MIN_SCREEN_INTERVAL_MS: u64 = 10;
+
+/// Stream events to a subscribed client until it disconnects or the server shuts down.
+pub async fn handle_events(
+    options: EventOptions,
+    mut writer: OwnedWriteHalf,
+    runtime: &Runtime,
+) -> Result<(), ServerError> {
+    let EventOptions {
+        filter: ids,
+        include_output,
+        screen_interval,
+        since_seq,
+        labels,
+        annotations,
+        selector,
+        commands,
+        event_types,
+    } = options;
+
+    if let Some(unknown) = event_types.iter().find(|t| !Event::KINDS.contains(&t.as_str())) {
+        send(&mut writer, Response::error(format!("unknown event type: {unknown}"))).await;
+        return Ok(());
+    }
+    let selector = match agent_selector(&labels, &annotations, selector.as_deref()) {
+        Ok(selector) => selector,
+        Err(e) => {
+            send(&mut writer, Response::error(e.to_string())).await;
+            return Ok(());
+        }
+    };
+    let filter = EventFilter {
+        ids,
+        selector,
+        commands,
+        event_types,
+        matches: HashMap::new(),
+    };
+
+    let screen_interval = screen_interval.filter(|_| filter.wants("screen_changed"));
+    // Screen tracking needs to see output events even when the client doesn't
+    let mut subscription =
+        runtime.subscribe_from(since_seq, include_output || screen_interval.is_some());
+    let mut flush = screen_interval.map(|ms| {
+        tokio::time::interval(Duration::from_millis(ms.max(MIN_SCREEN_INTERVAL_MS)))
+    });
+
+    info!(?filter, %include_output, ?screen_interval, ?since_seq, "Events subscription started");
+
+    let mut subscriber = Subscriber {
+        writer,
+        runtime,
+        filter,
+        include_output,
+        screens: screen_interval.map(|_| ScreenWatch::default()),
+    };
+
+    loop {
+        tokio::select! {
+            message = subscription.recv() => {
+                let Some(message) = message else {
+                    // Event bus dropped (server shutting down)
+                    debug!("Events bus closed");
+                    break;
+                };
+                if !subscriber.forward(message).await {
+                    break;
+                }
+                // Report the final screen of agents that just exited
+                if subscriber.screens.as_ref().is_some_and(ScreenWatch::has_exited)
+                    && !subscriber.flush_screens(&mut subscription).await
+                {
+                    break;
+                }
+            }
+
+            () = tick(flush.as_mut()) => {
+                if !subscriber.flush_screens(&mut subscription).await {
+                    break;
+                }
+            }
+        }
+    }
+
+    info!("Events subscription ended");
+    Ok(())
+}
+
+/// One client's event stream.
+struct Subscriber<'a> {
+    writer: OwnedWriteHalf,
+    runtime: &'a Runtime,
+    filter: EventFilter,
+    include_output: bool,
+    /// Screens to report changes for, if `screen_interval` was set.
+    screens: Option<ScreenWatch>,
+}
+
+impl Subscriber<'_> {
+    /// Send a message from the bus if the client wants it. Returns false if
+    /// the client disconnected.
+    async fn forward(&mut self, message: EventMessage) -> bool {
+        let (seq, event) = match message {
+            EventMessage::Event { seq, event } => (seq, event),
+            lagged @ EventMessage::Lagged { .. } => {
+                warn!(?lagged, "Events subscriber lagged");
+                return send(&mut self.writer, lagged.into()).await;
+            }
+        };
+
+        if !self.filter.matches_agent(self.runtime, &event).await {
+            return true;
+        }
+
+        if let Some(screens) = &mut self.screens {
+            match &event {
+                Event::AgentOutput { id, .. } | Event::Resized { id, .. } => {
+                    screens.mark_dirty(id);
+                }
+                Event::AgentExited { id, .. } => screens.mark_exited(id),
+                _ => {}
+            }
+        }
+
+        // Skip output events if not requested
+        if !self.include_output && matches!(event, Event::AgentOutput { .. }) {
+            return true;
+        }
+        if !self.filter.wants(event.kind()) {
+            return true;
+        }
+        send(&mut self.writer, Response::Event { seq, event }).await
+    }
+
+    /// Send `screen_changed` events for screens that changed, each under a
+    /// sequence number of its own. Events numbered before those are sent
+    /// first, so the stream stays in sequence order and resuming from any
+    /// number it carried loses nothing. Returns false if the client disconnected.
+    async fn flush_screens(&mut self, subscription: &mut EventSubscription) -> bool {
+        // Agents may exit while the earlier events are sent; report them too
+        while let Some(screens) = &mut self.screens {
+            let ids = screens.take_dirty();
+            if ids.is_empty() {
+                break;
+            }
+
+            let first = self.runtime.events().reserve(ids.len() as u64);
+            while let Some(message) = subscription.try_recv_before(first) {
+                if !self.forward(message).await {
+                    return false;
+                }
+            }
+
+            for (seq, id) in (first..).zip(ids) {
+                let Some(screens) = &mut self.screens else {
+                    break;
+                };
+                if let Some(event) = screens.changes(self.runtime, &id).await
+                    && !send(&mut self.writer, Response::Event { seq, event }).await
+                {
+                    return false;
+                }
+            }
+
+            if !self.screens.as_ref().is_some_and(ScreenWatch::has_exited) {
+                break;
+            }
+        }
+        true
+    }
+}
+
+/// Which events a subscription wants. Agent filters are checked against the
+/// labels and command carried by each agent's spawn and label events, so
+/// agents spawned or relabeled after subscribing are picked up, and replayed
+/// events are judged by the agent as it was when they happened.
+#[derive(Debug)]
+struct EventFilter {
+    /// Agent IDs (empty = all agents).
+    ids: Vec<String>,
+    /// Labels, annotations and selector expression, combined.
+    selector: Selector,
+    /// Programs the agent may run (empty = any).
+    commands: Vec<String>,
+    /// Event type names (empty = all).
+    event_types: Vec<String>,
+    /// How each agent seen so far matches, kept until it is removed, so its
+    /// exit is judged like its other events even after it is gone.
+    matches: HashMap<String, AgentMatch>,
+}
+
+/// How one agent matches a subscription's filters.
+#[derive(Debug, Clone, Copy)]
+struct AgentMatch {
+    /// Whether it runs one of the wanted programs.
+    command: bool,
+    /// Whether its labels and annotations match the selector.
+    selector: bool,
+}
+
+impl EventFilter {
+    async fn matches_agent(&mut self, runtime: &Runtime, event: &Event) -> bool {
+        let id = event.id();
+        if !self.ids.is_empty() && !self.ids.iter().any(|f| f == id) {
+            return false;
+        }
+        if self.selector.is_empty() && self.commands.is_empty() {
+            return true;
+        }
+
+        let known = match event {
+            Event::AgentSpawned { command, labels, annotations, .. } => Some(AgentMatch {
+                command: self.commands.is_empty() || runs_command(command, &self.commands),
+                selector: self.selector.matches(labels, annotations),
+            }),
+            Event::LabelsChanged { labels, annotations, .. } => {
+                self.matches.get(id).map(|m| AgentMatch {
+                    selector: self.selector.matches(labels, annotations),
+                    ..*m
+                })
+            }
+            _ => self.matches.get(id).copied(),
+        };
+        // Agents spawned before the subscription (and beyond a replay) are
+        // looked up once as they are now
+        let agent = match known {
+            Some(agent) => agent,
+            None => match runtime.agent_matches(id, &self.selector, &self.commands).await {
+                Some((command, selector)) => AgentMatch { command, selector },
+                None => return false,
+            },
+        };
+
+        if matches!(event, Event::AgentRemoved { .. }) {
+            self.matches.remove(id);
+        } else if let Some(cached) = self.matches.get_mut(id) {
+            *cached = agent;
+        } else {
+            self.matches.insert(id.to_string(), agent);
+        }
+        agent.command && agent.selector
+    }
+
+    fn wants(&self, kind: &str) -> bool {
+        self.event_types.is_empty() || self.event_types.iter().any(|t| t == kind)
+    }
+}
+
+/// Write one message to the client. Returns false if the client disconnected.
+async fn send(writer: &mut OwnedWriteHalf, response: Response) -> bool {
+    let mut json =
+        serde_json::to_string(&response).expect("Response serialization should never fail");
+    json.push('\n');
+
+    if writer.write_all(json.as_bytes()).await.is_err() {
+        debug!("Events client disconnected");
+        return false;
+    }
+    true
+}
+
+/// Wait for the next flush tick, or forever if screen changes weren't requested.
+async fn tick(interval: Option<&mut Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Per-subscription state for coalesced `screen_changed` events.
+#[derive(Debug, Default)]
+struct ScreenWatch {
+    /// Agents with output or a resize since the last flush.
+    dirty: HashSet<String>,
+    /// Dirty agents that have exited, to forget once their final screen is sent.
+    exited: HashSet<String>,
+    /// Rows, cursor and size last reported for each agent.
+    last: HashMap<String, ReportedScreen>,
+}
+
+/// What a subscriber was last told about a screen.
+#[derive(Debug, Default)]
+struct ReportedScreen {
+    rows: Vec<String>,
+    cursor: (u16, u16),
+    size: (u16, u16),
+}
+
+impl ScreenWatch {
+    fn mark_dirty(&mut self, id: &str) {
+        if !self.dirty.contains(id) {
+            self.dirty.insert(id.to_string());
+        }
+    }
+
+    fn mark_exited(&mut self, id: &str) {
+        self.mark_dirty(id);
+        self.exited.insert(id.to_string());
+    }
+
+    fn has_exited(&self) -> bool {
+        !self.exited.is_empty()
+    }
+
+    fn take_dirty(&mut self) -> Vec<String> {
+        self.dirty.drain().collect()
+    }
+
+    /// Build a `screen_changed` message for an agent against what was last reported,
+    /// or None if nothing visible changed. An exited agent is forgotten afterwards.
+    async fn changes(&mut self, runtime: &Runtime, id: &str) -> Option<Event> {
+        let exited = self.exited.remove(id);
+        if exited {
+            self.dirty.remove(id);
+        }
+        let mut last = self.last.remove(id).unwrap_or_default();
+        let (rows, cursor, size) = runtime.screen_rows(id).await.ok()?;
+        let event = last.update(id, rows, cursor, size);
+        if !exited {
+            self.last.insert(id.to_string(), last);
+        }
+        event
+    }
+}
+
+impl ReportedScreen {
+    /// The event for a screen that differs from this one, which it then describes.
+    fn update(
+        &mut self,
+        id: &str,
+        rows: Vec<String>,
+        cursor: (u16, u16),
+        size: (u16, u16),
+    ) -> Option<Event> {
+        // After a resize every row is sent, since rows may have moved or gone
+        let resized = self.size != (0, 0) && self.size != size;
+        let changed: Vec<RowChange> = rows
+            .iter()
+            .enumerate()
+            .filter(|(i, text)| {
+                resized || self.rows.get(*i).map_or(!text.is_empty(), |last| last != *text)
+            })
+            .map(|(i, text)| RowChange {
+                row: i as u16,
+                text: text.clone(),
+            })
+            .collect();
+        if changed.is_empty() && cursor == self.cursor && size == self.size {
+            return None;
+        }
+
+        *self = Self { rows, cursor, size };
+        Some(Event::ScreenChanged {
+            id: id.to_string(),
+            rows: changed,
+            cursor,
+            size,
+        })
+    }
+}
@@ -0,0 +1,201 @@
+//! Per-agent input queue.
+//!
+//! PTY masters are non-blocking, so a write can be short or fail with EAGAIN
+//! when the child isn't reading. Input is queued and written as the PTY
+//! accepts it, both right away and from the reader task's poll loop.
+
+use std::collections::VecDeque;
+use std::os::fd::BorrowedFd;
+
+/// Default limit on input waiting for an agent to read it.
+pub const DEFAULT_MAX_INPUT: u64 = 1024 * 1024;
+
+/// Most terminal query replies that may wait at once. A program that keeps
+/// asking without reading its input gets no more answers beyond this.
+pub const MAX_PENDING_REPLIES: u64 = 64 * 1024;
+
+/// A run of bytes queued together.
+#[derive(Debug)]
+struct Segment {
+    data: Vec<u8>,
+    /// How much of `data` has been written.
+    written: usize,
+    /// Whether these are answers to terminal queries rather than input.
+    reply: bool,
+}
+
+/// Input waiting to be written to an agent's PTY.
+///
+/// Answers to terminal queries share the queue, so they reach the program
+/// in order with its input, but don't count towards the limit or the stats.
+#[derive(Debug)]
+pub struct InputQueue {
+    segments: VecDeque<Segment>,
+    limit: u64,
+    /// Input bytes queued but not yet written.
+    pending: u64,
+    /// Reply bytes queued but not yet written.
+    pending_replies: u64,
+    /// Total bytes ever queued.
+    queued: u64,
+    /// Total bytes written to the PTY.
+    written: u64,
+}
+
+impl InputQueue {
+    /// Create an empty queue holding at most `limit` unwritten bytes.
+    #[must_use]
+    pub const fn new(limit: u64) -> Self {
+        Self {
+            segments: VecDeque::new(),
+            limit,
+            pending: 0,
+            pending_replies: 0,
+            queued: 0,
+            written: 0,
+        }
+    }
+
+    /// Queue bytes for writing. Returns the `written` count at which all of
+    /// them have been written, or None if they don't fit under the limit.
+    pub fn push(&mut self, data: &[u8]) -> Option<u64> {
+        if self.pending + data.len() as u64 > self.limit {
+            return None;
+        }
+        self.append(data, false);
+        self.pending += data.len() as u64;
+        self.queued += data.len() as u64;
+        Some(self.queued)
+    }
+
+    /// Queue answers to terminal queries, outside the limit and the stats.
+    /// Returns false if too many replies are already waiting.
+    pub fn push_reply(&mut self, data: &[u8]) -> bool {
+        if self.pending_replies + data.len() as u64 > MAX_PENDING_REPLIES {
+            return false;
+        }
+        self.append(data, true);
+        self.pending_replies += data.len() as u64;
+        true
+    }
+
+    fn append(&mut self, data: &[u8], reply: bool) {
+        match self.segments.back_mut() {
+            Some(last) if last.reply == reply => last.data.extend_from_slice(data),
+            _ => self.segments.push_back(Segment {
+                data: data.to_vec(),
+                written: 0,
+                reply,
+            }),
+        }
+    }
+
+    /// Write as much pending input as the PTY accepts without blocking.
+    pub fn flush(&mut self, fd: BorrowedFd<'_>) -> Result<(), nix::Error> {
+        while let Some(segment) = self.segments.front_mut() {
+            match nix::unistd::write(fd, &segment.data[segment.written..]) {
+                Ok(0) | Err(nix::Error::EAGAIN) => break,
+                Ok(n) => {
+                    segment.written += n;
+                    if segment.reply {
+                        self.pending_replies -= n as u64;
+                    } else {
+                        self.pending -= n as u64;
+                        self.written += n as u64;
+                    }
+                    if segment.written == segment.data.len() {
+                        self.segments.pop_front();
+                    }
+                }
+                Err(nix::Error::EINTR) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether nothing, input or replies, is waiting to be written.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Input bytes queued but not yet written.
+    #[must_use]
+    pub const fn pending(&self) -> u64 {
+        self.pending
+    }
+
+    /// Total bytes ever queued.
+    #[must_use]
+    pub const fn queued(&self) -> u64 {
+        self.queued
+    }
+
+    /// Total bytes written to the PTY.
+    #[must_use]
+    pub const fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Most unwritten bytes the queue will hold.
+    #[must_use]
+    pub const fn limit(&self) -> u64 {
+        self.limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::fcntl::{fcntl, FcntlArg, OFlag};
+    use std::os::fd::AsFd;
+
+    #[test]
+    fn test_input_queue_backpressure() {
+        let (read_end, write_end) = nix::unistd::pipe().unwrap();
+        fcntl(&write_end, FcntlArg::F_SETFL(OFlag::O_NONBLOCK)).unwrap();
+
+        // More than a pipe buffer holds, so the first flush can't write it all
+        let data = vec![b'x'; 256 * 1024];
+        let mut queue = InputQueue::new(data.len() as u64);
+        assert_eq!(queue.push(&data), Some(data.len() as u64));
+        assert_eq!(queue.push(b"y"), None, "over the limit");
+
+        queue.flush(write_end.as_fd()).unwrap();
+        assert!(queue.written() > 0);
+        assert!(queue.pending() > 0, "pipe should have filled up");
+
+        // Drain the reader side until everything has gone through
+        let mut buf = vec![0; 64 * 1024];
+        let mut read = 0;
+        while read < data.len() {
+            read += nix::unistd::read(&read_end, &mut buf).unwrap();
+            queue.flush(write_end.as_fd()).unwrap();
+        }
+        assert_eq!(queue.pending(), 0);
+        assert_eq!(queue.written(), data.len() as u64);
+        assert_eq!(queue.queued(), queue.written());
+    }
+
+    #[test]
+    fn test_replies_bypass_limit_and_stats() {
+        let (read_end, write_end) = nix::unistd::pipe().unwrap();
+        fcntl(&write_end, FcntlArg::F_SETFL(OFlag::O_NONBLOCK)).unwrap();
+
+        // A full queue still takes replies, which keep their place in line
+        let mut queue = InputQueue::new(2);
+        assert_eq!(queue.push(b"ab"), Some(2));
+        assert!(queue.push_reply(b"\x1b[0n"));
+        assert_eq!(queue.push(b"c"), None);
+        assert_eq!(queue.pending(), 2);
+        assert!(!queue.push_reply(&vec![b'x'; MAX_PENDING_REPLIES as usize]));
+
+        queue.flush(write_end.as_fd()).unwrap();
+        assert!(queue.is_empty());
+        assert_eq!((queue.queued(), queue.written()), (2, 2));
+        let mut buf = [0; 16];
+        let n = nix::unistd::read(&read_end, &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"ab\x1b[0n");
+    }
+}
@@ -67,6 +67,11 @@ impl AgentManager {
         self.agents.values()
     }
 
+    /// List all agents, mutably.
+    pub fn list_mut(&mut self) -> impl Iterator<Item = &mut Agent> {
+        self.agents.values_mut()
+    }
+
     /// Get the number of agents.
     #[must_use] 
     pub fn len(&self) -> usize {
@@ -98,7 +103,7 @@ mod tests {
         let mut ids = std::collections::HashSet::new();
         for _ in 0..100 {
             let id = manager.generate_id();
-            assert!(ids.insert(id.clone()), "Generated duplicate ID: {}", id);
+            assert!(ids.insert(id.clone()), "Generated duplicate ID: {id}");
         }
     }
 
@@ -110,11 +115,10 @@ mod tests {
         // Should be adjective-noun format (contains a hyphen)
         assert!(
             id.contains('-'),
-            "ID should be adjective-noun format: {}",
-            id
+            "ID should be adjective-noun format: {id}"
         );
 
         // Should be lowercase
-        assert_eq!(id, id.to_lowercase(), "ID should be lowercase: {}", id);
+        assert_eq!(id, id.to_lowercase(), "ID should be lowercase: {id}");
     }
 }
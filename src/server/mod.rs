@@ -1,7 +1,7 @@
 //! The botty server.
 //!
-//! Owns PTYs, agents, transcripts, and virtual screens.
-//! Listens on a Unix socket for client requests.
+//! The [`Runtime`] owns PTYs, agents, transcripts, and virtual screens.
+//! The [`Server`] listens on a Unix socket and serves client requests from it.
 
 // These casts are intentional and safe:
 // - PIDs are always positive (i32 -> u32)  
@@ -16,26 +16,35 @@
 #![allow(clippy::significant_drop_tightening)]
 
 mod agent;
+mod bus;
+mod events;
+mod input;
 mod manager;
+mod mouse;
+mod runtime;
 mod screen;
+mod shell;
 mod transcript;
 
 pub use agent::{Agent, AgentState as InternalAgentState};
+pub use bus::{EventBus, EventSubscription};
 pub use manager::AgentManager;
+pub use runtime::Runtime;
 pub use screen::Screen;
 pub use transcript::Transcript;
 
 use crate::protocol::{
-    AgentInfo, AgentState, AttachEndReason, DumpFormat, Event, ExitReason, Request, Response, TranscriptEntry,
+    AttachEndReason, DumpFormat, Event, EventOptions, Request, Response, SpawnOptions,
 };
-use crate::pty;
-use nix::sys::signal::Signal;
+use crate::redact::Redactor;
+use crate::selector::Selector;
+use std::collections::BTreeMap;
 #[cfg(unix)]
 use std::os::unix::fs::FileTypeExt;
 use std::os::fd::BorrowedFd;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::Duration;
 use thiserror::Error;
 use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
 use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
@@ -58,34 +67,68 @@ pub enum ServerError {
     #[error("failed to spawn agent: {0}")]
     Spawn(#[source] crate::pty::PtyError),
 
+    #[error("agent name already in use: {0}")]
+    NameInUse(String),
+
+    #[error("agent {0} exited before all input was sent")]
+    AgentExited(String),
+
+    #[error("input queue for {id} is full ({pending} bytes waiting, limit {limit})")]
+    InputFull { id: String, pending: u64, limit: u64 },
+
+    #[error("write failed: {0}")]
+    Write(#[source] nix::Error),
+
+    #[error("resize failed: {0}")]
+    Resize(#[source] crate::pty::PtyError),
+
+    #[error("failed to kill some agents: {0}")]
+    Signal(String),
+
+    #[error("{0}")]
+    NoMatch(String),
+
+    #[error("{0}")]
+    InvalidRequest(String),
+
     #[error("I/O error: {0}")]
     Io(#[source] std::io::Error),
 }
 
-/// The botty server.
+/// The botty server: a Unix socket frontend over a [`Runtime`].
 pub struct Server {
     socket_path: PathBuf,
-    manager: Arc<Mutex<AgentManager>>,
+    runtime: Runtime,
     shutdown_tx: broadcast::Sender<()>,
-    /// Broadcast channel for events (spawned, output, exited).
-    event_tx: broadcast::Sender<Event>,
 }
 
 impl Server {
-    /// Create a new server that will listen on the given socket path.
+    /// Create a new server with its own runtime that will listen on the given socket path.
+    ///
+    /// Must be called from within a Tokio runtime.
     #[must_use] 
     pub fn new(socket_path: PathBuf) -> Self {
+        Self::with_runtime(socket_path, Runtime::new())
+    }
+
+    /// Create a server that serves an existing runtime, so in-process users
+    /// and socket clients see the same agents.
+    #[must_use]
+    pub fn with_runtime(socket_path: PathBuf, runtime: Runtime) -> Self {
         let (shutdown_tx, _) = broadcast::channel(1);
-        // Event channel with enough capacity for bursty output
-        let (event_tx, _) = broadcast::channel(1024);
         Self {
             socket_path,
-            manager: Arc::new(Mutex::new(AgentManager::new())),
+            runtime,
             shutdown_tx,
-            event_tx,
         }
     }
 
+    /// The runtime this server is serving.
+    #[must_use]
+    pub const fn runtime(&self) -> &Runtime {
+        &self.runtime
+    }
+
     /// Run the server event loop.
     pub async fn run(&mut self) -> Result<(), ServerError> {
         // Security: Check for symlink attack before removing existing socket
@@ -123,17 +166,6 @@ impl Server {
         
         info!("Server listening on {:?}", self.socket_path);
 
-        // Start the PTY output reader task
-        let manager = Arc::clone(&self.manager);
-        let event_tx = self.event_tx.clone();
-        let mut pty_shutdown = self.shutdown_tx.subscribe();
-        tokio::spawn(async move {
-            tokio::select! {
-                () = pty_reader_task(manager, event_tx) => {}
-                _ = pty_shutdown.recv() => {}
-            }
-        });
-
         let mut shutdown_rx = self.shutdown_tx.subscribe();
 
         loop {
@@ -142,11 +174,10 @@ impl Server {
                     match result {
                         Ok((stream, _addr)) => {
                             debug!("Accepted connection");
-                            let manager = Arc::clone(&self.manager);
+                            let runtime = self.runtime.clone();
                             let shutdown_tx = self.shutdown_tx.clone();
-                            let event_tx = self.event_tx.clone();
                             tokio::spawn(async move {
-                                if let Err(e) = handle_connection(stream, manager, shutdown_tx, event_tx).await {
+                                if let Err(e) = handle_connection(stream, runtime, shutdown_tx).await {
                                     error!("Connection error: {}", e);
                                 }
                             });
@@ -163,8 +194,9 @@ impl Server {
             }
         }
 
-        // Clean up socket
+        // Clean up socket and shell integration files
         std::fs::remove_file(&self.socket_path).ok();
+        shell::cleanup();
         info!("Server shut down");
         Ok(())
     }
@@ -178,9 +210,8 @@ impl Server {
 /// Handle a single client connection.
 async fn handle_connection(
     stream: UnixStream,
-    manager: Arc<Mutex<AgentManager>>,
+    runtime: Runtime,
     shutdown_tx: broadcast::Sender<()>,
-    event_tx: broadcast::Sender<Event>,
 ) -> Result<(), ServerError> {
     let (reader, writer) = stream.into_split();
     let mut reader = BufReader::new(reader);
@@ -221,8 +252,8 @@ async fn handle_connection(
                 *readonly,
                 reader.into_inner(),
                 writer,
-                &manager,
-                &event_tx,
+                runtime.manager(),
+                runtime.events(),
             )
             .await;
 
@@ -249,14 +280,30 @@ async fn handle_connection(
         }
 
         // Handle events request specially - it switches to streaming mode
-        if let Request::Events { filter, include_output } = &request {
-            let events_result = handle_events(
-                filter.clone(),
-                *include_output,
-                writer,
-                &event_tx,
-            )
-            .await;
+        if let Request::Events {
+            filter,
+            include_output,
+            screen_interval,
+            since_seq,
+            labels,
+            annotations,
+            selector,
+            commands,
+            event_types,
+        } = request
+        {
+            let options = EventOptions {
+                filter,
+                include_output,
+                screen_interval,
+                since_seq,
+                labels,
+                annotations,
+                selector,
+                commands,
+                event_types,
+            };
+            let events_result = events::handle_events(options, writer, &runtime).await;
 
             match events_result {
                 Ok(()) => {
@@ -271,7 +318,7 @@ async fn handle_connection(
         }
 
         let is_shutdown = matches!(request, Request::Shutdown);
-        let response = handle_request(request, &manager, &event_tx).await;
+        let response = handle_request(request, &runtime).await;
 
         let mut json = serde_json::to_string(&response)
             .expect("Response serialization should never fail");
@@ -291,392 +338,234 @@ async fn handle_connection(
     Ok(())
 }
 
-/// Handle a single request.
-async fn handle_request(
-    request: Request,
-    manager: &Arc<Mutex<AgentManager>>,
-    event_tx: &broadcast::Sender<Event>,
-) -> Response {
-    match request {
-        Request::Ping => Response::Pong,
-
-        Request::Spawn { cmd, rows, cols, name, labels, timeout, max_output, env, env_clear } => {
-            if cmd.is_empty() {
-                return Response::error("command is empty");
-            }
-
-            // Parse environment variables
-            let env_vars: Vec<(String, String)> = env
-                .iter()
-                .filter_map(|s| {
-                    let mut parts = s.splitn(2, '=');
-                    match (parts.next(), parts.next()) {
-                        (Some(key), Some(value)) if !key.is_empty() => {
-                            Some((key.to_string(), value.to_string()))
-                        }
-                        _ => None, // Skip malformed entries
-                    }
-                })
-                .collect();
-
-            // Build resource limits if any are specified
-            let limits = if timeout.is_some() || max_output.is_some() {
-                Some(crate::protocol::ResourceLimits { timeout, max_output })
-            } else {
-                None
-            };
-
-            // Validate and resolve agent ID
-            let mut mgr = manager.lock().await;
-            let id = if let Some(custom_name) = name {
-                // Validate custom name - must be non-empty and shell-safe
-                // Only allow alphanumeric, hyphen, and underscore to prevent command injection
-                if custom_name.is_empty() {
-                    return Response::error("agent name cannot be empty");
-                }
-                if !custom_name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
-                    return Response::error("agent name must contain only alphanumeric characters, hyphens, and underscores");
-                }
-                if custom_name.len() > 64 {
-                    return Response::error("agent name must be 64 characters or fewer");
-                }
-                // Check for uniqueness - allow reusing names of exited agents
-                if let Some(existing) = mgr.get(&custom_name) {
-                    if existing.is_running() {
-                        return Response::error(format!("agent name already in use: {custom_name}"));
-                    }
-                    // Remove the exited agent to reuse the name
-                    mgr.remove(&custom_name);
-                }
-                custom_name
-            } else {
-                mgr.generate_id()
-            };
-            drop(mgr); // Release lock before spawning
-
-            let spawn_env = pty::SpawnEnv {
-                vars: env_vars,
-                clear: env_clear,
+/// Handle a single request by dispatching it to the runtime.
+async fn handle_request(request: Request, runtime: &Runtime) -> Response {
+    let result = match request {
+        Request::Ping => Ok(Response::Pong),
+
+        Request::Spawn {
+            cmd,
+            rows,
+            cols,
+            name,
+            labels,
+            annotations,
+            timeout,
+            max_output,
+            max_input,
+            env,
+            env_clear,
+            shell_integration,
+            term,
+            terminal,
+            no_terminal_replies,
+        } => {
+            let options = SpawnOptions {
+                cmd,
+                rows,
+                cols,
+                name,
+                labels,
+                annotations,
+                timeout,
+                max_output,
+                max_input,
+                env,
+                env_clear,
+                shell_integration,
+                term,
+                terminal,
+                no_terminal_replies,
             };
-            match pty::spawn_with_env(&cmd, rows, cols, &spawn_env) {
-                Ok(pty_process) => {
-                    let mut mgr = manager.lock().await;
-                    // Double-check uniqueness (in case of race) - only block if running
-                    if let Some(existing) = mgr.get(&id) {
-                        if existing.is_running() {
-                            return Response::error(format!("agent name already in use: {id}"));
-                        }
-                        mgr.remove(&id);
-                    }
-                    let pid = pty_process.pid.as_raw() as u32;
-                    let agent = Agent::new(id.clone(), cmd.clone(), labels.clone(), limits, pty_process, rows, cols);
-                    mgr.add(agent);
-                    info!(%id, %pid, ?labels, ?limits, "Spawned agent");
-                    
-                    // Publish spawn event
-                    let _ = event_tx.send(Event::AgentSpawned {
-                        id: id.clone(),
-                        pid,
-                        command: cmd,
-                        labels,
-                    });
-                    
-                    Response::Spawned { id, pid }
-                }
-                Err(e) => Response::error(format!("spawn failed: {e}")),
-            }
-        }
-
-        Request::List { labels } => {
-            let mgr = manager.lock().await;
-            let agents: Vec<AgentInfo> = mgr
-                .list()
-                .filter(|agent| labels.is_empty() || agent.has_labels(&labels))
-                .map(|agent| {
-                    let elapsed = agent.started_at.elapsed();
-                    let now_millis = SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .unwrap_or_default()
-                        .as_millis() as u64;
-                    let started_at = now_millis.saturating_sub(elapsed.as_millis() as u64);
-
-                    AgentInfo {
-                        id: agent.id.clone(),
-                        pid: agent.pid(),
-                        state: match agent.state {
-                            InternalAgentState::Running => AgentState::Running,
-                            InternalAgentState::Exited { .. } => AgentState::Exited,
-                        },
-                        command: agent.command.clone(),
-                        labels: agent.labels.clone(),
-                        size: agent.screen.size(),
-                        started_at,
-                        exit_code: agent.exit_code(),
-                        exit_reason: agent.exit_reason,
-                        limits: agent.limits,
-                    }
-                })
-                .collect();
-            Response::Agents { agents }
+            runtime
+                .spawn(options)
+                .await
+                .map(|(id, pid)| Response::Spawned { id, pid })
         }
 
-        Request::Kill { id, labels, all, signal, proc_filter } => {
-            // Validate signal number - only allow standard signals (1-31)
-            // Real-time signals (32-64) and invalid numbers are rejected
-            if !(1..=31).contains(&signal) {
-                return Response::error(format!("invalid signal number: {signal} (must be 1-31)"));
+        Request::List { labels, annotations, selector } => {
+            match agent_selector(&labels, &annotations, selector.as_deref()) {
+                Ok(selector) => Ok(Response::Agents {
+                    agents: runtime.list(&selector).await,
+                }),
+                Err(e) => Err(e),
             }
+        }
 
-            let mgr = manager.lock().await;
-
-            // Determine which agents to kill
-            let targets: Vec<String> = if let Some(ref agent_id) = id {
-                // Kill by specific ID
-                vec![agent_id.clone()]
-            } else if all {
-                // Kill all running agents
-                mgr.list()
-                    .filter(|a| a.is_running())
-                    .map(|a| a.id.clone())
-                    .collect()
-            } else if proc_filter.is_some() || !labels.is_empty() {
-                // Kill by proc filter and/or labels (AND logic when both specified)
-                mgr.list()
-                    .filter(|a| {
-                        if !a.is_running() {
-                            return false;
-                        }
-                        if !labels.is_empty() && !a.has_labels(&labels) {
-                            return false;
-                        }
-                        if let Some(ref pf) = proc_filter {
-                            if !a.command.join(" ").contains(pf.as_str()) {
-                                return false;
-                            }
-                        }
-                        true
-                    })
-                    .map(|a| a.id.clone())
-                    .collect()
+        Request::Kill { id, labels, annotations, selector, all, signal, proc_filter } => {
+            if let Some(agent_id) = id {
+                runtime.kill(&agent_id, signal).await
             } else {
-                return Response::error("must specify agent ID, --label, --proc, or --all");
-            };
-
-            if targets.is_empty() {
-                if id.is_some() {
-                    return Response::error(format!("agent not found: {}", id.unwrap()));
-                }
-                if all {
-                    return Response::error("no running agents to kill");
+                match agent_selector(&labels, &annotations, selector.as_deref()) {
+                    Ok(selector) => runtime
+                        .kill_matching(&selector, proc_filter.as_deref(), all, signal)
+                        .await
+                        .map(|_| ()),
+                    Err(e) => Err(e),
                 }
-                if proc_filter.is_some() && !labels.is_empty() {
-                    return Response::error("no agents match the specified process filter and labels");
-                }
-                if proc_filter.is_some() {
-                    return Response::error("no agents match the specified process filter");
-                }
-                return Response::error("no agents match the specified labels");
-            }
-            
-            let sig = Signal::try_from(signal).unwrap_or(Signal::SIGTERM);
-            let mut errors = Vec::new();
-            let mut killed = 0;
-            
-            for target_id in targets {
-                if let Some(agent) = mgr.get(&target_id) {
-                    // Check if agent already exited
-                    if !agent.is_running() {
-                        info!(%target_id, "Agent already exited, nothing to kill");
-                        continue;
-                    }
-                    match agent.pty.signal(sig) {
-                        Ok(()) => {
-                            info!(%target_id, ?sig, "Sent signal to agent");
-                            killed += 1;
-                        }
-                        Err(e) => {
-                            errors.push(format!("{target_id}: {e}"));
-                        }
-                    }
-                }
-            }
-            
-            if !errors.is_empty() {
-                Response::error(format!("failed to kill some agents: {}", errors.join(", ")))
-            } else if killed == 0 && id.is_some() {
-                Response::error(format!("agent not found: {}", id.unwrap()))
-            } else {
-                Response::Ok
             }
+            .map(|()| Response::Ok)
         }
 
-        Request::Send { id, data, newline } => {
-            let mgr = manager.lock().await;
-            if let Some(agent) = mgr.get(&id) {
+        Request::Send {
+            id,
+            data,
+            newline,
+            paste,
+            delay_ms,
+            jitter_ms,
+        } => {
+            let typed = delay_ms > 0 || jitter_ms > 0;
+            let result = if paste && typed {
+                Err(ServerError::InvalidRequest(
+                    "a paste can't be typed with a delay".into(),
+                ))
+            } else if paste {
+                runtime.paste(&id, data.as_bytes(), newline).await
+            } else if typed {
+                let mut text = data;
+                if newline {
+                    text.push('\n');
+                }
+                let delay = Duration::from_millis(delay_ms);
+                let jitter = Duration::from_millis(jitter_ms);
+                runtime.type_text(&id, &text, delay, jitter).await
+            } else {
                 let mut bytes = data.into_bytes();
                 if newline {
                     bytes.push(b'\n');
                 }
-
-                // Write to PTY master
-                let fd = agent.pty.master_fd();
-                // SAFETY: The fd is valid for the lifetime of the agent
-                #[allow(unsafe_code)]
-                let borrowed_fd = unsafe { BorrowedFd::borrow_raw(fd) };
-                match nix::unistd::write(borrowed_fd, &bytes)
-                {
-                    Ok(_) => Response::Ok,
-                    Err(e) => Response::error(format!("write failed: {e}")),
-                }
-            } else {
-                Response::error(format!("agent not found: {id}"))
-            }
+                runtime.send(&id, &bytes).await
+            };
+            result.map(|()| Response::Ok)
         }
 
-        Request::SendBytes { id, data } => {
-            let mgr = manager.lock().await;
-            if let Some(agent) = mgr.get(&id) {
-                let fd = agent.pty.master_fd();
-                // SAFETY: The fd is valid for the lifetime of the agent
-                #[allow(unsafe_code)]
-                let borrowed_fd = unsafe { BorrowedFd::borrow_raw(fd) };
-                match nix::unistd::write(borrowed_fd, &data)
-                {
-                    Ok(_) => Response::Ok,
-                    Err(e) => Response::error(format!("write failed: {e}")),
-                }
-            } else {
-                Response::error(format!("agent not found: {id}"))
-            }
+        Request::SendBytes { id, data } => runtime.send(&id, &data).await.map(|()| Response::Ok),
+
+        Request::SendMouse {
+            id,
+            action,
+            row,
+            col,
+            button,
+            to,
+        } => runtime
+            .send_mouse(&id, action, button, (row, col), to)
+            .await
+            .map(|()| Response::Ok),
+
+        Request::SendKeys { id, keys } => {
+            runtime.send_keys(&id, &keys).await.map(|()| Response::Ok)
         }
 
+        Request::Broadcast {
+            ids,
+            labels,
+            annotations,
+            selector,
+            data,
+            keys,
+        } => match agent_selector(&labels, &annotations, selector.as_deref()) {
+            Ok(selector) => runtime
+                .broadcast(&ids, &selector, &data, &keys)
+                .await
+                .map(|results| Response::Broadcast { results }),
+            Err(e) => Err(e),
+        },
+
         Request::Tail {
             id,
             lines: _,
             follow: _,
         } => {
-            let mgr = manager.lock().await;
-            if let Some(agent) = mgr.get(&id) {
-                // Return full transcript - client handles offset tracking
-                let data = agent.transcript.all_bytes();
-                Response::Output { data }
-            } else {
-                Response::error(format!("agent not found: {id}"))
-            }
+            // Return full transcript - client handles offset tracking
+            runtime
+                .transcript(&id)
+                .await
+                .map(|data| Response::Output { data })
         }
 
         Request::Dump { id, since, format } => {
-            let mgr = manager.lock().await;
-            if let Some(agent) = mgr.get(&id) {
-                let entries: Vec<TranscriptEntry> = if let Some(ts) = since {
-                    agent
-                        .transcript
-                        .since(ts)
-                        .into_iter()
-                        .map(|e| TranscriptEntry {
-                            timestamp: e.timestamp,
-                            data: e.data.clone(),
-                        })
-                        .collect()
-                } else {
-                    agent
-                        .transcript
-                        .all()
-                        .map(|e| TranscriptEntry {
-                            timestamp: e.timestamp,
-                            data: e.data.clone(),
-                        })
-                        .collect()
-                };
-
-                match format {
-                    DumpFormat::Jsonl => Response::Transcript { entries },
-                    DumpFormat::Text => {
-                        let data: Vec<u8> = entries.iter().flat_map(|e| e.data.clone()).collect();
-                        Response::Output { data }
-                    }
-                }
-            } else {
-                Response::error(format!("agent not found: {id}"))
-            }
+            runtime.dump(&id, since).await.map(|entries| match format {
+                DumpFormat::Jsonl => Response::Transcript { entries },
+                DumpFormat::Text => Response::Output {
+                    data: entries.into_iter().flat_map(|e| e.data).collect(),
+                },
+            })
         }
 
-        Request::Snapshot { id, strip_colors } => {
-            let mgr = manager.lock().await;
-            if let Some(agent) = mgr.get(&id) {
-                let content = if strip_colors {
-                    agent.screen.snapshot()
-                } else {
-                    agent.screen.contents_formatted()
+        Request::Snapshot {
+            id,
+            strip_colors,
+            region,
+            redact,
+        } => match Redactor::new(&redact) {
+            Ok(redactor) => {
+                let snapshot = match region {
+                    Some(region) => runtime.snapshot_region(&id, region, strip_colors).await,
+                    None if strip_colors => runtime.snapshot(&id).await,
+                    None => runtime.snapshot_formatted(&id).await,
                 };
-                let cursor = agent.screen.cursor_position();
-                let size = agent.screen.size();
-                Response::Snapshot {
-                    content,
-                    cursor,
-                    size,
-                }
-            } else {
-                Response::error(format!("agent not found: {id}"))
+                snapshot.map(|s| Response::Snapshot {
+                    content: redactor.apply(&s.content),
+                    cursor: s.cursor,
+                    size: s.size,
+                })
             }
-        }
+            Err(e) => Err(ServerError::InvalidRequest(e.to_string())),
+        },
 
         Request::Attach { id, readonly: _ } => {
             // Attach is handled specially in handle_connection
             // If we get here, something went wrong
-            let mgr = manager.lock().await;
-            if mgr.get(&id).is_some() {
-                Response::error("attach request should not reach handle_request")
+            if runtime.manager().lock().await.get(&id).is_some() {
+                Err(ServerError::InvalidRequest(
+                    "attach request should not reach handle_request".into(),
+                ))
             } else {
-                Response::error(format!("agent not found: {id}"))
+                Err(ServerError::AgentNotFound(id))
             }
         }
 
         Request::Events { .. } => {
             // Events is handled specially in handle_connection
             // If we get here, something went wrong
-            Response::error("events request should not reach handle_request")
+            Err(ServerError::InvalidRequest(
+                "events request should not reach handle_request".into(),
+            ))
         }
 
-        Request::Resize { id, rows, cols, clear_transcript } => {
-            // Validate dimensions to prevent crashes or resource exhaustion
-            const MIN_SIZE: u16 = 1;
-            const MAX_SIZE: u16 = 500;
-            if rows < MIN_SIZE || rows > MAX_SIZE || cols < MIN_SIZE || cols > MAX_SIZE {
-                return Response::error(format!(
-                    "invalid dimensions: {}x{} (must be {}-{})",
-                    cols, rows, MIN_SIZE, MAX_SIZE
-                ));
-            }
-            
-            let mut mgr = manager.lock().await;
-            if let Some(agent) = mgr.get_mut(&id) {
-                // Resize the PTY
-                if let Err(e) = agent.pty.resize(rows, cols) {
-                    return Response::error(format!("resize failed: {e}"));
-                }
-                // Update the screen model
-                agent.screen.resize(rows, cols);
-                // Optionally clear transcript (useful for view mode to avoid
-                // displaying output rendered at old size)
-                if clear_transcript {
-                    agent.transcript.clear();
-                    info!(%id, %rows, %cols, "Resized agent and cleared transcript");
-                } else {
-                    info!(%id, %rows, %cols, "Resized agent");
-                }
-                Response::Ok
-            } else {
-                Response::error(format!("agent not found: {id}"))
-            }
-        }
+        Request::Label { id, add, remove, set, unset } => runtime
+            .label(&id, add, &remove, set, &unset)
+            .await
+            .map(|()| Response::Ok),
+
+        Request::Resize { id, rows, cols, clear_transcript } => runtime
+            .resize(&id, rows, cols, clear_transcript)
+            .await
+            .map(|()| Response::Ok),
+
+        Request::Remove { id } => runtime.remove(&id).await.map(|()| Response::Ok),
 
         Request::Shutdown => {
+            // The connection handler triggers shutdown after responding
             info!("Shutdown requested");
-            // TODO: Actually trigger shutdown
-            Response::Ok
+            Ok(Response::Ok)
         }
-    }
+    };
+
+    result.unwrap_or_else(|e| Response::error(e.to_string()))
+}
+
+/// Combine label, annotation and selector expression filters into one selector.
+pub(crate) fn agent_selector(
+    labels: &[String],
+    annotations: &BTreeMap<String, String>,
+    selector: Option<&str>,
+) -> Result<Selector, ServerError> {
+    let selector = Selector::parse(selector.unwrap_or_default())
+        .map_err(|e| ServerError::InvalidRequest(e.to_string()))?;
+    Ok(selector.with_labels(labels).with_annotations(annotations))
 }
 
 /// Handle attach mode - streaming I/O between client and agent PTY.
@@ -686,7 +575,7 @@ async fn handle_attach(
     mut reader: OwnedReadHalf,
     mut writer: OwnedWriteHalf,
     manager: &Arc<Mutex<AgentManager>>,
-    event_tx: &broadcast::Sender<Event>,
+    events: &EventBus,
 ) -> Result<(), ServerError> {
     // Check if agent exists, get initial info, and mark as attached
     let size = {
@@ -752,6 +641,7 @@ async fn handle_attach(
         &mut reader,
         &mut writer,
         manager,
+        events,
     )
     .await;
 
@@ -774,7 +664,7 @@ async fn handle_attach(
     // Publish exit event outside the lock to avoid holding it during broadcast
     // (pty_reader_task skips attached agents, so we must publish here)
     if let AttachEndReason::AgentExited { exit_code } = &end_reason {
-        let _ = event_tx.send(Event::AgentExited {
+        events.publish(Event::AgentExited {
             id: agent_id.clone(),
             exit_code: *exit_code,
         });
@@ -791,65 +681,6 @@ async fn handle_attach(
     result.map(|_| ())
 }
 
-/// Handle event streaming - subscribe to agent lifecycle events.
-async fn handle_events(
-    filter: Vec<String>,
-    include_output: bool,
-    mut writer: OwnedWriteHalf,
-    event_tx: &broadcast::Sender<Event>,
-) -> Result<(), ServerError> {
-    let mut event_rx = event_tx.subscribe();
-    
-    info!(?filter, %include_output, "Events subscription started");
-
-    loop {
-        match event_rx.recv().await {
-            Ok(event) => {
-                // Filter by agent ID if specified
-                let agent_id = match &event {
-                    Event::AgentSpawned { id, .. }
-                    | Event::AgentOutput { id, .. }
-                    | Event::AgentExited { id, .. } => id,
-                };
-
-                // Skip if not in filter (unless filter is empty = all)
-                if !filter.is_empty() && !filter.contains(agent_id) {
-                    continue;
-                }
-
-                // Skip output events if not requested
-                if !include_output && matches!(event, Event::AgentOutput { .. }) {
-                    continue;
-                }
-
-                // Send event to client
-                let response = Response::Event(event);
-                let mut json = serde_json::to_string(&response)
-                    .expect("Response serialization should never fail");
-                json.push('\n');
-                
-                if writer.write_all(json.as_bytes()).await.is_err() {
-                    // Client disconnected
-                    debug!("Events client disconnected");
-                    break;
-                }
-            }
-            Err(broadcast::error::RecvError::Closed) => {
-                // Channel closed (server shutting down)
-                debug!("Events channel closed");
-                break;
-            }
-            Err(broadcast::error::RecvError::Lagged(n)) => {
-                // We missed some events - log but continue
-                warn!("Events subscriber lagged, missed {n} events");
-            }
-        }
-    }
-
-    info!("Events subscription ended");
-    Ok(())
-}
-
 /// Run the attach mode I/O bridge.
 ///
 /// Note on FD safety: We don't pass `pty_fd` as a parameter anymore. Instead, we
@@ -862,6 +693,7 @@ async fn run_attach_bridge(
     reader: &mut OwnedReadHalf,
     writer: &mut OwnedWriteHalf,
     manager: &Arc<Mutex<AgentManager>>,
+    events: &EventBus,
 ) -> Result<AttachEndReason, ServerError> {
     let mut input_buf = [0u8; 4096];
     let mut output_buf = [0u8; 4096];
@@ -881,13 +713,11 @@ async fn run_attach_bridge(
                     }
                     Ok(n) => {
                         // Get fd while holding lock to ensure it's valid
-                        let mgr = manager.lock().await;
-                        if let Some(agent) = mgr.get(agent_id) {
-                            let pty_fd = agent.pty.master_fd();
-                            // SAFETY: fd is valid because we hold the lock and agent exists
-                            #[allow(unsafe_code)]
-                            let borrowed_fd = unsafe { BorrowedFd::borrow_raw(pty_fd) };
-                            if let Err(e) = nix::unistd::write(borrowed_fd, &input_buf[..n]) {
+                        // Queued input is written here and on every poll tick
+                        // as the PTY accepts it
+                        let mut mgr = manager.lock().await;
+                        if let Some(agent) = mgr.get_mut(agent_id) {
+                            if let Err(e) = runtime::queue_input(agent, &input_buf[..n]) {
                                 warn!("Failed to write to PTY: {e}");
                                 return Ok(AttachEndReason::Error {
                                     message: format!("PTY write error: {e}"),
@@ -912,7 +742,7 @@ async fn run_attach_bridge(
                 if let Some(agent) = mgr.get_mut(agent_id) {
                     // Check for exit
                     if let Ok(Some(code)) = agent.pty.try_wait() {
-                        agent.state = InternalAgentState::Exited { code };
+                        agent.set_exited(code);
                         return Ok(AttachEndReason::AgentExited { exit_code: Some(code) });
                     }
 
@@ -922,6 +752,10 @@ async fn run_attach_bridge(
                         });
                     }
 
+                    if let Err(e) = runtime::flush_input(agent) {
+                        warn!("Failed to write to PTY: {e}");
+                    }
+
                     // Read from PTY - fd is valid because we hold lock
                     let pty_fd = agent.pty.master_fd();
                     // SAFETY: fd is valid because we hold the lock and agent exists
@@ -933,6 +767,9 @@ async fn run_attach_bridge(
                             // Update transcript and screen
                             agent.transcript.append(data);
                             agent.screen.process(data);
+                            runtime::publish_screen_events(agent, events);
+                            // The client's own terminal answers queries while attached
+                            agent.screen.take_replies();
                             // Send to client
                             drop(mgr); // Release lock before async write
                             writer.write_all(data).await.map_err(ServerError::Io)?;
@@ -942,7 +779,7 @@ async fn run_attach_bridge(
                         Err(nix::Error::EIO) => {
                             // PTY closed - agent probably exited
                             if let Ok(Some(code)) = agent.pty.try_wait() {
-                                agent.state = InternalAgentState::Exited { code };
+                                agent.set_exited(code);
                                 return Ok(AttachEndReason::AgentExited { exit_code: Some(code) });
                             }
                         }
@@ -961,117 +798,6 @@ async fn run_attach_bridge(
     }
 }
 
-/// Background task that reads from PTY masters and updates transcripts/screens.
-async fn pty_reader_task(manager: Arc<Mutex<AgentManager>>, event_tx: broadcast::Sender<Event>) {
-    use tokio::time::{interval, Duration};
-
-    let mut poll_interval = interval(Duration::from_millis(10));
-
-    loop {
-        poll_interval.tick().await;
-
-        let mut mgr = manager.lock().await;
-        let ids: Vec<String> = mgr.list().map(|a| a.id.clone()).collect();
-
-        for id in ids {
-            if let Some(agent) = mgr.get_mut(&id) {
-                // Skip agents that aren't running or are currently attached
-                // (attached agents have their I/O handled by run_attach_bridge)
-                if !agent.is_running() || agent.attached {
-                    continue;
-                }
-
-                // Check for timeout
-                if agent.is_timed_out() {
-                    if !agent.sigterm_sent {
-                        // First, send SIGTERM for graceful shutdown
-                        info!(%id, "Agent timeout - sending SIGTERM");
-                        let _ = agent.pty.signal(Signal::SIGTERM);
-                        agent.sigterm_sent = true;
-                        agent.sigterm_sent_at = Some(std::time::Instant::now());
-                    } else if agent.should_sigkill() {
-                        // Grace period expired, send SIGKILL
-                        info!(%id, "Agent timeout grace period expired - sending SIGKILL");
-                        let _ = agent.pty.signal(Signal::SIGKILL);
-                    }
-                }
-
-                // Try to read from the PTY master
-                let fd = agent.pty.master_fd();
-                let mut buf = [0u8; 4096];
-
-                // SAFETY: The fd is valid for the lifetime of the agent
-                #[allow(unsafe_code)]
-                let borrowed_fd = unsafe { BorrowedFd::borrow_raw(fd) };
-                
-                // Non-blocking read
-                match nix::unistd::read(borrowed_fd, &mut buf) {
-                    Ok(n) if n > 0 => {
-                        let data = &buf[..n];
-                        agent.transcript.append(data);
-                        agent.screen.process(data);
-                        
-                        // Publish output event
-                        let _ = event_tx.send(Event::AgentOutput {
-                            id: id.clone(),
-                            data: data.to_vec(),
-                        });
-                    }
-                    // No data available (empty read or EAGAIN/EWOULDBLOCK)
-                    Ok(_) | Err(nix::Error::EAGAIN) => {}
-                    Err(nix::Error::EIO) => {
-                        // PTY closed - child probably exited
-                        if let Ok(Some(code)) = agent.pty.try_wait() {
-                            agent.state = InternalAgentState::Exited { code };
-                            // Determine exit reason based on exit code:
-                            // - 128 + signal_num indicates killed by signal
-                            // - SIGTERM (15) -> 143, SIGKILL (9) -> 137
-                            agent.exit_reason = Some(if agent.sigterm_sent && (code == 143 || code == 137) {
-                                // Process was killed by our timeout signals
-                                ExitReason::Timeout
-                            } else {
-                                ExitReason::Normal
-                            });
-                            info!(%id, %code, exit_reason = ?agent.exit_reason, "Agent exited");
-                            
-                            // Publish exit event
-                            let _ = event_tx.send(Event::AgentExited {
-                                id: id.clone(),
-                                exit_code: Some(code),
-                            });
-                        }
-                    }
-                    Err(e) => {
-                        warn!(%id, %e, "PTY read error");
-                    }
-                }
-
-                // Check if child exited
-                if agent.is_running()
-                    && let Ok(Some(code)) = agent.pty.try_wait() {
-                        agent.state = InternalAgentState::Exited { code };
-                        // Determine exit reason based on exit code:
-                        // - 128 + signal_num indicates killed by signal
-                        // - SIGTERM (15) -> 143, SIGKILL (9) -> 137
-                        agent.exit_reason = Some(if agent.sigterm_sent && (code == 143 || code == 137) {
-                            // Process was killed by our timeout signals
-                            ExitReason::Timeout
-                        } else {
-                            ExitReason::Normal
-                        });
-                        info!(%id, %code, exit_reason = ?agent.exit_reason, "Agent exited");
-                        
-                        // Publish exit event
-                        let _ = event_tx.send(Event::AgentExited {
-                            id: id.clone(),
-                            exit_code: Some(code),
-                        });
-                    }
-            }
-        }
-    }
-}
-
 /// Check if a server is running by trying to connect.
 pub async fn is_server_running(socket_path: &Path) -> bool {
     UnixStream::connect(socket_path).await.is_ok()
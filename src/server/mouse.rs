@@ -0,0 +1,170 @@
+//! Mouse input, encoded for the reporting mode the application enabled.
+
+use crate::protocol::{MouseAction, MouseButton};
+use thiserror::Error;
+use vt100::{MouseProtocolEncoding, MouseProtocolMode};
+
+/// Why a mouse action couldn't be encoded.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum MouseError {
+    #[error("the application hasn't enabled mouse reporting")]
+    NotEnabled,
+
+    #[error("{0}")]
+    Invalid(&'static str),
+
+    #[error("position ({row}, {col}) is too far out for the application's mouse encoding")]
+    Unencodable { row: u16, col: u16 },
+}
+
+/// Encode a mouse action as the reports a terminal would send for it.
+///
+/// Positions are 0-indexed (row, col). Releases aren't reported in X10 mode
+/// (`Press`), and drag motion is only reported when the application asked for
+/// motion events.
+pub fn encode(
+    mode: MouseProtocolMode,
+    encoding: MouseProtocolEncoding,
+    action: MouseAction,
+    button: Option<MouseButton>,
+    at: (u16, u16),
+    to: Option<(u16, u16)>,
+) -> Result<Vec<u8>, MouseError> {
+    if mode == MouseProtocolMode::None {
+        return Err(MouseError::NotEnabled);
+    }
+    let button = button.unwrap_or(match action {
+        MouseAction::Scroll => MouseButton::WheelDown,
+        MouseAction::Click | MouseAction::Drag => MouseButton::Left,
+    });
+    let code = button_code(button);
+    let releases = mode != MouseProtocolMode::Press;
+
+    let mut out = Vec::new();
+    match action {
+        MouseAction::Click => {
+            out.extend(report(encoding, code, at, false)?);
+            // The wheel has no release
+            if releases && !button.is_wheel() {
+                out.extend(report(encoding, code, at, true)?);
+            }
+        }
+        MouseAction::Scroll => {
+            if !button.is_wheel() {
+                return Err(MouseError::Invalid("scroll needs the up or down button"));
+            }
+            out.extend(report(encoding, code, at, false)?);
+        }
+        MouseAction::Drag => {
+            let to = to.ok_or(MouseError::Invalid("drag needs an end position"))?;
+            if button.is_wheel() {
+                return Err(MouseError::Invalid("can't drag with the wheel"));
+            }
+            out.extend(report(encoding, code, at, false)?);
+            if matches!(
+                mode,
+                MouseProtocolMode::ButtonMotion | MouseProtocolMode::AnyMotion
+            ) {
+                out.extend(report(encoding, code + 32, to, false)?);
+            }
+            if releases {
+                out.extend(report(encoding, code, to, true)?);
+            }
+        }
+    }
+    Ok(out)
+}
+
+const fn button_code(button: MouseButton) -> u8 {
+    match button {
+        MouseButton::Left => 0,
+        MouseButton::Middle => 1,
+        MouseButton::Right => 2,
+        MouseButton::WheelUp => 64,
+        MouseButton::WheelDown => 65,
+    }
+}
+
+/// One mouse report. SGR reports keep the button on release and end in `m`;
+/// the older encodings report every release as button 3.
+fn report(
+    encoding: MouseProtocolEncoding,
+    code: u8,
+    (row, col): (u16, u16),
+    release: bool,
+) -> Result<Vec<u8>, MouseError> {
+    let (x, y) = (u32::from(col) + 1, u32::from(row) + 1);
+    if encoding == MouseProtocolEncoding::Sgr {
+        let end = if release { 'm' } else { 'M' };
+        return Ok(format!("\x1b[<{code};{x};{y}{end}").into_bytes());
+    }
+
+    let code = if release { 3 } else { code };
+    let mut out = vec![0x1b, b'[', b'M', 32 + code];
+    for v in [x, y] {
+        let v = v + 32;
+        match encoding {
+            MouseProtocolEncoding::Utf8 if v < 0x800 => {
+                let c = char::from_u32(v).ok_or(MouseError::Unencodable { row, col })?;
+                let mut buf = [0; 4];
+                out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+            _ => out.push(u8::try_from(v).map_err(|_| MouseError::Unencodable { row, col })?),
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use MouseProtocolEncoding::{Default as X10, Sgr, Utf8};
+    use MouseProtocolMode::{AnyMotion, ButtonMotion, Press, PressRelease};
+
+    #[test]
+    fn test_sgr_mouse() {
+        let click = encode(PressRelease, Sgr, MouseAction::Click, None, (4, 9), None);
+        assert_eq!(click.unwrap(), b"\x1b[<0;10;5M\x1b[<0;10;5m");
+
+        let right = encode(PressRelease, Sgr, MouseAction::Click, Some(MouseButton::Right), (0, 0), None);
+        assert_eq!(right.unwrap(), b"\x1b[<2;1;1M\x1b[<2;1;1m");
+
+        let scroll = encode(PressRelease, Sgr, MouseAction::Scroll, Some(MouseButton::WheelUp), (1, 1), None);
+        assert_eq!(scroll.unwrap(), b"\x1b[<64;2;2M");
+
+        let drag = encode(ButtonMotion, Sgr, MouseAction::Drag, None, (0, 0), Some((2, 5)));
+        assert_eq!(drag.unwrap(), b"\x1b[<0;1;1M\x1b[<32;6;3M\x1b[<0;6;3m");
+    }
+
+    #[test]
+    fn test_x10_and_utf8_mouse() {
+        // X10 mode only reports presses
+        let click = encode(Press, X10, MouseAction::Click, None, (0, 0), None);
+        assert_eq!(click.unwrap(), b"\x1b[M !!");
+
+        let click = encode(PressRelease, X10, MouseAction::Click, None, (0, 0), None);
+        assert_eq!(click.unwrap(), b"\x1b[M !!\x1b[M#!!");
+
+        // No motion reports unless the application asked for them
+        let drag = encode(PressRelease, X10, MouseAction::Drag, None, (0, 0), Some((1, 1)));
+        assert_eq!(drag.unwrap(), b"\x1b[M !!\x1b[M#\"\"");
+
+        assert_eq!(
+            encode(AnyMotion, X10, MouseAction::Click, None, (0, 300), None),
+            Err(MouseError::Unencodable { row: 0, col: 300 })
+        );
+        let wide = encode(Press, Utf8, MouseAction::Click, None, (0, 300), None).unwrap();
+        assert_eq!(wide, "\x1b[M \u{14d}!".as_bytes());
+    }
+
+    #[test]
+    fn test_mouse_errors() {
+        let none = MouseProtocolMode::None;
+        assert_eq!(
+            encode(none, Sgr, MouseAction::Click, None, (0, 0), None),
+            Err(MouseError::NotEnabled)
+        );
+        assert!(encode(PressRelease, Sgr, MouseAction::Drag, None, (0, 0), None).is_err());
+        assert!(encode(PressRelease, Sgr, MouseAction::Scroll, Some(MouseButton::Left), (0, 0), None).is_err());
+    }
+}
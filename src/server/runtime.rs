@@ -0,0 +1,1357 @@
+//! Embedded agent runtime.
+//!
+//! The [`Runtime`] owns the agent manager, the event bus, and the background
+//! PTY reader. Library users can drive agents in-process through it; the
+//! socket [`Server`](super::Server) is a frontend that translates requests
+//! into runtime calls.
+//!
+//! ```ignore
+//! let runtime = Runtime::new();
+//! let mut events = runtime.subscribe();
+//! let (id, _pid) = runtime.spawn(SpawnOptions::new(["bash"])).await?;
+//! runtime.send(&id, b"echo hello\n").await?;
+//! let snapshot = runtime.snapshot(&id).await?;
+//! ```
+
+use super::agent::{Agent, AgentState as InternalAgentState};
+use super::bus::{EventBus, EventSubscription};
+use super::manager::AgentManager;
+use super::mouse;
+use super::screen::ScreenEvent;
+use super::shell;
+use super::ServerError;
+use crate::protocol::{
+    AgentId, AgentInfo, AgentState, Event, InputStats, MouseAction, OutputStats, Region, MouseButton,
+    ResourceLimits, SendResult, Snapshot, SpawnOptions, TranscriptEntry,
+};
+use crate::keys::{encode_key, KeyModes};
+use crate::pty;
+use crate::selector::Selector;
+use nix::sys::signal::Signal;
+use std::collections::BTreeMap;
+use std::os::fd::BorrowedFd;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// Aborts the PTY reader task when the last runtime handle is dropped.
+struct ReaderGuard(JoinHandle<()>);
+
+impl Drop for ReaderGuard {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// In-process agent runtime.
+///
+/// Cloning is cheap; all clones share the same agents and event bus.
+#[derive(Clone)]
+pub struct Runtime {
+    manager: Arc<Mutex<AgentManager>>,
+    /// Sequenced event bus (lifecycle, output, and screen events).
+    events: EventBus,
+    _reader: Arc<ReaderGuard>,
+}
+
+impl Runtime {
+    /// Create a runtime and start its PTY reader task.
+    ///
+    /// Must be called from within a Tokio runtime.
+    #[must_use]
+    pub fn new() -> Self {
+        let manager = Arc::new(Mutex::new(AgentManager::new()));
+        let events = EventBus::new();
+        let reader = tokio::spawn(pty_reader_task(Arc::clone(&manager), events.clone()));
+        Self {
+            manager,
+            events,
+            _reader: Arc::new(ReaderGuard(reader)),
+        }
+    }
+
+    /// Subscribe to agent events (including output) published from now on.
+    ///
+    /// Slow subscribers receive `EventMessage::Lagged` for events that were
+    /// dropped from the bounded event log before they read them.
+    #[must_use]
+    pub fn subscribe(&self) -> EventSubscription {
+        self.events.subscribe(true)
+    }
+
+    /// Subscribe to agent events, first replaying retained events after
+    /// `since_seq` if given.
+    #[must_use]
+    pub fn subscribe_from(&self, since_seq: Option<u64>, include_output: bool) -> EventSubscription {
+        match since_seq {
+            Some(seq) => self.events.subscribe_since(seq, include_output),
+            None => self.events.subscribe(include_output),
+        }
+    }
+
+    /// The shared agent manager, for frontends that need direct access (attach).
+    pub(crate) const fn manager(&self) -> &Arc<Mutex<AgentManager>> {
+        &self.manager
+    }
+
+    /// The event bus, for frontends that publish events themselves (attach).
+    pub(crate) const fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    /// Spawn a new agent. Returns its ID and PID.
+    pub async fn spawn(&self, options: SpawnOptions) -> Result<(AgentId, u32), ServerError> {
+        let SpawnOptions {
+            cmd,
+            rows,
+            cols,
+            name,
+            labels,
+            annotations,
+            timeout,
+            max_output,
+            max_input,
+            env,
+            env_clear,
+            shell_integration,
+            term,
+            terminal,
+            no_terminal_replies,
+        } = options;
+
+        validate_annotations(&annotations)?;
+
+        if cmd.is_empty() {
+            return Err(ServerError::InvalidRequest("command is empty".into()));
+        }
+
+        // Describe the terminal first, so explicit env values override it
+        let term = term.as_deref().unwrap_or(pty::DEFAULT_TERM);
+        if term.is_empty() || term.contains(['/', '=', '\0']) {
+            return Err(ServerError::InvalidRequest(format!("invalid TERM: {term:?}")));
+        }
+        let mut env_vars = pty::terminal_env(term);
+
+        // Parse environment variables
+        env_vars.extend(env.iter().filter_map(|s| {
+            let mut parts = s.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) if !key.is_empty() => {
+                    Some((key.to_string(), value.to_string()))
+                }
+                _ => None, // Skip malformed entries
+            }
+        }));
+
+        // Build resource limits if any are specified
+        let limits = if timeout.is_some() || max_output.is_some() || max_input.is_some() {
+            Some(ResourceLimits {
+                timeout,
+                max_output,
+                max_input,
+            })
+        } else {
+            None
+        };
+
+        // Validate and resolve agent ID
+        let mut mgr = self.manager.lock().await;
+        let id = if let Some(custom_name) = name {
+            // Validate custom name - must be non-empty and shell-safe
+            // Only allow alphanumeric, hyphen, and underscore to prevent command injection
+            if custom_name.is_empty() {
+                return Err(ServerError::InvalidRequest("agent name cannot be empty".into()));
+            }
+            if !custom_name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                return Err(ServerError::InvalidRequest(
+                    "agent name must contain only alphanumeric characters, hyphens, and underscores".into(),
+                ));
+            }
+            if custom_name.len() > 64 {
+                return Err(ServerError::InvalidRequest(
+                    "agent name must be 64 characters or fewer".into(),
+                ));
+            }
+            // Check for uniqueness - allow reusing names of exited agents
+            if let Some(existing) = mgr.get(&custom_name) {
+                if existing.is_running() {
+                    return Err(ServerError::NameInUse(custom_name));
+                }
+                // Remove the exited agent to reuse the name
+                mgr.remove(&custom_name);
+                self.events.publish(Event::AgentRemoved { id: custom_name.clone() });
+            }
+            custom_name
+        } else {
+            mgr.generate_id()
+        };
+        drop(mgr); // Release lock before spawning
+
+        let spawn_cmd = if shell_integration {
+            shell::integrate(&cmd, &mut env_vars).map_err(ServerError::Io)?
+        } else {
+            cmd.clone()
+        };
+        let spawn_env = pty::SpawnEnv {
+            vars: env_vars,
+            clear: env_clear,
+        };
+        let pty_process =
+            pty::spawn_with_env(&spawn_cmd, rows, cols, &spawn_env).map_err(ServerError::Spawn)?;
+
+        let mut mgr = self.manager.lock().await;
+        // Double-check uniqueness (in case of race) - only block if running
+        if let Some(existing) = mgr.get(&id) {
+            if existing.is_running() {
+                return Err(ServerError::NameInUse(id));
+            }
+            mgr.remove(&id);
+            self.events.publish(Event::AgentRemoved { id: id.clone() });
+        }
+        let pid = pty_process.pid.as_raw() as u32;
+        let mut agent = Agent::new(id.clone(), cmd.clone(), labels.clone(), limits, pty_process, rows, cols);
+        agent.annotations = annotations.clone();
+        if !no_terminal_replies {
+            agent.screen.answer_queries(terminal);
+        }
+        mgr.add(agent);
+        drop(mgr);
+        info!(%id, %pid, ?labels, ?limits, "Spawned agent");
+
+        // Publish spawn event
+        self.events.publish(Event::AgentSpawned {
+            id: id.clone(),
+            pid,
+            command: cmd,
+            labels,
+            annotations,
+        });
+
+        Ok((id, pid))
+    }
+
+    /// List agents (including exited ones) matching the selector.
+    pub async fn list(&self, selector: &Selector) -> Vec<AgentInfo> {
+        let mgr = self.manager.lock().await;
+        mgr.list()
+            .filter(|agent| agent.matches(selector))
+            .map(agent_info)
+            .collect()
+    }
+
+    /// Check whether an agent currently runs one of `commands` (empty = any)
+    /// and whether it matches the selector, in that order. None if the agent
+    /// doesn't exist.
+    pub async fn agent_matches(
+        &self,
+        id: &str,
+        selector: &Selector,
+        commands: &[String],
+    ) -> Option<(bool, bool)> {
+        let mgr = self.manager.lock().await;
+        mgr.get(id).map(|agent| {
+            (commands.is_empty() || agent.runs_command(commands), agent.matches(selector))
+        })
+    }
+
+    /// Change an agent's labels and annotations, publishing `labels_changed`
+    /// if anything changed. Removals are applied before additions.
+    pub async fn label(
+        &self,
+        id: &str,
+        add: Vec<String>,
+        remove: &[String],
+        set: BTreeMap<String, String>,
+        unset: &[String],
+    ) -> Result<(), ServerError> {
+        validate_annotations(&set)?;
+        let mut mgr = self.manager.lock().await;
+        let agent = mgr
+            .get_mut(id)
+            .ok_or_else(|| ServerError::AgentNotFound(id.to_string()))?;
+        if agent.relabel(add, remove, set, unset) {
+            info!(%id, labels = ?agent.labels, annotations = ?agent.annotations, "Relabeled agent");
+            self.events.publish(Event::LabelsChanged {
+                id: id.to_string(),
+                labels: agent.labels.clone(),
+                annotations: agent.annotations.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Forget an exited agent. Running agents must be killed first.
+    pub async fn remove(&self, id: &str) -> Result<(), ServerError> {
+        let mut mgr = self.manager.lock().await;
+        match mgr.get(id) {
+            None => Err(ServerError::AgentNotFound(id.to_string())),
+            Some(agent) if agent.is_running() => Err(ServerError::InvalidRequest(format!(
+                "agent {id} is still running"
+            ))),
+            Some(_) => {
+                mgr.remove(id);
+                self.events.publish(Event::AgentRemoved { id: id.to_string() });
+                Ok(())
+            }
+        }
+    }
+
+    /// Send a signal to a single agent.
+    pub async fn kill(&self, id: &str, signal: i32) -> Result<(), ServerError> {
+        let sig = validate_signal(signal)?;
+        let mut mgr = self.manager.lock().await;
+        let agent = mgr
+            .get_mut(id)
+            .filter(|a| a.is_running())
+            .ok_or_else(|| ServerError::AgentNotFound(id.to_string()))?;
+        agent
+            .pty
+            .signal(sig)
+            .map_err(|e| ServerError::Signal(format!("{id}: {e}")))?;
+        agent.signalled = true;
+        info!(%id, ?sig, "Sent signal to agent");
+        Ok(())
+    }
+
+    /// Send a signal to every running agent matching the labels and process filter
+    /// (AND logic when both are given), or to all running agents if `all` is set.
+    ///
+    /// Returns the number of agents signalled.
+    pub async fn kill_matching(
+        &self,
+        selector: &Selector,
+        proc_filter: Option<&str>,
+        all: bool,
+        signal: i32,
+    ) -> Result<usize, ServerError> {
+        let sig = validate_signal(signal)?;
+        if !all && selector.is_empty() && proc_filter.is_none() {
+            return Err(ServerError::InvalidRequest(
+                "must specify agent ID, --label, --annotation, --selector, --proc, or --all".into(),
+            ));
+        }
+
+        let mut mgr = self.manager.lock().await;
+        let targets: Vec<&mut Agent> = mgr
+            .list_mut()
+            .filter(|a| a.is_running())
+            .filter(|a| all || a.matches(selector))
+            .filter(|a| all || proc_filter.is_none_or(|pf| a.command.join(" ").contains(pf)))
+            .collect();
+
+        if targets.is_empty() {
+            let message = if all {
+                "no running agents to kill"
+            } else if proc_filter.is_some() && !selector.is_empty() {
+                "no agents match the specified process filter and labels"
+            } else if proc_filter.is_some() {
+                "no agents match the specified process filter"
+            } else {
+                "no agents match the specified labels"
+            };
+            return Err(ServerError::NoMatch(message.into()));
+        }
+
+        let count = targets.len();
+        let mut errors = Vec::new();
+        for agent in targets {
+            match agent.pty.signal(sig) {
+                Ok(()) => {
+                    agent.signalled = true;
+                    info!(id = %agent.id, ?sig, "Sent signal to agent");
+                }
+                Err(e) => errors.push(format!("{}: {e}", agent.id)),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(count)
+        } else {
+            Err(ServerError::Signal(errors.join(", ")))
+        }
+    }
+
+    /// Write raw bytes to an agent's PTY, returning once the PTY has accepted
+    /// all of them.
+    pub async fn send(&self, id: &str, data: &[u8]) -> Result<(), ServerError> {
+        let mut mgr = self.manager.lock().await;
+        let agent = mgr
+            .get_mut(id)
+            .ok_or_else(|| ServerError::AgentNotFound(id.to_string()))?;
+        let target = queue_input(agent, data)?;
+        drop(mgr);
+        self.wait_written(id, target).await
+    }
+
+    /// Wait until an agent's PTY has accepted its input up to `target` bytes.
+    ///
+    /// The reader task drains input queues on every tick too, but flushing
+    /// here means a send doesn't wait a whole tick for each pipe-full.
+    async fn wait_written(&self, id: &str, target: u64) -> Result<(), ServerError> {
+        loop {
+            {
+                let mut mgr = self.manager.lock().await;
+                let agent = mgr
+                    .get_mut(id)
+                    .ok_or_else(|| ServerError::AgentNotFound(id.to_string()))?;
+                flush_input(agent)?;
+                if agent.input.written() >= target {
+                    return Ok(());
+                }
+                if !agent.is_running() {
+                    return Err(ServerError::AgentExited(id.to_string()));
+                }
+            }
+            tokio::time::sleep(INPUT_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Type text into an agent one character at a time, `delay` plus a random
+    /// extra of up to `jitter` apart.
+    ///
+    /// The manager lock is only taken for each write, so the agent (and every
+    /// other request) keeps running while typing. Stops with
+    /// [`ServerError::AgentExited`] if the agent exits partway through.
+    pub async fn type_text(
+        &self,
+        id: &str,
+        text: &str,
+        delay: Duration,
+        jitter: Duration,
+    ) -> Result<(), ServerError> {
+        let mut buf = [0; 4];
+        for (i, c) in text.chars().enumerate() {
+            if i > 0 {
+                tokio::time::sleep(delay + random_duration(jitter)).await;
+            }
+            let mut mgr = self.manager.lock().await;
+            let agent = mgr
+                .get_mut(id)
+                .ok_or_else(|| ServerError::AgentNotFound(id.to_string()))?;
+            if !agent.is_running() {
+                return Err(ServerError::AgentExited(id.to_string()));
+            }
+            let target = queue_input(agent, c.encode_utf8(&mut buf).as_bytes())?;
+            drop(mgr);
+            self.wait_written(id, target).await?;
+        }
+        Ok(())
+    }
+
+    /// Paste text into an agent: wrapped in bracketed paste markers if the
+    /// agent has enabled bracketed paste mode, otherwise written as plain input.
+    /// The trailing newline, if requested, is sent after the paste so it submits it.
+    pub async fn paste(&self, id: &str, data: &[u8], newline: bool) -> Result<(), ServerError> {
+        let mut mgr = self.manager.lock().await;
+        let agent = mgr
+            .get_mut(id)
+            .ok_or_else(|| ServerError::AgentNotFound(id.to_string()))?;
+        let mut bytes = if agent.screen.bracketed_paste() {
+            bracketed_paste(data)
+        } else {
+            data.to_vec()
+        };
+        if newline {
+            bytes.push(b'\n');
+        }
+        let target = queue_input(agent, &bytes)?;
+        drop(mgr);
+        self.wait_written(id, target).await
+    }
+
+    /// Send a mouse action to an agent, encoded for the mouse reporting mode it
+    /// enabled. Positions are 0-indexed (row, col) and must be on screen.
+    pub async fn send_mouse(
+        &self,
+        id: &str,
+        action: MouseAction,
+        button: Option<MouseButton>,
+        at: (u16, u16),
+        to: Option<(u16, u16)>,
+    ) -> Result<(), ServerError> {
+        let mut mgr = self.manager.lock().await;
+        let agent = mgr
+            .get_mut(id)
+            .ok_or_else(|| ServerError::AgentNotFound(id.to_string()))?;
+
+        let (rows, cols) = agent.screen.size();
+        for (row, col) in std::iter::once(at).chain(to) {
+            if row >= rows || col >= cols {
+                return Err(ServerError::InvalidRequest(format!(
+                    "position ({row}, {col}) is outside the {rows}x{cols} screen"
+                )));
+            }
+        }
+        let (mode, encoding) = agent.screen.mouse_protocol();
+        let data = mouse::encode(mode, encoding, action, button, at, to)
+            .map_err(|e| ServerError::InvalidRequest(format!("{id}: {e}")))?;
+        let target = queue_input(agent, &data)?;
+        drop(mgr);
+        self.wait_written(id, target).await
+    }
+
+    /// Send named keys to an agent, encoded for its current cursor and keypad modes.
+    pub async fn send_keys(&self, id: &str, keys: &[String]) -> Result<(), ServerError> {
+        let mut mgr = self.manager.lock().await;
+        let agent = mgr
+            .get_mut(id)
+            .ok_or_else(|| ServerError::AgentNotFound(id.to_string()))?;
+        let data = encode_keys(keys, agent.screen.key_modes())?;
+        let target = queue_input(agent, &data)?;
+        drop(mgr);
+        self.wait_written(id, target).await
+    }
+
+    /// Write the same input to the listed agents and to every running agent
+    /// matching the selector, reporting success or failure for each one. The
+    /// keys are sent after `data`, encoded for each agent's own modes.
+    ///
+    /// A failure for one agent doesn't stop the others from being written to.
+    pub async fn broadcast(
+        &self,
+        ids: &[String],
+        selector: &Selector,
+        data: &[u8],
+        keys: &[String],
+    ) -> Result<Vec<SendResult>, ServerError> {
+        if ids.is_empty() && selector.is_empty() {
+            return Err(ServerError::InvalidRequest(
+                "must specify agent IDs, --label, --annotation, or --selector".into(),
+            ));
+        }
+        // Reject unknown keys before writing anything
+        encode_keys(keys, KeyModes::default())?;
+
+        let mut mgr = self.manager.lock().await;
+        let mut targets: Vec<String> = Vec::new();
+        let matching = mgr
+            .list()
+            .filter(|a| !selector.is_empty() && a.is_running() && a.matches(selector))
+            .map(|a| &a.id);
+        for id in ids.iter().chain(matching) {
+            if !targets.contains(id) {
+                targets.push(id.clone());
+            }
+        }
+        if targets.is_empty() {
+            return Err(ServerError::NoMatch("no running agents match the selector".into()));
+        }
+
+        // Queue everything first so the agents all get their input together,
+        // then wait for each of them to accept it
+        let queued: Vec<(String, Result<u64, String>)> = targets
+            .into_iter()
+            .map(|id| {
+                let result = match mgr.get_mut(&id) {
+                    None => Err(ServerError::AgentNotFound(id.clone()).to_string()),
+                    Some(agent) if !agent.is_running() => Err("agent is not running".to_string()),
+                    Some(agent) => encode_keys(keys, agent.screen.key_modes())
+                        .and_then(|encoded| queue_input(agent, &[data, &encoded].concat()))
+                        .map_err(|e| e.to_string()),
+                };
+                (id, result)
+            })
+            .collect();
+        drop(mgr);
+
+        let mut results = Vec::with_capacity(queued.len());
+        for (id, result) in queued {
+            let error = match result {
+                Ok(target) => self.wait_written(&id, target).await.err().map(|e| e.to_string()),
+                Err(e) => Some(e),
+            };
+            results.push(SendResult { id, error });
+        }
+        Ok(results)
+    }
+
+    /// Get the text of each row of an agent's screen, the cursor position and
+    /// the screen size.
+    pub async fn screen_rows(
+        &self,
+        id: &str,
+    ) -> Result<(Vec<String>, (u16, u16), (u16, u16)), ServerError> {
+        let mgr = self.manager.lock().await;
+        let agent = mgr
+            .get(id)
+            .ok_or_else(|| ServerError::AgentNotFound(id.to_string()))?;
+        Ok((agent.screen.rows(), agent.screen.cursor_position(), agent.screen.size()))
+    }
+
+    /// Get a normalized (color-stripped) snapshot of an agent's screen.
+    pub async fn snapshot(&self, id: &str) -> Result<Snapshot, ServerError> {
+        self.snapshot_with(id, true, None).await
+    }
+
+    /// Get a snapshot of an agent's screen with ANSI colors preserved.
+    pub async fn snapshot_formatted(&self, id: &str) -> Result<Snapshot, ServerError> {
+        self.snapshot_with(id, false, None).await
+    }
+
+    /// Get a snapshot of part of an agent's screen, with or without colors.
+    pub async fn snapshot_region(
+        &self,
+        id: &str,
+        region: Region,
+        strip_colors: bool,
+    ) -> Result<Snapshot, ServerError> {
+        self.snapshot_with(id, strip_colors, Some(region)).await
+    }
+
+    async fn snapshot_with(
+        &self,
+        id: &str,
+        strip_colors: bool,
+        region: Option<Region>,
+    ) -> Result<Snapshot, ServerError> {
+        let mgr = self.manager.lock().await;
+        let agent = mgr
+            .get(id)
+            .ok_or_else(|| ServerError::AgentNotFound(id.to_string()))?;
+        let screen = &agent.screen;
+        let content = match (region, strip_colors) {
+            (None, true) => screen.snapshot(),
+            (None, false) => screen.contents_formatted(),
+            (Some(region), true) => screen.region_snapshot(region),
+            (Some(region), false) => screen.region_formatted(region),
+        };
+        Ok(Snapshot {
+            content,
+            cursor: agent.screen.cursor_position(),
+            size: agent.screen.size(),
+        })
+    }
+
+    /// Get the full transcript of an agent's raw output.
+    pub async fn transcript(&self, id: &str) -> Result<Vec<u8>, ServerError> {
+        let mgr = self.manager.lock().await;
+        let agent = mgr
+            .get(id)
+            .ok_or_else(|| ServerError::AgentNotFound(id.to_string()))?;
+        Ok(agent.transcript.all_bytes())
+    }
+
+    /// Get timestamped transcript entries, optionally only those since a Unix timestamp (millis).
+    pub async fn dump(
+        &self,
+        id: &str,
+        since: Option<u64>,
+    ) -> Result<Vec<TranscriptEntry>, ServerError> {
+        let mgr = self.manager.lock().await;
+        let agent = mgr
+            .get(id)
+            .ok_or_else(|| ServerError::AgentNotFound(id.to_string()))?;
+        let to_entry = |e: &super::transcript::TranscriptEntry| TranscriptEntry {
+            timestamp: e.timestamp,
+            data: e.data.clone(),
+        };
+        Ok(if let Some(ts) = since {
+            agent.transcript.since(ts).into_iter().map(to_entry).collect()
+        } else {
+            agent.transcript.all().map(to_entry).collect()
+        })
+    }
+
+    /// Resize an agent's terminal, optionally clearing its transcript.
+    pub async fn resize(
+        &self,
+        id: &str,
+        rows: u16,
+        cols: u16,
+        clear_transcript: bool,
+    ) -> Result<(), ServerError> {
+        // Validate dimensions to prevent crashes or resource exhaustion
+        const MIN_SIZE: u16 = 1;
+        const MAX_SIZE: u16 = 500;
+        if !(MIN_SIZE..=MAX_SIZE).contains(&rows) || !(MIN_SIZE..=MAX_SIZE).contains(&cols) {
+            return Err(ServerError::InvalidRequest(format!(
+                "invalid dimensions: {cols}x{rows} (must be {MIN_SIZE}-{MAX_SIZE})"
+            )));
+        }
+
+        let mut mgr = self.manager.lock().await;
+        let agent = mgr
+            .get_mut(id)
+            .ok_or_else(|| ServerError::AgentNotFound(id.to_string()))?;
+        // Resize the PTY
+        agent.pty.resize(rows, cols).map_err(ServerError::Resize)?;
+        // Update the screen model
+        agent.screen.resize(rows, cols);
+        self.events.publish(Event::Resized {
+            id: id.to_string(),
+            rows,
+            cols,
+        });
+        // Optionally clear transcript (useful for view mode to avoid
+        // displaying output rendered at old size)
+        if clear_transcript {
+            agent.transcript.clear();
+            info!(%id, %rows, %cols, "Resized agent and cleared transcript");
+        } else {
+            info!(%id, %rows, %cols, "Resized agent");
+        }
+        Ok(())
+    }
+}
+
+impl Default for Runtime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Validate a signal number - only standard signals (1-31) are allowed.
+///
+/// Real-time signals (32-64) and invalid numbers are rejected.
+fn validate_signal(signal: i32) -> Result<Signal, ServerError> {
+    if !(1..=31).contains(&signal) {
+        return Err(ServerError::InvalidRequest(format!(
+            "invalid signal number: {signal} (must be 1-31)"
+        )));
+    }
+    Ok(Signal::try_from(signal).unwrap_or(Signal::SIGTERM))
+}
+
+/// Validate annotation keys - non-empty, without whitespace or selector
+/// operators, so they can be written as `KEY=VALUE` and used in selectors.
+fn validate_annotations(annotations: &BTreeMap<String, String>) -> Result<(), ServerError> {
+    match annotations
+        .keys()
+        .find(|k| k.is_empty() || k.contains(|c: char| c.is_whitespace() || ",()|!=".contains(c)))
+    {
+        Some(key) => Err(ServerError::InvalidRequest(format!("invalid annotation key: {key:?}"))),
+        None => Ok(()),
+    }
+}
+
+/// Build the public description of an agent.
+fn agent_info(agent: &Agent) -> AgentInfo {
+    let elapsed = agent.started_at.elapsed();
+    let now_millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let started_at = now_millis.saturating_sub(elapsed.as_millis() as u64);
+    let exited_at = agent
+        .exited_at
+        .map(|t| now_millis.saturating_sub(t.elapsed().as_millis() as u64));
+
+    AgentInfo {
+        id: agent.id.clone(),
+        pid: agent.pid(),
+        state: match agent.state {
+            InternalAgentState::Running => AgentState::Running,
+            InternalAgentState::Exited { .. } => AgentState::Exited,
+        },
+        command: agent.command.clone(),
+        labels: agent.labels.clone(),
+        annotations: agent.annotations.clone(),
+        size: agent.screen.size(),
+        started_at,
+        exited_at,
+        exit_code: agent.exit_code(),
+        exit_reason: agent.exit_reason,
+        limits: agent.limits,
+        shell: agent.screen.shell_state(),
+        input: InputStats {
+            queued: agent.input.queued(),
+            written: agent.input.written(),
+            limit: agent.input.limit(),
+        },
+        output: OutputStats {
+            written: agent.transcript.written(),
+            dropped: agent.transcript.dropped(),
+        },
+    }
+}
+
+/// A random duration up to `max`, inclusive.
+fn random_duration(max: Duration) -> Duration {
+    use std::hash::{BuildHasher, RandomState};
+
+    if max.is_zero() {
+        return max;
+    }
+    // Each RandomState is freshly seeded, which is plenty for typing jitter
+    let random = RandomState::new().hash_one(std::time::Instant::now());
+    let max_ms = u64::try_from(max.as_millis()).unwrap_or(u64::MAX);
+    Duration::from_millis(random % max_ms.saturating_add(1))
+}
+
+/// Start and end markers of a bracketed paste.
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// Wrap data in bracketed paste markers. End markers inside the data are
+/// dropped so the pasted text can't end the paste early.
+fn bracketed_paste(data: &[u8]) -> Vec<u8> {
+    let mut out = PASTE_START.to_vec();
+    let mut rest = data;
+    while let Some(pos) = rest.windows(PASTE_END.len()).position(|w| w == PASTE_END) {
+        out.extend_from_slice(&rest[..pos]);
+        rest = &rest[pos + PASTE_END.len()..];
+    }
+    out.extend_from_slice(rest);
+    out.extend_from_slice(PASTE_END);
+    out
+}
+
+/// Encode key names for the given modes.
+fn encode_keys(keys: &[String], modes: KeyModes) -> Result<Vec<u8>, ServerError> {
+    let mut data = Vec::new();
+    for key in keys {
+        let bytes = encode_key(key, modes)
+            .ok_or_else(|| ServerError::InvalidRequest(format!("unknown key: {key}")))?;
+        data.extend(bytes);
+    }
+    Ok(data)
+}
+
+/// How often a send checks whether the PTY has accepted its input.
+const INPUT_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Queue input for an agent and write as much of it as the PTY accepts now.
+///
+/// Returns the agent's `written` count at which all of it has been written.
+pub(super) fn queue_input(agent: &mut Agent, data: &[u8]) -> Result<u64, ServerError> {
+    let target = agent.input.push(data).ok_or_else(|| ServerError::InputFull {
+        id: agent.id.clone(),
+        pending: agent.input.pending(),
+        limit: agent.input.limit(),
+    })?;
+    if data.iter().any(|&b| b == b'\r' || b == b'\n') {
+        agent.screen.input_submitted();
+    }
+    flush_input(agent)?;
+    Ok(target)
+}
+
+/// Write as much of an agent's queued input as its PTY accepts without blocking.
+pub(super) fn flush_input(agent: &mut Agent) -> Result<(), ServerError> {
+    if agent.input.is_empty() {
+        return Ok(());
+    }
+    let fd = agent.pty.master_fd();
+    // SAFETY: The fd is valid for the lifetime of the agent, which the caller's lock keeps alive
+    #[allow(unsafe_code)]
+    let borrowed_fd = unsafe { BorrowedFd::borrow_raw(fd) };
+    agent.input.flush(borrowed_fd).map_err(ServerError::Write)
+}
+
+/// Record that an agent exited and publish the exit event.
+fn mark_exited(agent: &mut Agent, code: i32, events: &EventBus) {
+    agent.set_exited(code);
+    info!(id = %agent.id, %code, exit_reason = ?agent.exit_reason, "Agent exited");
+
+    // Publish exit event
+    events.publish(Event::AgentExited {
+        id: agent.id.clone(),
+        exit_code: Some(code),
+    });
+}
+
+/// Background task that reads from PTY masters and updates transcripts/screens.
+async fn pty_reader_task(manager: Arc<Mutex<AgentManager>>, events: EventBus) {
+    use tokio::time::{interval, Duration};
+
+    let mut poll_interval = interval(Duration::from_millis(10));
+
+    loop {
+        poll_interval.tick().await;
+
+        let mut mgr = manager.lock().await;
+        let ids: Vec<String> = mgr.list().map(|a| a.id.clone()).collect();
+
+        for id in ids {
+            if let Some(agent) = mgr.get_mut(&id) {
+                // Skip agents that aren't running or are currently attached
+                // (attached agents have their I/O handled by run_attach_bridge)
+                if !agent.is_running() || agent.attached {
+                    continue;
+                }
+
+                // Check for timeout
+                if agent.is_timed_out() {
+                    if !agent.sigterm_sent {
+                        // First, send SIGTERM for graceful shutdown
+                        info!(%id, "Agent timeout - sending SIGTERM");
+                        let _ = agent.pty.signal(Signal::SIGTERM);
+                        agent.sigterm_sent = true;
+                        agent.sigterm_sent_at = Some(std::time::Instant::now());
+                    } else if agent.should_sigkill() {
+                        // Grace period expired, send SIGKILL
+                        info!(%id, "Agent timeout grace period expired - sending SIGKILL");
+                        let _ = agent.pty.signal(Signal::SIGKILL);
+                    }
+                }
+
+                // Write queued input, read pending output, then check whether
+                // the child exited
+                if let Err(e) = flush_input(agent) {
+                    warn!(%id, %e, "PTY write error");
+                }
+                read_pending(agent, &events, CHUNKS_PER_TICK);
+                if let Ok(Some(code)) = agent.pty.try_wait() {
+                    // Drain what the child wrote before exiting so no output is lost
+                    read_pending(agent, &events, MAX_DRAIN_CHUNKS);
+                    mark_exited(agent, code, &events);
+                }
+            }
+        }
+    }
+}
+
+/// Publish the events an agent's screen recognised while processing output.
+pub(super) fn publish_screen_events(agent: &mut Agent, events: &EventBus) {
+    for event in agent.screen.take_events() {
+        let id = agent.id.clone();
+        events.publish(match event {
+            ScreenEvent::CommandStarted { command } => Event::CommandStarted { id, command },
+            ScreenEvent::CommandFinished { command, exit_code } => {
+                Event::CommandFinished { id, command, exit_code }
+            }
+            ScreenEvent::TitleChanged { title } => Event::TitleChanged { id, title },
+            ScreenEvent::Bell => Event::Bell { id },
+            ScreenEvent::AltScreen { active: true } => Event::AltScreenEntered { id },
+            ScreenEvent::AltScreen { active: false } => Event::AltScreenExited { id },
+            ScreenEvent::CursorVisibility { visible } => {
+                Event::CursorVisibilityChanged { id, visible }
+            }
+            ScreenEvent::ScreenCleared => Event::ScreenCleared { id },
+        });
+    }
+}
+
+/// Write the answers to any terminal queries the agent just made back to it.
+/// They don't count as input sent to the agent, so they bypass its input limit.
+fn answer_queries(agent: &mut Agent) {
+    let replies = agent.screen.take_replies();
+    if replies.is_empty() {
+        return;
+    }
+    if !agent.input.push_reply(&replies) {
+        warn!(id = %agent.id, "Too many unread terminal query replies; dropping more");
+    } else if let Err(e) = flush_input(agent) {
+        warn!(id = %agent.id, %e, "Failed to answer terminal query");
+    }
+}
+
+/// PTY reads per agent per reader tick, so one chatty agent can't starve the rest.
+const CHUNKS_PER_TICK: usize = 16;
+
+/// Upper bound on reads when draining an exited agent, in case a surviving
+/// grandchild keeps the PTY open and writing.
+const MAX_DRAIN_CHUNKS: usize = 256;
+
+/// Read up to `max_chunks` chunks of pending output from an agent's PTY into
+/// its transcript and screen, publishing an output event per chunk.
+fn read_pending(agent: &mut Agent, events: &EventBus, max_chunks: usize) {
+    let fd = agent.pty.master_fd();
+    let mut buf = [0u8; 4096];
+
+    // SAFETY: The fd is valid for the lifetime of the agent
+    #[allow(unsafe_code)]
+    let borrowed_fd = unsafe { BorrowedFd::borrow_raw(fd) };
+
+    for _ in 0..max_chunks {
+        // Non-blocking read
+        match nix::unistd::read(borrowed_fd, &mut buf) {
+            Ok(n) if n > 0 => {
+                let data = &buf[..n];
+                agent.transcript.append(data);
+                agent.screen.process(data);
+
+                // Publish output event
+                events.publish(Event::AgentOutput {
+                    id: agent.id.clone(),
+                    data: data.to_vec(),
+                });
+                publish_screen_events(agent, events);
+                answer_queries(agent);
+            }
+            // No data available (empty read or EAGAIN/EWOULDBLOCK), or the
+            // PTY closed (EIO) because the child exited
+            Ok(_) | Err(nix::Error::EAGAIN | nix::Error::EIO) => break,
+            Err(e) => {
+                warn!(id = %agent.id, %e, "PTY read error");
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::EventMessage;
+    use std::time::Duration;
+
+    async fn next_event(events: &mut EventSubscription) -> Event {
+        match events.recv().await.expect("recv failed") {
+            EventMessage::Event { event, .. } => event,
+            EventMessage::Lagged { missed } => panic!("lagged by {missed} events"),
+        }
+    }
+
+    /// Poll an agent's screen until `pred` holds, returning its content.
+    async fn wait_for_screen(runtime: &Runtime, id: &str, pred: impl Fn(&str) -> bool) -> String {
+        let mut content = String::new();
+        for _ in 0..50 {
+            content = runtime.snapshot(id).await.expect("snapshot failed").content;
+            if pred(&content) {
+                return content;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("screen of {id} never matched: {content:?}");
+    }
+
+    #[tokio::test]
+    async fn test_runtime_spawn_snapshot_and_events() {
+        let runtime = Runtime::new();
+        let mut events = runtime.subscribe();
+
+        let (id, _pid) = runtime
+            .spawn(SpawnOptions::new(["sh", "-c", "echo RUNTIME_TEST; sleep 10"]))
+            .await
+            .expect("spawn failed");
+
+        assert!(matches!(
+            next_event(&mut events).await,
+            Event::AgentSpawned { id: ref spawned, .. } if *spawned == id
+        ));
+
+        wait_for_screen(&runtime, &id, |s| s.contains("RUNTIME_TEST")).await;
+
+        let agents = runtime.list(&Selector::default()).await;
+        assert_eq!(agents.len(), 1);
+        assert_eq!(agents[0].id, id);
+
+        runtime.kill(&id, 9).await.expect("kill failed");
+    }
+
+    #[tokio::test]
+    async fn test_runtime_errors() {
+        let runtime = Runtime::new();
+
+        assert!(matches!(
+            runtime.snapshot("missing").await,
+            Err(ServerError::AgentNotFound(ref id)) if id == "missing"
+        ));
+        assert!(matches!(
+            runtime.spawn(SpawnOptions::new(Vec::<String>::new())).await,
+            Err(ServerError::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            runtime.kill_matching(&Selector::default(), None, true, 9).await,
+            Err(ServerError::NoMatch(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_runtime_remove() {
+        let runtime = Runtime::new();
+        let mut events = runtime.subscribe();
+
+        assert!(matches!(
+            runtime.remove("missing").await,
+            Err(ServerError::AgentNotFound(_))
+        ));
+
+        // Only exited agents can be removed
+        let (id, _pid) = runtime
+            .spawn(SpawnOptions::new(["sleep", "0.2"]))
+            .await
+            .expect("spawn failed");
+        assert!(matches!(runtime.remove(&id).await, Err(ServerError::InvalidRequest(_))));
+        while !matches!(next_event(&mut events).await, Event::AgentExited { .. }) {}
+
+        runtime.remove(&id).await.expect("remove failed");
+        assert!(matches!(next_event(&mut events).await, Event::AgentRemoved { id: ref removed } if *removed == id));
+        assert!(runtime.list(&Selector::default()).await.is_empty());
+        assert!(matches!(runtime.remove(&id).await, Err(ServerError::AgentNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_runtime_relabel() {
+        let runtime = Runtime::new();
+        let mut options = SpawnOptions::new(["sleep", "10"]);
+        options.labels = vec!["idle".into()];
+        options.annotations = BTreeMap::from([("task".into(), "1234".into())]);
+        let (id, _pid) = runtime.spawn(options).await.expect("spawn failed");
+        let mut events = runtime.subscribe();
+
+        let busy = vec!["busy".to_string()];
+        let set = BTreeMap::from([("owner".to_string(), "planner".to_string())]);
+        runtime
+            .label(&id, busy.clone(), &["idle".into()], set.clone(), &[])
+            .await
+            .expect("label failed");
+
+        match next_event(&mut events).await {
+            Event::LabelsChanged { labels, annotations, .. } => {
+                assert_eq!(labels, busy);
+                assert_eq!(annotations.len(), 2);
+            }
+            other => panic!("unexpected event: {other:?}"),
+        }
+        let selected = Selector::default().with_labels(&busy).with_annotations(&set);
+        assert_eq!(runtime.list(&selected).await.len(), 1);
+        assert!(runtime.list(&Selector::parse("idle").unwrap()).await.is_empty());
+
+        // Invalid keys are rejected and missing agents reported
+        let bad = BTreeMap::from([("has space".to_string(), String::new())]);
+        assert!(matches!(
+            runtime.label(&id, vec![], &[], bad, &[]).await,
+            Err(ServerError::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            runtime.label("missing", busy, &[], BTreeMap::new(), &[]).await,
+            Err(ServerError::AgentNotFound(_))
+        ));
+
+        runtime.kill(&id, 9).await.expect("kill failed");
+    }
+
+    #[tokio::test]
+    async fn test_runtime_broadcast() {
+        let runtime = Runtime::new();
+        let mut ids = Vec::new();
+        for label in ["worker", "worker", "other"] {
+            let mut options = SpawnOptions::new(["cat"]);
+            options.labels = vec![label.into()];
+            ids.push(runtime.spawn(options).await.expect("spawn failed").0);
+        }
+
+        let selector = Selector::parse("worker").unwrap();
+        let results = runtime
+            .broadcast(&["missing".into()], &selector, b"BROADCAST\n", &[])
+            .await
+            .expect("broadcast failed");
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].id, "missing");
+        assert!(!results[0].is_ok());
+        assert!(results[1..].iter().all(SendResult::is_ok));
+
+        // Both workers echo the input; the other agent gets nothing
+        for id in &ids[..2] {
+            wait_for_screen(&runtime, id, |s| s.contains("BROADCAST")).await;
+        }
+        let other = runtime.snapshot(&ids[2]).await.expect("snapshot failed");
+        assert!(!other.content.contains("BROADCAST"));
+
+        assert!(matches!(
+            runtime.broadcast(&[], &Selector::default(), b"x", &[]).await,
+            Err(ServerError::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            runtime.broadcast(&[], &Selector::parse("nobody").unwrap(), b"x", &[]).await,
+            Err(ServerError::NoMatch(_))
+        ));
+
+        runtime
+            .kill_matching(&Selector::default(), None, true, 9)
+            .await
+            .expect("kill failed");
+    }
+
+    #[tokio::test]
+    async fn test_runtime_paste() {
+        let runtime = Runtime::new();
+        let script = r"printf '\033[?2004h'; exec cat -v";
+        let (bracketed, _) = runtime
+            .spawn(SpawnOptions::new(["sh", "-c", script]))
+            .await
+            .expect("spawn failed");
+        let (plain, _) = runtime
+            .spawn(SpawnOptions::new(["cat", "-v"]))
+            .await
+            .expect("spawn failed");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        for id in [&bracketed, &plain] {
+            runtime.paste(id, b"one\ntwo", true).await.expect("paste failed");
+        }
+
+        wait_for_screen(&runtime, &bracketed, |s| s.contains("^[[200~one")).await;
+        wait_for_screen(&runtime, &bracketed, |s| s.contains("two^[[201~")).await;
+        let content = wait_for_screen(&runtime, &plain, |s| s.contains("two")).await;
+        assert!(!content.contains("200~"));
+
+        assert_eq!(
+            bracketed_paste(b"a\x1b[201~b"),
+            b"\x1b[200~ab\x1b[201~".to_vec()
+        );
+
+        runtime
+            .kill_matching(&Selector::default(), None, true, 9)
+            .await
+            .expect("kill failed");
+    }
+
+    #[tokio::test]
+    async fn test_runtime_type_text() {
+        let runtime = Runtime::new();
+        let (id, _pid) = runtime
+            .spawn(SpawnOptions::new(["cat"]))
+            .await
+            .expect("spawn failed");
+
+        let typing = tokio::spawn({
+            let runtime = runtime.clone();
+            let id = id.clone();
+            async move {
+                let start = std::time::Instant::now();
+                let delay = Duration::from_millis(50);
+                runtime
+                    .type_text(&id, "typed\n", delay, Duration::from_millis(5))
+                    .await
+                    .map(|()| start.elapsed())
+            }
+        });
+
+        // The lock isn't held between keystrokes
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let snapshot = tokio::time::timeout(Duration::from_millis(100), runtime.snapshot(&id));
+        assert!(snapshot.await.is_ok(), "snapshot blocked while typing");
+
+        let elapsed = typing.await.expect("join").expect("type failed");
+        assert!(elapsed >= Duration::from_millis(250), "typed too fast: {elapsed:?}");
+        // Both the echo and cat's output
+        wait_for_screen(&runtime, &id, |s| s.matches("typed").count() == 2).await;
+        runtime.kill(&id, 9).await.expect("kill failed");
+
+        // Typing stops when the agent exits
+        let (short, _pid) = runtime
+            .spawn(SpawnOptions::new(["sleep", "0.2"]))
+            .await
+            .expect("spawn failed");
+        let result = runtime
+            .type_text(&short, "a long line nobody reads", Duration::from_millis(50), Duration::ZERO)
+            .await;
+        assert!(matches!(result, Err(ServerError::AgentExited(_))), "{result:?}");
+    }
+
+    #[tokio::test]
+    async fn test_runtime_large_input() {
+        let runtime = Runtime::new();
+        // Raw mode, so the line discipline passes everything through unechoed
+        let script = "stty raw -echo; head -c 200000 | wc -c";
+        let (id, _pid) = runtime
+            .spawn(SpawnOptions::new(["sh", "-c", script]))
+            .await
+            .expect("spawn failed");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        // Far more than the PTY buffers, so it has to be written in pieces
+        let data = vec![b'x'; 200_000];
+        runtime.send(&id, &data).await.expect("send failed");
+        let info = runtime.list(&Selector::default()).await.remove(0);
+        assert_eq!(info.input.queued, 200_000);
+        assert_eq!(info.input.written, 200_000);
+
+        wait_for_screen(&runtime, &id, |s| s.contains("200000")).await;
+
+        // Input that can't fit under the limit is rejected outright
+        let mut options = SpawnOptions::new(["sleep", "10"]);
+        options.max_input = Some(16);
+        let (limited, _pid) = runtime.spawn(options).await.expect("spawn failed");
+        assert!(matches!(
+            runtime.send(&limited, &[b'x'; 64]).await,
+            Err(ServerError::InputFull { limit: 16, .. })
+        ));
+
+        runtime
+            .kill_matching(&Selector::default(), None, true, 9)
+            .await
+            .expect("kill failed");
+    }
+
+    #[tokio::test]
+    async fn test_runtime_answers_terminal_queries() {
+        let runtime = Runtime::new();
+        // Ask for the cursor position and show the raw answer, ESC as "E"
+        let script = r#"stty -echo -icanon min 1; printf 'x\033[6n'; r=$(dd bs=1 count=6 2>/dev/null); printf '%s' "$r" | tr '\033' E; echo; sleep 10"#;
+        let (answered, _) = runtime
+            .spawn(SpawnOptions::new(["sh", "-c", script]))
+            .await
+            .expect("spawn failed");
+        let mut options = SpawnOptions::new(["sh", "-c", script]);
+        options.no_terminal_replies = true;
+        let (unanswered, _) = runtime.spawn(options).await.expect("spawn failed");
+
+        wait_for_screen(&runtime, &answered, |s| s.contains("E[1;2R")).await;
+
+        let snapshot = runtime.snapshot(&unanswered).await.expect("snapshot failed");
+        assert!(!snapshot.content.contains("[1;2R"), "{}", snapshot.content);
+
+        runtime
+            .kill_matching(&Selector::default(), None, true, 9)
+            .await
+            .expect("kill failed");
+    }
+
+    #[tokio::test]
+    async fn test_runtime_command_events() {
+        let runtime = Runtime::new();
+        let mut events = runtime.subscribe();
+
+        // A program emitting OSC 133 markers directly, as an integrated shell would
+        let script = r"printf '\033]133;C\007done\n\033]133;D;3\007'; sleep 10";
+        let (id, _pid) = runtime
+            .spawn(SpawnOptions::new(["sh", "-c", script]))
+            .await
+            .expect("spawn failed");
+
+        let mut seen = Vec::new();
+        while seen.len() < 2 {
+            let event = tokio::time::timeout(Duration::from_secs(5), next_event(&mut events))
+                .await
+                .expect("timed out waiting for command events");
+            if matches!(event, Event::CommandStarted { .. } | Event::CommandFinished { .. }) {
+                seen.push(event);
+            }
+        }
+        assert!(matches!(seen[0], Event::CommandStarted { command: 1, .. }));
+        assert!(matches!(
+            seen[1],
+            Event::CommandFinished { command: 1, exit_code: Some(3), .. }
+        ));
+
+        let shell = runtime.list(&Selector::default()).await[0].shell.expect("shell state");
+        assert_eq!(shell.last_exit_code, Some(3));
+
+        runtime.kill(&id, 9).await.expect("kill failed");
+    }
+
+    #[tokio::test]
+    async fn test_runtime_screen_events() {
+        let runtime = Runtime::new();
+        let mut events = runtime.subscribe();
+
+        let script = r"printf '\033]2;needs input\007\033[?1049h\007'; sleep 10";
+        let (id, _pid) = runtime
+            .spawn(SpawnOptions::new(["sh", "-c", script]))
+            .await
+            .expect("spawn failed");
+
+        let mut seen = Vec::new();
+        while !seen.iter().any(|e| matches!(e, Event::Bell { .. })) {
+            let event = tokio::time::timeout(Duration::from_secs(5), next_event(&mut events))
+                .await
+                .expect("timed out waiting for screen events");
+            if !matches!(event, Event::AgentSpawned { .. } | Event::AgentOutput { .. }) {
+                seen.push(event);
+            }
+        }
+        // In the order the program sent them
+        assert_eq!(seen.len(), 3, "unexpected events: {seen:?}");
+        assert!(matches!(&seen[0], Event::TitleChanged { title, .. } if title == "needs input"));
+        assert!(matches!(&seen[1], Event::AltScreenEntered { .. }));
+        assert!(matches!(&seen[2], Event::Bell { id: agent } if *agent == id));
+
+        runtime.kill(&id, 9).await.expect("kill failed");
+    }
+}
@@ -1,8 +1,231 @@
 //! Virtual screen model using vt100.
 
+use crate::keys::KeyModes;
+use crate::protocol::{Region, ShellState, TerminalIdentity};
+use std::ops::Range;
+
+/// Something noteworthy that happened on the screen while processing output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScreenEvent {
+    /// The shell started running command number `command` (1-based, OSC 133).
+    CommandStarted { command: u64 },
+    /// Command number `command` finished (OSC 133).
+    CommandFinished { command: u64, exit_code: Option<i32> },
+    /// The window title changed.
+    TitleChanged { title: String },
+    /// The program rang the bell (audible or visual).
+    Bell,
+    /// The alternate screen was entered or left.
+    AltScreen { active: bool },
+    /// The cursor was shown or hidden.
+    CursorVisibility { visible: bool },
+    /// The whole screen was cleared.
+    ScreenCleared,
+}
+
+/// Tracks OSC 133 (FinalTerm/shell integration) prompt markers:
+/// `A` prompt start, `B` prompt end, `C` command output start, `D[;code]` command end.
+#[derive(Debug, Default)]
+#[allow(clippy::struct_excessive_bools)] // Independent flags, not a state machine
+struct ShellTracker {
+    /// Whether any marker has been seen.
+    active: bool,
+    /// Whether the shell emits `B` (prompt end) markers.
+    saw_prompt_end: bool,
+    at_prompt: bool,
+    running: bool,
+    commands: u64,
+    last_exit_code: Option<i32>,
+}
+
+impl ShellTracker {
+    fn mark(&mut self, kind: &[u8], arg: Option<&[u8]>) -> Option<ScreenEvent> {
+        self.active = true;
+        match kind {
+            b"A" => self.at_prompt = !self.saw_prompt_end,
+            b"B" => {
+                self.saw_prompt_end = true;
+                self.at_prompt = true;
+            }
+            b"C" => {
+                self.at_prompt = false;
+                self.running = true;
+                self.commands += 1;
+                return Some(ScreenEvent::CommandStarted { command: self.commands });
+            }
+            // A D without a preceding C is the shell's first prompt or an empty line
+            b"D" if self.running => {
+                self.running = false;
+                let exit_code = arg
+                    .and_then(|a| std::str::from_utf8(a).ok())
+                    .and_then(|a| a.parse().ok());
+                self.last_exit_code = exit_code;
+                return Some(ScreenEvent::CommandFinished {
+                    command: self.commands,
+                    exit_code,
+                });
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+/// vt100 callbacks for sequences the parser doesn't handle itself.
+#[derive(Debug, Default)]
+struct Callbacks {
+    shell: ShellTracker,
+    title: String,
+    pending: Vec<ScreenEvent>,
+    /// Terminal to answer queries as, or None to leave them unanswered.
+    identity: Option<TerminalIdentity>,
+    /// Answers to queries, waiting to be written back to the program.
+    replies: Vec<u8>,
+}
+
+impl Callbacks {
+    /// Answer a device status, device attributes or window size query the way
+    /// a real terminal would.
+    fn answer(&mut self, screen: &vt100::Screen, private: Option<u8>, params: &[&[u16]], c: char) {
+        let Some(identity) = self.identity else {
+            return;
+        };
+        let param = params.first().and_then(|p| p.first()).copied().unwrap_or(0);
+        let (row, col) = screen.cursor_position();
+        let (rows, cols) = screen.size();
+        let reply = match (private, c, param) {
+            // DSR: operating status, then cursor position (CPR and DECXCPR)
+            (None, 'n', 5) => b"\x1b[0n".to_vec(),
+            (None, 'n', 6) => format!("\x1b[{};{}R", row + 1, col + 1).into_bytes(),
+            (Some(b'?'), 'n', 6) => format!("\x1b[?{};{}R", row + 1, col + 1).into_bytes(),
+            // Primary and secondary device attributes
+            (None, 'c', 0) => primary_attributes(identity).to_vec(),
+            (Some(b'>'), 'c', 0) => secondary_attributes(identity).to_vec(),
+            // XTWINOPS: text area size in characters
+            (None, 't', 18) => format!("\x1b[8;{rows};{cols}t").into_bytes(),
+            _ => return,
+        };
+        self.replies.extend(reply);
+    }
+}
+
+/// What a terminal sends for primary device attributes (`CSI c`).
+const fn primary_attributes(identity: TerminalIdentity) -> &'static [u8] {
+    match identity {
+        TerminalIdentity::Vt100 => b"\x1b[?1;2c",
+        TerminalIdentity::Vt220 => b"\x1b[?62;1;6;22c",
+        TerminalIdentity::Xterm => b"\x1b[?64;1;2;6;9;15;18;21;22c",
+    }
+}
+
+/// What a terminal sends for secondary device attributes (`CSI > c`): its
+/// type, firmware version and keyboard.
+const fn secondary_attributes(identity: TerminalIdentity) -> &'static [u8] {
+    match identity {
+        TerminalIdentity::Vt100 => b"\x1b[>0;10;0c",
+        TerminalIdentity::Vt220 => b"\x1b[>1;10;0c",
+        TerminalIdentity::Xterm => b"\x1b[>41;390;0c",
+    }
+}
+
+impl vt100::Callbacks for Callbacks {
+    fn audible_bell(&mut self, _: &mut vt100::Screen) {
+        self.pending.push(ScreenEvent::Bell);
+    }
+
+    fn visual_bell(&mut self, _: &mut vt100::Screen) {
+        self.pending.push(ScreenEvent::Bell);
+    }
+
+    fn set_window_title(&mut self, _: &mut vt100::Screen, title: &[u8]) {
+        let title = String::from_utf8_lossy(title);
+        if title != self.title {
+            self.title = title.into_owned();
+            self.pending.push(ScreenEvent::TitleChanged { title: self.title.clone() });
+        }
+    }
+
+    fn unhandled_csi(
+        &mut self,
+        screen: &mut vt100::Screen,
+        i1: Option<u8>,
+        i2: Option<u8>,
+        params: &[&[u16]],
+        c: char,
+    ) {
+        if i2.is_none() {
+            self.answer(screen, i1, params, c);
+        }
+    }
+
+    fn unhandled_osc(&mut self, _: &mut vt100::Screen, params: &[&[u8]]) {
+        if let [b"133", kind, rest @ ..] = params
+            && let Some(event) = self.shell.mark(kind, rest.first().copied())
+        {
+            self.pending.push(event);
+        }
+    }
+}
+
+/// A sequence that [`Screen::process`] reports on by itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Marker {
+    /// DECSET or DECRST, which may switch the alternate screen or cursor.
+    PrivateMode,
+    /// ED 2, ED 3 or RIS (full reset), which clear the whole screen.
+    Clear,
+}
+
+/// Where the scanner is within an escape sequence.
+#[derive(Debug, Clone, Copy, Default)]
+enum ScanState {
+    #[default]
+    Ground,
+    Escape,
+    /// Inside `CSI`, with the first parameter byte and how many there were.
+    Csi { private: bool, first: u8, len: usize },
+}
+
+/// Finds the ends of the sequences in [`Marker`] in output, across reads, so
+/// the screen can be checked right after each one and its events come out in
+/// byte order alongside title and bell events.
+#[derive(Debug, Default)]
+struct MarkerScanner {
+    state: ScanState,
+}
+
+impl MarkerScanner {
+    /// Feed one byte, returning the marker it completes, if any.
+    const fn feed(&mut self, byte: u8) -> Option<Marker> {
+        let (state, marker) = match (self.state, byte) {
+            (_, 0x1b) => (ScanState::Escape, None),
+            (ScanState::Escape, b'[') => (ScanState::Csi { private: false, first: 0, len: 0 }, None),
+            (ScanState::Escape, b'c') => (ScanState::Ground, Some(Marker::Clear)),
+            (ScanState::Csi { len: 0, .. }, b'?') => {
+                (ScanState::Csi { private: true, first: 0, len: 0 }, None)
+            }
+            (ScanState::Csi { private, first, len }, b'0'..=b'9' | b';') => {
+                let first = if len == 0 { byte } else { first };
+                (ScanState::Csi { private, first, len: len.saturating_add(1) }, None)
+            }
+            (ScanState::Csi { private: true, .. }, b'h' | b'l') => {
+                (ScanState::Ground, Some(Marker::PrivateMode))
+            }
+            (ScanState::Csi { private: false, first: b'2' | b'3', len: 1 }, b'J') => {
+                (ScanState::Ground, Some(Marker::Clear))
+            }
+            _ => (ScanState::Ground, None),
+        };
+        self.state = state;
+        marker
+    }
+}
+
 /// Virtual screen backed by vt100.
 pub struct Screen {
-    parser: vt100::Parser,
+    parser: vt100::Parser<Callbacks>,
+    /// Finds mode switches and clears, even when split across reads.
+    markers: MarkerScanner,
 }
 
 impl Screen {
@@ -10,13 +233,46 @@ impl Screen {
     #[must_use]
     pub fn new(rows: u16, cols: u16) -> Self {
         Self {
-            parser: vt100::Parser::new(rows, cols, 0),
+            parser: vt100::Parser::new_with_callbacks(rows, cols, 0, Callbacks::default()),
+            markers: MarkerScanner::default(),
         }
     }
 
     /// Process output bytes through the terminal parser.
+    ///
+    /// The bytes are fed in pieces that end after each mode switch or clear, so
+    /// the resulting events are queued in the order the program sent them.
     pub fn process(&mut self, data: &[u8]) {
+        let mut start = 0;
+        for (i, &byte) in data.iter().enumerate() {
+            if let Some(marker) = self.markers.feed(byte) {
+                self.process_until(&data[start..=i], Some(marker));
+                start = i + 1;
+            }
+        }
+        if start < data.len() {
+            self.process_until(&data[start..], None);
+        }
+    }
+
+    /// Process a piece of output that ends with `marker`, if any.
+    fn process_until(&mut self, data: &[u8], marker: Option<Marker>) {
+        let screen = self.parser.screen();
+        let (alt_before, hidden_before) = (screen.alternate_screen(), screen.hide_cursor());
         self.parser.process(data);
+
+        let screen = self.parser.screen();
+        let (alt_after, hidden_after) = (screen.alternate_screen(), screen.hide_cursor());
+        let pending = &mut self.parser.callbacks_mut().pending;
+        if alt_before != alt_after {
+            pending.push(ScreenEvent::AltScreen { active: alt_after });
+        }
+        if hidden_before != hidden_after {
+            pending.push(ScreenEvent::CursorVisibility { visible: !hidden_after });
+        }
+        if marker == Some(Marker::Clear) {
+            pending.push(ScreenEvent::ScreenCleared);
+        }
     }
 
     /// Get the current screen contents as a string.
@@ -30,12 +286,65 @@ impl Screen {
     /// This returns the screen text with color/style escape codes but without
     /// cursor positioning or screen-clearing sequences.
     #[must_use]
+    pub fn contents_formatted(&self) -> String {
+        let (rows, cols) = self.size();
+        self.formatted_rect(0..rows, 0..cols)
+    }
+
+    /// Like [`Self::contents_formatted`], for only part of the screen.
+    #[must_use]
+    pub fn region_formatted(&self, region: Region) -> String {
+        let (rows, cols) = self.region_bounds(region);
+        self.formatted_rect(rows, cols)
+    }
+
+    /// Like [`Self::snapshot`], for only part of the screen.
+    #[must_use]
+    pub fn region_snapshot(&self, region: Region) -> String {
+        let (rows, cols) = self.region_bounds(region);
+        let lines: Vec<String> = self
+            .parser
+            .screen()
+            .rows(cols.start, cols.end - cols.start)
+            .skip(rows.start.into())
+            .take(rows.len())
+            .map(|row| row.trim_end().to_string())
+            .collect();
+        let end = lines.iter().rposition(|l| !l.is_empty()).map_or(0, |i| i + 1);
+        lines[..end].join("\n")
+    }
+
+    /// The rows and columns a region covers, clipped to the screen.
+    fn region_bounds(&self, region: Region) -> (Range<u16>, Range<u16>) {
+        let (rows, cols) = self.size();
+        let (row_range, col_range) = match region {
+            Region::Rows { start, end } => (start..end, 0..cols),
+            Region::Rect { row, col, rows, cols } => {
+                (row..row.saturating_add(rows), col..col.saturating_add(cols))
+            }
+            Region::LastLine => {
+                let last = self.rows().iter().rposition(|r| !r.is_empty());
+                let last = last.and_then(|i| u16::try_from(i).ok()).unwrap_or(0);
+                (last..last + 1, 0..cols)
+            }
+            Region::CursorLine => {
+                let (row, _) = self.cursor_position();
+                (row..row + 1, 0..cols)
+            }
+        };
+        let clip = |range: Range<u16>, max: u16| {
+            let start = range.start.min(max);
+            start..range.end.clamp(start, max)
+        };
+        (clip(row_range, rows), clip(col_range, cols))
+    }
+
+    /// The screen contents within a rectangle, with ANSI formatting.
     #[allow(clippy::similar_names)] // fg/bg are intentionally similar
     #[allow(clippy::too_many_lines)] // Complex function, splitting would reduce clarity
-    pub fn contents_formatted(&self) -> String {
+    fn formatted_rect(&self, rows: Range<u16>, cols: Range<u16>) -> String {
         use std::fmt::Write;
         let screen = self.parser.screen();
-        let (rows, cols) = screen.size();
         let mut result = String::new();
         let mut current_fg: Option<vt100::Color> = None;
         let mut current_bg: Option<vt100::Color> = None;
@@ -46,12 +355,12 @@ impl Screen {
         let mut current_inverse = false;
         let mut trailing_empty_rows = 0;
 
-        for row in 0..rows {
+        for row in rows {
             let mut row_text = String::new();
             let mut row_has_content = false;
             let mut trailing_spaces = 0;
 
-            for col in 0..cols {
+            for col in cols.clone() {
                 if let Some(cell) = screen.cell(row, col) {
                     // Skip wide character continuations
                     if cell.is_wide_continuation() {
@@ -224,6 +533,14 @@ impl Screen {
         result.into_bytes()
     }
 
+    /// Get the text of each row, without trailing whitespace.
+    #[must_use]
+    pub fn rows(&self) -> Vec<String> {
+        let screen = self.parser.screen();
+        let (_, cols) = screen.size();
+        screen.rows(0, cols).map(|row| row.trim_end().to_string()).collect()
+    }
+
     /// Get the cursor position (row, col), 0-indexed.
     #[must_use]
     pub fn cursor_position(&self) -> (u16, u16) {
@@ -242,11 +559,71 @@ impl Screen {
         self.parser.screen().alternate_screen()
     }
 
+    /// Check if the application has enabled bracketed paste mode.
+    #[must_use]
+    pub fn bracketed_paste(&self) -> bool {
+        self.parser.screen().bracketed_paste()
+    }
+
+    /// Mouse reporting mode and encoding the application has selected.
+    #[must_use]
+    pub fn mouse_protocol(&self) -> (vt100::MouseProtocolMode, vt100::MouseProtocolEncoding) {
+        let screen = self.parser.screen();
+        (screen.mouse_protocol_mode(), screen.mouse_protocol_encoding())
+    }
+
+    /// Cursor and keypad modes the application has selected, which change
+    /// what some keys send.
+    #[must_use]
+    pub fn key_modes(&self) -> KeyModes {
+        let screen = self.parser.screen();
+        KeyModes {
+            application_cursor: screen.application_cursor(),
+            application_keypad: screen.application_keypad(),
+        }
+    }
+
     /// Resize the screen.
     pub fn resize(&mut self, rows: u16, cols: u16) {
-        // vt100::Parser doesn't have a resize method, so we create a new parser
-        // and copy the contents. This is a limitation we may need to work around.
-        self.parser = vt100::Parser::new(rows, cols, 0);
+        // Resize in place so terminal modes (application cursor/keypad, etc.)
+        // survive, as they would in a real terminal
+        self.parser.screen_mut().set_size(rows, cols);
+    }
+
+    /// Shell integration state, if the program has emitted OSC 133 markers.
+    #[must_use]
+    pub fn shell_state(&self) -> Option<ShellState> {
+        let shell = &self.parser.callbacks().shell;
+        shell.active.then_some(ShellState {
+            at_prompt: shell.at_prompt,
+            running: shell.running,
+            commands: shell.commands,
+            last_exit_code: shell.last_exit_code,
+        })
+    }
+
+    /// Note that input ending a line was sent, so a prompt shown before it no
+    /// longer counts as the shell waiting for input.
+    pub fn input_submitted(&mut self) {
+        self.parser.callbacks_mut().shell.at_prompt = false;
+    }
+
+    /// Answer the program's terminal queries (cursor position, device status
+    /// and attributes) as the given terminal. Queries are ignored until this
+    /// is called.
+    pub fn answer_queries(&mut self, identity: TerminalIdentity) {
+        self.parser.callbacks_mut().identity = Some(identity);
+    }
+
+    /// Take the answers to queries seen since the last call, to be written
+    /// back to the program.
+    pub fn take_replies(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.parser.callbacks_mut().replies)
+    }
+
+    /// Take the screen events recognised since the last call.
+    pub fn take_events(&mut self) -> Vec<ScreenEvent> {
+        std::mem::take(&mut self.parser.callbacks_mut().pending)
     }
 
     /// Get a snapshot of the screen as normalized text.
@@ -307,7 +684,7 @@ mod tests {
         screen.process(b"\x1b[31mHello\x1b[0m");
         let snapshot = screen.snapshot();
         assert_eq!(snapshot.trim(), "Hello");
-        assert!(!snapshot.contains("\x1b"));
+        assert!(!snapshot.contains('\x1b'));
     }
 
     #[test]
@@ -317,7 +694,7 @@ mod tests {
         screen.process(b"\x1b[31mRED\x1b[0m \x1b[32mGREEN\x1b[0m");
 
         let formatted = screen.contents_formatted();
-        eprintln!("formatted output: {:?}", formatted);
+        eprintln!("formatted output: {formatted:?}");
 
         // Should contain the text
         assert!(formatted.contains("RED"));
@@ -339,6 +716,169 @@ mod tests {
 
         // Text should be on one line (no spurious newlines in the middle)
         let lines: Vec<&str> = formatted.lines().collect();
-        assert_eq!(lines.len(), 1, "Expected 1 line, got: {:?}", lines);
+        assert_eq!(lines.len(), 1, "Expected 1 line, got: {lines:?}");
+    }
+
+    #[test]
+    fn test_region_snapshots() {
+        let mut screen = Screen::new(6, 20);
+        screen.process(b"first line\r\nsecond line\r\nthird\x1b[6;1H\x1b[31mSTATUS\x1b[0m ok\x1b[2;8H");
+
+        let rows = screen.region_snapshot(Region::Rows { start: 1, end: 3 });
+        assert_eq!(rows, "second line\nthird");
+        let rect = Region::Rect { row: 0, col: 6, rows: 2, cols: 4 };
+        assert_eq!(screen.region_snapshot(rect), "line\n lin");
+        assert_eq!(screen.region_snapshot(Region::LastLine), "STATUS ok");
+        assert_eq!(screen.region_snapshot(Region::CursorLine), "second line");
+
+        // Clipped to the screen, and empty when entirely off it
+        let tail = Region::Rows { start: 5, end: u16::MAX };
+        assert_eq!(screen.region_snapshot(tail), "STATUS ok");
+        let off = Region::Rect { row: 10, col: 30, rows: 2, cols: 2 };
+        assert_eq!(screen.region_snapshot(off), "");
+
+        let formatted = screen.region_formatted(Region::LastLine);
+        assert!(formatted.contains("\x1b[0;31mSTATUS"), "{formatted:?}");
+        assert!(!formatted.contains("first"));
+    }
+
+    #[test]
+    fn test_terminal_query_replies() {
+        let mut screen = Screen::new(24, 80);
+        screen.process(b"ab\x1b[6n");
+        assert!(screen.take_replies().is_empty(), "queries ignored by default");
+
+        screen.answer_queries(TerminalIdentity::Xterm);
+        // Each cursor report is for the position when the query was seen
+        screen.process(b"\r\ncd\x1b[6nef\x1b[?6n\x1b[5n");
+        assert_eq!(screen.take_replies(), b"\x1b[2;3R\x1b[?2;5R\x1b[0n");
+
+        screen.process(b"\x1b[c\x1b[>c\x1b[18t");
+        assert_eq!(
+            screen.take_replies(),
+            b"\x1b[?64;1;2;6;9;15;18;21;22c\x1b[>41;390;0c\x1b[8;24;80t"
+        );
+
+        screen.answer_queries(TerminalIdentity::Vt100);
+        screen.process(b"\x1b[0c\x1b[>0c");
+        assert_eq!(screen.take_replies(), b"\x1b[?1;2c\x1b[>0;10;0c");
+
+        // Sequences that aren't queries get no answer
+        screen.process(b"\x1b[3n\x1b[1c\x1b[14t");
+        assert!(screen.take_replies().is_empty());
+    }
+
+    #[test]
+    fn test_osc133_command_tracking() {
+        let mut screen = Screen::new(24, 80);
+        assert!(screen.shell_state().is_none());
+
+        // First prompt: the leading D has no command to finish
+        screen.process(b"\x1b]133;D;0\x07\x1b]133;A\x07$ \x1b]133;B\x07");
+        let state = screen.shell_state().unwrap();
+        assert!(state.at_prompt);
+        assert_eq!(state.commands, 0);
+        assert!(screen.take_events().is_empty());
+
+        screen.input_submitted();
+        assert!(!screen.shell_state().unwrap().at_prompt);
+
+        screen.process(b"false\r\n\x1b]133;C\x07");
+        assert!(screen.shell_state().unwrap().running);
+        screen.process(b"\x1b]133;D;1\x07\x1b]133;A\x07$ \x1b]133;B\x07");
+
+        assert_eq!(
+            screen.take_events(),
+            vec![
+                ScreenEvent::CommandStarted { command: 1 },
+                ScreenEvent::CommandFinished { command: 1, exit_code: Some(1) },
+            ]
+        );
+        let state = screen.shell_state().unwrap();
+        assert!(state.at_prompt && !state.running);
+        assert_eq!(state.last_exit_code, Some(1));
+        assert_eq!(screen.snapshot(), "$ false\n$");
+
+        // State survives a resize
+        screen.resize(10, 40);
+        assert_eq!(screen.shell_state().unwrap().commands, 1);
+    }
+
+    #[test]
+    fn test_key_modes() {
+        let mut screen = Screen::new(24, 80);
+        assert_eq!(screen.key_modes(), KeyModes::default());
+
+        // DECCKM on, DECKPAM on (what vim and less send at startup)
+        screen.process(b"\x1b[?1h\x1b=");
+        let modes = KeyModes {
+            application_cursor: true,
+            application_keypad: true,
+        };
+        assert_eq!(screen.key_modes(), modes);
+
+        screen.resize(10, 40);
+        assert_eq!(screen.key_modes(), modes);
+
+        screen.process(b"\x1b[?1l\x1b>");
+        assert_eq!(screen.key_modes(), KeyModes::default());
+    }
+
+    #[test]
+    fn test_screen_events() {
+        let mut screen = Screen::new(24, 80);
+
+        screen.process(b"\x1b]2;build\x07\x1b]2;build\x07\x07");
+        assert_eq!(
+            screen.take_events(),
+            vec![ScreenEvent::TitleChanged { title: "build".into() }, ScreenEvent::Bell]
+        );
+
+        screen.process(b"\x1b[?1049h\x1b[?25l");
+        assert_eq!(
+            screen.take_events(),
+            vec![
+                ScreenEvent::AltScreen { active: true },
+                ScreenEvent::CursorVisibility { visible: false },
+            ]
+        );
+
+        // A clear sequence split across two reads is reported once
+        screen.process(b"text\x1b[");
+        assert!(screen.take_events().is_empty());
+        screen.process(b"2J");
+        assert_eq!(screen.take_events(), vec![ScreenEvent::ScreenCleared]);
+        screen.process(b"more");
+        assert!(screen.take_events().is_empty());
+
+        screen.process(b"\x1b[?1049l\x1b[?25h");
+        assert_eq!(
+            screen.take_events(),
+            vec![
+                ScreenEvent::AltScreen { active: false },
+                ScreenEvent::CursorVisibility { visible: true },
+            ]
+        );
+
+        // Toggles within one read are all reported, in order with the bell
+        screen.process(b"\x1b[?1049h\x07\x1b[?1049l\x1b[?25;1049l\x1b[?2");
+        screen.process(b"5h\x1b[?1049h\x1b[2J\x1b]2;full\x07");
+        assert_eq!(
+            screen.take_events(),
+            vec![
+                ScreenEvent::AltScreen { active: true },
+                ScreenEvent::Bell,
+                ScreenEvent::AltScreen { active: false },
+                ScreenEvent::CursorVisibility { visible: false },
+                ScreenEvent::CursorVisibility { visible: true },
+                ScreenEvent::AltScreen { active: true },
+                ScreenEvent::ScreenCleared,
+                ScreenEvent::TitleChanged { title: "full".into() },
+            ]
+        );
+
+        // Other erases aren't clears
+        screen.process(b"\x1b[J\x1b[12J\x1b[?2J");
+        assert!(screen.take_events().is_empty());
     }
 }
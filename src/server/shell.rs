@@ -0,0 +1,297 @@
+//! Shell integration: rc snippets that make bash and zsh emit OSC 133 prompt markers.
+//!
+//! The snippets source the user's usual startup files first, then hook the prompt
+//! so every prompt, command start and command end is marked in the output stream.
+//! A login bash can't be pointed at another rc file, so it runs as a plain
+//! interactive shell whose rc file reads the login profiles instead.
+
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+const BASH_RC: &str = r#"# botty shell integration: emit OSC 133 prompt markers
+if [ -n "${BOTTY_LOGIN+x}" ]; then
+  unset BOTTY_LOGIN
+  if [ -f /etc/profile ]; then . /etc/profile; fi
+  for __botty_profile in ~/.bash_profile ~/.bash_login ~/.profile; do
+    if [ -f "$__botty_profile" ]; then . "$__botty_profile"; break; fi
+  done
+  unset __botty_profile
+else
+  if [ -z "${BOTTY_RCFILE+x}" ]; then BOTTY_RCFILE=~/.bashrc; fi
+  if [ -n "$BOTTY_RCFILE" ] && [ -f "$BOTTY_RCFILE" ]; then . "$BOTTY_RCFILE"; fi
+  unset BOTTY_RCFILE
+fi
+__botty_prompt_command() { printf '\033]133;D;%s\007' "$?"; }
+PROMPT_COMMAND="__botty_prompt_command${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+PS1='\[\033]133;A\007\]'"$PS1"'\[\033]133;B\007\]'
+PS0='\[\033]133;C\007\]'"$PS0"
+"#;
+
+const ZSH_ENV: &str = r#"# botty shell integration: load the user's zshenv
+if [ -f "${BOTTY_ZDOTDIR:-$HOME}/.zshenv" ]; then . "${BOTTY_ZDOTDIR:-$HOME}/.zshenv"; fi
+"#;
+
+const ZSH_PROFILE: &str = r#"# botty shell integration: load the user's zprofile
+if [ -f "${BOTTY_ZDOTDIR:-$HOME}/.zprofile" ]; then . "${BOTTY_ZDOTDIR:-$HOME}/.zprofile"; fi
+"#;
+
+const ZSH_RC: &str = r#"# botty shell integration: emit OSC 133 prompt markers
+if [ -n "${BOTTY_ZDOTDIR+x}" ]; then ZDOTDIR=$BOTTY_ZDOTDIR; else unset ZDOTDIR; fi
+unset BOTTY_ZDOTDIR
+if [ -f "${ZDOTDIR:-$HOME}/.zshrc" ]; then . "${ZDOTDIR:-$HOME}/.zshrc"; fi
+__botty_precmd() { printf '\033]133;D;%s\007' "$?"; }
+__botty_preexec() { printf '\033]133;C\007'; }
+precmd_functions=(__botty_precmd $precmd_functions)
+preexec_functions+=(__botty_preexec)
+PS1=$'%{\e]133;A\a%}'"$PS1"$'%{\e]133;B\a%}'
+"#;
+
+/// Rewrite a command so an interactive bash or zsh emits OSC 133 markers.
+///
+/// Returns the command to spawn, adding any environment variables it needs to
+/// `env`. Commands that aren't an interactive bash or zsh are returned unchanged.
+/// A leading `-` on the program name (`-bash`) asks for a login shell, as it
+/// does for `login(1)`.
+pub fn integrate(cmd: &[String], env: &mut Vec<(String, String)>) -> io::Result<Vec<String>> {
+    let program = cmd
+        .first()
+        .and_then(|c| Path::new(c).file_name())
+        .and_then(|c| c.to_str())
+        .unwrap_or_default();
+    let (program, login) = match program.strip_prefix('-') {
+        Some(program) => (program, true),
+        None => (program, false),
+    };
+    if !matches!(program, "bash" | "zsh") {
+        return Ok(cmd.to_vec());
+    }
+
+    let mut cmd = cmd.to_vec();
+    if login {
+        // Nothing is installed as "-bash"; run the shell and ask for a login
+        cmd[0] = cmd[0].replacen(&format!("-{program}"), program, 1);
+        cmd.insert(1, "--login".into());
+    }
+    let integrated = if program == "bash" {
+        integrate_bash(&cmd, env)?
+    } else {
+        integrate_zsh(&cmd, env)?
+    };
+    Ok(integrated.unwrap_or(cmd))
+}
+
+fn integrate_bash(cmd: &[String], env: &mut Vec<(String, String)>) -> io::Result<Option<Vec<String>>> {
+    let mut out = vec![cmd[0].clone()];
+    let mut user_rc = None;
+    let mut login = false;
+    let mut profile = true;
+    let mut args = cmd[1..].iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            // A login shell ignores --rcfile, so read its profiles from ours
+            "-l" | "--login" => login = true,
+            "--noprofile" => {
+                profile = false;
+                out.push(arg.clone());
+            }
+            "--norc" => user_rc = Some(String::new()),
+            "--rcfile" | "--init-file" => match args.next() {
+                Some(file) => user_rc = Some(file.clone()),
+                None => return Ok(None),
+            },
+            // Not an interactive shell: a command string or a script
+            "-c" => return Ok(None),
+            a if !a.starts_with('-') => return Ok(None),
+            _ => out.push(arg.clone()),
+        }
+    }
+
+    let rc = write_file(&integration_dir()?, "bashrc", BASH_RC)?;
+    out.push("--rcfile".into());
+    out.push(rc.to_string_lossy().into_owned());
+    if login {
+        // Without profiles a login shell reads no startup files at all
+        if profile {
+            env.push(("BOTTY_LOGIN".into(), "1".into()));
+        } else {
+            env.push(("BOTTY_RCFILE".into(), String::new()));
+        }
+    } else if let Some(user_rc) = user_rc {
+        env.push(("BOTTY_RCFILE".into(), user_rc));
+    }
+    Ok(Some(out))
+}
+
+fn integrate_zsh(cmd: &[String], env: &mut Vec<(String, String)>) -> io::Result<Option<Vec<String>>> {
+    // Skip non-interactive invocations and ones that disable startup files
+    if cmd[1..]
+        .iter()
+        .any(|a| a == "-c" || a == "-f" || a == "--no-rcs" || !a.starts_with('-'))
+    {
+        return Ok(None);
+    }
+
+    let dir = integration_dir()?.join("zsh");
+    private_dir(&dir)?;
+    // Login shells read .zprofile before .zshrc; .zlogin and .zlogout come
+    // after .zshrc has restored the user's ZDOTDIR
+    write_file(&dir, ".zshenv", ZSH_ENV)?;
+    write_file(&dir, ".zprofile", ZSH_PROFILE)?;
+    write_file(&dir, ".zshrc", ZSH_RC)?;
+
+    let user_zdotdir = env
+        .iter()
+        .find(|(k, _)| k == "ZDOTDIR")
+        .map(|(_, v)| v.clone())
+        .or_else(|| std::env::var("ZDOTDIR").ok());
+    env.retain(|(k, _)| k != "ZDOTDIR");
+    if let Some(user_zdotdir) = user_zdotdir {
+        env.push(("BOTTY_ZDOTDIR".into(), user_zdotdir));
+    }
+    env.push(("ZDOTDIR".into(), dir.to_string_lossy().into_owned()));
+    Ok(Some(cmd.to_vec()))
+}
+
+/// Where this server keeps its rc files: the user's runtime directory (the
+/// same place as the default socket), or the temp directory without one.
+fn integration_path() -> PathBuf {
+    dirs::runtime_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(format!("botty-shell-{}", std::process::id()))
+}
+
+/// Per-server directory holding the rc files, readable only by this user.
+fn integration_dir() -> io::Result<PathBuf> {
+    let dir = integration_path();
+    private_dir(&dir)?;
+    Ok(dir)
+}
+
+/// Remove this server's rc files, on shutdown.
+pub fn cleanup() {
+    let dir = integration_path();
+    // Only remove a directory this user owns, never whatever a symlink points at
+    if fs::symlink_metadata(&dir).is_ok_and(|m| m.is_dir() && is_private(&m)) {
+        fs::remove_dir_all(&dir).ok();
+    }
+}
+
+/// Create a directory only this user can use, or check that an existing one
+/// is exactly that. The path is predictable, so anything else at it may have
+/// been planted by another user.
+fn private_dir(dir: &Path) -> io::Result<()> {
+    match fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => return Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e),
+    }
+    let meta = fs::symlink_metadata(dir)?;
+    if meta.is_dir() && is_private(&meta) {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is not a private directory owned by this user", dir.display()),
+        ))
+    }
+}
+
+fn is_private(meta: &fs::Metadata) -> bool {
+    #[allow(unsafe_code)]
+    let uid = unsafe { libc::getuid() };
+    meta.uid() == uid && meta.mode() & 0o777 == 0o700
+}
+
+/// Write a file that doesn't exist yet, never following a symlink. One that
+/// exists was written by an earlier spawn, since the directory is private.
+fn write_file(dir: &Path, name: &str, contents: &str) -> io::Result<PathBuf> {
+    let path = dir.join(name);
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path);
+    match file {
+        Ok(mut file) => file.write_all(contents.as_bytes())?,
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e),
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cmd(args: &[&str]) -> Vec<String> {
+        args.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_integrate_bash() {
+        let mut env = Vec::new();
+        let out = integrate(&cmd(&["/bin/bash", "--norc", "--noprofile"]), &mut env).unwrap();
+        assert_eq!(out[..2], cmd(&["/bin/bash", "--noprofile"]));
+        assert_eq!(out[2], "--rcfile");
+        assert!(fs::read_to_string(&out[3]).unwrap().contains("133;C"));
+        assert_eq!(env, vec![("BOTTY_RCFILE".to_string(), String::new())]);
+    }
+
+    #[test]
+    fn test_integrate_login_shells() {
+        // A login bash runs as an interactive one that reads the profiles
+        for login in [cmd(&["bash", "-l"]), cmd(&["bash", "--login"]), cmd(&["-bash"])] {
+            let mut env = Vec::new();
+            let out = integrate(&login, &mut env).unwrap();
+            assert_eq!(out[..2], cmd(&["bash", "--rcfile"]), "{login:?}");
+            assert!(fs::read_to_string(&out[2]).unwrap().contains("bash_profile"));
+            assert_eq!(env, vec![("BOTTY_LOGIN".to_string(), "1".to_string())]);
+        }
+
+        let mut env = Vec::new();
+        let out = integrate(&cmd(&["bash", "-l", "--noprofile"]), &mut env).unwrap();
+        assert_eq!(out[..2], cmd(&["bash", "--noprofile"]));
+        assert_eq!(env, vec![("BOTTY_RCFILE".to_string(), String::new())]);
+
+        // zsh stays a login shell and finds the user's .zprofile through ours
+        let mut env = Vec::new();
+        let out = integrate(&cmd(&["-zsh"]), &mut env).unwrap();
+        assert_eq!(out, cmd(&["zsh", "--login"]));
+        let (_, zdotdir) = env.iter().find(|(k, _)| k == "ZDOTDIR").unwrap();
+        let profile = fs::read_to_string(Path::new(zdotdir).join(".zprofile")).unwrap();
+        assert!(profile.contains("/.zprofile"));
+    }
+
+    #[test]
+    fn test_private_dir_rejects_shared_dirs() {
+        let dir = std::env::temp_dir().join(format!("botty-shell-test-{}", std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        private_dir(&dir).unwrap();
+        private_dir(&dir).unwrap();
+        assert_eq!(fs::metadata(&dir).unwrap().mode() & 0o777, 0o700);
+
+        fs::set_permissions(&dir, std::os::unix::fs::PermissionsExt::from_mode(0o777)).unwrap();
+        assert!(private_dir(&dir).is_err());
+
+        // A symlink is refused even when it points at a private directory
+        let link = dir.with_extension("link");
+        fs::remove_file(&link).ok();
+        fs::set_permissions(&dir, std::os::unix::fs::PermissionsExt::from_mode(0o700)).unwrap();
+        std::os::unix::fs::symlink(&dir, &link).unwrap();
+        assert!(private_dir(&link).is_err());
+
+        fs::remove_file(&link).ok();
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_integrate_leaves_other_commands_alone() {
+        let mut env = Vec::new();
+        for c in [cmd(&["bash", "-c", "echo hi"]), cmd(&["bash", "script.sh"]), cmd(&["python3"])] {
+            assert_eq!(integrate(&c, &mut env).unwrap(), c);
+        }
+        assert!(env.is_empty());
+    }
+}
@@ -21,6 +21,10 @@ pub struct Transcript {
     max_size: usize,
     /// Current total size in bytes.
     current_size: usize,
+    /// Bytes appended since creation.
+    written: u64,
+    /// Bytes evicted to stay under `max_size`.
+    dropped: u64,
     /// Entries in the buffer.
     entries: VecDeque<TranscriptEntry>,
 }
@@ -32,6 +36,8 @@ impl Transcript {
         Self {
             max_size,
             current_size: 0,
+            written: 0,
+            dropped: 0,
             entries: VecDeque::new(),
         }
     }
@@ -56,11 +62,13 @@ impl Transcript {
         };
 
         let entry_size = entry.data.len();
+        self.written += entry_size as u64;
 
         // Remove old entries if we exceed max size
         while self.current_size + entry_size > self.max_size && !self.entries.is_empty() {
             if let Some(old) = self.entries.pop_front() {
                 self.current_size -= old.data.len();
+                self.dropped += old.data.len() as u64;
             }
         }
 
@@ -103,6 +111,18 @@ impl Transcript {
         self.current_size
     }
 
+    /// Get the number of bytes appended since creation, including any since dropped or cleared.
+    #[must_use]
+    pub const fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Get the number of bytes evicted to stay under the maximum size.
+    #[must_use]
+    pub const fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
     /// Get all data as a single byte vector.
     #[must_use]
     pub fn all_bytes(&self) -> Vec<u8> {
@@ -143,6 +163,13 @@ mod tests {
         assert_eq!(all.len(), 2);
         assert_eq!(all[0].data, b"world");
         assert_eq!(all[1].data, b"!");
+        assert_eq!(t.written(), 11);
+        assert_eq!(t.dropped(), 5);
+
+        // Clearing is not dropping
+        t.clear();
+        assert_eq!(t.written(), 11);
+        assert_eq!(t.dropped(), 5);
     }
 
     #[test]
@@ -11,10 +11,18 @@
 //! 
 //! let snapshot = agent.snapshot().await?;
 //! assert!(snapshot.contains("hello"));
+//!
+//! // Compare with tests/snapshots/hello.snap (see `crate::golden`)
+//! agent.assert_snapshot("hello").await?;
 //! ```
 
-use crate::{Client, Request, Response, Server};
+use crate::golden::{self, GoldenError};
+use crate::{
+    AgentState, Client, ClientError, ExitReason, Redaction, Redactor, Region, Server, ShellState,
+    SpawnOptions,
+};
 use regex::Regex;
+use std::io::IsTerminal;
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
@@ -43,6 +51,37 @@ pub enum TestError {
 
     #[error("server error: {0}")]
     ServerError(String),
+
+    #[error(transparent)]
+    Golden(#[from] GoldenError),
+}
+
+impl From<ClientError> for TestError {
+    fn from(err: ClientError) -> Self {
+        match err {
+            ClientError::AgentNotFound(id) => Self::AgentNotFound(id),
+            ClientError::ServerError(message) => Self::RequestFailed(message),
+            other => Self::RequestFailed(other.to_string()),
+        }
+    }
+}
+
+/// How an agent exited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AgentExit {
+    /// Exit code, 128 + the signal number if the agent was killed by a signal.
+    pub code: Option<i32>,
+    /// Why the agent exited.
+    pub reason: ExitReason,
+}
+
+/// Golden snapshots go in `tests/snapshots` of the crate under test, or of
+/// the working directory outside cargo.
+fn default_snapshot_dir() -> PathBuf {
+    std::env::var_os("CARGO_MANIFEST_DIR")
+        .map_or_else(PathBuf::new, PathBuf::from)
+        .join("tests")
+        .join("snapshots")
 }
 
 /// Test harness that manages server lifecycle and provides agent spawning.
@@ -50,6 +89,10 @@ pub struct TestHarness {
     socket_path: PathBuf,
     client: Arc<Mutex<Client>>,
     server_handle: JoinHandle<()>,
+    /// Redaction rules given to every agent handle.
+    redact: Vec<Redaction>,
+    /// Where golden snapshots are stored.
+    snapshot_dir: PathBuf,
 }
 
 impl TestHarness {
@@ -73,9 +116,27 @@ impl TestHarness {
             socket_path,
             client: Arc::new(Mutex::new(client)),
             server_handle,
+            redact: Vec::new(),
+            snapshot_dir: default_snapshot_dir(),
         }
     }
 
+    /// Store golden snapshots for [`AgentHandle::assert_snapshot`] in `dir`
+    /// instead of `tests/snapshots` in the crate being tested.
+    #[must_use]
+    pub fn with_snapshot_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.snapshot_dir = dir.into();
+        self
+    }
+
+    /// Redact volatile content such as timestamps and PIDs from the snapshots
+    /// of every agent spawned from now on, so golden snapshots are stable.
+    #[must_use]
+    pub fn with_redactions(mut self, redact: Vec<Redaction>) -> Self {
+        self.redact = redact;
+        self
+    }
+
     /// Generate a unique socket path for this test.
     fn unique_socket_path() -> PathBuf {
         let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
@@ -95,51 +156,54 @@ impl TestHarness {
         rows: u16,
         cols: u16,
     ) -> Result<AgentHandle, TestError> {
-        let request = Request::Spawn {
-            cmd: cmd.iter().map(std::string::ToString::to_string).collect(),
-            rows,
-            cols,
-            name: None,
-            labels: vec![],
-            timeout: None,
-            max_output: None,
-            env: vec![],
-            env_clear: false,
-        };
+        let mut options = SpawnOptions::new(cmd.iter().copied());
+        options.rows = rows;
+        options.cols = cols;
+        self.spawn_with_options(options).await
+    }
 
-        let response = self
+    /// Spawn a new agent with full control over the spawn options.
+    pub async fn spawn_with_options(&self, options: SpawnOptions) -> Result<AgentHandle, TestError> {
+        let id = self
             .client
             .lock()
             .await
-            .request(request)
+            .spawn(options)
             .await
-            .map_err(|e| TestError::RequestFailed(e.to_string()))?;
-
-        match response {
-            Response::Spawned { id, .. } => Ok(AgentHandle {
-                id,
-                client: Arc::clone(&self.client),
-            }),
-            Response::Error { message } => Err(TestError::SpawnFailed(message)),
-            _ => Err(TestError::SpawnFailed("unexpected response".into())),
-        }
+            .map_err(|e| match e {
+                ClientError::ServerError(message) => TestError::SpawnFailed(message),
+                other => other.into(),
+            })?;
+
+        Ok(AgentHandle {
+            id,
+            client: Arc::clone(&self.client),
+            region: None,
+            redact: self.redact.clone(),
+            snapshot_dir: self.snapshot_dir.clone(),
+        })
     }
 
     /// List all agents.
     pub async fn list(&self) -> Result<Vec<String>, TestError> {
-        let response = self
-            .client
-            .lock()
-            .await
-            .request(Request::List { labels: vec![] })
-            .await
-            .map_err(|e| TestError::RequestFailed(e.to_string()))?;
+        let agents = self.client.lock().await.list(&[]).await?;
+        Ok(agents.into_iter().map(|a| a.id).collect())
+    }
 
-        match response {
-            Response::Agents { agents } => Ok(agents.into_iter().map(|a| a.id).collect()),
-            Response::Error { message } => Err(TestError::RequestFailed(message)),
-            _ => Err(TestError::RequestFailed("unexpected response".into())),
-        }
+    /// Wait for an agent to exit.
+    pub async fn wait_for_exit(
+        &self,
+        id: &str,
+        timeout_duration: Duration,
+    ) -> Result<AgentExit, TestError> {
+        let handle = AgentHandle {
+            id: id.to_string(),
+            client: Arc::clone(&self.client),
+            region: None,
+            redact: self.redact.clone(),
+            snapshot_dir: self.snapshot_dir.clone(),
+        };
+        handle.wait_for_exit(timeout_duration).await
     }
 
     /// Get the socket path (useful for direct connections).
@@ -150,7 +214,7 @@ impl TestHarness {
 
     /// Shutdown the server gracefully.
     pub async fn shutdown(self) {
-        let _ = self.client.lock().await.request(Request::Shutdown).await;
+        let _ = self.client.lock().await.shutdown().await;
         self.server_handle.abort();
         // Clean up socket file
         std::fs::remove_file(&self.socket_path).ok();
@@ -169,6 +233,12 @@ impl Drop for TestHarness {
 pub struct AgentHandle {
     id: String,
     client: Arc<Mutex<Client>>,
+    /// Part of the screen that snapshots and waits look at (None = all of it).
+    region: Option<Region>,
+    /// Redaction rules applied to snapshots.
+    redact: Vec<Redaction>,
+    /// Where golden snapshots are stored.
+    snapshot_dir: PathBuf,
 }
 
 impl AgentHandle {
@@ -178,6 +248,26 @@ impl AgentHandle {
         &self.id
     }
 
+    /// A handle to the same agent whose snapshots and waits only look at
+    /// `region` of the screen, e.g. a TUI's status line.
+    #[must_use]
+    pub fn with_region(&self, region: Region) -> Self {
+        Self {
+            region: Some(region),
+            ..self.clone()
+        }
+    }
+
+    /// A handle to the same agent whose snapshots have volatile content
+    /// replaced by `redact` instead of the harness's rules.
+    #[must_use]
+    pub fn with_redactions(&self, redact: Vec<Redaction>) -> Self {
+        Self {
+            redact,
+            ..self.clone()
+        }
+    }
+
     /// Send text input to the agent (with newline).
     pub async fn send(&self, text: &str) -> Result<(), TestError> {
         self.send_raw(text, true).await
@@ -190,81 +280,41 @@ impl AgentHandle {
 
     /// Send text with explicit newline control.
     async fn send_raw(&self, text: &str, newline: bool) -> Result<(), TestError> {
-        let request = Request::Send {
-            id: self.id.clone(),
-            data: text.to_string(),
-            newline,
-        };
-
-        let response = self
-            .client
-            .lock()
-            .await
-            .request(request)
-            .await
-            .map_err(|e| TestError::RequestFailed(e.to_string()))?;
-
-        match response {
-            Response::Ok => Ok(()),
-            Response::Error { message } => {
-                if message.contains("not found") {
-                    Err(TestError::AgentNotFound(self.id.clone()))
-                } else {
-                    Err(TestError::RequestFailed(message))
-                }
-            }
-            _ => Err(TestError::RequestFailed("unexpected response".into())),
-        }
+        self.client.lock().await.send(&self.id, text, newline).await?;
+        Ok(())
     }
 
     /// Send raw bytes to the agent.
     pub async fn send_bytes(&self, data: &[u8]) -> Result<(), TestError> {
-        let request = Request::SendBytes {
-            id: self.id.clone(),
-            data: data.to_vec(),
-        };
-
-        let response = self
-            .client
-            .lock()
-            .await
-            .request(request)
-            .await
-            .map_err(|e| TestError::RequestFailed(e.to_string()))?;
-
-        match response {
-            Response::Ok => Ok(()),
-            Response::Error { message } => Err(TestError::RequestFailed(message)),
-            _ => Err(TestError::RequestFailed("unexpected response".into())),
-        }
+        self.client.lock().await.send_bytes(&self.id, data).await?;
+        Ok(())
     }
 
-    /// Get a snapshot of the agent's screen.
+    /// Get a snapshot of the agent's screen (or of its region, if it has
+    /// one), with its redaction rules applied.
     pub async fn snapshot(&self) -> Result<String, TestError> {
-        let request = Request::Snapshot {
-            id: self.id.clone(),
-            strip_colors: true,
-        };
-
-        let response = self
+        let snapshot = self
             .client
             .lock()
             .await
-            .request(request)
-            .await
-            .map_err(|e| TestError::RequestFailed(e.to_string()))?;
-
-        match response {
-            Response::Snapshot { content, .. } => Ok(content),
-            Response::Error { message } => {
-                if message.contains("not found") {
-                    Err(TestError::AgentNotFound(self.id.clone()))
-                } else {
-                    Err(TestError::RequestFailed(message))
-                }
-            }
-            _ => Err(TestError::RequestFailed("unexpected response".into())),
-        }
+            .snapshot_redacted(&self.id, self.region, true, &self.redact)
+            .await?;
+        Ok(snapshot.content)
+    }
+
+    /// Compare the screen (or its region) with the golden snapshot `name`,
+    /// redacted with the handle's rules.
+    ///
+    /// On a mismatch, or when there's no golden yet, the screen is written to
+    /// `name.snap.new` in the snapshot directory for review with
+    /// `botty accept`, and the error carries a diff.
+    pub async fn assert_snapshot(&self, name: &str) -> Result<(), TestError> {
+        let content = self.snapshot().await?;
+        let redactor =
+            Redactor::new(&self.redact).map_err(|e| TestError::RequestFailed(e.to_string()))?;
+        let color = golden::use_color(std::io::stderr().is_terminal());
+        golden::check(&self.snapshot_dir.join(name), &content, &redactor, color)?;
+        Ok(())
     }
 
     /// Wait until the screen contains the given substring.
@@ -339,14 +389,59 @@ impl AgentHandle {
         Err(TestError::Timeout)
     }
 
-    /// Wait for a shell prompt (common patterns like $, >, #).
+    /// Wait for a shell prompt.
     ///
-    /// This uses a heuristic pattern that matches common shell prompts.
+    /// If the agent emits OSC 133 prompt markers (see `SpawnOptions::shell_integration`)
+    /// this waits until the shell is actually waiting for input. Otherwise it falls
+    /// back to a heuristic pattern that matches common shell prompts (like $, >, #).
     /// For custom prompts, use `wait_for_pattern` or `wait_for_prompt_custom`.
     pub async fn wait_for_prompt(&self, timeout_duration: Duration) -> Result<String, TestError> {
         // Common shell prompts: ends with $, #, >, or % followed by optional whitespace
         // Also matches things like "user@host:~$ " or "(venv) $ "
-        self.wait_for_pattern(r"[$#>%]\s*$", timeout_duration).await
+        let heuristic =
+            Regex::new(r"[$#>%]\s*$").map_err(|e| TestError::RequestFailed(e.to_string()))?;
+        let deadline = Instant::now() + timeout_duration;
+        let poll_interval = Duration::from_millis(50);
+
+        while Instant::now() < deadline {
+            let shell = self.client.lock().await.agent(&self.id).await?.shell;
+            let snapshot = self.snapshot().await?;
+            if shell.map_or_else(|| heuristic.is_match(&snapshot), |shell| shell.at_prompt) {
+                return Ok(snapshot);
+            }
+            tokio::time::sleep(poll_interval).await;
+        }
+
+        Err(TestError::Timeout)
+    }
+
+    /// Wait for the agent to exit, returning its exit code and why it exited.
+    pub async fn wait_for_exit(&self, timeout_duration: Duration) -> Result<AgentExit, TestError> {
+        let deadline = Instant::now() + timeout_duration;
+        let poll_interval = Duration::from_millis(50);
+
+        while Instant::now() < deadline {
+            if let Some(exit) = self.exit_status().await? {
+                return Ok(exit);
+            }
+            tokio::time::sleep(poll_interval).await;
+        }
+
+        Err(TestError::Timeout)
+    }
+
+    /// How the agent exited, or None if it's still running.
+    pub async fn exit_status(&self) -> Result<Option<AgentExit>, TestError> {
+        let info = self.client.lock().await.agent(&self.id).await?;
+        Ok(matches!(info.state, AgentState::Exited).then(|| AgentExit {
+            code: info.exit_code,
+            reason: info.exit_reason.unwrap_or(ExitReason::Normal),
+        }))
+    }
+
+    /// Get the shell integration state (None unless the agent emits OSC 133 markers).
+    pub async fn shell_state(&self) -> Result<Option<ShellState>, TestError> {
+        Ok(self.client.lock().await.agent(&self.id).await?.shell)
     }
 
     /// Wait for a custom prompt pattern.
@@ -391,27 +486,8 @@ impl AgentHandle {
 
     /// Send a signal to the agent.
     pub async fn signal(&self, signal: i32) -> Result<(), TestError> {
-        let request = Request::Kill {
-            id: Some(self.id.clone()),
-            labels: vec![],
-            all: false,
-            signal,
-            proc_filter: None,
-        };
-
-        let response = self
-            .client
-            .lock()
-            .await
-            .request(request)
-            .await
-            .map_err(|e| TestError::RequestFailed(e.to_string()))?;
-
-        match response {
-            Response::Ok => Ok(()),
-            Response::Error { message } => Err(TestError::RequestFailed(message)),
-            _ => Err(TestError::RequestFailed("unexpected response".into())),
-        }
+        self.client.lock().await.kill(&self.id, signal).await?;
+        Ok(())
     }
 }
 
@@ -440,6 +516,57 @@ mod tests {
         harness.shutdown().await;
     }
 
+    #[tokio::test]
+    async fn test_harness_redactions() {
+        let harness = TestHarness::new()
+            .await
+            .with_redactions(vec![Redaction::Pid, Redaction::Timestamp]);
+
+        let agent = harness
+            .spawn(&["sh", "-c", "echo \"pid $$ at $(date -u +%Y-%m-%dT%H:%M:%SZ)\"; sleep 10"])
+            .await
+            .expect("spawn failed");
+
+        let snapshot = agent
+            .wait_for_content("pid", Duration::from_secs(5))
+            .await
+            .expect("wait failed");
+        assert_eq!(snapshot, "pid <PID> at <TIMESTAMP>");
+
+        let raw = agent.with_redactions(Vec::new()).snapshot().await.expect("snapshot");
+        assert!(!raw.contains('<'), "{raw}");
+
+        agent.kill().await.expect("kill failed");
+        harness.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_harness_assert_snapshot() {
+        let dir = std::env::temp_dir().join(format!("botty-harness-golden-{}", std::process::id()));
+        let harness = TestHarness::new()
+            .await
+            .with_snapshot_dir(&dir)
+            .with_redactions(vec![Redaction::Pid]);
+
+        let agent = harness
+            .spawn(&["sh", "-c", "echo \"GOLDEN pid $$\"; sleep 10"])
+            .await
+            .expect("spawn failed");
+        agent
+            .wait_for_content("GOLDEN", Duration::from_secs(5))
+            .await
+            .expect("wait failed");
+
+        let err = agent.assert_snapshot("golden").await.expect_err("no golden yet");
+        assert!(matches!(err, TestError::Golden(GoldenError::Missing { .. })), "{err}");
+        golden::accept(std::slice::from_ref(&dir)).expect("accept");
+        agent.assert_snapshot("golden").await.expect("matches the accepted golden");
+
+        agent.kill().await.expect("kill failed");
+        harness.shutdown().await;
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
     #[tokio::test]
     async fn test_harness_wait_for_stable() {
         let harness = TestHarness::new().await;
@@ -464,6 +591,7 @@ mod tests {
     }
 
     #[tokio::test]
+    #[allow(clippy::similar_names)] // agents vs agent1/agent2
     async fn test_harness_multiple_agents() {
         let harness = TestHarness::new().await;
 
@@ -498,6 +626,28 @@ mod tests {
         harness.shutdown().await;
     }
 
+    #[tokio::test]
+    async fn test_harness_client_errors() {
+        let harness = TestHarness::new().await;
+
+        // Server errors come back typed through the client
+        let missing = AgentHandle {
+            id: "missing".to_string(),
+            client: Arc::clone(&harness.client),
+            region: None,
+            redact: Vec::new(),
+            snapshot_dir: harness.snapshot_dir.clone(),
+        };
+        assert!(matches!(missing.snapshot().await, Err(TestError::AgentNotFound(ref id)) if id == "missing"));
+        assert!(matches!(missing.send("x").await, Err(TestError::AgentNotFound(_))));
+        assert!(matches!(
+            harness.spawn(&[]).await,
+            Err(TestError::SpawnFailed(_))
+        ));
+
+        harness.shutdown().await;
+    }
+
     #[tokio::test]
     async fn test_harness_send_and_receive() {
         let harness = TestHarness::new().await;
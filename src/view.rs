@@ -36,6 +36,7 @@ pub enum ViewMode {
 
 impl ViewMode {
     /// Parse mode from string.
+    #[allow(clippy::should_implement_trait)] // Returns ViewError directly for CLI messages
     pub fn from_str(s: &str) -> Result<Self, ViewError> {
         match s.to_lowercase().as_str() {
             "panes" | "pane" => Ok(Self::Panes),
@@ -90,8 +91,7 @@ impl TmuxView {
         Command::new("tmux")
             .args(["has-session", "-t", &self.session_name])
             .output()
-            .map(|o| o.status.success())
-            .unwrap_or(false)
+            .is_ok_and(|o| o.status.success())
     }
 
     /// Create a new tmux session (detached).
@@ -128,7 +128,7 @@ impl TmuxView {
         
         if let Err(e) = status {
             // Log but don't fail - session will still work, just won't persist on last pane exit
-            eprintln!("Warning: failed to set remain-on-exit: {}", e);
+            eprintln!("Warning: failed to set remain-on-exit: {e}");
         }
 
         // Enable pane border banners showing agent info
@@ -170,8 +170,8 @@ impl TmuxView {
                 .args(["list-panes", "-s", "-t", &self.session_name, "-F", format_str])
                 .output(),
         };
-        if let Ok(output) = output {
-            if output.status.success() {
+        if let Ok(output) = output
+            && output.status.success() {
                 let stdout = String::from_utf8_lossy(&output.stdout);
                 for line in stdout.lines() {
                     if let Some((pane_id, pane_agent)) = line.split_once(':')
@@ -190,7 +190,6 @@ impl TmuxView {
                     }
                 }
             }
-        }
     }
 
     /// Create a pane/window for an agent.
@@ -437,6 +436,7 @@ impl TmuxView {
     }
 
     /// Remove a window in windows mode.
+    #[allow(clippy::unnecessary_wraps)] // Mirrors remove_pane_split so callers can dispatch uniformly
     fn remove_window(&self, agent_id: &str) -> Result<(), ViewError> {
         // In windows mode, window name is the agent ID
         let _ = Command::new("tmux")
@@ -493,7 +493,7 @@ impl TmuxView {
     pub fn show_waiting_placeholder(&self) -> Result<(), ViewError> {
         // Create a simple script that displays the waiting message
         // Using a bash loop so it stays alive and can be killed when agents spawn
-        let placeholder_cmd = r#"printf '\033[2J\033[H\033[90m'; printf '
+        let placeholder_cmd = r"printf '\033[2J\033[H\033[90m'; printf '
     ╭─────────────────────────────────────╮
     │                                     │
     │      Waiting for agents...          │
@@ -501,7 +501,7 @@ impl TmuxView {
     │   Run: botty spawn -- <command>     │
     │                                     │
     ╰─────────────────────────────────────╯
-'; sleep 3600"#;  // 1-hour timeout to avoid running forever if abandoned
+'; sleep 3600";  // 1-hour timeout to avoid running forever if abandoned
 
         match self.mode {
             ViewMode::Panes => {
@@ -602,7 +602,7 @@ impl TmuxView {
     }
 
     /// Discover panes that already exist in the tmux session.
-    /// Reads @agent_id from each pane and populates active_panes.
+    /// Reads @`agent_id` from each pane and populates `active_panes`.
     /// Returns the set of agent IDs found.
     pub fn discover_existing_panes(&mut self) -> Result<HashSet<String>, ViewError> {
         #[allow(clippy::literal_string_with_formatting_args)]
@@ -642,8 +642,8 @@ impl TmuxView {
     }
 
     /// Get the sizes of all panes/windows, keyed by agent ID.
-    /// Uses @agent_id pane option which is immune to programs overwriting titles.
-    /// Returns a map of agent_id -> (rows, cols).
+    /// Uses @`agent_id` pane option which is immune to programs overwriting titles.
+    /// Returns a map of `agent_id` -> (rows, cols).
     pub fn get_pane_sizes(&self) -> Result<std::collections::HashMap<String, (u16, u16)>, ViewError> {
         let mut sizes = std::collections::HashMap::new();
 
@@ -671,11 +671,10 @@ impl TmuxView {
                     if agent_id.is_empty() {
                         continue;
                     }
-                    if let (Ok(rows), Ok(cols)) = (parts[1].parse::<u16>(), parts[2].parse::<u16>()) {
-                        if self.active_panes.contains(agent_id) {
+                    if let (Ok(rows), Ok(cols)) = (parts[1].parse::<u16>(), parts[2].parse::<u16>())
+                        && self.active_panes.contains(agent_id) {
                             sizes.insert(agent_id.to_string(), (rows, cols));
                         }
-                    }
                 }
             }
         }
@@ -689,7 +688,7 @@ impl TmuxView {
         // Create a resize command that will be called on pane resize
         // This iterates through panes and calls botty resize for each
         let resize_cmd = format!(
-            r#"run-shell '{} resize-all-panes'"#,
+            r"run-shell '{} resize-all-panes'",
             self.botty_path
         );
 
@@ -1,8 +1,9 @@
-//! End-to-end CLI tests using assert_cmd.
+//! End-to-end CLI tests using `assert_cmd`.
 //!
 //! These tests run the actual botty binary and verify stdout/stderr/exit codes.
 
 use assert_cmd::Command;
+use assert_cmd::cargo::cargo_bin_cmd;
 use predicates::prelude::*;
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicU32, Ordering};
@@ -38,7 +39,7 @@ impl TestEnv {
 
     fn start_server(&mut self) {
         let child = std::process::Command::new(env!("CARGO_BIN_EXE_botty"))
-            .arg(&self.socket_arg())
+            .arg(self.socket_arg())
             .arg("server")
             .spawn()
             .expect("failed to start server");
@@ -48,8 +49,8 @@ impl TestEnv {
     }
 
     fn botty(&self) -> Command {
-        let mut cmd = Command::cargo_bin("botty").unwrap();
-        cmd.arg(&self.socket_arg());
+        let mut cmd = cargo_bin_cmd!("botty");
+        cmd.arg(self.socket_arg());
         cmd
     }
 }
@@ -59,7 +60,7 @@ impl Drop for TestEnv {
         // Try to shut down the server gracefully
         if self.server_process.is_some() {
             let _ = std::process::Command::new(env!("CARGO_BIN_EXE_botty"))
-                .arg(&self.socket_arg())
+                .arg(self.socket_arg())
                 .arg("shutdown")
                 .output();
         }
@@ -77,8 +78,7 @@ impl Drop for TestEnv {
 
 #[test]
 fn test_help() {
-    Command::cargo_bin("botty")
-        .unwrap()
+    cargo_bin_cmd!("botty")
         .arg("--help")
         .assert()
         .success()
@@ -90,8 +90,7 @@ fn test_help() {
 
 #[test]
 fn test_version() {
-    Command::cargo_bin("botty")
-        .unwrap()
+    cargo_bin_cmd!("botty")
         .arg("--version")
         .assert()
         .success()
@@ -100,8 +99,7 @@ fn test_version() {
 
 #[test]
 fn test_spawn_help() {
-    Command::cargo_bin("botty")
-        .unwrap()
+    cargo_bin_cmd!("botty")
         .args(["spawn", "--help"])
         .assert()
         .success()
@@ -237,8 +235,7 @@ fn test_agent_not_found() {
 
 #[test]
 fn test_spawn_requires_command() {
-    Command::cargo_bin("botty")
-        .unwrap()
+    cargo_bin_cmd!("botty")
         .args(["spawn", "--"])
         .assert()
         .failure();
@@ -327,6 +324,431 @@ fn test_wait_for_content() {
     env.botty().args(["kill", &agent_id]).assert().success();
 }
 
+#[test]
+fn test_wait_prompt_with_shell_integration() {
+    let mut env = TestEnv::new();
+    env.start_server();
+
+    let output = env
+        .botty()
+        .args(["spawn", "--shell-integration", "--", "bash", "--norc", "--noprofile"])
+        .output()
+        .expect("failed to run spawn");
+    assert!(output.status.success());
+    let agent_id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    env.botty()
+        .args(["wait", &agent_id, "--prompt", "--timeout", "5"])
+        .assert()
+        .success();
+
+    // Sending a command invalidates the current prompt until the next one appears
+    env.botty()
+        .args(["send", &agent_id, "sleep 0.3; echo PROMPT_DONE"])
+        .assert()
+        .success();
+    env.botty()
+        .args(["wait", &agent_id, "--prompt", "--timeout", "5", "--print"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("PROMPT_DONE"));
+
+    env.botty().args(["kill", &agent_id]).assert().success();
+}
+
+#[test]
+fn test_wait_prompt_in_login_shell() {
+    let mut env = TestEnv::new();
+    env.start_server();
+
+    // A login shell still gets prompt marks, after reading the user's profile
+    let home = std::env::temp_dir().join(format!("botty-login-home-{}", std::process::id()));
+    std::fs::create_dir_all(&home).expect("failed to create home");
+    std::fs::write(home.join(".bash_profile"), "echo LOGIN_PROFILE_READ\n")
+        .expect("failed to write profile");
+
+    let home_var = format!("HOME={}", home.display());
+    let output = env
+        .botty()
+        .args(["spawn", "--shell-integration", "--env", &home_var, "--", "bash", "-l"])
+        .output()
+        .expect("failed to run spawn");
+    assert!(output.status.success());
+    let agent_id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    env.botty()
+        .args(["wait", &agent_id, "--prompt", "--timeout", "5"])
+        .assert()
+        .success();
+    env.botty()
+        .args(["snapshot", &agent_id])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("LOGIN_PROFILE_READ"));
+
+    env.botty().args(["kill", &agent_id]).assert().success();
+    std::fs::remove_dir_all(&home).ok();
+}
+
+#[test]
+fn test_wait_for_exit() {
+    let mut env = TestEnv::new();
+    env.start_server();
+
+    let spawn = |env: &TestEnv, script: &str| {
+        let output = env
+            .botty()
+            .args(["spawn", "--", "sh", "-c", script])
+            .output()
+            .expect("failed to run spawn");
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    };
+
+    // The agent's own exit status is passed on
+    let failing = spawn(&env, "sleep 0.3; exit 3");
+    env.botty()
+        .args(["wait", &failing, "--exit", "--timeout", "5"])
+        .assert()
+        .code(3);
+    env.botty()
+        .args(["wait", &failing, "--code", "3", "--timeout", "5"])
+        .assert()
+        .success();
+    env.botty()
+        .args(["wait", &failing, "--code", "0", "--timeout", "5"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("exited with code 3, expected 0"));
+
+    // AND: both the output and the exit are needed
+    let passing = spawn(&env, "echo ALL_PASSED; sleep 0.3");
+    env.botty()
+        .args(["wait", &passing, "--contains", "ALL_PASSED", "--exit", "--timeout", "5"])
+        .assert()
+        .success();
+
+    // OR: whichever comes first, here the output while the agent still runs
+    let slow = spawn(&env, "echo STARTED; sleep 30");
+    env.botty()
+        .args(["wait", &slow, "--any", "--contains", "STARTED", "--exit", "--timeout", "5"])
+        .assert()
+        .success();
+    env.botty()
+        .args(["wait", &slow, "--exit", "--timeout", "1"])
+        .assert()
+        .failure();
+
+    env.botty().args(["kill", &slow]).assert().success();
+}
+
+#[test]
+fn test_wait_named_conditions() {
+    let mut env = TestEnv::new();
+    env.start_server();
+
+    let output = env
+        .botty()
+        .args(["spawn", "--", "sh", "-c", "echo Compiling; sleep 0.5; echo 'Finished: FAIL'; sleep 30"])
+        .output()
+        .expect("failed to run spawn");
+    assert!(output.status.success());
+    let agent_id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    // Reports which of the alternatives fired
+    env.botty()
+        .args(["wait", &agent_id, "--any", "--match", "pass=PASS", "--match", "fail=FAIL"])
+        .assert()
+        .success()
+        .stdout("fail\n");
+    env.botty()
+        .args(["wait", &agent_id, "--any", "--match", "pass=PASS", "--match", "fail=FAIL", "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""matched":["fail"]"#));
+
+    // A screen that already shows every step, in order, meets the sequence
+    env.botty()
+        .args([
+            "wait", &agent_id, "--sequence", "--match", "compiling=Compiling",
+            "--match-pattern", "finished=Finished: \\w+", "--timeout", "1",
+        ])
+        .assert()
+        .success();
+    // A step that never comes is named in the timeout
+    env.botty()
+        .args([
+            "wait", &agent_id, "--sequence", "--match", "compiling=Compiling",
+            "--match", "done=DONE", "--timeout", "1",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("\"done\" (step 2 of 2)"));
+
+    env.botty()
+        .args(["wait", &agent_id, "--match", "x=a", "--match", "x=b"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("more than once"));
+
+    env.botty().args(["kill", &agent_id]).assert().success();
+}
+
+#[test]
+fn test_wait_sequence_keeps_command_line_order() {
+    let mut env = TestEnv::new();
+    env.start_server();
+
+    let script = "echo Compiling; sleep 1; echo 'Finished: PASS'; sleep 30";
+    let spawn = |env: &TestEnv| {
+        let output = env
+            .botty()
+            .args(["spawn", "--", "sh", "-c", script])
+            .output()
+            .expect("failed to run spawn");
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    };
+
+    // Steps of different kinds are taken in the order given, not grouped by kind
+    let agent_id = spawn(&env);
+    env.botty()
+        .args([
+            "wait", &agent_id, "--sequence", "--contains", "Compiling",
+            "--match-pattern", "finished=Finished: \\w+", "--timeout", "5", "--json",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""matched":["contains","finished"]"#));
+
+    // Out of order, the first step only appears once the second has
+    let agent_id = spawn(&env);
+    env.botty()
+        .args([
+            "wait", &agent_id, "--sequence", "--contains", "Finished",
+            "--match", "compiling=Compiling", "--timeout", "3",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("\"compiling\" (step 2 of 2)"));
+
+    env.botty().args(["kill", "--all"]).assert().success();
+}
+
+#[test]
+fn test_wait_sequence_output_at_once() {
+    let mut env = TestEnv::new();
+    env.start_server();
+
+    // Every step's output arrives within one poll of the screen
+    let output = env
+        .botty()
+        .args(["spawn", "--", "sh", "-c", "echo Compiling; echo Finished; sleep 30"])
+        .output()
+        .expect("failed to run spawn");
+    assert!(output.status.success());
+    let agent_id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    env.botty()
+        .args([
+            "wait", &agent_id, "--sequence", "--match", "c=Compiling", "--match", "f=Finished",
+            "--timeout", "5", "--json",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""matched":["c","f"]"#));
+    // The same steps in the other order don't match the screen
+    env.botty()
+        .args([
+            "wait", &agent_id, "--sequence", "--match", "f=Finished", "--match", "c=Compiling",
+            "--timeout", "1",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("\"c\" (step 2 of 2)"));
+
+    env.botty().args(["kill", &agent_id]).assert().success();
+}
+
+#[test]
+fn test_snapshot_and_wait_regions() {
+    let mut env = TestEnv::new();
+    env.start_server();
+
+    let script = r"printf 'header\nDONE earlier\n\033[24;1Hworking'; sleep 0.5; printf '\033[24;1H\033[2KDONE'; sleep 30";
+    let output = env
+        .botty()
+        .args(["spawn", "--", "sh", "-c", script])
+        .output()
+        .expect("failed to run spawn");
+    assert!(output.status.success());
+    let agent_id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    env.botty()
+        .args(["wait", &agent_id, "--contains", "working", "--timeout", "5"])
+        .assert()
+        .success();
+    env.botty()
+        .args(["snapshot", &agent_id, "--rows", "0..1"])
+        .assert()
+        .success()
+        .stdout("header\n");
+    env.botty()
+        .args(["snapshot", &agent_id, "--region", "1,0,1,4"])
+        .assert()
+        .success()
+        .stdout("DONE\n");
+    env.botty()
+        .args(["assert", &agent_id, "--last-line", "--not-contains", "DONE"])
+        .assert()
+        .success();
+
+    // Only the status line counts, not the stale DONE above it
+    env.botty()
+        .args(["wait", &agent_id, "--last-line", "--contains", "DONE", "--timeout", "5", "--print"])
+        .assert()
+        .success()
+        .stdout("DONE\n");
+
+    env.botty()
+        .args(["snapshot", &agent_id, "--last-line", "--cursor-line"])
+        .assert()
+        .failure();
+
+    env.botty().args(["kill", &agent_id]).assert().success();
+}
+
+#[test]
+fn test_snapshot_redactions() {
+    let mut env = TestEnv::new();
+    env.start_server();
+
+    let script = r#"echo "pid $$ started $(date -u +%Y-%m-%dT%H:%M:%S.%NZ) build=v$$"; sleep 30"#;
+    let output = env
+        .botty()
+        .args(["spawn", "--", "sh", "-c", script])
+        .output()
+        .expect("failed to run spawn");
+    assert!(output.status.success());
+    let agent_id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    env.botty()
+        .args(["wait", &agent_id, "--contains", "build", "--timeout", "5"])
+        .assert()
+        .success();
+    env.botty()
+        .args(["snapshot", &agent_id, "--redact-all", "--redact-pattern", r"v\d+=v<N>"])
+        .assert()
+        .success()
+        .stdout("pid <PID> started <TIMESTAMP> build=v<N>\n");
+
+    // A snapshot saved without redaction still matches once both are redacted
+    let saved = env.socket_path.with_extension("snap");
+    let raw = env
+        .botty()
+        .args(["snapshot", &agent_id])
+        .output()
+        .expect("failed to run snapshot");
+    let raw = String::from_utf8_lossy(&raw.stdout);
+    let earlier = raw.trim_end().replace(|c: char| c.is_ascii_digit(), "1");
+    std::fs::write(&saved, earlier).expect("write snapshot");
+    let saved_arg = saved.display().to_string();
+    env.botty()
+        .args(["snapshot", &agent_id, "--diff", &saved_arg])
+        .assert()
+        .failure();
+    env.botty()
+        .args(["snapshot", &agent_id, "--diff", &saved_arg, "--redact", "pid,timestamp"])
+        .args(["--redact-pattern", r"v\d+=v<N>"])
+        .assert()
+        .success()
+        .stdout("No changes\n");
+    std::fs::remove_file(&saved).ok();
+
+    env.botty()
+        .args(["snapshot", &agent_id, "--redact", "colors"])
+        .assert()
+        .failure();
+    env.botty()
+        .args(["snapshot", &agent_id, "--redact-pattern", "(=x"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid redaction pattern"));
+    // The server refuses patterns long enough to be costly to compile
+    let long = format!("{}=x", "a".repeat(1001));
+    env.botty()
+        .args(["snapshot", &agent_id, "--redact-pattern", &long])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("redaction pattern too long"));
+
+    env.botty().args(["kill", &agent_id]).assert().success();
+}
+
+#[test]
+fn test_snapshot_golden_and_accept() {
+    let mut env = TestEnv::new();
+    env.start_server();
+
+    let script = r#"echo "pid $$ first"; sleep 0.5; echo "second"; sleep 30"#;
+    let output = env
+        .botty()
+        .args(["spawn", "--", "sh", "-c", script])
+        .output()
+        .expect("failed to run spawn");
+    assert!(output.status.success());
+    let agent_id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    let dir = env.socket_path.with_extension("golden");
+    let name = dir.join("screen").display().to_string();
+    env.botty()
+        .args(["wait", &agent_id, "--contains", "first", "--timeout", "5"])
+        .assert()
+        .success();
+
+    // No golden yet: fails and leaves the screen for review
+    env.botty()
+        .args(["snapshot", &agent_id, "--golden", &name, "--redact", "pid"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("no golden snapshot"));
+    assert_eq!(
+        std::fs::read_to_string(dir.join("screen.snap.new")).expect("new snapshot"),
+        "pid <PID> first\n"
+    );
+    env.botty()
+        .args(["accept", &dir.display().to_string()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("screen.snap"));
+
+    env.botty()
+        .args(["wait", &agent_id, "--contains", "second", "--timeout", "5"])
+        .assert()
+        .success();
+    env.botty()
+        .args(["snapshot", &agent_id, "--golden", &name, "--redact", "pid"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("+second"));
+    env.botty()
+        .args(["accept", &name])
+        .assert()
+        .success();
+    env.botty()
+        .args(["snapshot", &agent_id, "--golden", &name, "--redact", "pid"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Snapshot matches"));
+    env.botty()
+        .args(["accept", &name])
+        .assert()
+        .failure();
+
+    std::fs::remove_dir_all(&dir).ok();
+    env.botty().args(["kill", &agent_id]).assert().success();
+}
+
 #[test]
 fn test_wait_timeout() {
     let mut env = TestEnv::new();
@@ -464,6 +886,108 @@ fn test_exec_exit_code_propagation() {
         .assert()
         .failure()
         .code(2);
+
+    // Death by signal is reported as 128 + signal
+    env.botty()
+        .args(["exec", "--timeout", "5", "--", "kill -9 $$"])
+        .assert()
+        .failure()
+        .code(137);
+
+    // Exec agents don't linger once their output has been read
+    env.botty()
+        .args(["list", "--all"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("exited").not());
+}
+
+#[test]
+fn test_exec_output_longer_than_screen() {
+    let mut env = TestEnv::new();
+    env.start_server();
+
+    // Output far beyond the 24-row screen must come back complete
+    let output = env
+        .botty()
+        .args(["exec", "--timeout", "10", "--", "seq 1 500"])
+        .output()
+        .expect("failed to run exec");
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 500, "unexpected output: {stdout}");
+    assert_eq!(lines[0], "1");
+    assert_eq!(lines[499], "500");
+}
+
+#[test]
+fn test_exec_explicit_exit_and_json_timing() {
+    let mut env = TestEnv::new();
+    env.start_server();
+
+    // A command that calls `exit` itself still reports its real status
+    let output = env
+        .botty()
+        .args(["exec", "--json", "--timeout", "5", "--", "echo done; sleep 0.2; exit 3"])
+        .output()
+        .expect("failed to run exec");
+    assert_eq!(output.status.code(), Some(3));
+
+    let result: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("exec --json should print JSON");
+    assert_eq!(result["exit_code"], 3);
+    assert_eq!(result["output"], "done\n");
+    let duration = result["duration_ms"].as_u64().expect("duration_ms should be set");
+    assert!(duration >= 200, "duration {duration}ms should include the sleep");
+    assert_eq!(result["truncated"], false);
+}
+
+#[test]
+fn test_exec_timeout_kills_and_removes_agent() {
+    let mut env = TestEnv::new();
+    env.start_server();
+
+    env.botty()
+        .args(["exec", "--timeout", "1", "--", "sleep 30"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("timeout waiting for command completion"));
+
+    env.botty()
+        .args(["list", "--all"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("sleep").not());
+}
+
+#[test]
+fn test_exec_reports_truncated_output() {
+    let mut env = TestEnv::new();
+    env.start_server();
+
+    // Output beyond --max-output loses its start, and says so
+    let output = env
+        .botty()
+        .args(["exec", "--max-output", "1000", "--timeout", "10", "--", "seq 1 2000"])
+        .output()
+        .expect("failed to run exec");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.ends_with("2000\n"), "unexpected output: {stdout}");
+    assert!(!stdout.starts_with("1\n"), "unexpected output: {stdout}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("output exceeded --max-output"), "stderr: {stderr}");
+
+    let output = env
+        .botty()
+        .args(["exec", "--json", "--max-output", "1000", "--timeout", "10", "--", "seq 1 2000"])
+        .output()
+        .expect("failed to run exec");
+    let result: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("exec --json should print JSON");
+    assert_eq!(result["truncated"], true);
 }
 
 #[test]
@@ -505,6 +1029,272 @@ fn test_kill_idempotent() {
         .success();
 }
 
+#[test]
+fn test_label_and_annotate_running_agent() {
+    let mut env = TestEnv::new();
+    env.start_server();
+
+    env.botty()
+        .args(["spawn", "--name", "tagged", "--label", "idle", "--annotation", "task=1234"])
+        .args(["--", "sleep", "30"])
+        .assert()
+        .success();
+
+    // Move the agent to the next phase
+    env.botty()
+        .args(["label", "tagged", "--remove", "idle", "--add", "busy", "--set", "owner=planner"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("labels: busy"))
+        .stdout(predicate::str::contains("annotations: owner=planner,task=1234"));
+
+    // Filters see the new labels and annotations
+    env.botty()
+        .args(["list", "--format", "text", "--label", "busy", "--annotation", "owner=planner"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("tagged"))
+        .stdout(predicate::str::contains("{owner=planner,task=1234}"));
+    env.botty()
+        .args(["list", "--format", "text", "--label", "idle"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("tagged").not());
+
+    // Malformed annotations are rejected by the CLI
+    env.botty()
+        .args(["label", "tagged", "--set", "no-equals"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("KEY=VALUE"));
+
+    env.botty().args(["kill", "--annotation", "task=1234"]).assert().success();
+}
+
+#[test]
+fn test_selector_expressions() {
+    let mut env = TestEnv::new();
+    env.start_server();
+
+    for (name, extra_label, env_name) in [
+        ("w1", "batch", "env=dev"),
+        ("w2", "batch", "env=prod"),
+        ("lead", "leader", "env=dev"),
+    ] {
+        env.botty()
+            .args(["spawn", "--name", name, "--label", "worker", "--label", extra_label])
+            .args(["--annotation", env_name, "--", "sleep", "30"])
+            .assert()
+            .success();
+    }
+
+    env.botty()
+        .args(["list", "--format", "text", "--selector", "worker,!leader,env in (dev,test)"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("w1"))
+        .stdout(predicate::str::contains("w2").not())
+        .stdout(predicate::str::contains("lead").not());
+
+    // Kill every worker except the leader
+    env.botty()
+        .args(["kill", "--selector", "worker,!leader"])
+        .assert()
+        .success();
+    std::thread::sleep(Duration::from_millis(300));
+    env.botty()
+        .args(["list", "--format", "text", "--selector", "worker"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("lead"))
+        .stdout(predicate::str::contains("w1").not())
+        .stdout(predicate::str::contains("w2").not());
+
+    env.botty()
+        .args(["list", "--selector", "env in (dev"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unbalanced"));
+
+    env.botty().args(["kill", "--all"]).assert().success();
+}
+
+#[test]
+fn test_send_keys_follows_application_cursor_mode() {
+    let mut env = TestEnv::new();
+    env.start_server();
+
+    // Turn on application cursor keys (as vim does), then show what arrives
+    env.botty()
+        .args(["spawn", "--name", "appkeys", "--", "sh", "-c"])
+        .arg(r"printf '\033[?1h'; exec cat -v")
+        .assert()
+        .success();
+    std::thread::sleep(Duration::from_millis(200));
+
+    env.botty()
+        .args(["send-keys", "appkeys", "up", "ctrl-left", "f5", "enter"])
+        .assert()
+        .success();
+    env.botty()
+        .args(["wait", "appkeys", "--contains", "^[OA^[[1;5D^[[15~", "--timeout", "5"])
+        .assert()
+        .success();
+
+    env.botty().args(["kill", "appkeys"]).assert().success();
+}
+
+#[test]
+fn test_send_file_as_paste() {
+    let mut env = TestEnv::new();
+    env.start_server();
+
+    let path = std::env::temp_dir().join(format!("botty-paste-{}.txt", std::process::id()));
+    std::fs::write(&path, "first line\nsecond line").unwrap();
+
+    // The agent enables bracketed paste, so the file arrives as one paste
+    env.botty()
+        .args(["spawn", "--name", "repl", "--", "sh", "-c"])
+        .arg(r"printf '\033[?2004h'; exec cat -v")
+        .assert()
+        .success();
+    std::thread::sleep(Duration::from_millis(200));
+
+    env.botty()
+        .args(["send", "repl", "--paste", "--file"])
+        .arg(&path)
+        .assert()
+        .success();
+    env.botty()
+        .args(["wait", "repl", "--contains", "second line^[[201~", "--timeout", "5"])
+        .assert()
+        .success();
+
+    env.botty()
+        .args(["send", "repl", "text", "--file"])
+        .arg(&path)
+        .assert()
+        .failure();
+
+    env.botty().args(["kill", "repl"]).assert().success();
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn test_send_mouse() {
+    let mut env = TestEnv::new();
+    env.start_server();
+
+    // Turn on press/release reporting with SGR encoding (as ratatui apps do)
+    env.botty()
+        .args(["spawn", "--name", "mouse", "--", "sh", "-c"])
+        .arg(r"printf '\033[?1000h\033[?1006h'; exec cat -v")
+        .assert()
+        .success();
+    env.botty()
+        .args(["spawn", "--name", "nomouse", "--", "cat"])
+        .assert()
+        .success();
+    std::thread::sleep(Duration::from_millis(200));
+
+    env.botty()
+        .args(["send-mouse", "mouse", "click", "--row", "4", "--col", "9"])
+        .assert()
+        .success();
+    env.botty()
+        .args(["send-mouse", "mouse", "scroll", "--row", "0", "--col", "0", "--button", "up"])
+        .assert()
+        .success();
+    env.botty().args(["send-keys", "mouse", "enter"]).assert().success();
+    env.botty()
+        .args(["wait", "mouse", "--contains", "^[[<0;10;5M^[[<0;10;5m^[[<64;1;1M", "--timeout", "5"])
+        .assert()
+        .success();
+
+    // Drags need an end position
+    env.botty()
+        .args(["send-mouse", "mouse", "drag", "--row", "0", "--col", "0"])
+        .assert()
+        .failure();
+
+    env.botty()
+        .args(["send-mouse", "nomouse", "click", "--row", "0", "--col", "0"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("hasn't enabled mouse reporting"));
+
+    env.botty().args(["kill", "--all"]).assert().success();
+}
+
+#[test]
+fn test_send_with_typing_delay() {
+    let mut env = TestEnv::new();
+    env.start_server();
+
+    env.botty()
+        .args(["spawn", "--name", "typist", "--", "cat"])
+        .assert()
+        .success();
+
+    let start = std::time::Instant::now();
+    env.botty()
+        .args(["send", "typist", "slowly", "--delay", "40", "--jitter", "10"])
+        .assert()
+        .success();
+    // Six characters plus the newline, 40ms or more apart
+    assert!(start.elapsed() >= Duration::from_millis(240));
+    env.botty()
+        .args(["wait", "typist", "--contains", "slowly", "--timeout", "5"])
+        .assert()
+        .success();
+
+    env.botty()
+        .args(["send", "typist", "x", "--delay", "10", "--paste"])
+        .assert()
+        .failure();
+
+    env.botty().args(["kill", "typist"]).assert().success();
+}
+
+#[test]
+fn test_broadcast() {
+    let mut env = TestEnv::new();
+    env.start_server();
+
+    for (name, label) in [("b1", "worker"), ("b2", "worker"), ("b3", "other")] {
+        env.botty()
+            .args(["spawn", "--name", name, "--label", label, "--", "cat"])
+            .assert()
+            .success();
+    }
+
+    env.botty()
+        .args(["broadcast", "--label", "worker", "echo", "BROADCAST_MARKER"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("b1: ok"))
+        .stdout(predicate::str::contains("b2: ok"))
+        .stdout(predicate::str::contains("b3").not());
+
+    for name in ["b1", "b2"] {
+        env.botty()
+            .args(["wait", name, "--contains", "BROADCAST_MARKER", "--timeout", "5"])
+            .assert()
+            .success();
+    }
+
+    // A missing ID is reported per agent; the others are still written to
+    env.botty()
+        .args(["broadcast", "--id", "b3", "--id", "nope", "--keys", "ctrl-c"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("b3: ok"))
+        .stdout(predicate::str::contains("nope: agent not found"))
+        .stderr(predicate::str::contains("failed to send to 1 of 2 agents"));
+
+    env.botty().args(["kill", "--all"]).assert().success();
+}
+
 #[test]
 fn test_send_key() {
     let mut env = TestEnv::new();
@@ -621,3 +1411,154 @@ fn test_wait_combined_conditions() {
     // Clean up
     env.botty().args(["kill", &agent_id]).assert().success();
 }
+
+#[test]
+fn test_script_runs_steps_and_reports() {
+    let mut env = TestEnv::new();
+    env.start_server();
+
+    let dir = std::env::temp_dir().join(format!("botty-script-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let snapshot_path = dir.join("screen.txt");
+    let script_path = dir.join("script.json");
+    let script = serde_json::json!({
+        "timeout": 5,
+        "steps": [
+            { "spawn": { "cmd": ["bash", "--norc", "--noprofile"] }, "agent": "script-sh" },
+            { "send": "echo SCRIPT_$((40 + 2))" },
+            { "expect": "^SCRIPT_42$" },
+            { "expect_not": "command not found" },
+            { "snapshot": snapshot_path },
+            { "if": {
+                "match": "SCRIPT_42",
+                "then": [{ "send": "echo BRANCH_TAKEN" }, { "expect": "^BRANCH_TAKEN$" }],
+                "else": [{ "expect": "never matches" }]
+            } }
+        ]
+    });
+    std::fs::write(&script_path, script.to_string()).unwrap();
+
+    let output = env.botty().arg("script").arg(&script_path).output().unwrap();
+    assert!(output.status.success(), "script failed: {}", String::from_utf8_lossy(&output.stdout));
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["passed"], true);
+    let steps = report["steps"].as_array().unwrap();
+    assert_eq!(steps.len(), 8);
+    assert_eq!(steps[2]["detail"], "SCRIPT_42");
+    assert_eq!(steps[5]["detail"], "then");
+    assert_eq!(steps[7]["step"], "5.then.1");
+    assert!(std::fs::read_to_string(&snapshot_path).unwrap().contains("SCRIPT_42"));
+
+    // Spawned agents are cleaned up afterwards
+    env.botty()
+        .args(["list", "--format", "text"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("script-sh").not());
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_script_failure_exits_nonzero() {
+    let mut env = TestEnv::new();
+    env.start_server();
+
+    let dir = std::env::temp_dir().join(format!("botty-script-fail-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let script_path = dir.join("script.json");
+    let script = serde_json::json!({
+        "steps": [
+            { "spawn": { "cmd": ["sh", "-c", "echo hello; sleep 10"] } },
+            { "expect": "goodbye", "timeout": 0.3 },
+            { "send": "never sent" }
+        ]
+    });
+    std::fs::write(&script_path, script.to_string()).unwrap();
+
+    let output = env.botty().arg("script").arg(&script_path).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["passed"], false);
+    let steps = report["steps"].as_array().unwrap();
+    assert_eq!(steps.len(), 2);
+    assert_eq!(steps[1]["status"], "failed");
+    assert!(steps[1]["error"].as_str().unwrap().contains("timeout"));
+    assert_eq!(steps[1]["condition"], "goodbye");
+    assert!(steps[1]["snapshot"].as_str().unwrap().contains("hello"));
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_checks_roll_up_into_reports() {
+    let mut env = TestEnv::new();
+    env.start_server();
+
+    let dir = std::env::temp_dir().join(format!("botty-report-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let tap = dir.join("checks.tap");
+    let junit = dir.join("checks.xml");
+
+    let output = env
+        .botty()
+        .args(["spawn", "--", "sh", "-c", "echo ready; sleep 30"])
+        .output()
+        .unwrap();
+    let agent_id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    for report in [&tap, &junit] {
+        env.botty()
+            .args(["wait", &agent_id, "--contains", "ready", "--timeout", "5", "--report"])
+            .arg(report)
+            .assert()
+            .success();
+        env.botty()
+            .args(["assert", &agent_id, "--contains", "missing", "--report"])
+            .arg(report)
+            .assert()
+            .failure();
+    }
+
+    let script_path = dir.join("script.json");
+    let script = serde_json::json!({
+        "agent": agent_id,
+        "steps": [{ "expect": "ready" }]
+    });
+    std::fs::write(&script_path, script.to_string()).unwrap();
+    env.botty()
+        .arg("script")
+        .arg(&script_path)
+        .args(["--keep", "--report-format", "tap", "--report"])
+        .arg(&tap)
+        .assert()
+        .success();
+
+    let tap = std::fs::read_to_string(&tap).unwrap();
+    assert!(tap.starts_with("TAP version 13\n"), "{tap}");
+    assert!(tap.contains(&format!("ok 1 - {agent_id}: wait contains \"ready\"\n")), "{tap}");
+    assert!(tap.contains(&format!("not ok 2 - {agent_id}: assert contains \"missing\"\n")));
+    assert!(tap.contains("  message: \"expected output to contain: \\\"missing\\\"\"\n"), "{tap}");
+    assert!(tap.contains("  snapshot: |2\n    ready\n"), "{tap}");
+    assert!(tap.contains("ok 3 - "), "{tap}");
+    assert!(tap.contains("step 0 expect \"ready\""), "{tap}");
+    assert!(tap.ends_with("1..3\n"), "{tap}");
+
+    let junit = std::fs::read_to_string(&junit).unwrap();
+    assert!(junit.contains(r#"<testsuite name="botty" tests="2" failures="1""#), "{junit}");
+    assert!(junit.contains(&format!(r#"classname="{agent_id}""#)));
+    assert!(junit.contains("<system-out>ready</system-out>"), "{junit}");
+
+    // An unknown format is an error before anything runs
+    env.botty()
+        .args(["assert", &agent_id, "--contains", "ready", "--report"])
+        .arg(dir.join("checks.txt"))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("report format"));
+
+    env.botty().args(["kill", &agent_id]).assert().success();
+    std::fs::remove_dir_all(&dir).ok();
+}
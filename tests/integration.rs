@@ -2,8 +2,9 @@
 //!
 //! Each test uses a unique socket path to avoid conflicts.
 
-use botty::protocol::{AgentState, AttachEndReason};
-use botty::{Client, Request, Response, Server};
+use botty::protocol::{AgentState, AttachEndReason, ExitReason};
+use botty::{Client, Request, Response, Server, TerminalIdentity};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::time::Duration;
@@ -82,10 +83,16 @@ async fn test_spawn_and_list() {
             cols: 80,
             name: None,
             labels: vec![],
+            annotations: BTreeMap::new(),
             timeout: None,
             max_output: None,
+            max_input: None,
             env: vec![],
             env_clear: false,
+            shell_integration: false,
+            term: None,
+            terminal: TerminalIdentity::default(),
+            no_terminal_replies: false,
         })
         .await
         .expect("spawn failed");
@@ -95,11 +102,15 @@ async fn test_spawn_and_list() {
             assert!(pid > 0);
             id
         }
-        other => panic!("expected Spawned, got {:?}", other),
+        other => panic!("expected Spawned, got {other:?}"),
     };
 
     // List agents
-    let response = client.request(Request::List { labels: vec![] }).await.expect("list failed");
+    let response = client.request(Request::List {
+            labels: vec![],
+            annotations: BTreeMap::new(),
+            selector: None,
+        }).await.expect("list failed");
 
     match response {
         Response::Agents { agents } => {
@@ -107,7 +118,7 @@ async fn test_spawn_and_list() {
             assert_eq!(agents[0].id, agent_id);
             assert_eq!(agents[0].command, vec!["sleep", "10"]);
         }
-        other => panic!("expected Agents, got {:?}", other),
+        other => panic!("expected Agents, got {other:?}"),
     }
 
     // Kill the agent
@@ -115,6 +126,8 @@ async fn test_spawn_and_list() {
         .request(Request::Kill {
             id: Some(agent_id),
             labels: vec![],
+            annotations: BTreeMap::new(),
+            selector: None,
             all: false,
             signal: 15,
             proc_filter: None,
@@ -153,17 +166,23 @@ async fn test_spawn_send_snapshot() {
             cols: 80,
             name: None,
             labels: vec![],
+            annotations: BTreeMap::new(),
             timeout: None,
             max_output: None,
+            max_input: None,
             env: vec![],
             env_clear: false,
+            shell_integration: false,
+            term: None,
+            terminal: TerminalIdentity::default(),
+            no_terminal_replies: false,
         })
         .await
         .expect("spawn failed");
 
     let agent_id = match response {
         Response::Spawned { id, .. } => id,
-        other => panic!("expected Spawned, got {:?}", other),
+        other => panic!("expected Spawned, got {other:?}"),
     };
 
     // Give bash time to start
@@ -175,6 +194,9 @@ async fn test_spawn_send_snapshot() {
             id: agent_id.clone(),
             data: "echo BOTTY_TEST_OUTPUT".into(),
             newline: true,
+            paste: false,
+            delay_ms: 0,
+            jitter_ms: 0,
         })
         .await
         .expect("send failed");
@@ -189,6 +211,8 @@ async fn test_spawn_send_snapshot() {
         .request(Request::Snapshot {
             id: agent_id.clone(),
             strip_colors: true,
+            region: None,
+            redact: Vec::new(),
         })
         .await
         .expect("snapshot failed");
@@ -197,11 +221,10 @@ async fn test_spawn_send_snapshot() {
         Response::Snapshot { content, .. } => {
             assert!(
                 content.contains("BOTTY_TEST_OUTPUT"),
-                "snapshot should contain our output: {}",
-                content
+                "snapshot should contain our output: {content}"
             );
         }
-        other => panic!("expected Snapshot, got {:?}", other),
+        other => panic!("expected Snapshot, got {other:?}"),
     }
 
     // Kill and shutdown
@@ -209,6 +232,8 @@ async fn test_spawn_send_snapshot() {
         .request(Request::Kill {
             id: Some(agent_id),
             labels: vec![],
+            annotations: BTreeMap::new(),
+            selector: None,
             all: false,
             signal: 9,
             proc_filter: None,
@@ -239,6 +264,8 @@ async fn test_agent_not_found() {
         .request(Request::Snapshot {
             id: "nonexistent-agent".into(),
             strip_colors: true,
+            region: None,
+            redact: Vec::new(),
         })
         .await
         .expect("request failed");
@@ -247,7 +274,7 @@ async fn test_agent_not_found() {
         Response::Error { message } => {
             assert!(message.contains("not found"));
         }
-        other => panic!("expected Error, got {:?}", other),
+        other => panic!("expected Error, got {other:?}"),
     }
 
     // Shutdown
@@ -284,17 +311,23 @@ async fn test_screen_cursor_movement() {
             cols: 80,
             name: None,
             labels: vec![],
+            annotations: BTreeMap::new(),
             timeout: None,
             max_output: None,
+            max_input: None,
             env: vec![],
             env_clear: false,
+            shell_integration: false,
+            term: None,
+            terminal: TerminalIdentity::default(),
+            no_terminal_replies: false,
         })
         .await
         .expect("spawn failed");
 
     let agent_id = match response {
         Response::Spawned { id, .. } => id,
-        other => panic!("expected Spawned, got {:?}", other),
+        other => panic!("expected Spawned, got {other:?}"),
     };
 
     // Wait for output
@@ -305,6 +338,8 @@ async fn test_screen_cursor_movement() {
         .request(Request::Snapshot {
             id: agent_id.clone(),
             strip_colors: true,
+            region: None,
+            redact: Vec::new(),
         })
         .await
         .expect("snapshot failed");
@@ -313,11 +348,10 @@ async fn test_screen_cursor_movement() {
         Response::Snapshot { content, .. } => {
             assert!(
                 content.contains("XBC"),
-                "cursor movement should produce XBC: {}",
-                content
+                "cursor movement should produce XBC: {content}"
             );
         }
-        other => panic!("expected Snapshot, got {:?}", other),
+        other => panic!("expected Snapshot, got {other:?}"),
     }
 
     // Cleanup
@@ -325,6 +359,8 @@ async fn test_screen_cursor_movement() {
         .request(Request::Kill {
             id: Some(agent_id),
             labels: vec![],
+            annotations: BTreeMap::new(),
+            selector: None,
             all: false,
             signal: 9,
             proc_filter: None,
@@ -362,17 +398,23 @@ async fn test_transcript_tail() {
             cols: 80,
             name: None,
             labels: vec![],
+            annotations: BTreeMap::new(),
             timeout: None,
             max_output: None,
+            max_input: None,
             env: vec![],
             env_clear: false,
+            shell_integration: false,
+            term: None,
+            terminal: TerminalIdentity::default(),
+            no_terminal_replies: false,
         })
         .await
         .expect("spawn failed");
 
     let agent_id = match response {
         Response::Spawned { id, .. } => id,
-        other => panic!("expected Spawned, got {:?}", other),
+        other => panic!("expected Spawned, got {other:?}"),
     };
 
     // Wait for output
@@ -391,10 +433,10 @@ async fn test_transcript_tail() {
     match response {
         Response::Output { data } => {
             let text = String::from_utf8_lossy(&data);
-            assert!(text.contains("LINE_ONE"), "should contain LINE_ONE: {}", text);
-            assert!(text.contains("LINE_TWO"), "should contain LINE_TWO: {}", text);
+            assert!(text.contains("LINE_ONE"), "should contain LINE_ONE: {text}");
+            assert!(text.contains("LINE_TWO"), "should contain LINE_TWO: {text}");
         }
-        other => panic!("expected Output, got {:?}", other),
+        other => panic!("expected Output, got {other:?}"),
     }
 
     // Cleanup
@@ -402,6 +444,8 @@ async fn test_transcript_tail() {
         .request(Request::Kill {
             id: Some(agent_id),
             labels: vec![],
+            annotations: BTreeMap::new(),
+            selector: None,
             all: false,
             signal: 9,
             proc_filter: None,
@@ -438,17 +482,23 @@ async fn test_attach_and_detach() {
             cols: 80,
             name: None,
             labels: vec![],
+            annotations: BTreeMap::new(),
             timeout: None,
             max_output: None,
+            max_input: None,
             env: vec![],
             env_clear: false,
+            shell_integration: false,
+            term: None,
+            terminal: TerminalIdentity::default(),
+            no_terminal_replies: false,
         })
         .await
         .expect("spawn failed");
 
     let agent_id = match response {
         Response::Spawned { id, .. } => id,
-        other => panic!("expected Spawned, got {:?}", other),
+        other => panic!("expected Spawned, got {other:?}"),
     };
 
     tokio::time::sleep(Duration::from_millis(100)).await;
@@ -478,7 +528,7 @@ async fn test_attach_and_detach() {
             assert_eq!(id, agent_id);
             assert_eq!(size, (24, 80));
         }
-        other => panic!("expected AttachStarted, got {:?}", other),
+        other => panic!("expected AttachStarted, got {other:?}"),
     }
 
     // Detach by closing the connection (simulates client disconnect)
@@ -489,13 +539,17 @@ async fn test_attach_and_detach() {
     tokio::time::sleep(Duration::from_millis(100)).await;
 
     // Verify agent is still running (detach shouldn't kill it)
-    let response = client.request(Request::List { labels: vec![] }).await.expect("list failed");
+    let response = client.request(Request::List {
+            labels: vec![],
+            annotations: BTreeMap::new(),
+            selector: None,
+        }).await.expect("list failed");
     match response {
         Response::Agents { agents } => {
             assert_eq!(agents.len(), 1);
             assert_eq!(agents[0].id, agent_id);
         }
-        other => panic!("expected Agents, got {:?}", other),
+        other => panic!("expected Agents, got {other:?}"),
     }
 
     // Cleanup
@@ -503,6 +557,8 @@ async fn test_attach_and_detach() {
         .request(Request::Kill {
             id: Some(agent_id),
             labels: vec![],
+            annotations: BTreeMap::new(),
+            selector: None,
             all: false,
             signal: 9,
             proc_filter: None,
@@ -535,17 +591,23 @@ async fn test_attach_readonly_mode() {
             cols: 80,
             name: None,
             labels: vec![],
+            annotations: BTreeMap::new(),
             timeout: None,
             max_output: None,
+            max_input: None,
             env: vec![],
             env_clear: false,
+            shell_integration: false,
+            term: None,
+            terminal: TerminalIdentity::default(),
+            no_terminal_replies: false,
         })
         .await
         .expect("spawn failed");
 
     let agent_id = match response {
         Response::Spawned { id, .. } => id,
-        other => panic!("expected Spawned, got {:?}", other),
+        other => panic!("expected Spawned, got {other:?}"),
     };
 
     tokio::time::sleep(Duration::from_millis(200)).await;
@@ -584,6 +646,8 @@ async fn test_attach_readonly_mode() {
         .request(Request::Kill {
             id: Some(agent_id),
             labels: vec![],
+            annotations: BTreeMap::new(),
+            selector: None,
             all: false,
             signal: 9,
             proc_filter: None,
@@ -630,7 +694,7 @@ async fn test_attach_nonexistent_agent() {
         Response::Error { message } => {
             assert!(message.contains("not found"));
         }
-        other => panic!("expected Error, got {:?}", other),
+        other => panic!("expected Error, got {other:?}"),
     }
 
     // Cleanup
@@ -662,17 +726,23 @@ async fn test_attach_receives_output() {
             cols: 80,
             name: None,
             labels: vec![],
+            annotations: BTreeMap::new(),
             timeout: None,
             max_output: None,
+            max_input: None,
             env: vec![],
             env_clear: false,
+            shell_integration: false,
+            term: None,
+            terminal: TerminalIdentity::default(),
+            no_terminal_replies: false,
         })
         .await
         .expect("spawn failed");
 
     let agent_id = match response {
         Response::Spawned { id, .. } => id,
-        other => panic!("expected Spawned, got {:?}", other),
+        other => panic!("expected Spawned, got {other:?}"),
     };
 
     tokio::time::sleep(Duration::from_millis(100)).await;
@@ -721,8 +791,7 @@ async fn test_attach_receives_output() {
     let text = String::from_utf8_lossy(&output);
     assert!(
         text.contains("ATTACH_TEST_OUTPUT"),
-        "should receive command output through attach: {}",
-        text
+        "should receive command output through attach: {text}"
     );
 
     // Cleanup
@@ -731,6 +800,8 @@ async fn test_attach_receives_output() {
         .request(Request::Kill {
             id: Some(agent_id),
             labels: vec![],
+            annotations: BTreeMap::new(),
+            selector: None,
             all: false,
             signal: 9,
             proc_filter: None,
@@ -763,17 +834,23 @@ async fn test_attach_agent_exit() {
             cols: 80,
             name: None,
             labels: vec![],
+            annotations: BTreeMap::new(),
             timeout: None,
             max_output: None,
+            max_input: None,
             env: vec![],
             env_clear: false,
+            shell_integration: false,
+            term: None,
+            terminal: TerminalIdentity::default(),
+            no_terminal_replies: false,
         })
         .await
         .expect("spawn failed");
 
     let agent_id = match response {
         Response::Spawned { id, .. } => id,
-        other => panic!("expected Spawned, got {:?}", other),
+        other => panic!("expected Spawned, got {other:?}"),
     };
 
     // Connect for attach before agent exits
@@ -804,19 +881,17 @@ async fn test_attach_agent_exit() {
         match timeout(Duration::from_millis(100), stream.read(&mut buf)).await {
             Ok(Ok(n)) if n > 0 => {
                 // Try to parse as JSON (AttachEnded message)
-                if buf[0] == b'{' {
-                    if let Ok(response) = serde_json::from_slice::<Response>(&buf[..n]) {
-                        if let Response::AttachEnded { reason } = response {
+                if buf[0] == b'{'
+                    && let Ok(response) = serde_json::from_slice::<Response>(&buf[..n])
+                        && let Response::AttachEnded { reason } = response {
                             match reason {
                                 AttachEndReason::AgentExited { exit_code } => {
                                     assert_eq!(exit_code, Some(42));
                                     received_end = true;
                                 }
-                                other => panic!("expected AgentExited, got {:?}", other),
+                                other => panic!("expected AgentExited, got {other:?}"),
                             }
                         }
-                    }
-                }
             }
             Ok(Ok(0)) => break, // Connection closed
             _ => {}
@@ -825,6 +900,10 @@ async fn test_attach_agent_exit() {
 
     assert!(received_end, "should receive AttachEnded when agent exits");
 
+    // The exit reason is recorded just as when the reader task sees the exit
+    let info = client.agent(&agent_id).await.expect("agent lookup failed");
+    assert_eq!(info.exit_reason, Some(ExitReason::Normal));
+
     // Cleanup
     drop(stream);
     let _ = client.request(Request::Shutdown).await;
@@ -856,10 +935,16 @@ async fn test_kill_all() {
                 cols: 80,
                 name: Some(format!("agent-{i}")),
                 labels: vec![],
+                annotations: BTreeMap::new(),
                 timeout: None,
                 max_output: None,
+                max_input: None,
                 env: vec![],
                 env_clear: false,
+                shell_integration: false,
+                term: None,
+                terminal: TerminalIdentity::default(),
+                no_terminal_replies: false,
             })
             .await
             .expect("spawn failed");
@@ -868,12 +953,16 @@ async fn test_kill_all() {
     }
 
     // Verify we have 3 agents
-    let response = client.request(Request::List { labels: vec![] }).await.expect("list failed");
+    let response = client.request(Request::List {
+            labels: vec![],
+            annotations: BTreeMap::new(),
+            selector: None,
+        }).await.expect("list failed");
     match &response {
         Response::Agents { agents } => {
             assert_eq!(agents.len(), 3, "should have 3 agents");
         }
-        other => panic!("expected Agents, got {:?}", other),
+        other => panic!("expected Agents, got {other:?}"),
     }
 
     // Kill all agents
@@ -881,6 +970,8 @@ async fn test_kill_all() {
         .request(Request::Kill {
             id: None,
             labels: vec![],
+            annotations: BTreeMap::new(),
+            selector: None,
             all: true,
             signal: 9,
             proc_filter: None,
@@ -894,13 +985,17 @@ async fn test_kill_all() {
     tokio::time::sleep(Duration::from_millis(200)).await;
 
     // Verify all agents are gone (or exited)
-    let response = client.request(Request::List { labels: vec![] }).await.expect("list failed");
+    let response = client.request(Request::List {
+            labels: vec![],
+            annotations: BTreeMap::new(),
+            selector: None,
+        }).await.expect("list failed");
     match response {
         Response::Agents { agents } => {
             let running: Vec<_> = agents.iter().filter(|a| a.state == AgentState::Running).collect();
-            assert!(running.is_empty(), "no agents should be running after kill --all, got: {:?}", running);
+            assert!(running.is_empty(), "no agents should be running after kill --all, got: {running:?}");
         }
-        other => panic!("expected Agents, got {:?}", other),
+        other => panic!("expected Agents, got {other:?}"),
     }
 
     // Shutdown
@@ -929,6 +1024,8 @@ async fn test_kill_all_no_agents() {
         .request(Request::Kill {
             id: None,
             labels: vec![],
+            annotations: BTreeMap::new(),
+            selector: None,
             all: true,
             signal: 9,
             proc_filter: None,
@@ -938,12 +1035,391 @@ async fn test_kill_all_no_agents() {
 
     match response {
         Response::Error { message } => {
-            assert!(message.contains("no running agents"), "should say no running agents: {}", message);
+            assert!(message.contains("no running agents"), "should say no running agents: {message}");
         }
-        other => panic!("expected Error, got {:?}", other),
+        other => panic!("expected Error, got {other:?}"),
     }
 
     // Shutdown
     let _ = client.request(Request::Shutdown).await;
     server_handle.abort();
 }
+
+#[tokio::test]
+async fn test_typed_client_api() {
+    use botty::{ClientError, Event, EventOptions, SpawnOptions};
+    use futures::StreamExt;
+
+    let socket_path = unique_socket_path();
+    let _cleanup = SocketCleanup(socket_path.clone());
+
+    let server_socket = socket_path.clone();
+    let server_handle = tokio::spawn(async move {
+        let mut server = Server::new(server_socket);
+        server.run().await
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = Client::new(socket_path);
+    client.ping().await.expect("ping failed");
+
+    let events = client
+        .events(EventOptions {
+            filter: vec![],
+            include_output: true,
+            ..EventOptions::default()
+        })
+        .await
+        .expect("events failed");
+    let mut events = std::pin::pin!(events);
+
+    let mut options = SpawnOptions::new(["bash", "--norc", "--noprofile"]);
+    options.rows = 10;
+    options.cols = 40;
+    let id = client.spawn(options).await.expect("spawn failed");
+
+    client
+        .send(&id, "echo TYPED_CLIENT", true)
+        .await
+        .expect("send failed");
+
+    let mut snapshot = client.snapshot(&id).await.expect("snapshot failed");
+    for _ in 0..50 {
+        if snapshot.content.contains("TYPED_CLIENT") {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        snapshot = client.snapshot(&id).await.expect("snapshot failed");
+    }
+    assert!(snapshot.content.contains("TYPED_CLIENT"));
+    assert_eq!(snapshot.size, (10, 40));
+
+    // Typed errors for unknown agents
+    let err = client.snapshot("no-such-agent").await.unwrap_err();
+    assert!(matches!(err, ClientError::AgentNotFound(ref missing) if missing == "no-such-agent"));
+
+    client.kill(&id, 9).await.expect("kill failed");
+
+    // The event stream reports the exit
+    let exited = timeout(Duration::from_secs(5), async {
+        while let Some(event) = events.next().await {
+            if let Event::AgentExited { id: exited_id, .. } = event.expect("event error")
+                && exited_id == id
+            {
+                return true;
+            }
+        }
+        false
+    })
+    .await
+    .expect("timeout");
+    assert!(exited);
+
+    let _ = client.shutdown().await;
+    server_handle.abort();
+}
+
+#[tokio::test]
+async fn test_screen_changed_events() {
+    use botty::{Event, EventOptions, SpawnOptions};
+    use futures::StreamExt;
+
+    let socket_path = unique_socket_path();
+    let _cleanup = SocketCleanup(socket_path.clone());
+
+    let server_socket = socket_path.clone();
+    let server_handle = tokio::spawn(async move {
+        let mut server = Server::new(server_socket);
+        server.run().await
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = Client::new(socket_path);
+    let events = client
+        .events(EventOptions {
+            screen_interval: Some(50),
+            ..EventOptions::default()
+        })
+        .await
+        .expect("events failed");
+    let mut events = std::pin::pin!(events);
+
+    // Give the subscription time to register before the agent starts writing
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let id = client
+        .spawn(SpawnOptions::new([
+            "sh",
+            "-c",
+            "echo first; echo second; sleep 0.5; echo third; sleep 10",
+        ]))
+        .await
+        .expect("spawn failed");
+
+    // Collect row changes until "third" shows up; no raw output is delivered
+    let changes = timeout(Duration::from_secs(5), async {
+        let mut changes = Vec::new();
+        while let Some(event) = events.next().await {
+            match event.expect("event error") {
+                Event::ScreenChanged { id: agent, rows, .. } if agent == id => {
+                    let done = rows.iter().any(|r| r.text == "third");
+                    changes.push(rows);
+                    if done {
+                        break;
+                    }
+                }
+                Event::AgentOutput { .. } => panic!("output events were not requested"),
+                _ => {}
+            }
+        }
+        changes
+    })
+    .await
+    .expect("timeout waiting for screen changes");
+
+    let first = &changes[0];
+    assert_eq!(first[0].row, 0);
+    assert_eq!(first[0].text, "first");
+    assert_eq!(first[1].text, "second");
+
+    // The last change only carries the new row
+    let last = changes.last().unwrap();
+    assert_eq!(last.len(), 1);
+    assert_eq!(last[0].row, 2);
+
+    client.kill(&id, 9).await.expect("kill failed");
+    let _ = client.shutdown().await;
+    server_handle.abort();
+}
+
+#[tokio::test]
+async fn test_screen_changed_after_resize() {
+    use botty::{Event, EventMessage, EventOptions, SpawnOptions};
+    use futures::StreamExt;
+
+    let socket_path = unique_socket_path();
+    let _cleanup = SocketCleanup(socket_path.clone());
+
+    let server_socket = socket_path.clone();
+    let server_handle = tokio::spawn(async move {
+        let mut server = Server::new(server_socket);
+        server.run().await
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = Client::new(socket_path);
+    let mut options = SpawnOptions::new(["sh", "-c", "seq 1 8; sleep 10"]);
+    options.rows = 10;
+    let id = client.spawn(options).await.expect("spawn failed");
+
+    let stream = client
+        .event_stream(EventOptions {
+            filter: vec![id.clone()],
+            screen_interval: Some(20),
+            since_seq: Some(0),
+            ..EventOptions::default()
+        })
+        .await
+        .expect("events failed");
+    let mut stream = std::pin::pin!(stream);
+    let mut seqs = Vec::new();
+    let mut next_screen = async |seqs: &mut Vec<u64>| loop {
+        match stream.next().await.expect("stream ended").expect("event error") {
+            EventMessage::Event { seq, event } => {
+                seqs.push(seq);
+                if let Event::ScreenChanged { rows, size, .. } = event {
+                    return (rows, size);
+                }
+            }
+            EventMessage::Lagged { missed } => panic!("lagged by {missed} events"),
+        }
+    };
+
+    let (rows, size) = timeout(Duration::from_secs(5), next_screen(&mut seqs))
+        .await
+        .expect("timeout waiting for the first screen");
+    assert_eq!(size, (10, 80));
+    assert_eq!(rows.len(), 8, "first screen carries the non-empty rows: {rows:?}");
+
+    // Shrinking the screen sends every row at the new size, without any output
+    client.resize(&id, 4, 80, false).await.expect("resize failed");
+    let (rows, size) = timeout(Duration::from_secs(5), next_screen(&mut seqs))
+        .await
+        .expect("timeout waiting for the resized screen");
+    assert_eq!(size, (4, 80));
+    assert_eq!(rows.iter().map(|r| r.row).collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+
+    // Screen changes are numbered like every other event
+    assert!(seqs.windows(2).all(|w| w[0] < w[1]), "seqs not increasing: {seqs:?}");
+
+    client.kill(&id, 9).await.expect("kill failed");
+    let _ = client.shutdown().await;
+    server_handle.abort();
+}
+
+/// Read `count` events from a fresh subscription replaying after `since_seq`.
+async fn replay_events(
+    client: &Client,
+    since_seq: u64,
+    count: usize,
+) -> Vec<(u64, botty::Event)> {
+    use botty::{EventMessage, EventOptions};
+    use futures::StreamExt;
+
+    let stream = client
+        .event_stream(EventOptions {
+            since_seq: Some(since_seq),
+            ..EventOptions::default()
+        })
+        .await
+        .expect("events failed");
+    let mut stream = std::pin::pin!(stream);
+    let mut seen = Vec::new();
+    while seen.len() < count {
+        match stream.next().await.expect("stream ended").expect("event error") {
+            EventMessage::Event { seq, event } => seen.push((seq, event)),
+            EventMessage::Lagged { missed } => panic!("lagged by {missed} events"),
+        }
+    }
+    seen
+}
+
+#[tokio::test]
+async fn test_event_sequence_and_replay() {
+    use botty::{Event, SpawnOptions};
+
+    let socket_path = unique_socket_path();
+    let _cleanup = SocketCleanup(socket_path.clone());
+
+    let server_socket = socket_path.clone();
+    let server_handle = tokio::spawn(async move {
+        let mut server = Server::new(server_socket);
+        server.run().await
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Spawn three agents before anyone subscribes
+    let mut client = Client::new(socket_path);
+    let mut ids = Vec::new();
+    for _ in 0..3 {
+        ids.push(client.spawn(SpawnOptions::new(["sleep", "10"])).await.expect("spawn failed"));
+    }
+
+    // Replaying from the start delivers the missed events in order
+    let all = timeout(Duration::from_secs(5), replay_events(&client, 0, 3))
+        .await
+        .expect("timeout waiting for replay");
+    assert!(all.windows(2).all(|w| w[0].0 < w[1].0), "seqs not increasing: {all:?}");
+    for ((_, event), expected) in all.iter().zip(&ids) {
+        assert!(matches!(event, Event::AgentSpawned { id, .. } if id == expected));
+    }
+
+    // Resuming from the first spawn picks up with the second
+    let resumed = timeout(Duration::from_secs(5), replay_events(&client, all[0].0, 2))
+        .await
+        .expect("timeout waiting for resumed replay");
+    assert_eq!(resumed[0].0, all[1].0);
+    assert_eq!(resumed[1].0, all[2].0);
+
+    for id in &ids {
+        client.kill(id, 9).await.expect("kill failed");
+    }
+    let _ = client.shutdown().await;
+    server_handle.abort();
+}
+
+#[tokio::test]
+async fn test_event_filters_follow_new_agents() {
+    use botty::{ClientError, Event, EventOptions, SpawnOptions};
+    use futures::StreamExt;
+
+    let socket_path = unique_socket_path();
+    let _cleanup = SocketCleanup(socket_path.clone());
+
+    let server_socket = socket_path.clone();
+    let server_handle = tokio::spawn(async move {
+        let mut server = Server::new(server_socket);
+        server.run().await
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Unknown event types are rejected up front
+    let mut client = Client::new(socket_path);
+    let rejected = client
+        .events(EventOptions {
+            event_types: vec!["agent_exploded".into()],
+            ..EventOptions::default()
+        })
+        .await
+        .expect("events failed");
+    let mut rejected = std::pin::pin!(rejected);
+    assert!(matches!(
+        rejected.next().await,
+        Some(Err(ClientError::ServerError(message))) if message.contains("agent_exploded")
+    ));
+
+    // Subscribe before any worker exists
+    let events = client
+        .events(EventOptions {
+            labels: vec!["worker".into()],
+            event_types: vec!["agent_spawned".into(), "agent_exited".into()],
+            ..EventOptions::default()
+        })
+        .await
+        .expect("events failed");
+    let mut events = std::pin::pin!(events);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut other = SpawnOptions::new(["sleep", "10"]);
+    other.labels = vec!["db".into()];
+    let other = client.spawn(other).await.expect("spawn failed");
+    let mut worker = SpawnOptions::new(["sh", "-c", "echo working"]);
+    worker.labels = vec!["worker".into()];
+    let worker = client.spawn(worker).await.expect("spawn failed");
+
+    // Only the worker's spawn and exit come through, without its output
+    let seen = timeout(Duration::from_secs(5), async {
+        let mut seen = Vec::new();
+        while seen.len() < 2 {
+            seen.push(events.next().await.expect("stream ended").expect("event error"));
+        }
+        seen
+    })
+    .await
+    .expect("timeout waiting for worker events");
+    assert!(matches!(&seen[0], Event::AgentSpawned { id, .. } if *id == worker));
+    assert!(matches!(&seen[1], Event::AgentExited { id, .. } if *id == worker));
+
+    // Once the worker is gone, a replay still judges its events as they were
+    client.remove(&worker).await.expect("remove failed");
+    let replayed = client
+        .events(EventOptions {
+            labels: vec!["worker".into()],
+            event_types: vec!["agent_exited".into(), "agent_removed".into()],
+            since_seq: Some(0),
+            ..EventOptions::default()
+        })
+        .await
+        .expect("events failed");
+    let mut replayed = std::pin::pin!(replayed);
+    let seen = timeout(Duration::from_secs(5), async {
+        let mut seen = Vec::new();
+        while seen.len() < 2 {
+            seen.push(replayed.next().await.expect("stream ended").expect("event error"));
+        }
+        seen
+    })
+    .await
+    .expect("timeout waiting for replayed worker events");
+    assert!(matches!(&seen[0], Event::AgentExited { id, .. } if *id == worker));
+    assert!(matches!(&seen[1], Event::AgentRemoved { id } if *id == worker));
+
+    client.kill(&other, 9).await.expect("kill failed");
+    let _ = client.shutdown().await;
+    server_handle.abort();
+}
@@ -216,14 +216,14 @@ async fn test_rapid_spawn_kill_cycle() {
         let agent = harness
             .spawn(&["sh", "-c", &format!("echo 'Agent {i}'; sleep 10")])
             .await
-            .expect(&format!("spawn agent {i}"));
+            .unwrap_or_else(|_| panic!("spawn agent {i}"));
 
         agent
             .wait_for_content(&format!("Agent {i}"), Duration::from_secs(2))
             .await
-            .expect(&format!("agent {i} should output"));
+            .unwrap_or_else(|_| panic!("agent {i} should output"));
 
-        agent.kill().await.expect(&format!("kill agent {i}"));
+        agent.kill().await.unwrap_or_else(|_| panic!("kill agent {i}"));
     }
 
     // All agents should be killed, but may still be in list as exited
@@ -337,3 +337,137 @@ async fn test_tui_screen_updates() {
     tui.kill().await.ok();
     harness.shutdown().await;
 }
+
+/// Scenario: Orchestrator drives a shell and waits for exact prompts rather than
+/// guessing from the screen, using OSC 133 shell integration.
+#[tokio::test]
+async fn test_shell_integration_prompt_tracking() {
+    use botty::SpawnOptions;
+
+    let harness = TestHarness::new().await;
+
+    let mut options = SpawnOptions::new(["bash", "--norc", "--noprofile"]);
+    options.shell_integration = true;
+    let shell = harness.spawn_with_options(options).await.expect("spawn shell");
+    let timeout = Duration::from_secs(5);
+
+    shell.wait_for_prompt(timeout).await.expect("initial prompt");
+    let state = shell.shell_state().await.expect("state").expect("markers seen");
+    assert_eq!(state.commands, 0);
+
+    // The prompt only counts once the slow command has finished
+    shell.send("sleep 0.3; echo SLOW_DONE; false").await.expect("send");
+    let snapshot = shell.wait_for_prompt(timeout).await.expect("prompt after command");
+    assert!(snapshot.contains("SLOW_DONE"), "prompt returned early: {snapshot}");
+
+    let state = shell.shell_state().await.expect("state").expect("markers seen");
+    assert_eq!(state.commands, 1);
+    assert_eq!(state.last_exit_code, Some(1));
+    assert!(state.at_prompt && !state.running);
+
+    shell.kill().await.ok();
+    harness.shutdown().await;
+}
+
+/// Scenario: TUIs see the same terminal description no matter what environment
+/// the server was started from.
+#[tokio::test]
+async fn test_agents_get_terminal_environment() {
+    use botty::SpawnOptions;
+
+    let harness = TestHarness::new().await;
+    // The size comes from the PTY, not LINES/COLUMNS, which would go stale
+    let script = r#"echo "[$TERM|$COLORTERM|$LINES|$COLUMNS|$(stty size)]"; sleep 10"#;
+    let timeout = Duration::from_secs(5);
+
+    let mut options = SpawnOptions::new(["sh", "-c", script]);
+    options.env_clear = true;
+    options.rows = 30;
+    options.cols = 100;
+    let default = harness.spawn_with_options(options).await.expect("spawn default");
+    default
+        .wait_for_content("[xterm-256color|truecolor|||30 100]", timeout)
+        .await
+        .expect("default terminal environment");
+
+    let mut options = SpawnOptions::new(["sh", "-c", script]);
+    options.term = Some("vt100".to_string());
+    let vt100 = harness.spawn_with_options(options).await.expect("spawn vt100");
+    vt100
+        .wait_for_content("[vt100||||24 80]", timeout)
+        .await
+        .expect("overridden TERM");
+
+    // Explicit environment variables win over the defaults
+    let mut options = SpawnOptions::new(["sh", "-c", script]);
+    options.env = vec!["COLUMNS=132".to_string()];
+    let explicit = harness.spawn_with_options(options).await.expect("spawn explicit");
+    explicit
+        .wait_for_content("[xterm-256color|truecolor||132|24 80]", timeout)
+        .await
+        .expect("explicit COLUMNS");
+
+    let mut options = SpawnOptions::new(["true"]);
+    options.term = Some("../etc/passwd".to_string());
+    assert!(harness.spawn_with_options(options).await.is_err());
+
+    default.kill().await.ok();
+    vt100.kill().await.ok();
+    explicit.kill().await.ok();
+    harness.shutdown().await;
+}
+
+/// Scenario: Orchestrator waits for workers to finish and checks how each one ended.
+#[tokio::test]
+async fn test_wait_for_worker_exit() {
+    use botty::ExitReason;
+
+    let harness = TestHarness::new().await;
+    let timeout = Duration::from_secs(5);
+
+    let failing = harness
+        .spawn(&["sh", "-c", "sleep 0.2; exit 7"])
+        .await
+        .expect("spawn failing worker");
+    let exit = failing.wait_for_exit(timeout).await.expect("worker should exit");
+    assert_eq!(exit.code, Some(7));
+    assert_eq!(exit.reason, ExitReason::Normal);
+
+    let stuck = harness.spawn(&["sleep", "30"]).await.expect("spawn stuck worker");
+    assert!(stuck.exit_status().await.expect("status").is_none());
+    stuck.kill().await.expect("kill");
+    let exit = harness
+        .wait_for_exit(stuck.id(), timeout)
+        .await
+        .expect("worker should exit");
+    assert_eq!(exit.reason, ExitReason::Killed);
+    assert!(exit.code.is_some_and(|code| code > 128));
+
+    harness.shutdown().await;
+}
+
+/// Scenario: Orchestrator watches only a TUI's status line, so stale text
+/// elsewhere on the screen can't satisfy the wait.
+#[tokio::test]
+async fn test_wait_on_status_line() {
+    use botty::Region;
+
+    let harness = TestHarness::new().await;
+    let script = r"printf 'log: ready soon\033[24;1Hstatus: busy'; sleep 0.5; printf '\033[24;1H\033[2Kstatus: ready'; sleep 30";
+    let tui = harness.spawn(&["sh", "-c", script]).await.expect("spawn tui");
+    let status = tui.with_region(Region::LastLine);
+    let timeout = Duration::from_secs(5);
+
+    // The whole screen mentions "ready" straight away; the status line doesn't yet
+    tui.wait_for_content("ready", timeout).await.expect("log line");
+    assert_eq!(status.snapshot().await.expect("snapshot"), "status: busy");
+
+    let line = status
+        .wait_for_content("ready", timeout)
+        .await
+        .expect("status line should become ready");
+    assert_eq!(line, "status: ready");
+
+    tui.kill().await.ok();
+    harness.shutdown().await;
+}